@@ -17,6 +17,9 @@ use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FResult};
 use std::num::NonZeroU32;
 use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 
 
 /***** ERRORS *****/
@@ -42,6 +45,53 @@ impl Display for DimensionsParseError {
 }
 impl Error for DimensionsParseError {}
 
+/// Defines errors that may occur when parsing a [`Region`] struct.
+#[derive(Debug)]
+pub enum RegionParseError {
+    /// Did not find exactly four coordinates.
+    WrongCount { raw: String, got: usize },
+    /// Failed to parse a coordinate as an unsigned integer.
+    CoordParseFail { raw: String, err: std::num::ParseIntError },
+    /// The region does not contain any pixels.
+    Empty { raw: String },
+}
+impl Display for RegionParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use RegionParseError::*;
+        match self {
+            WrongCount { raw, got } => write!(f, "Expected four comma-separated coordinates in region '{raw}', got {got}"),
+            CoordParseFail { raw, err } => write!(f, "Cannot parse coordinate '{raw}' as an unsigned integer: {err}"),
+            Empty { raw } => write!(f, "Region '{raw}' is empty (x1 and y1 must be larger than x0 and y0, respectively)"),
+        }
+    }
+}
+impl Error for RegionParseError {}
+
+/// Defines errors that may occur when parsing a [`TimeLimit`] struct.
+#[derive(Debug)]
+pub enum TimeLimitParseError {
+    /// The time limit was empty.
+    Empty,
+    /// Failed to parse an amount as a number.
+    AmountParseFail { raw: String, err: std::num::ParseFloatError },
+    /// An amount had a unit we don't know.
+    UnknownUnit { raw: String, unit: String },
+    /// The time limit is not a positive, finite duration.
+    Illegal { raw: String },
+}
+impl Display for TimeLimitParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use TimeLimitParseError::*;
+        match self {
+            Empty => write!(f, "Time limit is empty"),
+            AmountParseFail { raw, err } => write!(f, "Cannot parse amount '{raw}' as a number: {err}"),
+            UnknownUnit { raw, unit } => write!(f, "Unknown unit '{unit}' in '{raw}' (expected 's', 'm', 'h' or 'd')"),
+            Illegal { raw } => write!(f, "Time limit '{raw}' is not a positive duration"),
+        }
+    }
+}
+impl Error for TimeLimitParseError {}
+
 
 
 
@@ -93,3 +143,160 @@ impl From<Dimensions> for (NonZeroU32, NonZeroU32) {
     #[inline]
     fn from(value: Dimensions) -> Self { (value.0, value.1) }
 }
+
+
+
+/// Defines an `<X0>,<Y0>,<X1>,<Y1>` window of pixels in a frame.
+///
+/// It spans from `(x0, y0)` (inclusive) to `(x1, y1)` (exclusive).
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Region {
+    /// The leftmost column in the region.
+    pub x0: u32,
+    /// The topmost row in the region.
+    pub y0: u32,
+    /// The column just right of the region.
+    pub x1: u32,
+    /// The row just below the region.
+    pub y1: u32,
+}
+
+impl Region {
+    /// Constructor for the Region that spans a whole frame.
+    ///
+    /// # Arguments
+    /// - `dims`: The dimensions of the frame, as a `(width, height)`-pair.
+    ///
+    /// # Returns
+    /// A new Region covering every pixel in the frame.
+    #[inline]
+    pub const fn full(dims: (u32, u32)) -> Self { Self { x0: 0, y0: 0, x1: dims.0, y1: dims.1 } }
+
+    /// Returns whether this Region fits in a frame of the given dimensions.
+    #[inline]
+    pub const fn fits(&self, dims: (u32, u32)) -> bool { self.x1 <= dims.0 && self.y1 <= dims.1 }
+
+    /// Returns whether the given pixel is within this Region.
+    #[inline]
+    pub const fn contains(&self, pixel: (u32, u32)) -> bool { pixel.0 >= self.x0 && pixel.0 < self.x1 && pixel.1 >= self.y0 && pixel.1 < self.y1 }
+
+    /// Returns an iterator over the pixels in this Region, in row-major order.
+    #[inline]
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let Self { x0, y0, x1, y1 } = *self;
+        (y0..y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+    }
+
+    /// Returns the position of the Region's top-left pixel, as an `(x, y)`-pair.
+    #[inline]
+    pub const fn pos(&self) -> (u32, u32) { (self.x0, self.y0) }
+
+    /// Returns the size of the Region, as a `(width, height)`-pair.
+    #[inline]
+    pub const fn dims(&self) -> (u32, u32) { (self.x1.saturating_sub(self.x0), self.y1.saturating_sub(self.y0)) }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult { write!(f, "{},{},{},{}", self.x0, self.y0, self.x1, self.y1) }
+}
+impl FromStr for Region {
+    type Err = RegionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Split on the commas
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        if parts.len() != 4 {
+            return Err(RegionParseError::WrongCount { raw: s.into(), got: parts.len() });
+        }
+
+        // Parse them
+        let mut coords: [u32; 4] = [0; 4];
+        for (coord, part) in coords.iter_mut().zip(parts) {
+            *coord = match u32::from_str(part) {
+                Ok(coord) => coord,
+                Err(err) => {
+                    return Err(RegionParseError::CoordParseFail { raw: part.into(), err });
+                },
+            };
+        }
+        let [x0, y0, x1, y1] = coords;
+        if x1 <= x0 || y1 <= y0 {
+            return Err(RegionParseError::Empty { raw: s.into() });
+        }
+
+        // Done, return the Region
+        Ok(Self { x0, y0, x1, y1 })
+    }
+}
+
+
+
+/// Defines a duration given as amounts with units, like `90s`, `10m` or `1h30m`.
+///
+/// The units are `s` (seconds, which is also assumed if there's no unit), `m` (minutes), `h`
+/// (hours) and `d` (days).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TimeLimit(pub Duration);
+
+impl Display for TimeLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult { write!(f, "{}s", self.0.as_secs_f64()) }
+}
+impl FromStr for TimeLimit {
+    type Err = TimeLimitParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(TimeLimitParseError::Empty);
+        }
+
+        // Parse every amount with its unit
+        let mut secs: f64 = 0.0;
+        let mut rem: &str = s.trim();
+        while !rem.is_empty() {
+            let amount_len: usize = rem.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rem.len());
+            let unit_len: usize = rem[amount_len..].find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(rem.len() - amount_len);
+            let (amount, unit): (&str, &str) = (&rem[..amount_len], rem[amount_len..amount_len + unit_len].trim());
+            let amount: f64 = match f64::from_str(amount) {
+                Ok(amount) => amount,
+                Err(err) => return Err(TimeLimitParseError::AmountParseFail { raw: amount.into(), err }),
+            };
+            secs += amount
+                * match unit {
+                    "" | "s" => 1.0,
+                    "m" => 60.0,
+                    "h" => 3600.0,
+                    "d" => 86400.0,
+                    unit => return Err(TimeLimitParseError::UnknownUnit { raw: s.into(), unit: unit.into() }),
+                };
+            rem = rem[amount_len + unit_len..].trim_start();
+        }
+
+        // Done, return the TimeLimit
+        match Duration::try_from_secs_f64(secs) {
+            Ok(limit) if !limit.is_zero() => Ok(Self(limit)),
+            _ => Err(TimeLimitParseError::Illegal { raw: s.into() }),
+        }
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_limit() {
+        assert_eq!(TimeLimit::from_str("90").unwrap().0, Duration::from_secs(90));
+        assert_eq!(TimeLimit::from_str("10m").unwrap().0, Duration::from_secs(600));
+        assert_eq!(TimeLimit::from_str("1h30m").unwrap().0, Duration::from_secs(5400));
+        assert_eq!(TimeLimit::from_str("1.5 h").unwrap().0, Duration::from_secs(5400));
+        assert!(matches!(TimeLimit::from_str(""), Err(TimeLimitParseError::Empty)));
+        assert!(matches!(TimeLimit::from_str("10x"), Err(TimeLimitParseError::UnknownUnit { .. })));
+        assert!(matches!(TimeLimit::from_str("m"), Err(TimeLimitParseError::AmountParseFail { .. })));
+        assert!(matches!(TimeLimit::from_str("0s"), Err(TimeLimitParseError::Illegal { .. })));
+    }
+}
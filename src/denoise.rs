@@ -0,0 +1,236 @@
+//  DENOISE.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements the `denoise` subcommand, which denoises images that were
+//!   rendered before.
+//!
+//!   OpenEXR files written by `render` contain the albedo and normal
+//!   buffers as layers, which are used to guide the denoiser. For other
+//!   images, the visualized `<name>.albedo.<ext>` and `<name>.normal.<ext>`
+//!   files next to it are used, if they exist.
+//
+
+use std::error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use console::style;
+use log::{debug, info, warn};
+
+use crate::math::{Colour, ToneMapping};
+use crate::render::aov::Aov;
+use crate::render::denoise::Denoiser;
+use crate::render::image::Image;
+use crate::render::Frame;
+
+
+/***** ERRORS *****/
+/// Defines the errors for the `denoise` subcommand.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to load an image.
+    ImageLoad { err: crate::render::image::Error },
+    /// An OpenEXR file did not have any layers.
+    NoLayers { path: PathBuf },
+    /// A guide has different dimensions than the image.
+    DimsMismatch { path: PathBuf, got: (u32, u32), expected: (u32, u32) },
+    /// Failed to save the denoised image.
+    ImageSave { path: PathBuf, err: crate::render::image::Error },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use Error::*;
+        match self {
+            ImageLoad { .. } => write!(f, "Failed to load image"),
+            NoLayers { path } => write!(f, "OpenEXR file '{}' has no layers", path.display()),
+            DimsMismatch { path, got, expected } => {
+                write!(f, "Guide '{}' has {}x{} pixels, but expected {}x{} pixels", path.display(), got.0, got.1, expected.0, expected.1)
+            },
+            ImageSave { path, .. } => write!(f, "Failed to save denoised image to '{}'", path.display()),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+        match self {
+            ImageLoad { err } => Some(err),
+            NoLayers { .. } => None,
+            DimsMismatch { .. } => None,
+            ImageSave { err, .. } => Some(err),
+        }
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns whether the given path refers to an OpenEXR file.
+#[inline]
+fn is_exr(path: &Path) -> bool { path.extension().map(|ext| ext.eq_ignore_ascii_case("exr")).unwrap_or(false) }
+
+/// Returns the path where `render` writes an AOV of the given image if it isn't an OpenEXR file.
+///
+/// # Arguments
+/// - `path`: The path of the image.
+/// - `aov`: The [`Aov`] to find.
+///
+/// # Returns
+/// The path `<stem>.<aov>.<ext>` next to the image.
+fn aov_path(path: &Path, aov: Aov) -> PathBuf {
+    let mut name: OsString = path.file_stem().unwrap_or_default().to_owned();
+    name.push(format!(".{}", aov.name()));
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+/// Loads a guide for the denoiser from a separate file.
+///
+/// For OpenEXR files, the layer named after the AOV is used (or the first one if there's no such
+/// layer), which contains its raw values. Other images contain the visualized AOV (see
+/// [`Aov::visualize()`]), which is undone for the normals.
+///
+/// # Arguments
+/// - `path`: The path of the file to load.
+/// - `aov`: The [`Aov`] that it contains.
+///
+/// # Returns
+/// The raw values of the AOV.
+///
+/// # Errors
+/// This function errors if we failed to load the file.
+fn load_guide(path: &Path, aov: Aov) -> Result<Image, Error> {
+    debug!("Loading {} guide '{}'...", aov.name(), path.display());
+    if is_exr(path) {
+        let mut layers: Vec<(String, Image)> = Image::layers_from_path(path).map_err(|err| Error::ImageLoad { err })?;
+        if layers.is_empty() {
+            return Err(Error::NoLayers { path: path.into() });
+        }
+        let i: usize = layers.iter().position(|(name, _)| name == aov.name()).unwrap_or(0);
+        return Ok(layers.swap_remove(i).1);
+    }
+
+    let mut image: Image = Image::from_written_path(path).map_err(|err| Error::ImageLoad { err })?;
+    if aov == Aov::Normal {
+        for colour in image.iter_mut() {
+            *colour = Colour::new(2.0 * colour.r - 1.0, 2.0 * colour.g - 1.0, 2.0 * colour.b - 1.0, colour.a);
+        }
+    }
+    Ok(image)
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Denoises an image that was rendered before.
+///
+/// The image is either an OpenEXR file, of which the [`Frame::BEAUTY`] layer (or else the first
+/// one) is denoised, or any other image. The denoiser is guided by the albedo and normal buffers:
+/// unless given explicitly, these are taken from the layers of an OpenEXR file, or from the files
+/// written next to other images (see `render --aov`).
+///
+/// If both the input and the output are OpenEXR files, then the other layers of the input are
+/// copied over. Either way, colours are denoised in linear space: HDR files (see
+/// [`Image::is_hdr_path()`]) are read and written as-is, and gamma correction is only undone and
+/// applied again for other images. Those are tone mapped already, so the given tone mapping is
+/// only applied when writing an HDR input to another format.
+///
+/// # Arguments
+/// - `input`: The path of the image to denoise.
+/// - `output`: The path to write the denoised image to.
+/// - `albedo`: The path of the albedo guide, if not the default one.
+/// - `normal`: The path of the normal guide, if not the default one.
+/// - `denoiser`: The [`Denoiser`] to denoise with.
+/// - `tone_mapping`: The [`ToneMapping`] to apply to HDR inputs when writing them to other formats.
+/// - `gamma_correction`: Whether the image is gamma corrected (and the result should be too), if not an HDR file.
+/// - `fix_dirs`: Whether to fix missing directories or chicken out.
+///
+/// # Errors
+/// This function errors if we failed to read the image or any guide, the guides don't fit the
+/// image, or we failed to write the result.
+#[allow(clippy::too_many_arguments)]
+pub fn denoise(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    albedo: Option<&Path>,
+    normal: Option<&Path>,
+    denoiser: &Denoiser,
+    tone_mapping: ToneMapping,
+    gamma_correction: bool,
+    fix_dirs: bool,
+) -> Result<(), Error> {
+    let (input, output): (&Path, &Path) = (input.as_ref(), output.as_ref());
+    info!("Denoising '{}' into '{}'...", input.display(), output.display());
+
+    // Load the image and its guides
+    debug!("Loading image '{}'...", input.display());
+    let (mut layers, beauty): (Vec<(String, Image)>, usize) = if is_exr(input) {
+        let layers: Vec<(String, Image)> = Image::layers_from_path(input).map_err(|err| Error::ImageLoad { err })?;
+        if layers.is_empty() {
+            return Err(Error::NoLayers { path: input.into() });
+        }
+        let beauty: usize = layers.iter().position(|(name, _)| name == Frame::BEAUTY).unwrap_or(0);
+        (layers, beauty)
+    } else {
+        (vec![(Frame::BEAUTY.into(), Image::from_written_path(input).map_err(|err| Error::ImageLoad { err })?)], 0)
+    };
+    let mut guides: [Option<Image>; 2] = [None, None];
+    for (guide, (aov, path)) in guides.iter_mut().zip([(Aov::Albedo, albedo), (Aov::Normal, normal)]) {
+        let path: PathBuf = match path {
+            Some(path) => path.into(),
+            None if is_exr(input) => {
+                *guide = layers.iter().find(|(name, _)| name == aov.name()).map(|(_, image)| image.clone());
+                if guide.is_none() {
+                    warn!("'{}' has no {} layer; denoising without it", input.display(), aov.name());
+                }
+                continue;
+            },
+            None => {
+                let path: PathBuf = aov_path(input, aov);
+                if !path.exists() {
+                    warn!("No {} guide '{}' found; denoising without it", aov.name(), path.display());
+                    continue;
+                }
+                path
+            },
+        };
+        let image: Image = load_guide(&path, aov)?;
+        if image.dims() != layers[beauty].1.dims() {
+            return Err(Error::DimsMismatch { path, got: image.dims(), expected: layers[beauty].1.dims() });
+        }
+        *guide = Some(image);
+    }
+
+    // Denoise in linear space, and only then develop it again (unless writing it as-is)
+    let image: &mut Image = &mut layers[beauty].1;
+    let tone_mapping: ToneMapping = if Image::is_hdr_path(input) { tone_mapping } else { ToneMapping::default() };
+    if gamma_correction && !Image::is_hdr_path(input) {
+        image.iter_mut().for_each(|colour| *colour = colour.from_srgb());
+    }
+    *image = denoiser.denoise(image, guides[0].as_ref(), guides[1].as_ref());
+    if !Image::is_hdr_path(output) {
+        *image = image.develop(tone_mapping, gamma_correction);
+    }
+
+    // Write the result
+    if is_exr(output) {
+        if !is_exr(input) {
+            layers.truncate(1);
+        }
+        Image::layers_to_path(layers.iter().map(|(name, image)| (name.as_str(), image)), output, fix_dirs)
+            .map_err(|err| Error::ImageSave { path: output.into(), err })?;
+    } else {
+        layers[beauty].1.to_path(output, fix_dirs).map_err(|err| Error::ImageSave { path: output.into(), err })?;
+    }
+    println!("Successfully {} {}", style("denoised").bold().green(), style(output.display()).bold());
+    Ok(())
+}
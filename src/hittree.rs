@@ -13,20 +13,22 @@
 use std::path::Path;
 use std::range::RangeInclusive;
 
-use crate::math::{AABB, Ray};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::math::{AABB, PixelSampler, Ray};
 use crate::specifications::Loadable;
 use crate::specifications::objects::{BoundingBoxable, HitRecord, Hittable, Object};
 use crate::specifications::scene::Environment;
 
 
 /***** HELPER FUNCTIONS *****/
-/// Manual iterator that collects not just the objects, but also their AABBs.
-fn iter_obj_aabb<T>(node: BVHNode<T>, len: usize) -> Vec<(T, AABB)> {
+/// Manual iterator that collects not just the objects, but also their indices and AABBs.
+fn iter_obj_aabb<T>(node: BVHNode<T>, len: usize) -> Vec<(usize, T, AABB)> {
     let mut res = Vec::with_capacity(len);
     let mut todo = vec![node];
     while let Some(node) = todo.pop() {
         match node {
-            BVHNode::Object(aabb, obj) => res.push((obj, aabb)),
+            BVHNode::Object(aabb, index, obj) => res.push((index, obj, aabb)),
             BVHNode::Next(_, lhs, rhs) => {
                 // Note the reversed order, since we're popping from the **end**
                 todo.extend([*rhs, *lhs]);
@@ -50,7 +52,7 @@ impl<'a, T> Iterator for Iter<'a, T> {
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
         match self.0.pop()? {
-            BVHNode::Object(_, obj) => Some(obj),
+            BVHNode::Object(_, _, obj) => Some(obj),
             BVHNode::Next(_, lhs, rhs) => {
                 // Note the reversed order, since we're popping from the **end**
                 self.0.extend([&**rhs, &**lhs]);
@@ -69,7 +71,7 @@ impl<'a, T> Iterator for IterMut<'a, T> {
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
         match self.0.pop()? {
-            BVHNode::Object(_, obj) => Some(obj),
+            BVHNode::Object(_, _, obj) => Some(obj),
             BVHNode::Next(_, lhs, rhs) => {
                 // Note the reversed order, since we're popping from the **end**
                 self.0.extend([&mut **rhs, &mut **lhs]);
@@ -88,7 +90,7 @@ impl<T> Iterator for IntoIter<T> {
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
         match self.0.pop()? {
-            BVHNode::Object(_, obj) => Some(obj),
+            BVHNode::Object(_, _, obj) => Some(obj),
             BVHNode::Next(_, lhs, rhs) => {
                 // Note the reversed order, since we're popping from the **end**
                 self.0.extend([*rhs, *lhs]);
@@ -124,8 +126,8 @@ impl Drop for Safetynet {
 enum BVHNode<T> {
     // NOTE: Time ranges in which the aabb is valid, are externally tracked through the [`HitVec`]
     // (only place of accessing this API).
-    /// It's a single object.
-    Object(AABB, T),
+    /// It's a single object, together with the index at which it was added to the [`HitTree`].
+    Object(AABB, usize, T),
     /// It's a link to a new set
     Next(AABB, Box<Self>, Box<Self>),
 }
@@ -136,26 +138,26 @@ impl<T> BVHNode<T> {
     /// as a tree.
     ///
     /// # Arguments
-    /// - `objs`: The objects to store together with their already-computed Axis-Aligned Bounding
-    ///   Boxes ([`AABB`]). Note that we expect a vector to avoid as many re-allocations as
-    ///   possible.
+    /// - `objs`: The objects to store together with their index in the [`HitTree`] and their
+    ///   already-computed Axis-Aligned Bounding Boxes ([`AABB`]). Note that we expect a vector to
+    ///   avoid as many re-allocations as possible.
     ///
     /// # Returns
     /// A new BVHNode that wraps the given `objs`.
     #[inline]
     #[track_caller]
-    fn new(mut objs: Vec<(T, AABB)>) -> Self {
+    fn new(mut objs: Vec<(usize, T, AABB)>) -> Self {
         // Handle base cases
         let objs_len: usize = objs.len();
         if objs_len == 0 {
             panic!("Cannot create BVHNode structure over an empty list of objects")
         } else if objs_len == 1 {
-            let (obj, aabb) = objs.swap_remove(0);
-            return Self::Object(aabb, obj);
+            let (index, obj, aabb) = objs.swap_remove(0);
+            return Self::Object(aabb, index, obj);
         }
 
         // Compute the bounding box for our objects
-        let aabb: AABB = objs.iter().map(|(_, aabb)| *aabb).collect();
+        let aabb: AABB = objs.iter().map(|(_, _, aabb)| *aabb).collect();
 
         // Find its largest axis
         let dims: [f64; 3] = aabb.dims();
@@ -168,7 +170,7 @@ impl<T> BVHNode<T> {
         };
 
         // Sort the list of objects along this axis
-        objs.sort_by(|(_, lhs), (_, rhs)| f64::total_cmp(&lhs.dim(largest).min(), &rhs.dim(largest).min()));
+        objs.sort_by(|(_, _, lhs), (_, _, rhs)| f64::total_cmp(&lhs.dim(largest).min(), &rhs.dim(largest).min()));
 
         // Now split the list equally down the middle... (as best we can)
         let rhs = objs.split_off(objs_len / 2);
@@ -193,7 +195,7 @@ impl<T> BVHNode<T> {
 
         // Compute the AABB
         let aabb: AABB = match (&self, &other) {
-            (Self::Object(lhs, _) | Self::Next(lhs, _, _), Self::Object(rhs, _) | Self::Next(rhs, _, _)) => AABB::surround(*lhs, *rhs),
+            (Self::Object(lhs, _, _) | Self::Next(lhs, _, _), Self::Object(rhs, _, _) | Self::Next(rhs, _, _)) => AABB::surround(*lhs, *rhs),
         };
 
         // Let's do this efficiently: take `self`...
@@ -224,7 +226,7 @@ impl<T: BoundingBoxable> BVHNode<T> {
     /// The compute AABB for this node.
     fn recompute_aabbs(&mut self, ts: RangeInclusive<u64>) -> AABB {
         match self {
-            Self::Object(aabb, obj) => {
+            Self::Object(aabb, _, obj) => {
                 *aabb = AABB::surround(obj.aabb(ts.start), obj.aabb(ts.last));
                 *aabb
             },
@@ -259,7 +261,7 @@ impl<T: Loadable> Loadable for BVHNode<T> {
     #[inline]
     fn load(&mut self, dir: &Path) -> Result<(), Self::Error> {
         match self {
-            Self::Object(_, obj) => obj.load(dir),
+            Self::Object(_, _, obj) => obj.load(dir),
             Self::Next(_, lhs, rhs) => {
                 lhs.load(dir)?;
                 rhs.load(dir)?;
@@ -272,16 +274,16 @@ impl<T> BoundingBoxable for BVHNode<T> {
     #[inline]
     fn aabb(&self, _t_us: u64) -> AABB {
         match self {
-            Self::Object(aabb, _) => *aabb,
+            Self::Object(aabb, _, _) => *aabb,
             Self::Next(aabb, _, _) => *aabb,
         }
     }
 }
-impl<T: Hittable> Hittable for BVHNode<T> {
+impl<T: Hittable> BVHNode<T> {
     /// Computes a hit on an object in the BVHNode.
     ///
     /// Unlike [`Hittable::hit()`](crate::specifications::objects::Hittable::hit()), this version
-    /// returns the material of the object that was hit. You can use this to scatter later.
+    /// also returns the index of the object that was hit.
     ///
     /// # Arguments
     /// - `ray`: The [`Ray`] to compute any hits with.
@@ -293,10 +295,9 @@ impl<T: Hittable> Hittable for BVHNode<T> {
     ///   environment.
     ///
     /// # Returns
-    /// A new [`HitRecord`] struct, which collects relevant information of this hit, or else
-    /// [`None`] if the ray does not hit.
-    #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+    /// The index of the object that was hit and a new [`HitRecord`] struct, which collects
+    /// relevant information of this hit, or else [`None`] if the ray does not hit.
+    fn hit_indexed(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<(usize, HitRecord<'_>)> {
         // Check if we're hit in the first place
         if !self.aabb(ray.time).hittest(ray, t_min, t_max) {
             return None;
@@ -305,14 +306,14 @@ impl<T: Hittable> Hittable for BVHNode<T> {
         // If so, then do a more detailled hit
         match self {
             // Normal object hit
-            Self::Object(_, obj) => obj.hit(ray, t_min, t_max, env),
+            Self::Object(_, index, obj) => obj.hit(ray, t_min, t_max, env, sampler).map(|record| (*index, record)),
             // Check which half of the BVH is hit instead
             Self::Next(_, lhs, rhs) => {
-                let lhs: Option<HitRecord> = lhs.hit(ray, t_min, t_max, env);
-                let rhs: Option<HitRecord> = rhs.hit(ray, t_min, t_max, env);
+                let lhs: Option<(usize, HitRecord)> = lhs.hit_indexed(ray, t_min, t_max, env, sampler);
+                let rhs: Option<(usize, HitRecord)> = rhs.hit_indexed(ray, t_min, t_max, env, sampler);
                 match (lhs, rhs) {
                     // Return the closest of the two hits if both
-                    (Some(lhs), Some(rhs)) if lhs.data.t <= rhs.data.t => Some(lhs),
+                    (Some(lhs), Some(rhs)) if lhs.1.data.t <= rhs.1.data.t => Some(lhs),
                     (Some(_), Some(rhs)) => Some(rhs),
                     // Else, return the hit half
                     (Some(lhs), None) => Some(lhs),
@@ -383,7 +384,8 @@ impl<T: BoundingBoxable> HitTree<T> {
     /// AABB's.
     ///
     /// # Arguments
-    /// - `objs`: The list of objec`T`s to initialize the HitTree with.
+    /// - `objs`: The list of objec`T`s to initialize the HitTree with. They are indexed in the
+    ///   order given (see [`HitTree::hit_indexed()`]).
     /// - `ts`: A range of time (as microseconds since the start of the scene, both ends inclusive)
     ///   for which to compute an AABB. Usually, this is the current time with a duration of the
     ///   shutter time.
@@ -393,11 +395,12 @@ impl<T: BoundingBoxable> HitTree<T> {
     #[inline]
     pub fn with_objs(objs: impl IntoIterator<Item = T>, ts: RangeInclusive<u64>) -> Self {
         // Compute the AABBs for all objects
-        let objs: Vec<(T, AABB)> = objs
+        let objs: Vec<(usize, T, AABB)> = objs
             .into_iter()
-            .map(|o| {
+            .enumerate()
+            .map(|(i, o)| {
                 let aabb = AABB::surround(o.aabb(ts.start), o.aabb(ts.last));
-                (o, aabb)
+                (i, o, aabb)
             })
             .collect();
         let len: usize = objs.len();
@@ -428,7 +431,8 @@ impl<T: Loadable> Loadable for HitTree<T> {
 impl<T: BoundingBoxable> HitTree<T> {
     /// Adds a new object to the HitTree.
     ///
-    /// This object will introduce a new split at the **top** of the tree, which is likely not
+    /// The object gets the next index (i.e., [`HitTree::len()`] before adding it). This object
+    /// will introduce a new split at the **top** of the tree, which is likely not
     /// optimal. Call [`HitTree::rebalance()`] to balance the tree again.
     ///
     /// # Arguments
@@ -454,12 +458,12 @@ impl<T: BoundingBoxable> HitTree<T> {
                 // Note we always use the largest time range
                 let aabb: AABB = AABB::surround(obj.aabb(self.ts[0]), obj.aabb(self.ts[1]));
                 // Insert a new node around the current and the root
-                root.wrap_into(BVHNode::Object(aabb, obj));
+                root.wrap_into(BVHNode::Object(aabb, self.len, obj));
             },
             None => {
                 // First node, ez
                 let aabb: AABB = AABB::surround(obj.aabb(ts.start), obj.aabb(ts.last));
-                self.elems = Some(BVHNode::Object(aabb, obj));
+                self.elems = Some(BVHNode::Object(aabb, self.len, obj));
                 self.ts = [ts.start, ts.last];
             },
         }
@@ -469,7 +473,7 @@ impl<T: BoundingBoxable> HitTree<T> {
 
     /// Adds a set of new objects to the HitTree.
     ///
-    /// This will introduce a new split at the **top** of the tree, which is likely not optimal.
+    /// The objects get the next indices, in the order given. This will introduce a new split at the **top** of the tree, which is likely not optimal.
     /// Call [`HitTree::rebalance()`] to balance the tree again.
     ///
     /// # Arguments
@@ -496,9 +500,10 @@ impl<T: BoundingBoxable> HitTree<T> {
                     objs.into_iter()
                         .map(|o| {
                             // Note we always use the largest time range
+                            let index: usize = self.len;
                             self.len += 1;
                             let aabb = AABB::surround(o.aabb(self.ts[0]), o.aabb(self.ts[1]));
-                            (o, aabb)
+                            (index, o, aabb)
                         })
                         .collect(),
                 );
@@ -510,9 +515,10 @@ impl<T: BoundingBoxable> HitTree<T> {
                 self.elems = Some(BVHNode::new(
                     objs.into_iter()
                         .map(|o| {
+                            let index: usize = self.len;
                             self.len += 1;
                             let aabb = AABB::surround(o.aabb(ts.start), o.aabb(ts.last));
-                            (o, aabb)
+                            (index, o, aabb)
                         })
                         .collect(),
                 ));
@@ -542,6 +548,18 @@ impl<T: BoundingBoxable> HitTree<T> {
         }
         self
     }
+
+    /// Recomputes the AABBs of all objects for the time range they were last computed for.
+    ///
+    /// Use this after changing the objects in-place (e.g., through [`HitTree::iter_mut()`]).
+    ///
+    /// # Returns
+    /// Self for chaining.
+    #[inline]
+    pub fn refresh_aabbs(&mut self) -> &mut Self {
+        let ts: RangeInclusive<u64> = (self.ts[0]..=self.ts[1]).into();
+        self.recompute_aabbs(ts)
+    }
 }
 impl<T> HitTree<T> {
     /// Rebalances the HitTree.
@@ -623,13 +641,84 @@ impl<T> BoundingBoxable for HitTree<T> {
 impl<T: Hittable> Hittable for HitTree<T> {
     #[inline]
     #[cfg_attr(debug_assertions, track_caller)]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+        self.hit_indexed(ray, t_min, t_max, env, sampler).map(|(_, record)| record)
+    }
+}
+impl<T: Hittable> HitTree<T> {
+    /// Computes a hit on an object in the HitTree, and tells which object was hit.
+    ///
+    /// Objects are indexed in the order in which they were added to the tree, which stays the same
+    /// when the tree is rebalanced or (de)serialized. For a tree built from a scene, this is the
+    /// index of the object in the scene file.
+    ///
+    /// # Arguments
+    /// - `ray`: The [`Ray`] to compute any hits with.
+    /// - `t_min`: The minimum point along the ray we still accept.
+    /// - `t_max`: The maximum point along the ray we still accept.
+    /// - `env`: An [`Environment`] struct relating information about the scene's total
+    ///   environment.
+    /// - `sampler`: The [`PixelSampler`] to draw random numbers from.
+    ///
+    /// # Returns
+    /// The index of the closest object that was hit and the [`HitRecord`] of the hit, or else
+    /// [`None`] if the ray does not hit anything.
+    #[inline]
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn hit_indexed(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<(usize, HitRecord<'_>)> {
         #[cfg(debug_assertions)]
         if ray.time < self.ts[0] || ray.time > self.ts[1] {
             panic!("HitTree initialized for time range {:?} cannot compute Ray hit at time {}", self.ts, ray.time);
         }
 
         // Run the hit
-        self.elems.as_ref().and_then(|elems| elems.hit(ray, t_min, t_max, env))
+        self.elems.as_ref().and_then(|elems| elems.hit_indexed(ray, t_min, t_max, env, sampler))
+    }
+}
+
+// Serde
+impl<T: Serialize> Serialize for HitTree<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        /// Only the objects (in the order of their indices) and the time range are serialized; the BVH is rebuilt when deserializing.
+        #[derive(Serialize)]
+        struct Raw<'a, T> {
+            ts:      [u64; 2],
+            objects: Vec<&'a T>,
+        }
+
+        // Collect the objects by index
+        let mut objects: Vec<(usize, &T)> = Vec::with_capacity(self.len);
+        let mut todo: Vec<&BVHNode<T>> = self.elems.iter().collect();
+        while let Some(node) = todo.pop() {
+            match node {
+                BVHNode::Object(_, index, obj) => objects.push((*index, obj)),
+                BVHNode::Next(_, lhs, rhs) => todo.extend([&**lhs, &**rhs]),
+            }
+        }
+        objects.sort_by_key(|(index, _)| *index);
+
+        Raw { ts: self.ts, objects: objects.into_iter().map(|(_, obj)| obj).collect() }.serialize(serializer)
+    }
+}
+impl<'de, T: BoundingBoxable + Deserialize<'de>> Deserialize<'de> for HitTree<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        /// Counterpart to the one in [`HitTree::serialize()`].
+        #[derive(Deserialize)]
+        struct Raw<T> {
+            ts:      [u64; 2],
+            objects: Vec<T>,
+        }
+
+        let raw: Raw<T> = Raw::deserialize(deserializer)?;
+        if raw.ts[0] > raw.ts[1] {
+            return Err(serde::de::Error::custom(format!("Invalid time range {:?}", raw.ts)));
+        }
+        Ok(Self::with_objs(raw.objects, (raw.ts[0]..=raw.ts[1]).into()))
     }
 }
@@ -21,5 +21,8 @@ pub mod math;
 pub mod specifications;
 
 // Declare the subcommand modules
+pub mod denoise;
 pub mod generate;
+pub mod merge;
 pub mod render;
+pub mod worker;
@@ -12,30 +12,43 @@
 //!   Entrypoint to the main `raytracer` application.
 //
 
-use std::num::NonZeroU64;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Parser, Subcommand};
+use console::style;
 use error_trace::{ErrorTrace as _, toplevel};
 use humanlog::{DebugMode, HumanLogger};
-use log::{debug, error, info};
-use raytracer::common::input::Dimensions;
-use raytracer::generate;
+use indicatif::HumanDuration;
+use log::{debug, error, info, warn};
+use raytracer::common::input::{Dimensions, Region, TimeLimit};
+use raytracer::{denoise, generate, merge, worker};
 use raytracer::hittree::HitTree;
-use raytracer::math::{AABB, Camera, Colour, Vec3};
+use raytracer::math::{AdaptiveSampling, Camera, Filter, FilterKind, Sampler, ToneMapOperator, ToneMapping};
+use raytracer::render::aov::Aov;
+use raytracer::render::backends::farm::{FarmRenderer, FarmRendererConfig};
 use raytracer::render::backends::multi::{MultiThreadRenderer, MultiThreadRendererConfig};
 use raytracer::render::backends::single::SingleThreadRenderer;
+use raytracer::render::cancel::CancelToken;
+use raytracer::render::checkpoint::{Checkpoint, CheckpointInfo, Checkpointer, SceneSource, scene_hash};
+use raytracer::render::denoise::Denoiser;
 use raytracer::render::image::Image;
-use raytracer::render::{RayRenderer as _, RenderBackend};
+use raytracer::render::integrators::{AmbientOcclusion, AnyIntegrator, DebugIntegrator, DebugView, DirectLighting, IntegratorKind, PathTracer};
+use raytracer::render::lights::MisHeuristic;
+use raytracer::render::pixel::Film;
+use raytracer::render::tui::Tui;
+use raytracer::render::{Frame, RayRenderer, RenderBackend};
 use raytracer::specifications::Loadable as _;
-use raytracer::specifications::animations::{Animation, Vertical};
-use raytracer::specifications::materials::{Dielectric, DiffuseLight, Isotropic, Lambertian, LambertianTexture, Material, Metal};
-use raytracer::specifications::objects::plane::Qd;
-use raytracer::specifications::objects::{AnimatedSphere, Box, ConstantDensity, Object, Quad, RotateY, Sphere, Translate};
-use raytracer::specifications::scene::{Background, Environment, SceneFile};
-use raytracer::specifications::textures::image::Image as TexImage;
-use raytracer::specifications::textures::{SpatialChecker, Texture};
+use raytracer::specifications::covers::{Book, cover};
+use raytracer::specifications::objects::Object;
+#[cfg(feature = "gltf")]
+use raytracer::specifications::objects::model::gltf::is_gltf_path;
+use raytracer::specifications::scene::{Environment, SceneFile};
 
 
 /***** ARGUMENTS *****/
@@ -70,6 +83,18 @@ enum RaytracerSubcommand {
     /// Generates something.
     #[clap(name = "generate", about = "Generates files for testing or for rendering.")]
     Generate(GenerateArguments),
+    /// Merges renders of parts of a frame.
+    #[clap(name = "merge", about = "Merges renders of (parts of) the same frame, e.g., rendered with '--region' on different machines.")]
+    Merge(MergeArguments),
+    /// Denoises a render.
+    #[clap(name = "denoise", about = "Denoises an image rendered before, guided by its albedo and normal buffers if it has them.")]
+    Denoise(DenoiseArguments),
+    /// Renders tiles for a render farm.
+    #[clap(
+        name = "serve-worker",
+        about = "Serves as a worker for a render farm, rendering the tiles handed out by any `render` with '--backend farm' that connects."
+    )]
+    ServeWorker(ServeWorkerArguments),
 }
 
 /// Defines the arguments for the `render` subcommand.
@@ -78,6 +103,14 @@ struct RenderArguments {
     /// The output size of the image.
     #[clap(short, long, help = "The size of the output image for this render. If omitted, defaults to the value in the scene file.")]
     dims:     Option<Dimensions>,
+    /// The window of the image to render.
+    #[clap(
+        long,
+        help = "If given, only renders the pixels in the given '<X0>,<Y0>,<X1>,<Y1>' window of the image (where X1 and Y1 are exclusive). The \
+                rest of the output image is left transparent. Use `merge` to stitch regions back together. If omitted, uses the value from the \
+                scene file (or renders the whole image)."
+    )]
+    region:   Option<Region>,
     /// Whether to fix missing directories when generating the output image or not.
     #[clap(short, long, help = "If given, will generate missing directories for the output image.")]
     fix_dirs: bool,
@@ -102,6 +135,31 @@ struct RenderArguments {
                 file."
     )]
     n_samples: Option<NonZeroU64>,
+    /// Determines how the random numbers for every sample are picked.
+    #[clap(
+        long,
+        help = "The sampler that picks the random numbers for the samples of every pixel. 'stratified', 'halton' and 'sobol' spread them out more \
+                evenly than 'independent', which makes the image converge faster. If omitted, uses the value from the scene file."
+    )]
+    sampler: Option<Sampler>,
+    /// Determines how samples are reconstructed into pixels.
+    #[clap(
+        long,
+        help = "The filter that reconstructs the samples into pixels. 'box' only counts every sample for the pixel it was taken in, while \
+                'tent', 'gaussian', 'mitchell' and 'lanczos' spread them over the pixels around it too, which gives smoother edges (and, for \
+                the latter two, sharper details). Cannot be changed when resuming. If omitted, uses the value from the scene file."
+    )]
+    filter: Option<FilterKind>,
+    /// Determines the radius of the filter.
+    #[clap(
+        long,
+        help = "The radius of the filter in pixels. If omitted, uses the value from the scene file, or the default of the filter ('0.5' for \
+                'box', '1' for 'tent', '1.5' for 'gaussian', '2' for 'mitchell' and '3' for 'lanczos')."
+    )]
+    filter_radius: Option<f64>,
+    /// Determines whether and how to sample pixels adaptively.
+    #[clap(flatten)]
+    adaptive: AdaptiveArguments,
     /// Determines the number of times a ray may bounce at most.
     #[clap(
         long,
@@ -109,11 +167,208 @@ struct RenderArguments {
                 setting to '0' not even fires the ray. If omitted, uses the value from the scene file."
     )]
     ray_max_depth: Option<usize>,
+    /// Determines the number of bounces after which rays may be terminated with Russian roulette.
+    #[clap(
+        long,
+        help = "The number of times a ray bounces before it may be terminated early with Russian roulette, based on how much light it still \
+                carries. If omitted, uses the value from the scene file."
+    )]
+    roulette_depth: Option<usize>,
+    /// Determines the maximum survival probability for Russian roulette.
+    #[clap(
+        long,
+        help = "The maximum probability with which a ray survives Russian roulette, in the range (0.0, 1.0]. If omitted, uses the value from \
+                the scene file."
+    )]
+    roulette_clamp: Option<f64>,
+    /// Determines the seed for all random numbers.
+    #[clap(
+        long,
+        help = "The seed from which all random numbers are derived. Rendering the same scene with the same seed always gives the same image, \
+                regardless of the backend used. If omitted, uses a random seed."
+    )]
+    seed: Option<u64>,
+    /// Determines what to compute for every ray.
+    #[clap(flatten)]
+    integrator: IntegratorArguments,
+    /// Where to write checkpoints to.
+    #[clap(
+        long,
+        help = "If given, periodically writes a checkpoint with all samples taken so far to the given path, and a final one when done. Use \
+                `render resume` to continue from it later (e.g., with more samples). When resuming, defaults to the resumed checkpoint."
+    )]
+    checkpoint: Option<PathBuf>,
+    /// The time between two checkpoints.
+    #[clap(long, default_value = "60", help = "The number of seconds between two checkpoints written with '--checkpoint'.")]
+    checkpoint_interval: u64,
+    /// The time after which to stop rendering.
+    #[clap(
+        long,
+        value_name = "DURATION",
+        help = "If given, stops rendering after this much time (e.g., '90s', '10m' or '1h30m'; seconds if no unit is given). The work in \
+                progress is finished, and the image is written with every pixel averaged over the samples it got so far. Pressing Ctrl-C \
+                does the same (press it twice to quit immediately). Combine with '--checkpoint' to continue later."
+    )]
+    time_limit: Option<TimeLimit>,
+    /// Whether to render progressively, and how many samples to take per pass.
+    #[clap(
+        long,
+        value_name = "SAMPLES",
+        help = "If given, renders the frame progressively in passes that each take this many more samples for every pixel. After every pass, \
+                the image rendered so far is written to the '--preview' path (or else the output path). The final image is the same as when \
+                rendering it in one go."
+    )]
+    progressive: Option<NonZeroU64>,
+    /// Where to write the intermediate results of progressive rendering to.
+    #[clap(
+        long,
+        requires = "progressive",
+        help = "The path to write the image rendered so far to after every pass of '--progressive' rendering, instead of the output path. It is \
+                replaced atomically, so it can be watched by an image viewer."
+    )]
+    preview: Option<PathBuf>,
+    /// Whether to show a live preview in the terminal.
+    #[clap(
+        long,
+        help = "If given, shows a downscaled live preview of the image in the terminal while rendering (instead of a progress bar), next to \
+                the progress statistics. Needs a terminal that supports 24-bit colours."
+    )]
+    tui: bool,
+    /// How often to refresh the live preview.
+    #[clap(long, value_name = "HZ", default_value = "4", help = "The number of times per second the preview of '--tui' is refreshed.")]
+    tui_rate: f64,
+    /// Determines which AOVs to render next to the image.
+    #[clap(
+        long = "aov",
+        value_delimiter = ',',
+        help = "Additional buffers to render next to the image, describing the surfaces first hit by the camera rays. Give as a comma-separated \
+                list, or by repeating the option. If the output path ends in '.exr', they are written as layers of that file with their raw \
+                values; otherwise, every buffer is written to a separate image next to it (e.g., 'image.depth.png'), scaled to be viewable. \
+                When resuming, only works if the checkpoint was rendered with AOVs too."
+    )]
+    aovs: Vec<Aov>,
+    /// Whether to denoise the rendered image.
+    #[clap(
+        long,
+        help = "If given, denoises the rendered image, guided by the albedo and normal buffers (which are rendered for this even if not given \
+                with '--aov'). When resuming, the checkpoint needs to have been rendered with '--aov' or '--denoise' for those."
+    )]
+    denoise: bool,
+    /// Determines how to denoise the rendered image.
+    #[clap(flatten)]
+    denoiser: DenoiserArguments,
+    /// Determines how to tone map the rendered image.
+    #[clap(flatten)]
+    tone_mapping: ToneMappingArguments,
 
     /// A once-more nested subcommand that defines what type of media to render.
     #[clap(subcommand)]
     media: RenderSubcommand,
 }
+/// Defines the arguments for configuring adaptive sampling.
+#[derive(Debug, Parser)]
+struct AdaptiveArguments {
+    /// Determines the error below which pixels stop sampling.
+    #[clap(
+        long,
+        help = "If given, enables adaptive sampling: after taking '--n-samples' samples, pixels keep sampling until their estimated error drops \
+                below this threshold (or until '--max-samples' is reached). If omitted, uses the value from the scene file."
+    )]
+    adaptive_threshold: Option<f64>,
+    /// Determines the maximum number of samples per pixel when sampling adaptively.
+    #[clap(
+        long,
+        help = "If given, enables adaptive sampling and defines the maximum number of samples any pixel may take. If omitted, uses the value \
+                from the scene file."
+    )]
+    max_samples: Option<NonZeroU64>,
+    /// Where to write a heatmap of the number of samples per pixel to.
+    #[clap(long, help = "If given, writes a heatmap of the number of samples taken for every pixel to the given path when sampling adaptively.")]
+    sample_heatmap: Option<PathBuf>,
+}
+/// Defines the arguments for configuring tone mapping.
+#[derive(Debug, Parser)]
+struct ToneMappingArguments {
+    /// Determines the operator that maps colours to ones that can be shown.
+    #[clap(
+        long,
+        help = "The operator that maps the rendered colours to ones that can be shown. 'clamp' blows bright colours out to white, while \
+                'reinhard', 'reinhard-extended', 'aces' and 'agx' compress them more gracefully. If omitted, uses the value from the scene file \
+                (or 'clamp')."
+    )]
+    tone_mapping: Option<ToneMapOperator>,
+    /// Determines the exposure.
+    #[clap(
+        long,
+        allow_negative_numbers = true,
+        help = "The exposure in stops (EV) before tone mapping, where every stop doubles the brightness. If omitted, uses the value from the \
+                scene file (or '0')."
+    )]
+    exposure: Option<f64>,
+    /// Determines the white point.
+    #[clap(
+        long,
+        help = "The (exposed) brightness that becomes white when using the 'reinhard-extended' tone mapping. If omitted, uses the value from the \
+                scene file (or '4')."
+    )]
+    white_point: Option<f64>,
+}
+/// Defines the arguments for configuring the denoiser.
+#[derive(Debug, Parser)]
+struct DenoiserArguments {
+    /// Determines the number of iterations of the denoiser.
+    #[clap(
+        long,
+        default_value = "5",
+        help = "The number of iterations of the denoiser. Every iteration reaches twice as far as the previous one, so more iterations remove \
+                coarser noise."
+    )]
+    denoise_iterations: usize,
+    /// Determines how much colours may differ before they stop being averaged.
+    #[clap(
+        long,
+        default_value = "0.25",
+        help = "How much colours may differ before the denoiser stops averaging them. Higher values remove more noise, but also blur more \
+                details."
+    )]
+    denoise_colour_sigma: f64,
+    /// Determines how much normals may differ before they stop being averaged.
+    #[clap(long, default_value = "0.3", help = "How much normals may differ before the denoiser stops averaging them.")]
+    denoise_normal_sigma: f64,
+    /// Determines how much albedos may differ before they stop being averaged.
+    #[clap(long, default_value = "0.1", help = "How much albedos may differ before the denoiser stops averaging them.")]
+    denoise_albedo_sigma: f64,
+}
+/// Defines the arguments for selecting and configuring the integrator.
+#[derive(Debug, Parser)]
+struct IntegratorArguments {
+    /// Determines how to combine directly sampled lights with rays scattering into them.
+    #[clap(
+        long,
+        default_value = "power",
+        help = "The heuristic with which to weigh directly sampled lights against rays that scatter into them (multiple importance sampling)."
+    )]
+    mis_heuristic: MisHeuristic,
+    /// Determines what to compute for every ray.
+    #[clap(
+        long,
+        default_value = "path",
+        help = "The integrator that computes the colour of every ray. Use 'path' for full path tracing, 'normals' or 'albedo' to inspect the \
+                surfaces hit, 'ao' for ambient occlusion or 'direct' for direct lighting only."
+    )]
+    integrator: IntegratorKind,
+    /// Determines the distance within which geometry occludes a point for ambient occlusion.
+    #[clap(
+        long,
+        default_value = "1.0",
+        help = "The distance within which geometry occludes a point when using the 'ao' integrator. Should be scaled with the size of the \
+                scene."
+    )]
+    ao_distance: f64,
+    /// Determines the number of rays cast per hit for ambient occlusion.
+    #[clap(long, default_value = "16", help = "The number of rays cast from every hit to find occluders when using the 'ao' integrator.")]
+    ao_samples: NonZeroUsize,
+}
 /// Defines the subcommands for the `render` subcommand.
 #[derive(Debug, Subcommand)]
 enum RenderSubcommand {
@@ -123,15 +378,28 @@ enum RenderSubcommand {
     /// Renders the cover of the book.
     #[clap(name = "cover", alias = "book", about = "Renders the cover of the Raytracing In One Weekend book.")]
     Cover(RenderCoverArguments),
+    /// Continues rendering from a checkpoint.
+    #[clap(
+        name = "resume",
+        about = "Continues rendering from a checkpoint written with '--checkpoint'. Only '--n-samples', the adaptive sampling and the tone \
+                 mapping options can be changed, which allows adding more samples to it."
+    )]
+    Resume(RenderResumeArguments),
 }
 /// Defines the arguments for the `render image` subcommand.
 #[derive(Debug, Parser)]
 struct RenderImageArguments {
     /// The path to the scene file to render.
-    #[clap(name = "SCENE_PATH", help = "The path to the scene file which we want to render.")]
+    #[clap(name = "SCENE_PATH", help = "The path to the scene file which we want to render. glTF files ('.gltf' or '.glb') are rendered with their own cameras and \
+                lights.")]
     scene_path:  PathBuf,
     /// The path to the image file to output.
-    #[clap(name = "OUTPUT_PATH", default_value = "./image.png", help = "The path to write the rendered image to.")]
+    #[clap(
+        name = "OUTPUT_PATH",
+        default_value = "./image.png",
+        help = "The path to write the rendered image to. OpenEXR ('.exr') and Radiance HDR ('.hdr') files get the unclamped linear colours, \
+                before gamma correction."
+    )]
     output_path: PathBuf,
 }
 /// Defines the arguments for the `render image` subcommand.
@@ -144,17 +412,28 @@ struct RenderCoverArguments {
     #[clap(short, long, default_value = "1000")]
     shutter_time: u64,
     /// The path to the image file to output.
-    #[clap(name = "OUTPUT_PATH", default_value = "./image.png", help = "The path to write the rendered image to.")]
+    #[clap(
+        name = "OUTPUT_PATH",
+        default_value = "./image.png",
+        help = "The path to write the rendered image to. OpenEXR ('.exr') and Radiance HDR ('.hdr') files get the unclamped linear colours, \
+                before gamma correction."
+    )]
     output_path: PathBuf,
 }
-/// Defines possible book covers.
-#[derive(Clone, Copy, Debug, ValueEnum)]
-#[clap(rename_all = "snake_case")]
-enum Book {
-    #[clap(alias = "book1")]
-    OneWeekend,
-    #[clap(alias = "book2")]
-    NextWeek,
+/// Defines the arguments for the `render resume` subcommand.
+#[derive(Debug, Parser)]
+struct RenderResumeArguments {
+    /// The path to the checkpoint to continue from.
+    #[clap(name = "CHECKPOINT_PATH", help = "The path to the checkpoint file to continue rendering from.")]
+    checkpoint_path: PathBuf,
+    /// The path to the image file to output.
+    #[clap(
+        name = "OUTPUT_PATH",
+        default_value = "./image.png",
+        help = "The path to write the rendered image to. OpenEXR ('.exr') and Radiance HDR ('.hdr') files get the unclamped linear colours, \
+                before gamma correction."
+    )]
+    output_path:     PathBuf,
 }
 
 /// Defines the arguments for the `generate` subcommand.
@@ -168,6 +447,93 @@ struct GenerateArguments {
     #[clap(subcommand)]
     subcommand: GenerateSubcommand,
 }
+/// Defines the arguments for the `merge` subcommand.
+#[derive(Debug, Parser)]
+struct MergeArguments {
+    /// The renders to merge.
+    #[clap(
+        name = "INPUTS",
+        required = true,
+        help = "The renders to merge. Either give checkpoints (written with '--checkpoint'; must end in '.json'), which are weighted by the \
+                number of samples they took, or images, which are averaged wherever they aren't transparent."
+    )]
+    inputs: Vec<PathBuf>,
+    /// The path to write the merged image to.
+    #[clap(short, long, default_value = "./image.png", help = "The path to write the merged image to.")]
+    output: PathBuf,
+    /// Whether to fix missing directories when writing the output image.
+    #[clap(short, long, help = "If given, will generate missing directories for the output image.")]
+    fix_dirs: bool,
+    /// Whether to enable gamma correction (or rather, to disable it).
+    #[clap(
+        long,
+        help = "If given, disables gamma correction. Merging always happens on linear colours, so give this if and only if the renders were made \
+                with it too."
+    )]
+    disable_gamma_correction: bool,
+    /// Determines how to tone map merged checkpoints.
+    #[clap(flatten)]
+    tone_mapping: ToneMappingArguments,
+}
+
+/// Defines the arguments for the `denoise` subcommand.
+#[derive(Debug, Parser)]
+struct DenoiseArguments {
+    /// The render to denoise.
+    #[clap(
+        name = "INPUT",
+        help = "The render to denoise. If it's an OpenEXR file written by `render`, its 'beauty' layer is denoised using its 'albedo' and \
+                'normal' layers; otherwise, the 'albedo' and 'normal' images written next to it with '--aov' are used (if any)."
+    )]
+    input: PathBuf,
+    /// The path to write the denoised image to.
+    #[clap(
+        short,
+        long,
+        default_value = "./image.png",
+        help = "The path to write the denoised image to. If both this and the input are OpenEXR files, their other layers are copied over."
+    )]
+    output: PathBuf,
+    /// The albedo to guide the denoiser with.
+    #[clap(long, help = "If given, uses the albedo buffer in the given file instead of the one belonging to the input.")]
+    albedo: Option<PathBuf>,
+    /// The normals to guide the denoiser with.
+    #[clap(long, help = "If given, uses the normal buffer in the given file instead of the one belonging to the input.")]
+    normal: Option<PathBuf>,
+    /// Whether to fix missing directories when writing the output image.
+    #[clap(short, long, help = "If given, will generate missing directories for the output image.")]
+    fix_dirs: bool,
+    /// Whether to enable gamma correction (or rather, to disable it).
+    #[clap(
+        long,
+        help = "If given, disables gamma correction. Denoising always happens on linear colours, so give this if and only if the render was made \
+                with it too. Ignored for OpenEXR and Radiance HDR files, which are always linear."
+    )]
+    disable_gamma_correction: bool,
+    /// Determines how to denoise the image.
+    #[clap(flatten)]
+    denoiser: DenoiserArguments,
+    /// Determines how to tone map an HDR image when writing it to another format.
+    #[clap(flatten)]
+    tone_mapping: ToneMappingArguments,
+}
+
+/// Defines the arguments for the `serve-worker` subcommand.
+#[derive(Debug, Parser)]
+struct ServeWorkerArguments {
+    /// The address to listen on.
+    #[clap(
+        short,
+        long,
+        default_value = "127.0.0.1:7878",
+        help = "The address to listen on for coordinators. Use '0.0.0.0:<PORT>' to accept coordinators on other machines."
+    )]
+    listen:    String,
+    /// The number of threads to render with.
+    #[clap(short = 'j', long, help = "The number of threads to render every tile with. If omitted, uses the number of hardware threads.")]
+    n_threads: Option<NonZeroUsize>,
+}
+
 /// Defines the things we can generate.
 #[derive(Debug, Subcommand)]
 enum GenerateSubcommand {
@@ -191,6 +557,302 @@ enum GenerateSubcommand {
 
 
 
+/***** HELPER FUNCTIONS *****/
+/// Builds the integrator selected by the user.
+///
+/// # Arguments
+/// - `args`: The [`IntegratorArguments`] that determine which integrator to use and how.
+/// - `max_depth`: The maximum bounce depth for rays.
+/// - `roulette_depth`: The number of bounces after which rays may be terminated with Russian roulette.
+/// - `roulette_clamp`: The maximum probability with which rays survive Russian roulette.
+///
+/// # Returns
+/// A new [`AnyIntegrator`] to render with.
+fn integrator(args: &IntegratorArguments, max_depth: usize, roulette_depth: usize, roulette_clamp: f64) -> AnyIntegrator {
+    let mis: MisHeuristic = args.mis_heuristic;
+    match args.integrator {
+        IntegratorKind::Path => PathTracer { max_depth, roulette_depth, roulette_clamp, mis }.into(),
+        IntegratorKind::Normals => DebugIntegrator { view: DebugView::Normals }.into(),
+        IntegratorKind::Albedo => DebugIntegrator { view: DebugView::Albedo }.into(),
+        IntegratorKind::AmbientOcclusion => AmbientOcclusion { distance: args.ao_distance, n_samples: args.ao_samples }.into(),
+        IntegratorKind::Direct => DirectLighting { mis }.into(),
+    }
+}
+
+
+
+
+
+/// Builds the denoiser from the arguments given by the user.
+///
+/// # Arguments
+/// - `args`: The [`DenoiserArguments`] that configure it.
+///
+/// # Returns
+/// A new [`Denoiser`] to denoise with.
+#[inline]
+fn denoiser(args: &DenoiserArguments) -> Denoiser {
+    Denoiser {
+        iterations:   args.denoise_iterations,
+        colour_sigma: args.denoise_colour_sigma,
+        normal_sigma: args.denoise_normal_sigma,
+        albedo_sigma: args.denoise_albedo_sigma,
+    }
+}
+
+/// Resolves the tone mapping from the one given by the user and the scene.
+///
+/// # Arguments
+/// - `args`: The [`ToneMappingArguments`] given by the user, which override those from the scene.
+/// - `scene`: The tone mapping from the scene.
+///
+/// # Returns
+/// The [`ToneMapping`] to develop images with.
+fn tone_mapping(args: &ToneMappingArguments, scene: ToneMapping) -> ToneMapping {
+    ToneMapping {
+        operator:    args.tone_mapping.unwrap_or(scene.operator),
+        exposure:    args.exposure.unwrap_or(scene.exposure),
+        white_point: args.white_point.unwrap_or(scene.white_point),
+    }
+}
+
+/// Checks whether a tone mapping makes sense, and complains if it doesn't.
+///
+/// # Arguments
+/// - `tone_mapping`: The [`ToneMapping`] to check.
+///
+/// # Returns
+/// Whether the tone mapping is valid.
+fn check_tone_mapping(tone_mapping: &ToneMapping) -> bool {
+    if !tone_mapping.exposure.is_finite() {
+        error!("Exposure must be a finite number, got {}", tone_mapping.exposure);
+        return false;
+    }
+    if tone_mapping.white_point <= 0.0 {
+        error!("White point must be positive, got {}", tone_mapping.white_point);
+        return false;
+    }
+    true
+}
+
+/// Resolves the adaptive sampling settings from the ones given by the user and the scene.
+///
+/// # Arguments
+/// - `args`: The [`AdaptiveArguments`] given by the user, which override those from the scene.
+/// - `scene`: The settings from the scene, if any.
+///
+/// # Returns
+/// The [`AdaptiveSampling`] to render with, or [`None`] if adaptive sampling is disabled.
+fn adaptive(args: &AdaptiveArguments, scene: Option<AdaptiveSampling>) -> Option<AdaptiveSampling> {
+    if args.adaptive_threshold.is_none() && args.max_samples.is_none() {
+        return scene;
+    }
+    let mut res: AdaptiveSampling = scene.unwrap_or_default();
+    if let Some(threshold) = args.adaptive_threshold {
+        res.threshold = threshold;
+    }
+    if let Some(max_samples) = args.max_samples {
+        res.max_samples = max_samples;
+    }
+    Some(res)
+}
+
+/// Writes the sample heatmap of a rendered frame to disk, if the user asked for it.
+///
+/// # Arguments
+/// - `args`: The [`AdaptiveArguments`] that determine where to write it to.
+/// - `frame`: The rendered [`Frame`].
+/// - `fix_dirs`: Whether to fix missing directories when writing or not.
+///
+/// # Returns
+/// Whether writing succeeded (or there was nothing to write).
+fn write_heatmap(args: &AdaptiveArguments, frame: &Frame, fix_dirs: bool) -> bool {
+    let path: &PathBuf = match &args.sample_heatmap {
+        Some(path) => path,
+        None => return true,
+    };
+    match frame.get(Frame::HEATMAP) {
+        Some(heatmap) => {
+            if let Err(err) = heatmap.to_path(path, fix_dirs) {
+                error!("Failed to save sample heatmap to '{}': {}", path.display(), err);
+                return false;
+            }
+            true
+        },
+        None => {
+            warn!("Not writing sample heatmap to '{}' because adaptive sampling is disabled", path.display());
+            true
+        },
+    }
+}
+
+/// Writes the image rendered so far to disk, replacing what was there atomically.
+///
+/// The image is first written to a hidden file next to the path, and then moved over it, such that
+/// anything watching the path never sees a half-written image.
+///
+/// # Arguments
+/// - `frame`: The [`Frame`] rendered so far.
+/// - `path`: The path to write the image to.
+/// - `fix_dirs`: Whether to fix missing directories when writing or not.
+fn write_preview(frame: &Frame, path: &Path, fix_dirs: bool) {
+    let mut name: OsString = OsString::from(".");
+    name.push(path.file_stem().unwrap_or_default());
+    name.push(".preview");
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    let tmp: PathBuf = path.with_file_name(name);
+
+    let image: &Image = if Image::is_hdr_path(path) { frame.linear() } else { frame.image() };
+    if let Err(err) = image.to_path(&tmp, fix_dirs) {
+        warn!("Failed to save preview to '{}': {}", tmp.display(), err);
+        return;
+    }
+    if let Err(err) = std::fs::rename(&tmp, path) {
+        warn!("Failed to move preview '{}' to '{}': {}", tmp.display(), path.display(), err);
+    }
+}
+
+/// Starts the live preview in the terminal, if the user asked for it.
+///
+/// # Arguments
+/// - `rate`: The number of times per second to refresh the preview, or [`None`] to not show it.
+/// - `cam`: The [`Camera`] whose tone mapping to use for it.
+/// - `gamma_correction`: Whether to apply gamma correction to it.
+///
+/// # Returns
+/// The running [`Tui`], or [`None`] if not asked for or if it failed to start (which is logged).
+fn spawn_tui(rate: Option<f64>, cam: &Camera, gamma_correction: bool) -> Option<Arc<Tui>> {
+    let rate: f64 = rate?;
+    match Tui::spawn(rate, cam.tone_mapping(), gamma_correction) {
+        Ok(tui) => Some(tui),
+        Err(err) => {
+            warn!("{}", toplevel!(("Failed to start live preview; showing progress bar instead"), err));
+            None
+        },
+    }
+}
+
+/// Renders a frame with any backend, progressively if the user asked for it.
+///
+/// # Arguments
+/// - `renderer`: The [`RayRenderer`] to render with.
+/// - `world`: The [`HitTree`] that contains the scene to render.
+/// - `cam`: The [`Camera`] to render with.
+/// - `env`: The [`Environment`] of the scene.
+/// - `film`: The [`Film`] with the samples taken so far.
+/// - `checkpoints`: If given, a [`Checkpointer`] that periodically writes the film to disk while rendering.
+/// - `cancel`: The [`CancelToken`] that stops rendering early.
+/// - `progressive`: If given, the number of samples per pass and the path to write the image rendered so far to after every pass.
+/// - `tui`: If given, the [`Tui`] that the renderer draws on. It is stopped when done.
+/// - `fix_dirs`: Whether to fix missing directories when writing previews or not.
+///
+/// # Returns
+/// The rendered [`Frame`].
+///
+/// # Errors
+/// This function errors if the renderer does.
+#[allow(clippy::too_many_arguments)]
+fn render_frame<R: RayRenderer>(
+    renderer: &R,
+    world: &HitTree,
+    cam: &Camera,
+    env: &Environment,
+    film: Film,
+    checkpoints: Option<&Checkpointer>,
+    cancel: &CancelToken,
+    progressive: Option<(NonZeroU64, &Path)>,
+    tui: Option<Arc<Tui>>,
+    fix_dirs: bool,
+) -> Result<Frame, R::Error> {
+    let start: Instant = Instant::now();
+    let res: Result<(Frame, usize), R::Error> = match progressive {
+        Some((pass_samples, preview)) => renderer.progressive_frame(world, cam, env, film, checkpoints, Some(cancel), pass_samples, |pass, frame| {
+            debug!("Writing preview of pass {pass} to '{}'...", preview.display());
+            write_preview(frame, preview, fix_dirs);
+        }),
+        None => renderer.resume_frame(world, cam, env, film, checkpoints, Some(cancel)).map(|frame| (frame, 1)),
+    };
+    if let Some(tui) = tui {
+        tui.stop();
+    }
+    let (frame, passes): (Frame, usize) = res?;
+    if let Some((pass_samples, _)) = progressive {
+        println!("Rendered {} {} of {} sample(s) per pixel", style(passes).bold(), style("progressive pass(es)").bold().green(), pass_samples);
+    }
+
+    // Tell the user how far we got if we were stopped early
+    if frame.interrupted() {
+        let (n_rendered, n_samples): (u64, u64) =
+            cam.region().pixels().map(|pixel| frame.film.get(pixel).n()).fold((0, 0), |(rendered, samples), n| (rendered + u64::from(n > 0), samples + n));
+        let n_pixels: u64 = cam.region().dims().0 as u64 * cam.region().dims().1 as u64;
+        println!(
+            "{} after {}: {} of {} pixel(s) got samples, {:.1} sample(s) per pixel on average",
+            style(if cancel.timed_out() { "Time limit reached" } else { "Interrupted" }).bold().yellow(),
+            HumanDuration(start.elapsed()),
+            style(n_rendered).bold(),
+            n_pixels,
+            n_samples as f64 / n_pixels as f64
+        );
+    }
+    Ok(frame)
+}
+
+/// Writes the image and the requested AOVs of a rendered frame to disk.
+///
+/// If the output path ends in `.exr`, then they are all written as layers of that file. Otherwise,
+/// the image is written to the output path and every AOV to a separate image next to it. Either
+/// way, HDR formats get the linear image instead of the gamma corrected and clamped one.
+///
+/// # Arguments
+/// - `frame`: The rendered [`Frame`].
+/// - `aovs`: The [`Aov`]s to write, if the frame has them.
+/// - `path`: The path to write the image to.
+/// - `fix_dirs`: Whether to fix missing directories when writing or not.
+///
+/// # Returns
+/// Whether writing succeeded.
+fn write_frame(frame: &Frame, aovs: &[Aov], path: &Path, fix_dirs: bool) -> bool {
+    let aovs: Vec<(Aov, &Image)> = aovs.iter().filter_map(|aov| frame.get(aov.name()).map(|image| (*aov, image))).collect();
+
+    // Write everything to one file if it supports layers
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr")) {
+        let layers = std::iter::once((Frame::BEAUTY, frame.linear())).chain(aovs.iter().map(|(aov, image)| (aov.name(), *image)));
+        if let Err(err) = Image::layers_to_path(layers, path, fix_dirs) {
+            error!("{}", err.trace());
+            return false;
+        }
+        return true;
+    }
+
+    // Otherwise, write them one-by-one
+    let image: &Image = if Image::is_hdr_path(path) { frame.linear() } else { frame.image() };
+    if let Err(err) = image.to_path(path, fix_dirs) {
+        error!("Failed to save rendered image to '{}': {}", path.display(), err);
+        return false;
+    }
+    for (aov, image) in aovs {
+        let mut name: OsString = path.file_stem().unwrap_or_default().to_owned();
+        name.push(format!(".{}", aov.name()));
+        if let Some(ext) = path.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+        let aov_path: PathBuf = path.with_file_name(name);
+        if let Err(err) = aov.visualize(image).to_path(&aov_path, fix_dirs) {
+            error!("Failed to save {} buffer to '{}': {}", aov.name(), aov_path.display(), err);
+            return false;
+        }
+    }
+    true
+}
+
+
+
+
+
 /***** ENTRYPOINT *****/
 fn main() -> ExitCode {
     // Read the command-line arguments
@@ -205,360 +867,383 @@ fn main() -> ExitCode {
     // Match on the subcommand
     match args.subcommand {
         RaytracerSubcommand::Render(render) => {
-            // Match further on the media type
-            match render.media {
+            if render.integrator.ao_distance <= 0.0 {
+                error!("Ambient occlusion distance must be positive, got {}", render.integrator.ao_distance);
+                return ExitCode::FAILURE;
+            }
+
+            // Find what to render, and where to continue from if we're resuming
+            let (source, seed, resumed, output_path): (SceneSource, u64, Option<(PathBuf, Checkpoint)>, PathBuf) = match render.media {
                 RenderSubcommand::Image(image) => {
-                    // Load the given scene file
-                    debug!("Loading scene file '{}'...", image.scene_path.display());
-                    let mut scene: SceneFile = match SceneFile::from_path(&image.scene_path) {
-                        Ok(scene) => scene,
+                    // Resolve the seed, picking a random one if the user didn't give any
+                    (SceneSource::File(image.scene_path), render.seed.unwrap_or_else(|| fastrand::u64(..)), None, image.output_path)
+                },
+                RenderSubcommand::Cover(cover) => (
+                    SceneSource::Cover { book: cover.book, shutter_time: cover.shutter_time },
+                    render.seed.unwrap_or_else(|| fastrand::u64(..)),
+                    None,
+                    cover.output_path,
+                ),
+                RenderSubcommand::Resume(resume) => {
+                    debug!("Loading checkpoint '{}'...", resume.checkpoint_path.display());
+                    let checkpoint: Checkpoint = match Checkpoint::from_path(&resume.checkpoint_path) {
+                        Ok(checkpoint) => checkpoint,
                         Err(err) => {
                             error!("{}", err.trace());
                             return ExitCode::FAILURE;
                         },
                     };
+                    if render.dims.is_some()
+                        || render.region.is_some()
+                        || render.disable_anti_aliasing
+                        || render.sampler.is_some()
+                        || render.ray_max_depth.is_some()
+                        || render.roulette_depth.is_some()
+                        || render.roulette_clamp.is_some()
+                        || render.seed.is_some()
+                    {
+                        warn!(
+                            "Only '--n-samples', the adaptive sampling and the tone mapping options can be changed when resuming; ignoring other \
+                             scene options"
+                        );
+                    }
+                    (checkpoint.info.source.clone(), checkpoint.info.seed, Some((resume.checkpoint_path, checkpoint)), resume.output_path)
+                },
+            };
+            info!("Using seed {seed}");
+
+            // Build the scene
+            let mut scene: SceneFile = match &source {
+                SceneSource::File(path) => {
+                    debug!("Loading scene file '{}'...", path.display());
+                    #[cfg(feature = "gltf")]
+                    let res: Result<SceneFile, String> = if is_gltf_path(path) {
+                        SceneFile::from_gltf(path).map_err(|err| err.trace().to_string())
+                    } else {
+                        SceneFile::from_path(path).map_err(|err| err.trace().to_string())
+                    };
+                    #[cfg(not(feature = "gltf"))]
+                    let res: Result<SceneFile, String> = SceneFile::from_path(path).map_err(|err| err.trace().to_string());
+                    match res {
+                        Ok(scene) => scene,
+                        Err(err) => {
+                            error!("{err}");
+                            return ExitCode::FAILURE;
+                        },
+                    }
+                },
+                SceneSource::Cover { book, shutter_time } => cover(*book, *shutter_time, seed),
+            };
+
+            // Load its external references, so changes to those are also part of the hash
+            let dir: PathBuf = match &source {
+                SceneSource::File(path) => path.parent().unwrap_or(path).into(),
+                SceneSource::Cover { .. } => PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            };
+            for (name, def) in scene.definitions.iter_mut() {
+                if let Err(err) = def.load(&dir) {
+                    error!("{}", toplevel!(("Failed to load external references in definition {name:?}"), err));
+                    return ExitCode::FAILURE;
+                }
+            }
+            for (i, obj) in scene.objects.iter_mut().enumerate() {
+                if let Err(err) = obj.load(&dir) {
+                    error!("{}", toplevel!(("Failed to load external references in object {i}"), err));
+                    return ExitCode::FAILURE;
+                }
+            }
+            let hash: u64 = match scene_hash(&scene.objects, &scene.definitions, &scene.environment) {
+                Ok(hash) => hash,
+                Err(err) => {
+                    error!("{}", toplevel!(("Failed to hash scene"), err));
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            // Decide how to render it, either from the checkpoint or from the scene and the arguments
+            let (mut info, film, checkpoint_path): (CheckpointInfo, Film, Option<PathBuf>) = match resumed {
+                Some((path, checkpoint)) => {
+                    if checkpoint.info.scene_hash != hash {
+                        error!("Scene has changed since checkpoint '{}' was written; cannot resume", path.display());
+                        return ExitCode::FAILURE;
+                    }
+                    (checkpoint.info, checkpoint.film, Some(render.checkpoint.unwrap_or(path)))
+                },
+                None => {
                     if let Some(dims) = render.dims {
                         scene.camera.dims = (dims.0, dims.1);
                     }
-                    if let Some(n_samples) = render.n_samples {
-                        scene.camera.n_samples = n_samples;
+                    if let Some(region) = render.region {
+                        scene.camera.region = Some(region);
+                    }
+                    if let Some(region) = scene.camera.region {
+                        let dims: (u32, u32) = (scene.camera.dims.0.into(), scene.camera.dims.1.into());
+                        if !region.fits(dims) {
+                            error!("Region {region} does not fit in an image of {}x{} pixels", dims.0, dims.1);
+                            return ExitCode::FAILURE;
+                        }
                     }
                     if render.disable_anti_aliasing {
                         // SAFETY: It's 1
                         scene.camera.n_samples = unsafe { NonZeroU64::new_unchecked(1) };
                     }
-
-                    // Convert that to a static HitList and load it
-                    for (i, obj) in scene.objects.iter_mut().enumerate() {
-                        if let Err(err) = obj.load(image.scene_path.parent().unwrap_or(&image.scene_path)) {
-                            error!("{}", toplevel!(("Failed to load external references in object {i}"), err));
-                            return ExitCode::FAILURE;
-                        }
+                    if let Some(sampler) = render.sampler {
+                        scene.camera.sampler = sampler;
                     }
-                    let list: HitTree = HitTree::with_objs(scene.objects, (0..=scene.camera.shutter_time.into()).into());
-
-                    // Now render based on the backend
-                    let output: Image = match render.backend {
-                        RenderBackend::SingleThreaded => {
-                            debug!("Rendering with single-threaded backend");
-                            let renderer: SingleThreadRenderer =
-                                SingleThreadRenderer::new(true, render.ray_max_depth.unwrap_or(50), !render.disable_gamma_correction);
-                            renderer.render_frame(&list, &Camera::from(scene.camera), &scene.environment).unwrap()
-                        },
-
-                        RenderBackend::MultiThreaded => {
-                            debug!("Rendering with multi-threaded backend");
-
-                            // Read the given file, if any
-                            let config: MultiThreadRendererConfig = match render.backend_config {
-                                Some(path) => {
-                                    debug!("Loading multi-threaded backend file '{}'...", path.display());
-                                    match MultiThreadRendererConfig::from_path(path) {
-                                        Ok(config) => config,
-                                        Err(err) => {
-                                            error!("{}", err.trace());
-                                            return ExitCode::FAILURE;
-                                        },
-                                    }
-                                },
-                                None => Default::default(),
-                            };
-
-                            // Create the backend
-                            let renderer: MultiThreadRenderer =
-                                match MultiThreadRenderer::new(true, render.ray_max_depth.unwrap_or(50), !render.disable_gamma_correction, config) {
-                                    Ok(renderer) => renderer,
-                                    Err(err) => {
-                                        error!("{}", err.trace());
-                                        return ExitCode::FAILURE;
-                                    },
-                                };
-
-                            // Now render with this backend
-                            renderer.render_frame(&list, &Camera::from(scene.camera), &scene.environment).unwrap()
-                        },
-                    };
-
-                    // Now write the image to disk
-                    if let Err(err) = output.to_path(&image.output_path, render.fix_dirs) {
-                        error!("Failed to save rendered image to '{}': {}", image.output_path.display(), err);
+                    if let Some(kind) = render.filter {
+                        scene.camera.filter = Filter { kind, radius: None };
+                    }
+                    if let Some(radius) = render.filter_radius {
+                        scene.camera.filter.radius = Some(radius);
+                    }
+                    if !(scene.camera.filter.radius() > 0.0 && scene.camera.filter.radius().is_finite()) {
+                        error!("Filter radius must be a positive number, got {}", scene.camera.filter.radius());
                         return ExitCode::FAILURE;
                     }
-                    ExitCode::SUCCESS
+                    if let Some(ray_max_depth) = render.ray_max_depth {
+                        scene.camera.ray_max_depth = ray_max_depth;
+                    }
+                    if let Some(roulette_depth) = render.roulette_depth {
+                        scene.camera.roulette_depth = roulette_depth;
+                    }
+                    if let Some(roulette_clamp) = render.roulette_clamp {
+                        scene.camera.roulette_clamp = roulette_clamp;
+                    }
+                    if scene.camera.roulette_clamp <= 0.0 || scene.camera.roulette_clamp > 1.0 {
+                        error!("Russian roulette clamp must be in the range (0.0, 1.0], got {}", scene.camera.roulette_clamp);
+                        return ExitCode::FAILURE;
+                    }
+
+                    // Build the integrator now we know the depths
+                    let integrator: AnyIntegrator =
+                        integrator(&render.integrator, scene.camera.ray_max_depth, scene.camera.roulette_depth, scene.camera.roulette_clamp);
+                    let dims: (u32, u32) = (scene.camera.dims.0.into(), scene.camera.dims.1.into());
+                    let film: Film = if render.aovs.is_empty() && !render.denoise { Film::new(dims) } else { Film::with_aovs(dims) };
+                    (CheckpointInfo { source, scene_hash: hash, seed, camera: scene.camera, integrator }, film, render.checkpoint)
                 },
+            };
 
-                RenderSubcommand::Cover(cover) => {
-                    // Generate the list of objects for the correct book
-                    let mut objects: Vec<Object> = match cover.book {
-                        Book::OneWeekend => {
-                            let mut objects: Vec<Object> = Vec::with_capacity(1 + 21 * 21 + 3);
-                            objects.push(Object::Sphere(Sphere {
-                                center:   Vec3::new(0.0, -1000.0, 0.0),
-                                radius:   1000.0,
-                                material: Material::LambertianTexture(LambertianTexture {
-                                    texture: Texture::SpatialChecker(SpatialChecker {
-                                        scale: 0.32,
-                                        black: Colour::new(0.2, 0.3, 0.1, 1.0),
-                                        white: Colour::new(0.9, 0.9, 0.9, 1.0),
-                                    }),
-                                }),
-                            }));
-                            for a in -11..11 {
-                                for b in -11..11 {
-                                    let mat = fastrand::f64();
-                                    let center = Vec3::new(a as f64 + 0.9 * fastrand::f64(), 0.2, b as f64 + 0.9 * fastrand::f64());
-                                    if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                                        if mat < 0.8 {
-                                            // It'll be a tiny diffuse sphere
-                                            let colour = Colour::new(fastrand::f64(), fastrand::f64(), fastrand::f64(), 1.0);
-                                            let sphere = Sphere { center, radius: 0.2, material: Material::Lambertian(Lambertian { colour }) };
-                                            objects.push(if fastrand::f64() < 0.1 {
-                                                Object::AnimatedSphere(AnimatedSphere {
-                                                    sphere,
-                                                    animation: Animation::Vertical(Vertical { len: 0.5 * fastrand::f64(), at: 0, duration: 1000 }),
-                                                })
-                                            } else {
-                                                Object::Sphere(sphere)
-                                            });
-                                        } else if mat < 0.95 {
-                                            // Metal, with random fuzziness
-                                            let colour = Colour::new(
-                                                fastrand::f64() / 2.0 + 0.5,
-                                                fastrand::f64() / 2.0 + 0.5,
-                                                fastrand::f64() / 2.0 + 0.5,
-                                                1.0,
-                                            );
-                                            let fuzz = fastrand::f64() / 2.0;
-                                            objects.push(Object::Sphere(Sphere {
-                                                center,
-                                                radius: 0.2,
-                                                material: Material::Metal(Metal { colour, fuzz }),
-                                            }));
-                                        } else {
-                                            // Glass
-                                            objects.push(Object::Sphere(Sphere {
-                                                center,
-                                                radius: 0.2,
-                                                material: Material::Dielectric(Dielectric {
-                                                    refraction_index: 1.5,
-                                                    colour: Colour::new(1.0, 1.0, 1.0, 1.0),
-                                                }),
-                                            }));
-                                        }
-                                    }
-                                }
-                            }
-                            objects.push(Object::Sphere(Sphere {
-                                center:   Vec3::new(0.0, 1.0, 0.0),
-                                radius:   1.0,
-                                material: Material::Dielectric(Dielectric { refraction_index: 1.5, colour: Colour::new(1.0, 1.0, 1.0, 1.0) }),
-                            }));
-                            objects.push(Object::Sphere(Sphere {
-                                center:   Vec3::new(-4.0, 1.0, 0.0),
-                                radius:   1.0,
-                                material: Material::Lambertian(Lambertian { colour: Colour::new(0.4, 0.2, 0.1, 1.0) }),
-                            }));
-                            objects.push(Object::Sphere(Sphere {
-                                center:   Vec3::new(4.0, 1.0, 0.0),
-                                radius:   1.0,
-                                material: Material::Metal(Metal { colour: Colour::new(0.7, 0.6, 0.5, 1.0), fuzz: 0.0 }),
-                            }));
-                            objects
-                        },
+            // The number of samples may always be changed, also when resuming (to add more)
+            if let Some(n_samples) = render.n_samples {
+                if !render.disable_anti_aliasing {
+                    info.camera.n_samples = n_samples;
+                }
+            }
+            info.camera.adaptive = adaptive(&render.adaptive, info.camera.adaptive);
+            if let Some(adaptive) = info.camera.adaptive {
+                if adaptive.threshold <= 0.0 {
+                    error!("Adaptive sampling threshold must be positive, got {}", adaptive.threshold);
+                    return ExitCode::FAILURE;
+                }
+            }
+            info.camera.tone_mapping = tone_mapping(&render.tone_mapping, info.camera.tone_mapping);
+            if !check_tone_mapping(&info.camera.tone_mapping) {
+                return ExitCode::FAILURE;
+            }
+            let cam: Camera = Camera::from(info.camera);
+            if film.dims() != cam.dims() {
+                error!("Checkpoint has {}x{} pixels, but its camera has {}x{}", film.dims().0, film.dims().1, cam.dims().0, cam.dims().1);
+                return ExitCode::FAILURE;
+            }
+            if !render.aovs.is_empty() && !film.has_aovs() {
+                warn!("Checkpoint was rendered without AOVs; not writing any");
+            }
+            if render.denoise && !film.has_aovs() {
+                warn!("Checkpoint was rendered without AOVs; denoising without albedo and normal buffers");
+            }
 
-                        Book::NextWeek => {
-                            // Define materials
-                            let ground = Material::Lambertian(Lambertian { colour: Colour::new(0.48, 0.83, 0.53, 1.0) });
-                            let light = Material::DiffuseLight(DiffuseLight { colour: Colour::new(7.0, 7.0, 7.0, 1.0) });
-                            let brown = Material::Lambertian(Lambertian { colour: Colour::new(0.7, 0.3, 0.1, 1.0) });
-                            let glass = Material::Dielectric(Dielectric { colour: Colour::new(1.0, 1.0, 1.0, 1.0), refraction_index: 1.5 });
-                            let grey_metal = Material::Metal(Metal { colour: Colour::new(0.8, 0.8, 0.9, 1.0), fuzz: 1.0 });
-                            let earth = Material::LambertianTexture(LambertianTexture {
-                                texture: Texture::Image(TexImage::ToLoad {
-                                    path:   PathBuf::from("tests/scenes/earthmap.jpg"),
-                                    format: Some(image::ImageFormat::Jpeg),
-                                }),
-                            });
-                            let perlin_wink = Material::Lambertian(Lambertian { colour: Colour::new(0.5, 0.5, 0.5, 1.0) });
-                            let white = Material::Lambertian(Lambertian { colour: Colour::new(0.73, 0.73, 0.73, 1.0) });
-
-                            // Define the ground
-                            let mut objects: Vec<Object> = Vec::with_capacity(1000);
-                            const BOXES_PER_SIDE: u32 = 20;
-                            for i in 0..BOXES_PER_SIDE {
-                                for j in 0..BOXES_PER_SIDE {
-                                    // Compute the dimensions of each box
-                                    let w = 100.0;
-                                    let x0 = -1000.0 + i as f64 * w;
-                                    let z0 = -1000.0 + j as f64 * w;
-                                    let y0 = 0.0;
-                                    let x1 = x0 + w;
-                                    let y1 = fastrand::f64() * 100.0 + 1.0;
-                                    let z1 = z0 + w;
-                                    objects.push(Object::Box(Box {
-                                        aabb:     AABB::from_points(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1)),
-                                        material: ground.clone(),
-                                    }));
-                                }
-                            }
+            // Convert the scene to a static HitTree
+            // The farm sends the loaded objects to its workers as-is (which link them themselves), so keep them around
+            let (definitions, objects): (BTreeMap<String, Object>, Vec<Object>) =
+                if render.backend == RenderBackend::Farm { (scene.definitions.clone(), scene.objects.clone()) } else { (BTreeMap::new(), Vec::new()) };
+            if let Err(err) = scene.link() {
+                error!("{}", toplevel!(("Failed to link instances to their definitions"), err));
+                return ExitCode::FAILURE;
+            }
+            let list: HitTree = HitTree::with_objs(scene.objects, (0..=info.camera.shutter_time.into()).into());
+            let checkpoints: Option<Checkpointer> =
+                checkpoint_path.map(|path| Checkpointer::new(path, Duration::from_secs(render.checkpoint_interval), info.clone()));
 
-                            // Define the ceiling light
-                            objects.push(Object::Quad(Quad {
-                                qd: Qd { pos: Vec3::new(123.0, 554.0, 147.0), u: Vec3::new(300.0, 0.0, 0.0), v: Vec3::new(0.0, 0.0, 265.0) },
-                                material: light,
-                            }));
-
-                            // Define the blurry sphere
-                            objects.push(Object::AnimatedSphere(AnimatedSphere {
-                                sphere:    Sphere { center: Vec3::new(400.0, 400.0, 200.0), radius: 50.0, material: brown },
-                                animation: Animation::Vertical(Vertical { len: 30.0, at: 0, duration: cover.shutter_time }),
-                            }));
-
-                            // Define the loose glass & metal spheres
-                            objects.push(Object::Sphere(Sphere { center: Vec3::new(260.0, 150.0, 45.0), radius: 50.0, material: glass.clone() }));
-                            objects.push(Object::Sphere(Sphere { center: Vec3::new(0.0, 150.0, 145.0), radius: 50.0, material: grey_metal }));
-
-                            // Define glossy sphere (a dense fog in a glass sphere)
-                            let boundary =
-                                Object::Sphere(Sphere { center: Vec3::new(360.0, 150.0, 145.0), radius: 70.0, material: glass.clone() });
-                            objects.push(boundary.clone());
-                            objects.push(Object::ConstantDensity(ConstantDensity {
-                                boundary: std::boxed::Box::new(boundary),
-                                density: 0.2,
-                                phase_function: Isotropic { colour: Colour::new(0.2, 0.4, 0.9, 1.0) },
-                            }));
-
-                            // Define the overall haze over the scene
-                            objects.push(Object::ConstantDensity(ConstantDensity {
-                                boundary: std::boxed::Box::new(Object::Sphere(Sphere {
-                                    center:   Vec3::new(0.0, 0.0, 0.0),
-                                    radius:   5000.0,
-                                    material: glass,
-                                })),
-                                density: 0.0001,
-                                phase_function: Isotropic { colour: Colour::new(1.0, 1.0, 1.0, 1.0) },
-                            }));
-
-                            // Define the earthy sphere and perlin noise sphere (although we just use a blank lambertian sphere)
-                            objects.push(Object::Sphere(Sphere { center: Vec3::new(400.0, 200.0, 400.0), radius: 100.0, material: earth }));
-                            objects.push(Object::Sphere(Sphere { center: Vec3::new(220.0, 280.0, 300.0), radius: 80.0, material: perlin_wink }));
-
-                            // Define the box made out of spheres
-                            const NUMBER_OF_SPHERES: usize = 1000;
-                            let mut orbs = Vec::with_capacity(NUMBER_OF_SPHERES);
-                            for _ in 0..NUMBER_OF_SPHERES {
-                                orbs.push(Object::Sphere(Sphere {
-                                    center:   Vec3::new(fastrand::f64() * 165.0, fastrand::f64() * 165.0, fastrand::f64() * 165.0),
-                                    radius:   10.0,
-                                    material: white.clone(),
-                                }));
+            // Stop rendering when the time is up or the user interrupts us (and quit if they insist)
+            let cancel: CancelToken = match render.time_limit {
+                Some(limit) => CancelToken::new().with_time_limit(limit.0),
+                None => CancelToken::new(),
+            };
+            let handler_cancel: CancelToken = cancel.clone();
+            let mut interrupted: bool = false;
+            if let Err(err) = ctrlc::set_handler(move || {
+                if interrupted {
+                    std::process::exit(130);
+                }
+                interrupted = true;
+                warn!("Interrupted; finishing the work in progress and writing what was rendered so far (press Ctrl-C again to quit immediately)");
+                handler_cancel.cancel();
+            }) {
+                warn!("Failed to handle Ctrl-C: {err} (interrupting will quit without writing anything)");
+            }
+
+            // Now render based on the backend
+            let preview: PathBuf = render.preview.clone().unwrap_or_else(|| output_path.clone());
+            let progressive: Option<(NonZeroU64, &Path)> = render.progressive.map(|pass_samples| (pass_samples, preview.as_path()));
+            let start_tui = || spawn_tui(render.tui.then_some(render.tui_rate), &cam, !render.disable_gamma_correction);
+            let mut output: Frame = match render.backend {
+                RenderBackend::SingleThreaded => {
+                    debug!("Rendering with single-threaded backend");
+                    let tui: Option<Arc<Tui>> = start_tui();
+                    let renderer: SingleThreadRenderer =
+                        SingleThreadRenderer::new(true, info.integrator, info.seed, !render.disable_gamma_correction).with_tui(tui.clone());
+                    render_frame(&renderer, &list, &cam, &scene.environment, film, checkpoints.as_ref(), &cancel, progressive, tui, render.fix_dirs).unwrap()
+                },
+
+                RenderBackend::MultiThreaded => {
+                    debug!("Rendering with multi-threaded backend");
+
+                    // Read the given file, if any
+                    let config: MultiThreadRendererConfig = match render.backend_config {
+                        Some(path) => {
+                            debug!("Loading multi-threaded backend file '{}'...", path.display());
+                            match MultiThreadRendererConfig::from_path(path) {
+                                Ok(config) => config,
+                                Err(err) => {
+                                    error!("{}", err.trace());
+                                    return ExitCode::FAILURE;
+                                },
                             }
-                            objects.push(Object::Translate(Translate {
-                                pos: Vec3::new(-100.0, 270.0, 395.0),
-                                obj: std::boxed::Box::new(Object::RotateY(RotateY {
-                                    angle: 15.0,
-                                    obj:   std::boxed::Box::new(Object::Group(std::boxed::Box::new(HitTree::with_objs(
-                                        orbs,
-                                        (0..=cover.shutter_time).into(),
-                                    )))),
-                                })),
-                            }));
-
-                            // Done
-                            objects
                         },
+                        None => Default::default(),
                     };
 
-                    // Ensure to load all
-                    for (i, obj) in objects.iter_mut().enumerate() {
-                        if let Err(err) = obj.load(&PathBuf::from(env!("CARGO_MANIFEST_DIR"))) {
-                            error!("{}", toplevel!(("Failed to load external references in object {i}"), err));
-                            return ExitCode::FAILURE;
-                        }
-                    }
+                    // Create the backend
+                    let renderer: MultiThreadRenderer =
+                        match MultiThreadRenderer::new(true, info.integrator, info.seed, !render.disable_gamma_correction, config) {
+                            Ok(renderer) => renderer,
+                            Err(err) => {
+                                error!("{}", err.trace());
+                                return ExitCode::FAILURE;
+                            },
+                        };
 
-                    // Convert that to a static HitList
-                    let list: HitTree = HitTree::with_objs(objects, (0..=cover.shutter_time).into());
-                    let dims: (u32, u32) = if let Some(dims) = render.dims { (dims.0.into(), dims.1.into()) } else { (800, 600) };
-                    let cam = match cover.book {
-                        Book::OneWeekend => Camera::new(
-                            dims,
-                            100,
-                            20.0,
-                            0.6,
-                            10.0,
-                            cover.shutter_time,
-                            Vec3::new(13.0, 2.0, 3.0),
-                            Vec3::new(0.0, 0.0, 0.0),
-                            Vec3::new(0.0, 1.0, 0.0),
-                        ),
-                        Book::NextWeek => Camera::new(
-                            dims,
-                            5000,
-                            40.0,
-                            0.0,
-                            0.0,
-                            cover.shutter_time,
-                            Vec3::new(478.0, 278.0, -600.0),
-                            Vec3::new(278.0, 278.0, 0.0),
-                            Vec3::new(0.0, 1.0, 0.0),
-                        ),
+                    // Now render with this backend
+                    let tui: Option<Arc<Tui>> = start_tui();
+                    let renderer: MultiThreadRenderer = renderer.with_tui(tui.clone());
+                    render_frame(&renderer, &list, &cam, &scene.environment, film, checkpoints.as_ref(), &cancel, progressive, tui, render.fix_dirs).unwrap()
+                },
+
+                RenderBackend::Farm => {
+                    debug!("Rendering with farm backend");
+
+                    // Read the given file, which we need to know the workers
+                    let Some(path) = render.backend_config else {
+                        error!("The farm backend needs a '--backend-config' file that lists its workers");
+                        return ExitCode::FAILURE;
                     };
-                    let env = match cover.book {
-                        Book::OneWeekend => Environment::default(),
-                        Book::NextWeek => Environment { background: Background::None, ..Default::default() },
+                    debug!("Loading farm backend file '{}'...", path.display());
+                    let config: FarmRendererConfig = match FarmRendererConfig::from_path(path) {
+                        Ok(config) => config,
+                        Err(err) => {
+                            error!("{}", err.trace());
+                            return ExitCode::FAILURE;
+                        },
                     };
 
-                    // Now render based on the backend
-                    let output: Image = match render.backend {
-                        RenderBackend::SingleThreaded => {
-                            debug!("Rendering with single-threaded backend");
-                            let renderer: SingleThreadRenderer =
-                                SingleThreadRenderer::new(true, render.ray_max_depth.unwrap_or(50), !render.disable_gamma_correction);
-                            renderer.render_frame(&list, &cam, &env).unwrap()
-                        },
+                    // Create the backend
+                    let loaded: SceneFile = SceneFile { environment: scene.environment, camera: info.camera, definitions, objects };
+                    let renderer: FarmRenderer =
+                        match FarmRenderer::new(true, loaded, info.integrator, info.seed, !render.disable_gamma_correction, config) {
+                            Ok(renderer) => renderer,
+                            Err(err) => {
+                                error!("{}", err.trace());
+                                return ExitCode::FAILURE;
+                            },
+                        };
 
-                        RenderBackend::MultiThreaded => {
-                            debug!("Rendering with multi-threaded backend");
-
-                            // Read the given file, if any
-                            let config: MultiThreadRendererConfig = match render.backend_config {
-                                Some(path) => {
-                                    debug!("Loading multi-threaded backend file '{}'...", path.display());
-                                    match MultiThreadRendererConfig::from_path(path) {
-                                        Ok(config) => config,
-                                        Err(err) => {
-                                            error!("{}", err.trace());
-                                            return ExitCode::FAILURE;
-                                        },
-                                    }
-                                },
-                                None => Default::default(),
-                            };
-
-                            // Create the backend
-                            let renderer: MultiThreadRenderer =
-                                match MultiThreadRenderer::new(true, render.ray_max_depth.unwrap_or(50), !render.disable_gamma_correction, config) {
-                                    Ok(renderer) => renderer,
-                                    Err(err) => {
-                                        error!("{}", err.trace());
-                                        return ExitCode::FAILURE;
-                                    },
-                                };
-
-                            // Now render with this backend
-                            renderer.render_frame(&list, &cam, &env).unwrap()
+                    // Now render with this backend
+                    let tui: Option<Arc<Tui>> = start_tui();
+                    let renderer: FarmRenderer = renderer.with_tui(tui.clone());
+                    match render_frame(&renderer, &list, &cam, &scene.environment, film, checkpoints.as_ref(), &cancel, progressive, tui, render.fix_dirs) {
+                        Ok(frame) => frame,
+                        Err(err) => {
+                            error!("{}", err.trace());
+                            return ExitCode::FAILURE;
                         },
-                    };
+                    }
+                },
+            };
+
+            // Write the final checkpoint, such that it can be resumed with more samples
+            if let Some(checkpoints) = &checkpoints {
+                if let Err(err) = checkpoints.write(&output.film) {
+                    error!("{}", err.trace());
+                    return ExitCode::FAILURE;
+                }
+                info!("Wrote checkpoint to '{}'", checkpoints.path().display());
+            }
+
+            // Denoise the image before writing it, if told to
+            if render.denoise {
+                debug!("Denoising rendered image...");
+                denoiser(&render.denoiser).denoise_frame(&mut output);
+            }
 
-                    // Now write the image to disk
-                    if let Err(err) = output.to_path(&cover.output_path, render.fix_dirs) {
-                        error!("Failed to save rendered image to '{}': {}", cover.output_path.display(), err);
+            // Now write the image to disk
+            if !write_frame(&output, &render.aovs, &output_path, render.fix_dirs) {
+                return ExitCode::FAILURE;
+            }
+            if !write_heatmap(&render.adaptive, &output, render.fix_dirs) {
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        },
+
+        RaytracerSubcommand::Merge(merge) => {
+            if !check_tone_mapping(&tone_mapping(&merge.tone_mapping, ToneMapping::default())) {
+                return ExitCode::FAILURE;
+            }
+            let tone = |first: ToneMapping| tone_mapping(&merge.tone_mapping, first);
+            if let Err(err) = merge::merge(&merge.inputs, &merge.output, tone, !merge.disable_gamma_correction, merge.fix_dirs) {
+                error!("{}", err.trace());
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        },
+
+        RaytracerSubcommand::Denoise(denoise) => {
+            let tone: ToneMapping = tone_mapping(&denoise.tone_mapping, ToneMapping::default());
+            if !check_tone_mapping(&tone) {
+                return ExitCode::FAILURE;
+            }
+            if let Err(err) = denoise::denoise(
+                &denoise.input,
+                &denoise.output,
+                denoise.albedo.as_deref(),
+                denoise.normal.as_deref(),
+                &denoiser(&denoise.denoiser),
+                tone,
+                !denoise.disable_gamma_correction,
+                denoise.fix_dirs,
+            ) {
+                error!("{}", err.trace());
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        },
+
+        RaytracerSubcommand::ServeWorker(serve) => {
+            let n_threads: usize = match serve.n_threads {
+                Some(n_threads) => n_threads.into(),
+                None => match std::thread::available_parallelism() {
+                    Ok(n_threads) => n_threads.into(),
+                    Err(err) => {
+                        error!("Failed to get available number of hardware threads: {err}");
                         return ExitCode::FAILURE;
-                    }
-                    ExitCode::SUCCESS
+                    },
                 },
+            };
+            if let Err(err) = worker::serve(&serve.listen, n_threads) {
+                error!("{}", err.trace());
+                return ExitCode::FAILURE;
             }
+            ExitCode::SUCCESS
         },
 
         RaytracerSubcommand::Generate(generate) => {
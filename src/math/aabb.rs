@@ -20,6 +20,7 @@ use serde::de::{self, SeqAccess, Visitor};
 use serde::ser::SerializeSeq;
 use serde::{Deserialize, Serialize};
 
+use super::sampler::PixelSampler;
 use super::ray::Ray;
 use super::vec3::Vec3;
 use crate::specifications::objects::{BoundingBoxable, HitRecord, Hittable};
@@ -335,7 +336,7 @@ impl BoundingBoxable for AABB {
 }
 impl Hittable for AABB {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment, _sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         // Compute the hitpoints with the box' intervals
         // The `Interval` takes care to order  them from small to large anyway
         let (invdirx, invdiry, invdirz): (f64, f64, f64) = (1.0 / ray.direct.x, 1.0 / ray.direct.y, 1.0 / ray.direct.z);
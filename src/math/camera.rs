@@ -15,8 +15,12 @@
 
 use std::f64::consts::PI;
 
+use super::filter::Filter;
 use super::ray::Ray;
+use super::sampler::{AdaptiveSampling, PixelSampler, Sampler};
+use super::tonemap::ToneMapping;
 use super::vec3::Vec3;
+use crate::common::input::Region;
 
 
 /***** HELPER FUNCTION *****/
@@ -24,16 +28,18 @@ use super::vec3::Vec3;
 #[inline]
 pub const fn degrees_to_radians(degrees: f64) -> f64 { degrees * PI / 180.0 }
 
-/// Samples a random point in a unit disk.
+/// Maps a point in the unit square to a point in a unit disk.
+///
+/// This uses Shirley's concentric mapping, which keeps nearby points nearby (and thus
+/// well-spread samples well-spread).
 #[inline]
-pub fn random_in_unit_disk() -> Vec3 {
-    // NOTE: Terrible, but hard to do better?
-    loop {
-        let vec = Vec3::new(2.0 * fastrand::f64_inclusive() - 1.0, 2.0 * fastrand::f64_inclusive() - 1.0, 0.0);
-        if vec.length2() < 1.0 {
-            return vec;
-        }
+pub fn square_to_unit_disk((u, v): (f64, f64)) -> Vec3 {
+    let (a, b): (f64, f64) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
     }
+    let (r, theta): (f64, f64) = if a.abs() > b.abs() { (a, (PI / 4.0) * (b / a)) } else { (b, (PI / 2.0) - (PI / 4.0) * (a / b)) };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 /// Samples a random point on the "defocus disk"
@@ -41,90 +47,37 @@ pub fn random_in_unit_disk() -> Vec3 {
 /// This is a small disk around the camera's center (aligned with the viewport) that we will
 /// sample from to simulate focal point blurring.
 #[inline]
-fn defocus_disk_sample(cam: &Camera) -> Vec3 {
-    let p = random_in_unit_disk();
+fn defocus_disk_sample(cam: &Camera, sampler: &mut PixelSampler) -> Vec3 {
+    let p = square_to_unit_disk(sampler.lens());
     cam.origin + (p.x * cam.defocus_u) + (p.y * cam.defocus_v)
 }
 
 
 
 
-/***** ITERATORS *****/
-/// Returns an iterator yielding rays casted through a [`Camera`].
-///
-/// Unlike earlier designs, this simply features everything in one go - coordinate generation,
-/// sampling and casting.
-#[derive(Clone, Copy, Debug)]
-pub struct Rays<'c> {
-    cam:   &'c Camera,
-    t_us:  u64,
-    index: u64,
-}
-
-impl<'c> ExactSizeIterator for Rays<'c> {
-    #[inline]
-    fn len(&self) -> usize { ((self.cam.dims.0 as u64 * self.cam.dims.1 as u64 * self.cam.n_samples as u64).saturating_sub(self.index)) as usize }
-}
-impl<'c> Iterator for Rays<'c> {
-    type Item = (u64, u32, u32, Ray);
-
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.len() == 0 {
-            return None;
-        }
-
-        // Cast the index into an (s, x, y)-pair.
-        let s: u64 = self.index % self.cam.n_samples;
-        let r: u64 = self.index / self.cam.n_samples;
-        let x: u64 = r % self.cam.dims.0 as u64;
-        let y: u64 = r / self.cam.dims.0 as u64;
-
-        // Randomly mod the XY-pair if we're sampling
-        let (x, y): (f64, f64) = if self.cam.n_samples > 1 { (x as f64 + fastrand::f64(), y as f64 + fastrand::f64()) } else { (x as f64, y as f64) };
-
-        // Convert the pixel values to logical values
-        let u: f64 = x / (self.cam.dims.0 as f64 - 1.0);
-        let v: f64 = y / (self.cam.dims.1 as f64 - 1.0);
-        let w: f64 = s as f64 / self.cam.n_samples as f64;
-
-        // Cast the ray
-        let ray = self.cam.cast(u, v, w, self.t_us);
-        self.index += 1;
-        Some((s, x as u32, y as u32, ray))
-    }
-
-    #[inline]
-    fn nth(&mut self, n: usize) -> Option<Self::Item> {
-        self.index += n as u64;
-        self.next()
-    }
-
-    #[inline]
-    fn count(self) -> usize { self.len() }
-
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let len: usize = self.len();
-        (len, Some(len))
-    }
-}
-
-
-
-
-
 /***** LIBRARY *****/
 /// The Camera struct defines a camera and controls for managing it.
 #[derive(Clone, Copy, Debug)]
 pub struct Camera {
     // Image properties
     /// The dimensions to render to.
-    dims: (u32, u32),
+    dims:   (u32, u32),
+    /// If given, only renders the pixels in this window of the frame.
+    region: Option<Region>,
 
     // Features
     /// The amount of samples to cast per-pixel.
     n_samples:     u64,
+    /// The [`Sampler`] that decides the random numbers of every sample.
+    sampler:       Sampler,
+    /// If given, keeps sampling pixels beyond `n_samples` until they converge.
+    adaptive:      Option<AdaptiveSampling>,
+    /// If given, pixels stop after this many samples for now, even if they'd take more otherwise.
+    sample_limit:  Option<u64>,
+    /// How the rendered colours are mapped to colours that can be shown.
+    tone_mapping:  ToneMapping,
+    /// How samples are reconstructed into pixels.
+    filter:        Filter,
     /// The amount of defocus to use. Set to 0 to disable.
     defocus_angle: f64,
     /// The amount of defocus to render in the horizontal direction.
@@ -155,6 +108,8 @@ impl Camera {
     /// # Arguments
     /// - `dims`: Defines the width x height of the resulting image, in pixels.
     /// - `n_samples`: The number of sample to cast per-ray.
+    /// - `sampler`: The [`Sampler`] that decides the random numbers of every sample.
+    /// - `adaptive`: If given, keeps sampling pixels beyond `n_samples` until they converge.
     /// - `vfov`: Defines the vertical field-of-view (fov) for the camera.
     /// - `defocus_angle`: The amount of defocus to use. Set to 0 to disable.
     /// - `focus_dist`: The distance between us and the focal point where the camera is sharp.
@@ -170,6 +125,8 @@ impl Camera {
     pub fn new(
         dims: (u32, u32),
         n_samples: u64,
+        sampler: Sampler,
+        adaptive: Option<AdaptiveSampling>,
         vfov: f64,
         defocus_angle: f64,
         mut focus_dist: f64,
@@ -211,7 +168,13 @@ impl Camera {
         // Use that to create ourselves
         Self {
             dims: (if dims.0 > 0 { dims.0 } else { panic!("Width cannot be 0") }, if dims.1 > 0 { dims.1 } else { panic!("Height cannot be 0") }),
+            region: None,
             n_samples: if n_samples > 0 { n_samples } else { panic!("Number of samples cannot be 0") },
+            sampler,
+            adaptive,
+            sample_limit: None,
+            tone_mapping: ToneMapping::default(),
+            filter: Filter::default(),
             defocus_angle,
             defocus_u,
             defocus_v,
@@ -222,6 +185,74 @@ impl Camera {
             vertical,
         }
     }
+
+    /// Restricts the Camera to only render part of its frame.
+    ///
+    /// The rays through the remaining pixels are the same as those of the whole frame, so renders
+    /// of different regions can be stitched together seamlessly.
+    ///
+    /// # Arguments
+    /// - `region`: The [`Region`] of pixels to render, or [`None`] to render all of them.
+    ///
+    /// # Returns
+    /// The same Camera, but only rendering the given region.
+    ///
+    /// # Panics
+    /// This function panics if the region does not fit in the Camera's frame.
+    #[inline]
+    #[track_caller]
+    pub fn with_region(mut self, region: Option<Region>) -> Self {
+        if let Some(region) = region {
+            if !region.fits(self.dims) {
+                panic!("Region {region} does not fit in frame of {}x{} pixels", self.dims.0, self.dims.1);
+            }
+        }
+        self.region = region;
+        self
+    }
+
+    /// Sets how the Camera's rendered colours are mapped to colours that can be shown.
+    ///
+    /// # Arguments
+    /// - `tone_mapping`: The [`ToneMapping`] to use.
+    ///
+    /// # Returns
+    /// The same Camera, but with the given tone mapping.
+    #[inline]
+    pub const fn with_tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.tone_mapping = tone_mapping;
+        self
+    }
+
+    /// Limits the number of samples that every pixel takes for now.
+    ///
+    /// Unlike lowering the number of samples, this doesn't change which samples are taken; pixels
+    /// simply stop early. Continuing with a higher limit later takes the remaining ones, which is
+    /// how frames are rendered progressively.
+    ///
+    /// # Arguments
+    /// - `limit`: The number of samples after which pixels stop, or [`None`] to take all of them.
+    ///
+    /// # Returns
+    /// The same Camera, but with the given sample limit.
+    #[inline]
+    pub const fn with_sample_limit(mut self, limit: Option<u64>) -> Self {
+        self.sample_limit = limit;
+        self
+    }
+
+    /// Sets how the Camera's samples are reconstructed into pixels.
+    ///
+    /// # Arguments
+    /// - `filter`: The [`Filter`] to use.
+    ///
+    /// # Returns
+    /// The same Camera, but with the given filter.
+    #[inline]
+    pub const fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
 }
 
 // Camera
@@ -231,32 +262,52 @@ impl Camera {
     /// # Arguments
     /// - `u`: The logical pixel index for the width, ranging `0.0` - `1.0`.
     /// - `v`: The logical pixel index for the height, ranging `0.0` - `1.0`.
-    /// - `s`: The logical pixel index for the sample, ranging `0.0` - `1.0`.
-    /// - `t_us`: The time at which the Ray is shot, as the number of microseconds since the start
-    ///   of the scene.
+    /// - `t_us`: The time at which the shutter opens, as the number of microseconds since the
+    ///   start of the scene.
+    /// - `sampler`: The [`PixelSampler`] to draw random numbers from (for defocus blur and the
+    ///   moment within the shutter time).
     ///
     /// # Returns
     /// A [`Ray`] casted through the Camera lens.
     #[inline]
-    pub fn cast(&self, u: f64, v: f64, s: f64, t_us: u64) -> Ray {
-        let ray_origin: Vec3 = if self.defocus_angle <= 0.0 { self.origin } else { defocus_disk_sample(self) };
+    pub fn cast(&self, u: f64, v: f64, t_us: u64, sampler: &mut PixelSampler) -> Ray {
+        let ray_origin: Vec3 = if self.defocus_angle <= 0.0 { self.origin } else { defocus_disk_sample(self, sampler) };
         Ray::with_time(
             ray_origin,
             self.lower_left + u * self.horizontal + v * self.vertical - ray_origin,
-            t_us + (s * self.shutter_time as f64).round() as u64,
+            t_us + (sampler.time() * self.shutter_time as f64) as u64,
         )
     }
 
-    /// Returns an iterator yielding [`Ray`]s casted through the Camera's lens.
+    /// Casts the [`Ray`] for a single sample of a single pixel.
     ///
     /// # Arguments
-    /// - `t_us`: The time at which we're casting them, given as microseconds since the start of
-    ///   the scene.
+    /// - `pixel`: The pixel to cast through, as an `(x, y)`-pair.
+    /// - `s`: The index of the sample within the pixel.
+    /// - `t_us`: The time at which the shutter opens, as the number of microseconds since the
+    ///   start of the scene.
+    /// - `seed`: The seed from which the random numbers for every ray are derived.
     ///
     /// # Returns
-    /// A [`Rays`] iterator that yields each of them.
-    #[inline]
-    pub const fn rays(&self, t_us: u64) -> Rays<'_> { Rays { cam: self, t_us, index: 0 } }
+    /// A tuple of the [`Ray`] casted, the [`PixelSampler`] that should be used to trace it
+    /// further and the offset of the sample from the pixel's centre (in pixels, in the range
+    /// `[-0.5, 0.5)`), which is `(0.0, 0.0)` if only one sample is taken.
+    pub fn sample(&self, pixel: (u32, u32), s: u64, t_us: u64, seed: u64) -> (Ray, PixelSampler, (f64, f64)) {
+        let mut sampler: PixelSampler = PixelSampler::new(self.sampler, seed, pixel.0, pixel.1, s, self.max_samples());
+
+        // Randomly mod the XY-pair if we're sampling
+        let (dx, dy): (f64, f64) = if self.max_samples() > 1 { sampler.pixel() } else { (0.0, 0.0) };
+        let (x, y): (f64, f64) = (pixel.0 as f64 + dx, pixel.1 as f64 + dy);
+
+        // Convert the pixel values to logical values
+        let u: f64 = x / (self.dims.0 as f64 - 1.0);
+        let v: f64 = y / (self.dims.1 as f64 - 1.0);
+
+        // Cast the ray
+        let ray: Ray = self.cast(u, v, t_us, &mut sampler);
+        let offset: (f64, f64) = if self.max_samples() > 1 { (dx - 0.5, dy - 0.5) } else { (0.0, 0.0) };
+        (ray, sampler, offset)
+    }
 }
 
 // Properties
@@ -265,46 +316,59 @@ impl Camera {
     #[inline]
     pub const fn dims(&self) -> (u32, u32) { self.dims }
 
+    /// Returns the [`Region`] of the frame that we actually render (which is all of it unless
+    /// restricted with [`Camera::with_region()`]).
+    #[inline]
+    pub const fn region(&self) -> Region {
+        match self.region {
+            Some(region) => region,
+            None => Region::full(self.dims),
+        }
+    }
+
     /// Returns the number of samples we draw per coordinate.
     #[inline]
     pub const fn n_samples(&self) -> u64 { self.n_samples }
-}
 
+    /// Returns the [`Sampler`] that decides the random numbers of every sample.
+    #[inline]
+    pub const fn sampler(&self) -> Sampler { self.sampler }
 
+    /// Returns how the rendered colours are mapped to colours that can be shown.
+    #[inline]
+    pub const fn tone_mapping(&self) -> ToneMapping { self.tone_mapping }
 
+    /// Returns how samples are reconstructed into pixels.
+    #[inline]
+    pub const fn filter(&self) -> Filter { self.filter }
 
+    /// Returns how to keep sampling pixels until they converge, if at all.
+    #[inline]
+    pub const fn adaptive(&self) -> Option<AdaptiveSampling> { self.adaptive }
+
+    /// Returns the maximum number of samples any pixel may take.
+    ///
+    /// This is the same as [`Camera::n_samples()`] unless adaptive sampling is enabled.
+    #[inline]
+    pub fn max_samples(&self) -> u64 {
+        match self.adaptive {
+            Some(adaptive) => self.n_samples.max(adaptive.max_samples.get()),
+            None => self.n_samples,
+        }
+    }
 
-/***** TESTS *****/
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::specifications::scene::CameraInfo;
-
-    #[test]
-    fn test_rays() {
-        let mut info = CameraInfo::default();
-        info.dims = (3.try_into().unwrap(), 2.try_into().unwrap());
-        info.n_samples = 3.try_into().unwrap();
-        let cam: Camera = info.into();
-        let mut rays = cam.rays(0);
-        assert!(matches!(rays.next(), Some((0, 0, 0, _))));
-        assert!(matches!(rays.next(), Some((1, 0, 0, _))));
-        assert!(matches!(rays.next(), Some((2, 0, 0, _))));
-        assert!(matches!(rays.next(), Some((0, 1, 0, _))));
-        assert!(matches!(rays.next(), Some((1, 1, 0, _))));
-        assert!(matches!(rays.next(), Some((2, 1, 0, _))));
-        assert!(matches!(rays.next(), Some((0, 2, 0, _))));
-        assert!(matches!(rays.next(), Some((1, 2, 0, _))));
-        assert!(matches!(rays.next(), Some((2, 2, 0, _))));
-        assert!(matches!(rays.next(), Some((0, 0, 1, _))));
-        assert!(matches!(rays.next(), Some((1, 0, 1, _))));
-        assert!(matches!(rays.next(), Some((2, 0, 1, _))));
-        assert!(matches!(rays.next(), Some((0, 1, 1, _))));
-        assert!(matches!(rays.next(), Some((1, 1, 1, _))));
-        assert!(matches!(rays.next(), Some((2, 1, 1, _))));
-        assert!(matches!(rays.next(), Some((0, 2, 1, _))));
-        assert!(matches!(rays.next(), Some((1, 2, 1, _))));
-        assert!(matches!(rays.next(), Some((2, 2, 1, _))));
-        assert!(matches!(rays.next(), None));
+    /// Returns the number of samples after which pixels stop for now, if limited at all.
+    #[inline]
+    pub const fn sample_limit(&self) -> Option<u64> { self.sample_limit }
+
+    /// Returns the maximum number of samples any pixel may take for now.
+    ///
+    /// This is the same as [`Camera::max_samples()`] unless a [sample limit](Camera::with_sample_limit()) is set.
+    #[inline]
+    pub fn limited_samples(&self) -> u64 {
+        match self.sample_limit {
+            Some(limit) => self.max_samples().min(limit),
+            None => self.max_samples(),
+        }
     }
 }
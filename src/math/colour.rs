@@ -86,12 +86,22 @@ impl Colour {
         Self { r: self.r.clamp(0.0, 1.0), g: self.g.clamp(0.0, 1.0), b: self.b.clamp(0.0, 1.0), a: self.a.clamp(0.0, 1.0) }
     }
 
-    /// Returns this Colour corrected for gamma.
+    /// Returns this (linear) Colour encoded with the sRGB transfer function, i.e., gamma corrected.
     ///
     /// # Returns
-    /// A new `Colour` instance with the same RGB-values, but corrected for gamma. The alpha channel is passed as-is.
-    pub fn gamma(&self) -> Self {
-        Self { r: f64::max(0.0, self.r.sqrt()), g: f64::max(0.0, self.g.sqrt()), b: f64::max(0.0, self.b.sqrt()), a: self.a }
+    /// A new `Colour` instance with the same RGB-values, but encoded for display. Negative values become zero. The alpha channel is passed as-is.
+    pub fn to_srgb(&self) -> Self {
+        let encode = |x: f64| -> f64 { if x <= 0.0031308 { 12.92 * x.max(0.0) } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 } };
+        Self { r: encode(self.r), g: encode(self.g), b: encode(self.b), a: self.a }
+    }
+
+    /// Returns this Colour with the sRGB transfer function undone, i.e., the inverse of [`Colour::to_srgb()`].
+    ///
+    /// # Returns
+    /// A new `Colour` instance with the same RGB-values, but back in linear space. Negative values become zero. The alpha channel is passed as-is.
+    pub fn from_srgb(&self) -> Self {
+        let decode = |x: f64| -> f64 { if x <= 0.04045 { x.max(0.0) / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) } };
+        Self { r: decode(self.r), g: decode(self.g), b: decode(self.b), a: self.a }
     }
 }
 
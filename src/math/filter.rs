@@ -0,0 +1,183 @@
+//  FILTER.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the reconstruction filters, which decide how much every sample
+//!   contributes to the pixels around the point where it was taken.
+//!
+//!   All filters are separable, i.e., the weight of a sample is the product
+//!   of the filter applied to its horizontal and its vertical distance to
+//!   the centre of a pixel.
+//
+
+use std::f64::consts::PI;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+
+/***** HELPER FUNCTIONS *****/
+/// Computes the (normalised) sinc function, `sin(pi * x) / (pi * x)`.
+#[inline]
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+/// Applies the Mitchell-Netravali cubic (with `B = C = 1/3`) to a distance in the range `[0, 2]`.
+#[inline]
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let (x2, x3): (f64, f64) = (x * x, x * x * x);
+    if x < 1.0 {
+        ((12.0 - 9.0 * B - 6.0 * C) * x3 + (-18.0 + 12.0 * B + 6.0 * C) * x2 + (6.0 - 2.0 * B)) / 6.0
+    } else {
+        ((-B - 6.0 * C) * x3 + (6.0 * B + 30.0 * C) * x2 + (-12.0 * B - 48.0 * C) * x + (8.0 * B + 24.0 * C)) / 6.0
+    }
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines the shapes of the reconstruction filters.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, ValueEnum)]
+pub enum FilterKind {
+    /// Every sample counts equally for every pixel it reaches. With the default radius of half a
+    /// pixel, that's only the pixel it was taken in.
+    #[clap(name = "box")]
+    #[default]
+    Box,
+    /// The weight drops off linearly with the distance (also known as a triangle filter).
+    #[clap(name = "tent", alias = "triangle")]
+    Tent,
+    /// The weight drops off like a Gaussian with a standard deviation of a third of the radius,
+    /// shifted down such that it reaches zero at the radius.
+    #[clap(name = "gaussian")]
+    Gaussian,
+    /// The Mitchell-Netravali cubic (with `B = C = 1/3`), which is sharper than the above but
+    /// slightly rings around edges.
+    #[clap(name = "mitchell", alias = "mitchell-netravali")]
+    Mitchell,
+    /// A sinc windowed by a wider sinc, which is the sharpest of all but rings the most.
+    #[clap(name = "lanczos")]
+    Lanczos,
+}
+impl FilterKind {
+    /// Returns the radius that is used for this filter if none is given.
+    ///
+    /// # Returns
+    /// The radius in pixels.
+    #[inline]
+    pub const fn default_radius(&self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 1.5,
+            Self::Mitchell => 2.0,
+            Self::Lanczos => 3.0,
+        }
+    }
+}
+
+
+
+/// Defines how samples are reconstructed into pixels.
+///
+/// Every sample is splatted to all pixels whose centres lie within the filter's radius of where
+/// it was taken, weighted by the filter. A pixel is the weighted average of all samples it got.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Filter {
+    /// The shape of the filter.
+    #[serde(default)]
+    pub kind:   FilterKind,
+    /// The radius of the filter in pixels, or [`None`] to use [`FilterKind::default_radius()`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+}
+impl Filter {
+    /// Computes the weight of a sample for a pixel.
+    ///
+    /// The footprint is half-open (i.e., `[-radius, radius)` in both directions), such that a
+    /// sample on the border between two pixels only counts for one of them with the default box
+    /// filter.
+    ///
+    /// # Arguments
+    /// - `dx`: The horizontal distance from the centre of the pixel to the sample, in pixels.
+    /// - `dy`: The vertical distance from the centre of the pixel to the sample, in pixels.
+    ///
+    /// # Returns
+    /// The weight of the sample. This may be negative for [`FilterKind::Mitchell`] and
+    /// [`FilterKind::Lanczos`].
+    #[inline]
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 { self.weight_1d(dx) * self.weight_1d(dy) }
+
+    /// Computes the weight of a sample in a single direction.
+    fn weight_1d(&self, d: f64) -> f64 {
+        let radius: f64 = self.radius();
+        if d < -radius || d >= radius {
+            return 0.0;
+        }
+        let x: f64 = d.abs();
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / radius,
+            FilterKind::Gaussian => {
+                let alpha: f64 = 4.5 / (radius * radius);
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            },
+            FilterKind::Mitchell => mitchell(2.0 * x / radius),
+            FilterKind::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+
+    /// Returns the radius of the filter.
+    ///
+    /// # Returns
+    /// The radius in pixels.
+    #[inline]
+    pub fn radius(&self) -> f64 { self.radius.unwrap_or(self.kind.default_radius()) }
+
+    /// Returns how many pixels beyond its own the samples of a pixel may reach.
+    ///
+    /// # Returns
+    /// The number of pixels in every direction, which is zero for the default box filter.
+    #[inline]
+    pub fn margin(&self) -> u32 { (self.radius() - 0.5).ceil().max(0.0) as u32 }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        // The default box filter only reaches the pixel of the sample itself
+        let filter = Filter::default();
+        assert_eq!(filter.margin(), 0);
+        assert_eq!(filter.weight(-0.5, 0.25), 1.0);
+        assert_eq!(filter.weight(0.5, 0.25), 0.0);
+
+        // Wider ones reach further, and all of them are largest in the centre and zero outside
+        for kind in FilterKind::value_variants() {
+            let filter = Filter { kind: *kind, radius: None };
+            let radius: f64 = filter.radius();
+            assert_eq!(filter.margin(), (radius - 0.5).ceil() as u32, "{kind:?}");
+            assert!(filter.weight(0.0, 0.0) > 0.0, "{kind:?}");
+            assert!((0..20).all(|i| filter.weight(i as f64 * radius / 20.0, 0.0) <= filter.weight(0.0, 0.0)), "{kind:?}");
+            assert_eq!(filter.weight(radius, 0.0), 0.0, "{kind:?}");
+            assert_eq!(filter.weight(0.0, -radius - 0.1), 0.0, "{kind:?}");
+        }
+        assert_eq!(Filter { kind: FilterKind::Tent, radius: Some(2.0) }.weight(1.0, 1.0), 0.25);
+        assert!(Filter { kind: FilterKind::Mitchell, radius: None }.weight(1.5, 0.0) < 0.0);
+    }
+}
@@ -16,7 +16,12 @@
 pub mod aabb;
 pub mod camera;
 pub mod colour;
+pub mod filter;
+pub mod random;
+pub mod sampler;
 pub mod ray;
+pub mod tonemap;
+pub mod transform;
 pub mod utils;
 pub mod vec3;
 
@@ -24,5 +29,10 @@ pub mod vec3;
 pub use aabb::AABB;
 pub use camera::Camera;
 pub use colour::Colour;
+pub use filter::{Filter, FilterKind};
+pub use random::Rng;
+pub use sampler::{AdaptiveSampling, PixelSampler, Sampler};
 pub use ray::Ray;
+pub use tonemap::{ToneMapOperator, ToneMapping};
+pub use transform::Transform;
 pub use vec3::Vec3;
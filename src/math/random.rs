@@ -0,0 +1,68 @@
+//  RANDOM.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the random number generator used throughout the raytracer.
+//!
+//!   Instead of drawing from one global state, every sample of every
+//!   pixel gets its own stream derived from a user-given seed. This way,
+//!   renders are reproducible regardless of which thread renders what.
+//
+
+pub use fastrand::Rng;
+
+
+/***** HELPER FUNCTIONS *****/
+/// Scrambles the bits of a number such that nearby inputs give unrelated outputs.
+///
+/// This is the finalizer of the SplitMix64 generator.
+#[inline]
+pub(crate) const fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Creates the random number generator for a single sample of a single pixel.
+///
+/// # Arguments
+/// - `seed`: The seed of the whole render.
+/// - `x`: The X-coordinate of the pixel.
+/// - `y`: The Y-coordinate of the pixel.
+/// - `s`: The index of the sample within the pixel.
+///
+/// # Returns
+/// A new [`Rng`] that always produces the same numbers for the same arguments.
+#[inline]
+pub fn sample_rng(seed: u64, x: u32, y: u32, s: u64) -> Rng { Rng::with_seed(mix(mix(mix(seed) ^ (((x as u64) << 32) | y as u64)) ^ s)) }
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_rng() {
+        // The same sample should always give the same stream...
+        let mut rng1: Rng = sample_rng(42, 3, 4, 5);
+        let mut rng2: Rng = sample_rng(42, 3, 4, 5);
+        for _ in 0..16 {
+            assert_eq!(rng1.u64(..), rng2.u64(..));
+        }
+
+        // ...but any other seed, pixel or sample another one
+        let first: u64 = sample_rng(42, 3, 4, 5).u64(..);
+        assert_ne!(first, sample_rng(43, 3, 4, 5).u64(..));
+        assert_ne!(first, sample_rng(42, 4, 3, 5).u64(..));
+        assert_ne!(first, sample_rng(42, 3, 4, 6).u64(..));
+    }
+}
@@ -0,0 +1,437 @@
+//  SAMPLER.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the [`Sampler`]s, which decide which random numbers are
+//!   drawn for every sample of every pixel.
+//!
+//!   Independent random numbers converge slowly, since they tend to clump
+//!   together. The other samplers spread the samples of a pixel out more
+//!   evenly over every dimension (sub-pixel position, lens, time and the
+//!   bounces of the path).
+//
+
+use std::num::NonZeroU64;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::random::{Rng, mix, sample_rng};
+
+
+/***** CONSTANTS *****/
+/// The dimension used for the X-offset of a sample within its pixel (the Y-offset uses the next one).
+const PIXEL_DIM: u32 = 0;
+/// The dimension used for the first coordinate of a sample on the lens (the second uses the next one).
+const LENS_DIM: u32 = 2;
+/// The dimension used for the time of a sample within the shutter time.
+const TIME_DIM: u32 = 4;
+/// The first dimension handed out to whoever traces the ray (i.e., the bounces).
+const FIRST_FREE_DIM: u32 = 5;
+
+/// The primes used as bases for the dimensions of the Halton sequence.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149,
+    151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns the default error threshold for [`AdaptiveSampling`].
+#[inline]
+pub const fn default_adaptive_sampling_threshold() -> f64 { 0.01 }
+
+/// Returns the default maximum number of samples per pixel for [`AdaptiveSampling`].
+#[inline]
+pub const fn default_adaptive_sampling_max_samples() -> NonZeroU64 {
+    // SAFETY: This works because the value is not 0.
+    unsafe { NonZeroU64::new_unchecked(1024) }
+}
+
+
+
+/// Converts 32 random bits to a float in the range `[0.0, 1.0)`.
+#[inline]
+fn to_f64(x: u32) -> f64 { x as f64 / (1u64 << 32) as f64 }
+
+/// Converts 64 random bits to a float in the range `[0.0, 1.0)`.
+#[inline]
+fn to_f64_64(x: u64) -> f64 { (x >> 11) as f64 / (1u64 << 53) as f64 }
+
+/// Randomly permutes the numbers `0..len`, returning where `i` ends up.
+///
+/// This is the hash-based permutation from Kensler's "Correlated Multi-Jittered Sampling"
+/// (2013), which needs no storage.
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut w: u32 = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    i.wrapping_add(seed) % len
+}
+
+/// Computes the radical inverse of a number in the given base (i.e., mirrors its digits around the decimal point).
+///
+/// If a `seed` is given, then the digits are randomly permuted first (using another permutation
+/// for every digit position). Without it, the higher bases spread few samples very poorly (e.g.,
+/// the first 16 samples in base 311 all end up in `[0, 16/311)`).
+fn radical_inverse(mut i: u64, base: u32, seed: Option<u32>) -> f64 {
+    let inv_base: f64 = 1.0 / base as f64;
+    let mut inv: f64 = inv_base;
+    let mut res: f64 = 0.0;
+    let mut pos: u32 = 0;
+    // NOTE: When scrambling, the (infinitely many) leading zeroes are permuted too, so continue until they no longer matter
+    while i > 0 || (seed.is_some() && inv > f64::EPSILON) {
+        let digit: u32 = (i % base as u64) as u32;
+        let digit: u32 = match seed {
+            Some(seed) => permute(digit, base, seed ^ mix(pos as u64) as u32),
+            None => digit,
+        };
+        res += digit as f64 * inv;
+        i /= base as u64;
+        inv *= inv_base;
+        pos += 1;
+    }
+    res.min(1.0 - f64::EPSILON)
+}
+
+/// Computes the first dimension of the Sobol sequence (which is simply the van der Corput sequence).
+#[inline]
+const fn sobol_0(i: u32) -> u32 { i.reverse_bits() }
+
+/// Computes the second dimension of the Sobol sequence.
+fn sobol_1(mut i: u32) -> u32 {
+    let mut res: u32 = 0;
+    let mut v: u32 = 1 << 31;
+    while i > 0 {
+        if i & 1 == 1 {
+            res ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    res
+}
+
+/// Scrambles the digits of a number such that every subtree of digits is randomly flipped (Owen scrambling).
+///
+/// This is the hash-based approximation from Burley's "Practical Hash-based Owen Scrambling" (2020).
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x: u32 = x.reverse_bits();
+    x ^= x.wrapping_mul(0x3d20adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x05526c56);
+    x ^= x.wrapping_mul(0x53a22864);
+    x.reverse_bits()
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines the ways in which the random numbers for the samples of a pixel can be picked.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, ValueEnum)]
+pub enum Sampler {
+    /// Every random number is picked independently of all others.
+    #[clap(name = "independent")]
+    #[default]
+    Independent,
+    /// Every dimension is divided into as many strata as there are samples, and every sample is
+    /// placed randomly within its own stratum (also known as jittered sampling).
+    #[clap(name = "stratified")]
+    Stratified,
+    /// Uses the Halton sequence, with its digits randomly permuted for every pixel.
+    #[clap(name = "halton")]
+    Halton,
+    /// Uses the Sobol sequence, randomly shuffled and Owen-scrambled for every pixel and dimension.
+    #[clap(name = "sobol")]
+    Sobol,
+}
+
+
+
+/// Defines how to adaptively sample pixels, i.e., keep sampling them until they converge.
+///
+/// Every pixel first takes the camera's usual number of samples. After that, it keeps taking
+/// samples until the estimated error of its mean drops below the threshold, or until it has
+/// taken the maximum number of samples.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AdaptiveSampling {
+    /// The error below which a pixel is considered converged.
+    ///
+    /// This is the standard error of the pixel's mean brightness, relative to how visible that
+    /// error is after gamma correction (i.e., the same error counts heavier in dark pixels).
+    #[serde(default = "default_adaptive_sampling_threshold")]
+    pub threshold:   f64,
+    /// The maximum number of samples to take for a single pixel.
+    #[serde(default = "default_adaptive_sampling_max_samples")]
+    pub max_samples: NonZeroU64,
+}
+impl Default for AdaptiveSampling {
+    #[inline]
+    fn default() -> Self { Self { threshold: default_adaptive_sampling_threshold(), max_samples: default_adaptive_sampling_max_samples() } }
+}
+
+
+
+/// Draws the random numbers of a single sample of a single pixel using a [`Sampler`].
+///
+/// The pixel offset, lens and time of the sample each use their own dimensions. All other random
+/// numbers (e.g., those consumed by materials at every bounce) are handed out in order, each
+/// taking the next dimension(s).
+#[derive(Clone, Debug)]
+pub struct PixelSampler {
+    /// The sampler that decides which numbers to produce.
+    sampler:   Sampler,
+    /// The seed for this pixel, derived from the render's seed.
+    seed:      u64,
+    /// The index of this sample within the pixel.
+    index:     u64,
+    /// The total number of samples for this pixel.
+    n_samples: u64,
+    /// The next dimension to hand out.
+    dim:       u32,
+    /// A random number generator for the [`Sampler::Independent`] sampler, and for any dimensions the others cannot provide.
+    rng:       Rng,
+}
+
+// Constructors
+impl PixelSampler {
+    /// Constructor for the PixelSampler.
+    ///
+    /// # Arguments
+    /// - `sampler`: The [`Sampler`] that decides which numbers to produce.
+    /// - `seed`: The seed of the whole render.
+    /// - `x`: The X-coordinate of the pixel.
+    /// - `y`: The Y-coordinate of the pixel.
+    /// - `index`: The index of this sample within the pixel.
+    /// - `n_samples`: The total number of samples that will be taken for this pixel.
+    ///
+    /// # Returns
+    /// A new PixelSampler that always produces the same numbers for the same arguments.
+    #[inline]
+    pub fn new(sampler: Sampler, seed: u64, x: u32, y: u32, index: u64, n_samples: u64) -> Self {
+        Self {
+            sampler,
+            seed: mix(mix(seed) ^ (((x as u64) << 32) | y as u64)),
+            index,
+            n_samples: n_samples.max(1),
+            dim: FIRST_FREE_DIM,
+            rng: sample_rng(seed, x, y, index),
+        }
+    }
+}
+
+// Sampling
+impl PixelSampler {
+    /// Returns the seed for a particular dimension of this pixel.
+    #[inline]
+    fn dim_seed(&self, dim: u32) -> u64 { mix(self.seed ^ mix(dim as u64 + 1)) }
+
+    /// Returns a random number in `[0.0, 1.0)` that is fixed for this pixel, sample and dimension.
+    #[inline]
+    fn jitter(&self, dim: u32, salt: u64) -> f64 { to_f64_64(mix(self.dim_seed(dim) ^ mix(self.index ^ (salt << 48)))) }
+
+    /// Computes the value of a given dimension for this sample.
+    fn sample_1d(&mut self, dim: u32) -> f64 {
+        match self.sampler {
+            Sampler::Independent => self.rng.f64(),
+            Sampler::Stratified => {
+                if self.index >= self.n_samples || self.n_samples > u32::MAX as u64 {
+                    return self.rng.f64();
+                }
+                let n: u32 = self.n_samples as u32;
+                let stratum: u32 = permute(self.index as u32, n, self.dim_seed(dim) as u32);
+                (stratum as f64 + self.jitter(dim, 0)) / n as f64
+            },
+            Sampler::Halton => match PRIMES.get(dim as usize) {
+                Some(base) => radical_inverse(self.index, *base, Some(self.dim_seed(dim) as u32)),
+                None => self.rng.f64(),
+            },
+            Sampler::Sobol => {
+                let seed: u64 = self.dim_seed(dim);
+                let index: u32 = owen_scramble(self.index as u32, seed as u32);
+                to_f64(owen_scramble(sobol_0(index), (seed >> 32) as u32))
+            },
+        }
+    }
+
+    /// Computes the value of a given pair of dimensions for this sample.
+    fn sample_2d(&mut self, dim: u32) -> (f64, f64) {
+        match self.sampler {
+            Sampler::Independent => (self.rng.f64(), self.rng.f64()),
+            Sampler::Stratified => {
+                if self.index >= self.n_samples || self.n_samples > u32::MAX as u64 {
+                    return (self.rng.f64(), self.rng.f64());
+                }
+                // Divide the square in a grid with (at least) as many cells as samples
+                let nx: u32 = (self.n_samples as f64).sqrt().ceil() as u32;
+                let ny: u32 = (self.n_samples as u32).div_ceil(nx);
+                let cell: u32 = permute(self.index as u32, nx * ny, self.dim_seed(dim) as u32);
+                ((((cell % nx) as f64) + self.jitter(dim, 0)) / nx as f64, (((cell / nx) as f64) + self.jitter(dim, 1)) / ny as f64)
+            },
+            Sampler::Halton => (self.sample_1d(dim), self.sample_1d(dim + 1)),
+            Sampler::Sobol => {
+                // Use the first two dimensions of Sobol for every pair, scrambled differently every time
+                let seed: u64 = self.dim_seed(dim);
+                let index: u32 = owen_scramble(self.index as u32, seed as u32);
+                let seed2: u64 = mix(seed);
+                (to_f64(owen_scramble(sobol_0(index), (seed >> 32) as u32)), to_f64(owen_scramble(sobol_1(index), seed2 as u32)))
+            },
+        }
+    }
+
+    /// Returns the offset of this sample within its pixel.
+    ///
+    /// # Returns
+    /// A pair of numbers in the range `[0.0, 1.0)`.
+    #[inline]
+    pub fn pixel(&mut self) -> (f64, f64) { self.sample_2d(PIXEL_DIM) }
+
+    /// Returns the point on the lens through which this sample is cast.
+    ///
+    /// # Returns
+    /// A pair of numbers in the range `[0.0, 1.0)`.
+    #[inline]
+    pub fn lens(&mut self) -> (f64, f64) { self.sample_2d(LENS_DIM) }
+
+    /// Returns the moment within the shutter time at which this sample is cast.
+    ///
+    /// # Returns
+    /// A number in the range `[0.0, 1.0)`.
+    #[inline]
+    pub fn time(&mut self) -> f64 { self.sample_1d(TIME_DIM) }
+
+    /// Returns the next random number for this sample.
+    ///
+    /// # Returns
+    /// A number in the range `[0.0, 1.0)`.
+    #[inline]
+    pub fn f64(&mut self) -> f64 {
+        let dim: u32 = self.dim;
+        self.dim += 1;
+        self.sample_1d(dim)
+    }
+
+    /// Returns the next pair of random numbers for this sample.
+    ///
+    /// Use this instead of calling [`PixelSampler::f64()`] twice if the numbers are used together
+    /// (e.g., to pick a direction), since they will be spread out better.
+    ///
+    /// # Returns
+    /// A pair of numbers in the range `[0.0, 1.0)`.
+    #[inline]
+    pub fn f64_2d(&mut self) -> (f64, f64) {
+        let dim: u32 = self.dim;
+        self.dim += 2;
+        self.sample_2d(dim)
+    }
+
+    /// Returns the next random index for this sample.
+    ///
+    /// # Arguments
+    /// - `len`: The number of indices to choose from.
+    ///
+    /// # Returns
+    /// A number in the range `0..len`.
+    #[inline]
+    pub fn usize(&mut self, len: usize) -> usize { ((self.f64() * len as f64) as usize).min(len.saturating_sub(1)) }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the pixel offsets of all samples of a single pixel.
+    fn pixel_offsets(sampler: Sampler, n_samples: u64) -> Vec<(f64, f64)> {
+        (0..n_samples).map(|s| PixelSampler::new(sampler, 42, 3, 4, s, n_samples).pixel()).collect()
+    }
+
+    #[test]
+    fn test_permute() {
+        let mut seen: Vec<u32> = (0..10).map(|i| permute(i, 10, 1234)).collect();
+        seen.sort();
+        assert_eq!(seen, (0..10).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_eq!(radical_inverse(1, 2, None), 0.5);
+        assert_eq!(radical_inverse(3, 2, None), 0.75);
+        assert_eq!(radical_inverse(1, 3, None), 1.0 / 3.0);
+
+        // Scrambling keeps the first samples in different strata
+        let mut strata: Vec<usize> = (0..16).map(|i| (radical_inverse(i, 311, Some(42)) * 311.0) as usize).collect();
+        strata.sort();
+        strata.dedup();
+        assert_eq!(strata.len(), 16);
+    }
+
+    #[test]
+    fn test_stratified_sampler() {
+        // Every cell of the 4x4 grid should get exactly one sample
+        let mut cells: Vec<usize> = pixel_offsets(Sampler::Stratified, 16).into_iter().map(|(u, v)| (v * 4.0) as usize * 4 + (u * 4.0) as usize).collect();
+        cells.sort();
+        assert_eq!(cells, (0..16).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_sobol_sampler() {
+        // Owen-scrambled Sobol is a (0, 2)-sequence, so for 16 samples every 4x4, 2x8, 8x2, 16x1 and 1x16 grid has one sample per cell
+        let offsets: Vec<(f64, f64)> = pixel_offsets(Sampler::Sobol, 16);
+        for (nx, ny) in [(4, 4), (2, 8), (8, 2), (16, 1), (1, 16)] {
+            let mut cells: Vec<usize> = offsets.iter().map(|(u, v)| (v * ny as f64) as usize * nx + (u * nx as f64) as usize).collect();
+            cells.sort();
+            assert_eq!(cells, (0..16).collect::<Vec<usize>>(), "Not stratified for {nx}x{ny} grid");
+        }
+    }
+
+    #[test]
+    fn test_pixel_sampler_range() {
+        for sampler in [Sampler::Independent, Sampler::Stratified, Sampler::Halton, Sampler::Sobol] {
+            for s in 0..64 {
+                let mut pixel = PixelSampler::new(sampler, 42, 3, 4, s, 64);
+                let (u, v): (f64, f64) = pixel.pixel();
+                let (w, z): (f64, f64) = pixel.f64_2d();
+                for x in [u, v, pixel.time(), w, z, pixel.f64()] {
+                    assert!((0.0..1.0).contains(&x), "{sampler:?} gave {x} out of range");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//  TONEMAP.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the tone mapping operators, which map the (unbounded) linear
+//!   colours that we render to the `[0, 1]` range that can be shown.
+//!
+//!   Every operator first scales the colours by the exposure. After tone
+//!   mapping, the colours are typically encoded with the sRGB transfer
+//!   function (see [`Colour::to_srgb()`]).
+//
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::colour::Colour;
+
+
+/***** CONSTANTS *****/
+/// The matrix that transforms linear sRGB colours into the AgX working space, as rows.
+const AGX_INSET: [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+/// The matrix that transforms colours from the AgX working space back to linear sRGB, as rows.
+const AGX_OUTSET: [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+/// The darkest exposure (in EV relative to middle grey) that AgX distinguishes.
+const AGX_MIN_EV: f64 = -12.47393;
+/// The brightest exposure (in EV relative to middle grey) that AgX distinguishes.
+const AGX_MAX_EV: f64 = 4.026069;
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns the default white point for [`ToneMapping`].
+#[inline]
+pub const fn default_tone_mapping_white_point() -> f64 { 4.0 }
+
+
+
+/// Computes the perceived brightness of a (linear) colour.
+#[inline]
+fn luminance(colour: &Colour) -> f64 { 0.2126 * colour.r + 0.7152 * colour.g + 0.0722 * colour.b }
+
+/// Scales the RGB-channels of a colour such that its luminance becomes the given one.
+#[inline]
+fn with_luminance(colour: &Colour, lum: f64, new_lum: f64) -> Colour {
+    if lum <= 0.0 {
+        return *colour;
+    }
+    let scale: f64 = new_lum / lum;
+    Colour { r: colour.r * scale, g: colour.g * scale, b: colour.b * scale, a: colour.a }
+}
+
+/// Multiplies the RGB-channels of a colour by a matrix.
+#[inline]
+fn transform(matrix: &[[f64; 3]; 3], colour: &Colour) -> Colour {
+    let [r, g, b]: [f64; 3] = matrix.map(|row| row[0] * colour.r + row[1] * colour.g + row[2] * colour.b);
+    Colour { r, g, b, a: colour.a }
+}
+
+/// Applies Krzysztof Narkowicz's fit of the ACES filmic curve to a single channel.
+#[inline]
+fn aces(x: f64) -> f64 { (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14) }
+
+/// Applies the AgX base contrast curve to a single channel, which is already in the log-encoded
+/// `[0, 1]` range.
+#[inline]
+fn agx_contrast(x: f64) -> f64 {
+    let x2: f64 = x * x;
+    let x4: f64 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+}
+
+/// Applies AgX to a colour.
+///
+/// This follows the minimal implementation of the AgX base look by Benjamin Wrensch.
+fn agx(colour: &Colour) -> Colour {
+    // Go to the AgX working space, and encode it logarithmically
+    let inset: Colour = transform(&AGX_INSET, colour);
+    let encode = |x: f64| -> f64 {
+        let ev: f64 = x.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV);
+        agx_contrast((ev - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV))
+    };
+    let encoded: Colour = Colour { r: encode(inset.r), g: encode(inset.g), b: encode(inset.b), a: colour.a };
+
+    // Go back and undo the display encoding of the curve, since that's done by the sRGB transfer function
+    let outset: Colour = transform(&AGX_OUTSET, &encoded);
+    Colour { r: outset.r.max(0.0).powf(2.2), g: outset.g.max(0.0).powf(2.2), b: outset.b.max(0.0).powf(2.2), a: colour.a }
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines the operators that map linear colours to the `[0, 1]` range.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, ValueEnum)]
+pub enum ToneMapOperator {
+    /// Clamps every channel, so anything brighter than white blows out to white.
+    #[clap(name = "clamp")]
+    #[default]
+    Clamp,
+    /// Maps the luminance `L` to `L / (1 + L)`, which never quite reaches white.
+    #[clap(name = "reinhard")]
+    Reinhard,
+    /// Like [`ToneMapOperator::Reinhard`], but maps the white point to white.
+    #[clap(name = "reinhard-extended", alias = "extended-reinhard")]
+    ReinhardExtended,
+    /// The filmic curve of the Academy Color Encoding System (as fitted by Krzysztof Narkowicz),
+    /// which adds some contrast and saturation.
+    #[clap(name = "aces")]
+    Aces,
+    /// The AgX base look, which desaturates bright colours like film does instead of shifting
+    /// their hue.
+    #[clap(name = "agx")]
+    Agx,
+}
+
+
+
+/// Defines how to map the linear colours that we render to colours that can be shown.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ToneMapping {
+    /// The operator that maps the colours.
+    #[serde(default)]
+    pub operator:    ToneMapOperator,
+    /// The exposure, in stops (EV). Every stop doubles the brightness of the colours before they
+    /// are mapped.
+    #[serde(default)]
+    pub exposure:    f64,
+    /// The (exposed) luminance that is mapped to white. Only used by
+    /// [`ToneMapOperator::ReinhardExtended`].
+    #[serde(default = "default_tone_mapping_white_point")]
+    pub white_point: f64,
+}
+impl Default for ToneMapping {
+    #[inline]
+    fn default() -> Self { Self { operator: ToneMapOperator::default(), exposure: 0.0, white_point: default_tone_mapping_white_point() } }
+}
+impl ToneMapping {
+    /// Maps a linear colour to one that can be shown.
+    ///
+    /// # Arguments
+    /// - `colour`: The linear [`Colour`] to map. Its alpha channel is passed as-is.
+    ///
+    /// # Returns
+    /// A new linear [`Colour`] with its RGB-values in the `[0, 1]` range.
+    pub fn apply(&self, colour: &Colour) -> Colour {
+        let exposure: f64 = self.exposure.exp2();
+        let colour: Colour = Colour { r: colour.r * exposure, g: colour.g * exposure, b: colour.b * exposure, a: colour.a };
+        let res: Colour = match self.operator {
+            ToneMapOperator::Clamp => colour,
+            ToneMapOperator::Reinhard => {
+                let lum: f64 = luminance(&colour);
+                with_luminance(&colour, lum, lum / (1.0 + lum))
+            },
+            ToneMapOperator::ReinhardExtended => {
+                let lum: f64 = luminance(&colour);
+                let white: f64 = self.white_point * self.white_point;
+                with_luminance(&colour, lum, lum * (1.0 + lum / white) / (1.0 + lum))
+            },
+            ToneMapOperator::Aces => Colour { r: aces(colour.r.max(0.0)), g: aces(colour.g.max(0.0)), b: aces(colour.b.max(0.0)), a: colour.a },
+            ToneMapOperator::Agx => agx(&colour),
+        };
+        Colour { r: res.r.clamp(0.0, 1.0), g: res.g.clamp(0.0, 1.0), b: res.b.clamp(0.0, 1.0), a: res.a }
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tone_mapping() {
+        let grey = |v: f64| Colour::new(v, v, v, 1.0);
+
+        // Clamping leaves dark colours alone, and every stop of exposure doubles them
+        let clamp = ToneMapping::default();
+        assert_eq!(clamp.apply(&grey(0.25)), grey(0.25));
+        assert_eq!(clamp.apply(&grey(7.0)), grey(1.0));
+        assert_eq!(ToneMapping { exposure: 1.0, ..clamp }.apply(&grey(0.25)), grey(0.5));
+
+        // Extended Reinhard maps the white point to white, but plain Reinhard never gets there
+        let reinhard = ToneMapping { operator: ToneMapOperator::Reinhard, ..clamp };
+        assert!((reinhard.apply(&grey(1.0)).r - 0.5).abs() < 1e-9);
+        let extended = ToneMapping { operator: ToneMapOperator::ReinhardExtended, white_point: 7.0, ..clamp };
+        assert!((extended.apply(&grey(7.0)).r - 1.0).abs() < 1e-9);
+
+        // All operators keep black black and don't decrease as colours get brighter
+        for operator in ToneMapOperator::value_variants() {
+            let tone: ToneMapping = ToneMapping { operator: *operator, ..clamp };
+            assert!(tone.apply(&grey(0.0)).r < 1e-3, "{operator:?}");
+            let values: Vec<f64> = [0.01, 0.1, 0.5, 1.0, 4.0, 16.0].iter().map(|v| tone.apply(&grey(*v)).r).collect();
+            assert!(values.windows(2).all(|w| w[0] <= w[1]), "{operator:?}: {values:?}");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//  TRANSFORM.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines an affine [`Transform`], i.e., any combination of
+//!   translations, rotations, scales and shears, as used by hierarchies of
+//!   nodes in model files.
+//
+
+use std::ops::Mul;
+
+use serde::{Deserialize, Serialize};
+
+use super::vec3::Vec3;
+
+
+/***** LIBRARY *****/
+/// An affine transformation, i.e., a linear map followed by a translation.
+///
+/// It is (de)serialized as its three rows.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Transform {
+    /// The top three rows of the 4x4 matrix of the transformation (the bottom one is always `[0, 0, 0, 1]`).
+    pub rows: [[f64; 4]; 3],
+}
+
+// Constructors
+impl Transform {
+    /// The transformation that leaves everything as-is.
+    pub const IDENTITY: Self = Self { rows: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]] };
+
+    /// Constructor for the Transform from a 4x4 matrix given as a list of columns (like in glTF).
+    ///
+    /// # Arguments
+    /// - `cols`: The four columns of the matrix. The bottom row is assumed to be `[0, 0, 0, 1]`.
+    ///
+    /// # Returns
+    /// A new Transform.
+    #[inline]
+    pub fn from_cols(cols: [[f64; 4]; 4]) -> Self { Self { rows: [0, 1, 2].map(|r| [cols[0][r], cols[1][r], cols[2][r], cols[3][r]]) } }
+
+    /// Constructor for the Transform that moves everything by the same amount.
+    ///
+    /// # Arguments
+    /// - `offset`: The offset to move by.
+    ///
+    /// # Returns
+    /// A new Transform.
+    #[inline]
+    pub fn translation(offset: Vec3) -> Self {
+        Self { rows: [[1.0, 0.0, 0.0, offset.x], [0.0, 1.0, 0.0, offset.y], [0.0, 0.0, 1.0, offset.z]] }
+    }
+}
+
+// Maths
+impl Transform {
+    /// Transforms a point, i.e., applies both the linear part and the translation.
+    #[inline]
+    pub fn point(&self, p: Vec3) -> Vec3 { self.vector(p) + Vec3::new(self.rows[0][3], self.rows[1][3], self.rows[2][3]) }
+
+    /// Transforms a direction, i.e., only applies the linear part.
+    #[inline]
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let [x, y, z] = self.rows.map(|row| row[0] * v.x + row[1] * v.y + row[2] * v.z);
+        Vec3::new(x, y, z)
+    }
+
+    /// Transforms a surface normal, which uses the inverse transpose of the linear part to stay
+    /// perpendicular to the (transformed) surface.
+    ///
+    /// The result is not normalized.
+    #[inline]
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        // The inverse transpose is the cofactor matrix divided by the determinant; we only need the sign of the latter
+        let [a, b, c] = self.rows.map(|row| Vec3::new(row[0], row[1], row[2]));
+        let (cx, cy, cz): (Vec3, Vec3, Vec3) = (b.cross(c), c.cross(a), a.cross(b));
+        let res: Vec3 = Vec3::new(cx.dot(n), cy.dot(n), cz.dot(n));
+        if self.determinant() < 0.0 { -res } else { res }
+    }
+
+    /// Returns the determinant of the linear part, which is negative if the transformation mirrors.
+    #[inline]
+    pub fn determinant(&self) -> f64 {
+        let [a, b, c] = self.rows.map(|row| Vec3::new(row[0], row[1], row[2]));
+        a.dot(b.cross(c))
+    }
+
+    /// Computes the transformation that undoes this one.
+    ///
+    /// # Returns
+    /// The inverse Transform, or [`None`] if this one flattens space (i.e., has a zero determinant).
+    pub fn inverse(&self) -> Option<Self> {
+        let det: f64 = self.determinant();
+        if det.abs() < 1e-300 {
+            return None;
+        }
+
+        // The inverse of the linear part is the transposed cofactor matrix divided by the determinant
+        let [a, b, c] = self.rows.map(|row| Vec3::new(row[0], row[1], row[2]));
+        let cols: [Vec3; 3] = [b.cross(c) / det, c.cross(a) / det, a.cross(b) / det];
+        let mut res = Self { rows: [0, 1, 2].map(|r| [cols[0][r], cols[1][r], cols[2][r], 0.0]) };
+
+        // Then we undo the translation after undoing the linear part
+        let t: Vec3 = -res.vector(Vec3::new(self.rows[0][3], self.rows[1][3], self.rows[2][3]));
+        for (r, offset) in [t.x, t.y, t.z].into_iter().enumerate() {
+            res.rows[r][3] = offset;
+        }
+        Some(res)
+    }
+}
+
+// Ops
+impl Default for Transform {
+    #[inline]
+    fn default() -> Self { Self::IDENTITY }
+}
+impl Mul for Transform {
+    type Output = Self;
+
+    /// Composes two transformations, such that `(a * b).point(p) == a.point(b.point(p))`.
+    #[inline]
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut rows: [[f64; 4]; 3] = [[0.0; 4]; 3];
+        for (r, row) in rows.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.rows[r][k] * rhs.rows[k][c]).sum::<f64>() + if c == 3 { self.rows[r][3] } else { 0.0 };
+            }
+        }
+        Self { rows }
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transform() {
+        // Scale by (2, 1, 1), then rotate 90 degrees around Z, then move by (1, 2, 3)
+        let scale = Transform { rows: [[2.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]] };
+        let rotate = Transform::from_cols([[0.0, 1.0, 0.0, 0.0], [-1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        let t: Transform = Transform::translation(Vec3::new(1.0, 2.0, 3.0)) * rotate * scale;
+        assert_eq!(t.point(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(1.0, 4.0, 3.0));
+        assert_eq!(t.vector(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 2.0, 0.0));
+
+        // Normals stay perpendicular to the surface they belong to
+        let (u, v): (Vec3, Vec3) = (Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let n: Vec3 = t.normal(u.cross(v));
+        assert!(n.dot(t.vector(u)).abs() < 1e-12 && n.dot(t.vector(v)).abs() < 1e-12);
+        assert!(n.dot(t.vector(u).cross(t.vector(v))) > 0.0);
+
+        // The inverse undoes everything
+        let inv: Transform = t.inverse().unwrap();
+        let p: Vec3 = Vec3::new(0.5, -2.0, 7.0);
+        assert!((inv.point(t.point(p)) - p).length() < 1e-12);
+        assert!((t * inv).rows.iter().flatten().zip(Transform::IDENTITY.rows.iter().flatten()).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(Transform { rows: [[0.0; 4]; 3] }.inverse().is_none());
+    }
+}
@@ -12,5 +12,21 @@
 //!   Provides a few functions and macros used throughout the `math` module.
 // 
 
+use super::vec3::Vec3;
+
 
 /***** LIBRARY *****/
+/// Computes two vectors that, together with the given one, form an orthonormal basis.
+///
+/// # Arguments
+/// - `w`: The (unit) vector to build the basis around.
+///
+/// # Returns
+/// A pair of (unit) vectors `(u, v)` perpendicular to each other and to `w`.
+pub fn orthonormal_basis(w: Vec3) -> (Vec3, Vec3) {
+    // Pick any axis that's not (nearly) parallel to `w` to cross with
+    let a: Vec3 = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let v: Vec3 = w.cross(a).unit();
+    let u: Vec3 = w.cross(v);
+    (u, v)
+}
@@ -0,0 +1,241 @@
+//  MERGE.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements the `merge` subcommand, which combines renders of parts
+//!   of the same frame (e.g., rendered on different machines) into one
+//!   final image.
+//
+
+use std::error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::path::{Path, PathBuf};
+
+use console::style;
+use log::{debug, info, warn};
+
+use crate::math::{Colour, ToneMapping};
+use crate::render::checkpoint::Checkpoint;
+use crate::render::image::Image;
+use crate::render::pixel::Film;
+use crate::specifications::scene::CameraInfo;
+
+
+/***** ERRORS *****/
+/// Defines the errors for the `merge` subcommand.
+#[derive(Debug)]
+pub enum Error {
+    /// Some inputs were checkpoints, some images.
+    MixedInputs,
+    /// Failed to load a checkpoint.
+    CheckpointLoad { err: crate::common::file::Error },
+    /// A checkpoint belongs to a different scene than the first one.
+    SceneMismatch { path: PathBuf, first: PathBuf },
+    /// Failed to load an image.
+    ImageLoad { err: crate::render::image::Error },
+    /// An input has different dimensions than the first one.
+    DimsMismatch { path: PathBuf, got: (u32, u32), expected: (u32, u32) },
+    /// Failed to save the merged image.
+    ImageSave { path: PathBuf, err: crate::render::image::Error },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use Error::*;
+        match self {
+            MixedInputs => write!(f, "Cannot merge checkpoints with images (give only checkpoints, or only images)"),
+            CheckpointLoad { .. } => write!(f, "Failed to load checkpoint"),
+            SceneMismatch { path, first } => {
+                write!(f, "Checkpoint '{}' renders a different scene or camera than checkpoint '{}'", path.display(), first.display())
+            },
+            ImageLoad { .. } => write!(f, "Failed to load image"),
+            DimsMismatch { path, got, expected } => {
+                write!(f, "Input '{}' has {}x{} pixels, but expected {}x{} pixels", path.display(), got.0, got.1, expected.0, expected.1)
+            },
+            ImageSave { path, .. } => write!(f, "Failed to save merged image to '{}'", path.display()),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+        match self {
+            MixedInputs => None,
+            CheckpointLoad { err } => Some(err),
+            SceneMismatch { .. } => None,
+            ImageLoad { err } => Some(err),
+            DimsMismatch { .. } => None,
+            ImageSave { err, .. } => Some(err),
+        }
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns whether the given path refers to a checkpoint (instead of an image).
+#[inline]
+fn is_checkpoint(path: &Path) -> bool { path.extension().map(|ext| ext.eq_ignore_ascii_case("json")).unwrap_or(false) }
+
+/// Returns whether two cameras see the same frame, i.e., are equal except for which pixels they
+/// render, how many samples they take and how they're tone mapped.
+#[inline]
+fn same_frame(lhs: &CameraInfo, rhs: &CameraInfo) -> bool {
+    *lhs == CameraInfo {
+        region: lhs.region,
+        n_samples: lhs.n_samples,
+        sampler: lhs.sampler,
+        adaptive: lhs.adaptive,
+        tone_mapping: lhs.tone_mapping,
+        ..*rhs
+    }
+}
+
+/// Loads an image written by [`Image::to_path()`].
+///
+/// # Arguments
+/// - `path`: The path of the image to load.
+///
+/// # Returns
+/// The loaded [`Image`].
+///
+/// # Errors
+/// This function errors if we failed to load the image.
+#[inline]
+fn load_image(path: &Path) -> Result<Image, Error> { Image::from_written_path(path).map_err(|err| Error::ImageLoad { err }) }
+
+/// Merges checkpoints by adding all of their samples together.
+///
+/// # Arguments
+/// - `inputs`: The paths of the checkpoints to merge.
+///
+/// # Returns
+/// A [`Film`] with the samples of all of them, together with the [`ToneMapping`] of the first.
+///
+/// # Errors
+/// This function errors if we failed to load a checkpoint, or if they don't render the same frame.
+fn merge_checkpoints(inputs: &[PathBuf]) -> Result<(Film, ToneMapping), Error> {
+    let mut res: Option<(&Path, Checkpoint)> = None;
+    for path in inputs {
+        debug!("Loading checkpoint '{}'...", path.display());
+        let checkpoint: Checkpoint = Checkpoint::from_path(path).map_err(|err| Error::CheckpointLoad { err })?;
+        match &mut res {
+            Some((first, res)) => {
+                // Only add samples of the same frame
+                if checkpoint.info.scene_hash != res.info.scene_hash || !same_frame(&checkpoint.info.camera, &res.info.camera) {
+                    return Err(Error::SceneMismatch { path: path.clone(), first: first.to_path_buf() });
+                }
+                if checkpoint.film.dims() != res.film.dims() {
+                    return Err(Error::DimsMismatch { path: path.clone(), got: checkpoint.film.dims(), expected: res.film.dims() });
+                }
+                if checkpoint.info.seed == res.info.seed {
+                    warn!(
+                        "Checkpoint '{}' has the same seed as an earlier one; any pixels they both rendered will count the same samples twice",
+                        path.display()
+                    );
+                }
+                res.film.merge(&checkpoint.film);
+            },
+            None => res = Some((path, checkpoint)),
+        }
+    }
+    Ok(res.map(|(_, checkpoint)| (checkpoint.film, checkpoint.info.camera.tone_mapping)).unwrap_or_else(|| (Film::new((0, 0)), ToneMapping::default())))
+}
+
+/// Merges images by averaging the pixels they rendered.
+///
+/// Since we don't know how many samples every image took, all images are weighed equally. Also
+/// note that images only have 8 bits per channel, so prefer merging checkpoints for exact results.
+///
+/// # Arguments
+/// - `inputs`: The paths of the images to merge.
+/// - `gamma_correction`: Whether the images are gamma corrected. HDR images (see
+///   [`Image::is_hdr_path()`]) are always assumed to be linear.
+///
+/// # Returns
+/// The merged (linear) [`Image`].
+///
+/// # Errors
+/// This function errors if we failed to load an image, or if they have different dimensions.
+fn merge_images(inputs: &[PathBuf], gamma_correction: bool) -> Result<Image, Error> {
+    let mut sum: Option<(Image, Vec<u32>)> = None;
+    for path in inputs {
+        debug!("Loading image '{}'...", path.display());
+        let image: Image = load_image(path)?;
+        let (sum, counts): &mut (Image, Vec<u32>) = sum.get_or_insert_with(|| (Image::new(image.dims()), vec![0; image.len()]));
+        if image.dims() != sum.dims() {
+            return Err(Error::DimsMismatch { path: path.clone(), got: image.dims(), expected: sum.dims() });
+        }
+
+        // Add the pixels that were rendered (i.e., aren't transparent) in linear space
+        let gamma_correction: bool = gamma_correction && !Image::is_hdr_path(path);
+        for ((sum, count), colour) in sum.iter_mut().zip(counts.iter_mut()).zip(image.iter()) {
+            if colour.a > 0.0 {
+                *sum += if gamma_correction { colour.from_srgb() } else { *colour };
+                *count += 1;
+            }
+        }
+    }
+
+    // Now average them
+    let (mut res, counts): (Image, Vec<u32>) = sum.unwrap_or_else(|| (Image::new((0u32, 0u32)), Vec::new()));
+    for (colour, count) in res.iter_mut().zip(counts) {
+        *colour = if count > 0 { (*colour * (1.0 / count as f64)).opaque() } else { Colour::zeroes() };
+    }
+    Ok(res)
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Merges renders of (parts of) the same frame into one image.
+///
+/// The inputs are either checkpoints or images. Checkpoints are merged exactly: their samples are
+/// added together, so overlapping renders (with different seeds) are weighted by the number of
+/// samples they took. Images are stitched by averaging the pixels they rendered (i.e., that are
+/// not transparent). Either way, colours are merged in linear space, and gamma correction is only
+/// applied to the result (unless it's written to an HDR format, which gets the linear colours).
+///
+/// Merged checkpoints are tone mapped like the first checkpoint was, which may be adapted with
+/// `tone_mapping`. Images are tone mapped already, so they're left alone.
+///
+/// # Arguments
+/// - `inputs`: The paths of the checkpoints (`.json`) or images to merge.
+/// - `output`: The path to write the merged image to.
+/// - `tone_mapping`: Adapts the [`ToneMapping`] of the first checkpoint to the one to use.
+/// - `gamma_correction`: Whether to apply gamma correction to the result. For images, this also
+///   means the inputs are assumed to be gamma corrected (unless they're HDR images).
+/// - `fix_dirs`: Whether to fix missing directories or chicken out.
+///
+/// # Errors
+/// This function errors if we failed to read any input, the inputs don't fit together, or we
+/// failed to write the result.
+pub fn merge(
+    inputs: &[PathBuf],
+    output: impl AsRef<Path>,
+    tone_mapping: impl FnOnce(ToneMapping) -> ToneMapping,
+    gamma_correction: bool,
+    fix_dirs: bool,
+) -> Result<(), Error> {
+    let output: &Path = output.as_ref();
+    info!("Merging {} render(s) into '{}'...", inputs.len(), output.display());
+
+    // Merge the inputs in the way that fits them
+    let (linear, tone_mapping): (Image, ToneMapping) = if inputs.iter().all(|path| is_checkpoint(path)) {
+        let (film, first): (Film, ToneMapping) = merge_checkpoints(inputs)?;
+        (film.linear(), tone_mapping(first))
+    } else if inputs.iter().any(|path| is_checkpoint(path)) {
+        return Err(Error::MixedInputs);
+    } else {
+        (merge_images(inputs, gamma_correction)?, ToneMapping::default())
+    };
+    let image: Image = if Image::is_hdr_path(output) { linear } else { linear.develop(tone_mapping, gamma_correction) };
+
+    // Write the result
+    image.to_path(output, fix_dirs).map_err(|err| Error::ImageSave { path: output.into(), err })?;
+    println!("Successfully {} {} render(s) to {}", style("merged").bold().green(), inputs.len(), style(output.display()).bold());
+    Ok(())
+}
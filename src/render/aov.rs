@@ -0,0 +1,178 @@
+//  AOV.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the arbitrary output variables (AOVs) that can be rendered
+//!   besides the beauty pass, and how they are shown in ordinary (8-bit)
+//!   images.
+//!
+//!   The buffers themselves are computed by
+//!   [`Film::aov()`](super::pixel::Film::aov()), and contain the raw
+//!   values (e.g., the depth in world units). Every pixel's alpha channel
+//!   is the fraction of its samples that hit anything.
+//
+
+use clap::ValueEnum;
+
+use super::image::Image;
+use crate::math::Colour;
+
+
+/***** HELPER FUNCTIONS *****/
+/// Finds the smallest and largest value of a channel over all pixels that hit anything.
+///
+/// # Arguments
+/// - `raw`: The [`Image`] with the raw values.
+/// - `channel`: The channel to read from every pixel.
+///
+/// # Returns
+/// A `(min, max)`-pair, or [`None`] if no pixel hit anything.
+fn range(raw: &Image, channel: impl Fn(&Colour) -> f64) -> Option<(f64, f64)> {
+    raw.iter().filter(|colour| colour.a > 0.0).map(channel).fold(None, |range, value| match range {
+        Some((min, max)) => Some((value.min(min), value.max(max))),
+        None => Some((value, value)),
+    })
+}
+
+/// Maps a value into the `[0, 1]` range.
+#[inline]
+fn normalize(value: f64, (min, max): (f64, f64)) -> f64 { if max > min { (value - min) / (max - min) } else { 1.0 } }
+
+/// Maps an object index to a colour that is easy to tell apart from its neighbours.
+///
+/// The hue is picked by stepping around the colour wheel by the golden ratio, which spreads
+/// consecutive indices out as far as possible.
+fn object_colour(index: f64) -> Colour {
+    let hue: f64 = (index * 0.618_033_988_749_895).fract() * 6.0;
+    let x: f64 = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b): (f64, f64, f64) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // Don't use fully saturated colours, those are hard on the eyes
+    Colour::new(0.2 + 0.75 * r, 0.2 + 0.75 * g, 0.2 + 0.75 * b, 1.0)
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines the arbitrary output variables (AOVs) that can be rendered next to the beauty pass.
+///
+/// All of them describe the first surface that the camera rays hit, averaged over the samples of
+/// every pixel that hit anything.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub enum Aov {
+    /// The distance along the camera ray to the hit, in all colour channels.
+    #[clap(name = "depth")]
+    Depth,
+    /// The shading normal at the hit, as XYZ in the RGB channels.
+    #[clap(name = "normal")]
+    Normal,
+    /// The base colour of the material at the hit.
+    #[clap(name = "albedo")]
+    Albedo,
+    /// The point in the world that was hit, as XYZ in the RGB channels.
+    #[clap(name = "position")]
+    Position,
+    /// The texture coordinates of the hit, as UV in the RG channels.
+    #[clap(name = "uv")]
+    Uv,
+    /// The index of the object that was hit (i.e., its index in the scene file), in all colour
+    /// channels. Unlike the others, this is taken from the first sample that hit anything instead
+    /// of averaged.
+    #[clap(name = "object")]
+    Object,
+}
+impl Aov {
+    /// All AOVs, in the order in which they're written.
+    pub const ALL: [Self; 6] = [Self::Depth, Self::Normal, Self::Albedo, Self::Position, Self::Uv, Self::Object];
+
+
+    /// Returns the name of the AOV, which is also the name of its buffer in a [`Frame`](super::Frame).
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Depth => "depth",
+            Self::Normal => "normal",
+            Self::Albedo => "albedo",
+            Self::Position => "position",
+            Self::Uv => "uv",
+            Self::Object => "object",
+        }
+    }
+
+    /// Turns the raw values of this AOV into something that can be looked at in an ordinary image.
+    ///
+    /// Depth and position are scaled to the range of values in the image (with depth going from
+    /// white nearby to black far away), normals are mapped from `[-1, 1]` to `[0, 1]` and every
+    /// object gets its own colour. Pixels that didn't hit anything are left transparent.
+    ///
+    /// # Arguments
+    /// - `raw`: The [`Image`] with the raw values, as computed by [`Film::aov()`](super::pixel::Film::aov()).
+    ///
+    /// # Returns
+    /// A new [`Image`] with all colours in the `[0, 1]` range.
+    pub fn visualize(&self, raw: &Image) -> Image {
+        // Find the range of the values that we scale, if any
+        let ranges: [Option<(f64, f64)>; 3] = match self {
+            Self::Depth => [range(raw, |c| c.r), None, None],
+            Self::Position => [range(raw, |c| c.r), range(raw, |c| c.g), range(raw, |c| c.b)],
+            _ => [None, None, None],
+        };
+        let [x, y, z]: [(f64, f64); 3] = ranges.map(|range| range.unwrap_or((0.0, 0.0)));
+
+        let mut res: Image = Image::new(raw.dims());
+        for (colour, raw) in res.iter_mut().zip(raw.iter()) {
+            if raw.a <= 0.0 {
+                *colour = Colour::zeroes();
+                continue;
+            }
+            let shown: Colour = match self {
+                Self::Depth => {
+                    let depth: f64 = 1.0 - normalize(raw.r, x);
+                    Colour::new(depth, depth, depth, 1.0)
+                },
+                Self::Normal => Colour::new(0.5 * (raw.r + 1.0), 0.5 * (raw.g + 1.0), 0.5 * (raw.b + 1.0), 1.0),
+                Self::Albedo | Self::Uv => raw.opaque(),
+                Self::Position => Colour::new(normalize(raw.r, x), normalize(raw.g, y), normalize(raw.b, z), 1.0),
+                Self::Object => object_colour(raw.r),
+            };
+            *colour = Colour { a: raw.a, ..shown }.clamp();
+        }
+        res
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visualize() {
+        let mut raw = Image::new((3u32, 1u32));
+        raw[(0u32, 0u32)] = Colour::new(2.0, 2.0, 2.0, 1.0);
+        raw[(1u32, 0u32)] = Colour::new(4.0, 4.0, 4.0, 0.5);
+
+        // Depth goes from white nearby to black far away, and leaves the background alone
+        let depth: Image = Aov::Depth.visualize(&raw);
+        assert_eq!(depth[(0u32, 0u32)], Colour::new(1.0, 1.0, 1.0, 1.0));
+        assert_eq!(depth[(1u32, 0u32)], Colour::new(0.0, 0.0, 0.0, 0.5));
+        assert_eq!(depth[(2u32, 0u32)], Colour::zeroes());
+
+        // Different objects get different colours
+        let objects: Image = Aov::Object.visualize(&raw);
+        assert_ne!(objects[(0u32, 0u32)].opaque(), objects[(1u32, 0u32)].opaque());
+        assert!(objects.iter().all(|c| [c.r, c.g, c.b, c.a].iter().all(|c| (0.0..=1.0).contains(c))));
+    }
+}
@@ -0,0 +1,617 @@
+//  FARM.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements a renderer that coordinates a render farm: it hands out
+//!   tiles of the frame to worker processes (started with `raytracer
+//!   serve-worker`, possibly on other machines) over TCP, and collects
+//!   the samples they took.
+//!
+//!   See [`crate::specifications::farm`] for the protocol.
+//
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use error_trace::ErrorTrace as _;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+
+use super::super::cancel::CancelToken;
+use super::super::checkpoint::Checkpointer;
+use super::super::integrators::AnyIntegrator;
+use super::super::pixel::Film;
+use super::super::tiles::{Tile, TileOrder, tiles};
+use super::super::tui::Tui;
+use super::super::{Frame, RayRenderer};
+use crate::common::file::{impl_toml_from_path, impl_toml_from_string, impl_toml_to_path, impl_toml_to_string};
+use crate::common::input::Region;
+use crate::hittree::HitTree;
+use crate::math::Camera;
+use crate::specifications::farm::{PROTOCOL_VERSION, Request, Response, read_message, write_message};
+use crate::specifications::scene::{Environment, SceneFile};
+
+
+/***** ERRORS *****/
+/// Defines errors that may occur when rendering on a render farm.
+#[derive(Debug)]
+pub enum Error {
+    /// The config didn't list any workers.
+    NoWorkers,
+    /// All workers dropped out before the frame was done.
+    AllWorkersLost { remaining: usize },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use Error::*;
+        match self {
+            NoWorkers => write!(f, "No workers given in the farm backend config"),
+            AllWorkersLost { remaining } => write!(f, "All workers dropped out with {remaining} tile(s) left to render"),
+        }
+    }
+}
+impl error::Error for Error {}
+
+
+
+/// Defines the reasons for dropping a worker.
+#[derive(Debug)]
+enum WorkerError {
+    /// Failed to connect to the worker.
+    Connect { err: std::io::Error },
+    /// Failed to talk to the worker.
+    Protocol { err: crate::specifications::farm::Error },
+    /// The worker speaks another version of the protocol.
+    VersionMismatch { got: u32 },
+    /// The worker told us it failed.
+    Remote { message: String },
+    /// The worker sent something else than we asked for.
+    Unexpected { what: &'static str },
+}
+impl Display for WorkerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use WorkerError::*;
+        match self {
+            Connect { .. } => write!(f, "Failed to connect"),
+            Protocol { .. } => write!(f, "Failed to communicate"),
+            VersionMismatch { got } => write!(f, "Worker speaks protocol version {got}, but we speak version {PROTOCOL_VERSION}"),
+            Remote { message } => write!(f, "Worker reported an error: {message}"),
+            Unexpected { what } => write!(f, "Worker did not send the expected {what}"),
+        }
+    }
+}
+impl error::Error for WorkerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use WorkerError::*;
+        match self {
+            Connect { err } => Some(err),
+            Protocol { err } => Some(err),
+            VersionMismatch { .. } => None,
+            Remote { .. } => None,
+            Unexpected { .. } => None,
+        }
+    }
+}
+impl From<crate::specifications::farm::Error> for WorkerError {
+    #[inline]
+    fn from(err: crate::specifications::farm::Error) -> Self { Self::Protocol { err } }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Connects to a worker.
+///
+/// # Arguments
+/// - `address`: The address of the worker, as a `<HOST>:<PORT>`-pair.
+/// - `timeout`: If given, the time after which we give up connecting (to every address the host resolves to).
+///
+/// # Returns
+/// A [`TcpStream`] connected to the worker.
+///
+/// # Errors
+/// This function errors if we failed to resolve the address or to connect to it.
+fn connect(address: &str, timeout: Option<Duration>) -> Result<TcpStream, std::io::Error> {
+    let Some(timeout) = timeout else { return TcpStream::connect(address) };
+    let mut res: Result<TcpStream, std::io::Error> =
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Address '{address}' did not resolve to anything")));
+    for addr in address.to_socket_addrs()? {
+        res = TcpStream::connect_timeout(&addr, timeout);
+        if res.is_ok() {
+            break;
+        }
+    }
+    res
+}
+
+
+
+
+
+/***** AUXILLARY *****/
+/// Defines the configuration options for the render farm.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FarmRendererConfig {
+    /// The addresses of the workers to render with, as `<HOST>:<PORT>`-pairs.
+    workers:    Vec<String>,
+    /// Defines the width and height of the tiles that are handed to the workers.
+    tile_size:  u32,
+    /// Defines the order in which the tiles are rendered.
+    tile_order: TileOrder,
+    /// The number of seconds after which a worker that doesn't answer is dropped. If omitted, waits forever.
+    timeout:    Option<u64>,
+}
+
+impl Default for FarmRendererConfig {
+    #[inline]
+    fn default() -> Self { Self { workers: Vec::new(), tile_size: 32, tile_order: TileOrder::default(), timeout: Some(300) } }
+}
+impl FarmRendererConfig {
+    impl_toml_from_string!();
+    impl_toml_to_string!();
+    impl_toml_from_path!();
+    impl_toml_to_path!();
+}
+
+
+
+/// The tiles that still have to be rendered.
+#[derive(Debug)]
+struct Queue {
+    /// The tiles that haven't been handed out yet.
+    todo: VecDeque<Tile>,
+    /// The number of tiles that are being rendered right now.
+    busy: usize,
+}
+
+/// Everything shared between the threads talking to the workers.
+struct Shared<'a> {
+    /// The tiles to render.
+    queue:       Mutex<Queue>,
+    /// Signals changes to the `queue`.
+    changed:     Condvar,
+    /// The film with all samples taken so far.
+    output:      Mutex<Film>,
+    /// The number of rays cast by all workers together.
+    n_rays:      AtomicU64,
+    /// If given, the number of samples after which pixels stop for now.
+    limit:       Option<u64>,
+    /// If given, writes checkpoints every now and then.
+    checkpoints: Option<&'a Checkpointer>,
+    /// If given, stops handing out tiles once cancelled.
+    cancel:      Option<&'a CancelToken>,
+    /// If given, shows the progress.
+    prgs:        Option<ProgressBar>,
+}
+
+impl Shared<'_> {
+    /// Takes the next tile to render.
+    ///
+    /// If there are none left but other workers are still busy, waits until they are done (since
+    /// they may drop out and give back their tile).
+    ///
+    /// # Returns
+    /// The next [`Tile`] to render, or [`None`] if all tiles have been rendered or rendering was cancelled.
+    fn take(&self) -> Option<Tile> {
+        let mut queue = self.queue.lock();
+        loop {
+            if self.cancel.is_some_and(CancelToken::is_cancelled) {
+                return None;
+            }
+            if let Some(tile) = queue.todo.pop_front() {
+                queue.busy += 1;
+                return Some(tile);
+            }
+            if queue.busy == 0 {
+                return None;
+            }
+            self.changed.wait(&mut queue);
+        }
+    }
+
+    /// Marks a tile as done or, if it wasn't rendered, puts it back for another worker.
+    ///
+    /// # Arguments
+    /// - `tile`: The [`Tile`] to return.
+    /// - `rendered`: Whether the tile was rendered.
+    fn give_back(&self, tile: Tile, rendered: bool) {
+        let mut queue = self.queue.lock();
+        queue.busy -= 1;
+        if !rendered {
+            queue.todo.push_front(tile);
+        }
+        self.changed.notify_all();
+    }
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// The FarmRenderer hands out tiles of the frame to worker processes over TCP.
+///
+/// Every tile is rendered exactly as the other backends would, so the frame is the same as when
+/// rendered locally with the same seed.
+#[derive(Debug)]
+pub struct FarmRenderer {
+    /// Whether to enable or disable the progress bar.
+    show_prgs: bool,
+    /// The scene that is sent to the workers.
+    scene: SceneFile,
+    /// The integrator that computes the colour of every ray.
+    integrator: AnyIntegrator,
+    /// The seed from which all random numbers are derived.
+    seed: u64,
+    /// Whether to apply gamma correction.
+    gamma_correction: bool,
+
+    /// The addresses of the workers.
+    workers:    Vec<String>,
+    /// The width and height of the tiles to send to each worker.
+    tile_size:  u32,
+    /// The order in which the tiles are rendered.
+    tile_order: TileOrder,
+    /// The time after which a worker that doesn't answer is dropped.
+    timeout:    Option<Duration>,
+    /// If given, draws a live preview of the frame in the terminal.
+    tui:        Option<Arc<Tui>>,
+}
+
+impl FarmRenderer {
+    /// Constructor for the FarmRenderer.
+    ///
+    /// # Arguments
+    /// - `show_prgs`: Whether or not to show the progress as we're rendering.
+    /// - `scene`: The [`SceneFile`] to send to the workers. Its external references must already be loaded, and its camera must be the one that
+    ///   is rendered with. The `world` and `env` given when rendering should be built from it.
+    /// - `integrator`: The [`AnyIntegrator`] that computes the colour of every ray.
+    /// - `seed`: The seed from which all random numbers are derived. The same seed always renders the same image, regardless of the workers.
+    /// - `gamma_correction`: Whether to apply gamma correction to the final image.
+    /// - `config`: Any FarmRenderer-specific config.
+    ///
+    /// # Returns
+    /// A new FarmRenderer instance.
+    ///
+    /// # Errors
+    /// This function errors if the config doesn't list any workers.
+    #[inline]
+    pub fn new(
+        show_prgs: bool,
+        scene: SceneFile,
+        integrator: AnyIntegrator,
+        seed: u64,
+        gamma_correction: bool,
+        config: impl Into<FarmRendererConfig>,
+    ) -> Result<Self, Error> {
+        let config = config.into();
+        if config.workers.is_empty() {
+            return Err(Error::NoWorkers);
+        }
+        Ok(Self {
+            show_prgs,
+            scene,
+            integrator,
+            seed,
+            gamma_correction,
+            workers: config.workers,
+            tile_size: config.tile_size,
+            tile_order: config.tile_order,
+            timeout: config.timeout.map(Duration::from_secs),
+            tui: None,
+        })
+    }
+
+    /// Draws a live preview of the frame in the terminal while rendering.
+    ///
+    /// Since both draw on the terminal, this hides the progress bar if a [`Tui`] is given.
+    ///
+    /// # Arguments
+    /// - `tui`: The [`Tui`] to draw on, or [`None`] to not draw anything.
+    ///
+    /// # Returns
+    /// The same FarmRenderer, but drawing on the given [`Tui`].
+    #[inline]
+    pub fn with_tui(mut self, tui: Option<Arc<Tui>>) -> Self {
+        self.show_prgs &= tui.is_none();
+        self.tui = tui;
+        self
+    }
+
+    /// Renders tiles on one worker until there are none left, or until it drops out.
+    ///
+    /// # Arguments
+    /// - `address`: The address of the worker.
+    /// - `shared`: The state shared with the threads talking to the other workers.
+    ///
+    /// # Errors
+    /// This function errors if the worker dropped out. Any tile it was rendering is given back to the others.
+    fn drive(&self, address: &str, shared: &Shared) -> Result<(), WorkerError> {
+        // Connect and introduce ourselves
+        let mut stream: TcpStream = connect(address, self.timeout).map_err(|err| WorkerError::Connect { err })?;
+        stream.set_read_timeout(self.timeout).map_err(|err| WorkerError::Connect { err })?;
+        stream.set_write_timeout(self.timeout).map_err(|err| WorkerError::Connect { err })?;
+        stream.set_nodelay(true).map_err(|err| WorkerError::Connect { err })?;
+        write_message(&mut stream, &Request::Hello { version: PROTOCOL_VERSION })?;
+        match read_message(&mut stream)? {
+            Response::Hello { version } if version == PROTOCOL_VERSION => {},
+            Response::Hello { version } => return Err(WorkerError::VersionMismatch { got: version }),
+            Response::Error { message } => return Err(WorkerError::Remote { message }),
+            _ => return Err(WorkerError::Unexpected { what: "hello" }),
+        }
+
+        // Send the scene
+        write_message(&mut stream, &Request::Scene {
+            scene:      Box::new(Cow::Borrowed(&self.scene)),
+            integrator: Cow::Borrowed(&self.integrator),
+            seed:       self.seed,
+        })?;
+        match read_message(&mut stream)? {
+            Response::Ready => {},
+            Response::Error { message } => return Err(WorkerError::Remote { message }),
+            _ => return Err(WorkerError::Unexpected { what: "ready" }),
+        }
+        debug!("Worker '{address}' is ready");
+
+        // Now render tiles until there are none left
+        while let Some(tile) = shared.take() {
+            let res: Result<(), WorkerError> = self.render_tile(&mut stream, tile, shared);
+            shared.give_back(tile, res.is_ok());
+            res?;
+        }
+
+        // Say goodbye, but it doesn't matter if the worker already left
+        if let Err(err) = write_message(&mut stream, &Request::Goodbye) {
+            debug!("Failed to say goodbye to worker '{address}': {err}");
+        }
+        Ok(())
+    }
+
+    /// Has a worker render a single tile, and writes the result to the shared film.
+    ///
+    /// # Arguments
+    /// - `stream`: The connection to a worker that is ready to render.
+    /// - `tile`: The [`Tile`] to render.
+    /// - `shared`: The state shared with the threads talking to the other workers.
+    ///
+    /// # Errors
+    /// This function errors if the worker failed to render the tile.
+    fn render_tile(&self, stream: &mut TcpStream, tile: Tile, shared: &Shared) -> Result<(), WorkerError> {
+        // Send where we left off, if anywhere
+        let start: Film = {
+            let lock = shared.output.lock();
+            let mut start: Film = lock.empty_like(tile.dims);
+            for (x, y) in tile.pixels() {
+                start.set((x - tile.pos.0, y - tile.pos.1), lock.get((x, y)));
+            }
+            start
+        };
+        let (n_start, start_aovs): (u64, bool) = (tile.pixels().map(|(x, y)| start.get((x - tile.pos.0, y - tile.pos.1)).n()).sum(), start.has_aovs());
+        write_message(&mut *stream, &Request::Render { tile, film: start, limit: shared.limit })?;
+        let film: Film = match read_message(&mut *stream)? {
+            Response::Rendered { tile: rendered, film } if rendered == tile && film.dims() == tile.dims && film.has_aovs() == start_aovs => film,
+            Response::Error { message } => return Err(WorkerError::Remote { message }),
+            _ => return Err(WorkerError::Unexpected { what: "rendered tile" }),
+        };
+        let n_end: u64 = tile.pixels().map(|(x, y)| film.get((x - tile.pos.0, y - tile.pos.1)).n()).sum();
+        shared.n_rays.fetch_add(n_end.saturating_sub(n_start), Ordering::Relaxed);
+        if let Some(tui) = &self.tui {
+            tui.progress(tile.dims.0 as u64 * tile.dims.1 as u64, n_end.saturating_sub(n_start));
+        }
+
+        // Then write it to the shared one (and copy it if we have to checkpoint, which we do without holding up the others)
+        let snapshot: Option<Film> = {
+            let mut lock = shared.output.lock();
+            lock.move_into(film, tile.pos);
+            if let Some(tui) = self.tui.as_ref().filter(|tui| tui.due()) {
+                tui.snapshot(&lock);
+            }
+            shared.checkpoints.filter(|checkpoints| checkpoints.due()).map(|_| lock.clone())
+        };
+        if let (Some(checkpoints), Some(snapshot)) = (shared.checkpoints, snapshot) {
+            if let Err(err) = checkpoints.write(&snapshot) {
+                warn!("Failed to write checkpoint: {err}");
+            }
+        }
+        if let Some(prgs) = &shared.prgs {
+            prgs.inc(tile.dims.0 as u64 * tile.dims.1 as u64);
+        }
+        Ok(())
+    }
+}
+impl RayRenderer for FarmRenderer {
+    type Error = Error;
+
+    fn resume_frame(
+        &self,
+        world: &HitTree,
+        cam: &Camera,
+        _env: &Environment,
+        film: Film,
+        checkpoints: Option<&Checkpointer>,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Frame, Self::Error> {
+        info!("Rendering scene ({} objects) on {} worker(s)...", world.len(), self.workers.len());
+
+        // Let us define the camera (static, for now), which may only want to render a region of the film
+        assert_eq!(film.dims(), cam.dims(), "Film and camera have different dimensions");
+        let region: Region = cam.region();
+        let dims: (u32, u32) = region.dims();
+        if let Some(tui) = &self.tui {
+            tui.start_pass(dims.0 as u64 * dims.1 as u64);
+        }
+
+        // Split the region into tiles, which we hand out to the workers
+        let todo: VecDeque<Tile> =
+            tiles(dims, self.tile_size, self.tile_order).into_iter().map(|tile| Tile { pos: (region.x0 + tile.pos.0, region.y0 + tile.pos.1), ..tile }).collect();
+        let shared: Shared = Shared {
+            queue: Mutex::new(Queue { todo, busy: 0 }),
+            changed: Condvar::new(),
+            output: Mutex::new(film),
+            n_rays: AtomicU64::new(0),
+            limit: cam.sample_limit(),
+            checkpoints,
+            cancel,
+            prgs: if self.show_prgs {
+                Some(
+                    ProgressBar::new(dims.0 as u64 * dims.1 as u64).with_style(
+                        ProgressStyle::with_template(" Pixel {human_pos}/{human_len} [{wide_bar}] {percent}% {elapsed} (ETA {eta}) ")
+                            .unwrap_or_else(|err| panic!("Invalid template given to progress bar: {err}"))
+                            .progress_chars("=> "),
+                    ),
+                )
+            } else {
+                None
+            },
+        };
+
+        // Talk to every worker on its own thread, dropping any that fail
+        let start: Instant = Instant::now();
+        std::thread::scope(|s| {
+            for address in &self.workers {
+                let shared: &Shared = &shared;
+                s.spawn(move || {
+                    if let Err(err) = self.drive(address, shared) {
+                        warn!("Dropped worker '{address}': {}", err.trace());
+                    }
+                });
+            }
+        });
+        let Shared { queue, output, n_rays, prgs, .. } = shared;
+        let film: Film = output.into_inner();
+        if let Some(tui) = &self.tui {
+            tui.snapshot(&film);
+        }
+
+        // If any tiles are left, we were either cancelled or nobody could render them
+        let remaining: usize = queue.into_inner().todo.len();
+        let interrupted: bool = remaining > 0 && cancel.is_some_and(CancelToken::is_cancelled);
+        if interrupted {
+            info!("Rendering cancelled with {remaining} tile(s) left");
+        } else if remaining > 0 {
+            if let Some(checkpoints) = checkpoints {
+                match checkpoints.write(&film) {
+                    Ok(_) => info!("Wrote checkpoint with the tiles rendered so far to '{}'", checkpoints.path().display()),
+                    Err(err) => warn!("Failed to write checkpoint: {err}"),
+                }
+            }
+            return Err(Error::AllWorkersLost { remaining });
+        }
+
+        // Complete the progress bar
+        if let Some(prgs) = prgs {
+            prgs.finish_with_message(format!(
+                "{} (averaged {:.2} rays/s)",
+                if interrupted { "Stopped" } else { "Done" },
+                n_rays.into_inner() as f64 / start.elapsed().as_secs() as f64
+            ));
+        }
+
+        // Fix the colours in the resulting image
+        Ok(Frame::new(film, cam, self.gamma_correction).with_interrupted(interrupted))
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::num::{NonZeroU32, NonZeroU64};
+
+    use super::*;
+    use crate::math::{Filter, FilterKind};
+    use crate::render::backends::SingleThreadRenderer;
+    use crate::render::integrators::PathTracer;
+    use crate::render::lights::MisHeuristic;
+    use crate::specifications::covers::{Book, cover};
+
+    /// Spawns a worker on a free port, and returns its address.
+    fn spawn_worker() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address: String = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || crate::worker::serve_on(listener, 2));
+        address
+    }
+
+    /// Spawns a worker that drops out as soon as it's asked to render a tile, and returns its address.
+    fn spawn_dropout() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address: String = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert!(matches!(read_message(&mut stream).unwrap(), Request::Hello { .. }));
+            write_message(&mut stream, &Response::Hello { version: PROTOCOL_VERSION }).unwrap();
+            assert!(matches!(read_message(&mut stream).unwrap(), Request::Scene { .. }));
+            write_message(&mut stream, &Response::Ready).unwrap();
+            assert!(matches!(read_message(&mut stream).unwrap(), Request::Render { .. }));
+        });
+        address
+    }
+
+    #[test]
+    fn test_farm() {
+        let mut scene: SceneFile = cover(Book::OneWeekend, 1000, 42);
+        scene.camera.dims = (NonZeroU32::new(24).unwrap(), NonZeroU32::new(16).unwrap());
+        scene.camera.n_samples = NonZeroU64::new(2).unwrap();
+        scene.camera.filter = Filter { kind: FilterKind::Gaussian, radius: None };
+        let integrator: AnyIntegrator = PathTracer { max_depth: 8, roulette_depth: 3, roulette_clamp: 0.95, mis: MisHeuristic::Power }.into();
+        let cam: Camera = Camera::from(scene.camera);
+        let world: HitTree = HitTree::with_objs(scene.objects.clone(), (0..=scene.camera.shutter_time.into()).into());
+        let expected: Frame =
+            SingleThreadRenderer::new(false, integrator, 42, true).resume_frame(&world, &cam, &scene.environment, Film::with_aovs(cam.dims()), None, None).unwrap();
+
+        // Workers that drop out (or were never there) don't change the result, nor the AOVs
+        let config = FarmRendererConfig {
+            workers:    vec![spawn_dropout(), spawn_worker(), "127.0.0.1:1".into(), spawn_worker()],
+            tile_size:  8,
+            tile_order: TileOrder::Scanline,
+            timeout:    Some(60),
+        };
+        let renderer: FarmRenderer = FarmRenderer::new(false, scene.clone(), integrator, 42, true, config.clone()).unwrap();
+        let frame: Frame = renderer.resume_frame(&world, &cam, &scene.environment, Film::with_aovs(cam.dims()), None, None).unwrap();
+        assert_eq!(format!("{:?}", frame.film), format!("{:?}", expected.film));
+        assert!(frame.get("object").is_some());
+
+        // Rendering progressively in passes doesn't change it either
+        let passes = FarmRendererConfig { workers: vec![spawn_worker(), spawn_worker()], ..config.clone() };
+        let renderer: FarmRenderer = FarmRenderer::new(false, scene.clone(), integrator, 42, true, passes).unwrap();
+        let mut previews: Vec<usize> = Vec::new();
+        let (frame, n_passes): (Frame, usize) = renderer
+            .progressive_frame(&world, &cam, &scene.environment, Film::with_aovs(cam.dims()), None, None, NonZeroU64::new(1).unwrap(), |pass, _| previews.push(pass))
+            .unwrap();
+        assert_eq!(format!("{:?}", frame.film), format!("{:?}", expected.film));
+        assert_eq!((n_passes, previews), (2, vec![1]));
+
+        // Cancelling stops it early, but what was rendered can still be resumed
+        let cancelled = FarmRendererConfig { workers: vec![spawn_worker()], ..config.clone() };
+        let renderer: FarmRenderer = FarmRenderer::new(false, scene.clone(), integrator, 42, true, cancelled).unwrap();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let frame: Frame = renderer.resume_frame(&world, &cam, &scene.environment, Film::with_aovs(cam.dims()), None, Some(&cancel)).unwrap();
+        assert!(frame.interrupted());
+        let frame: Frame = renderer.resume_frame(&world, &cam, &scene.environment, frame.film, None, None).unwrap();
+        assert!(!frame.interrupted());
+        assert_eq!(format!("{:?}", frame.film), format!("{:?}", expected.film));
+
+        // But without any workers left, the frame can't be rendered
+        let config = FarmRendererConfig { workers: vec![spawn_dropout()], ..config };
+        let renderer: FarmRenderer = FarmRenderer::new(false, scene.clone(), integrator, 42, true, config).unwrap();
+        assert!(matches!(renderer.render_frame(&world, &cam, &scene.environment), Err(Error::AllWorkersLost { .. })));
+        assert!(matches!(FarmRenderer::new(false, scene, integrator, 42, true, FarmRendererConfig::default()), Err(Error::NoWorkers)));
+    }
+}
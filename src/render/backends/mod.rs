@@ -6,10 +6,11 @@
 //
 
 // Modules
-mod cpu;
+pub mod farm;
 pub mod multi;
 pub mod single;
 
 // Module-wide imports
+pub use farm::FarmRenderer;
 pub use multi::MultiThreadRenderer;
 pub use single::SingleThreadRenderer;
@@ -9,29 +9,36 @@
 //    Yes
 //
 //  Description:
-//!   Implements a multi-threaded renderer that re-uses the
-//!   single-threaded renderer.
+//!   Implements a multi-threaded renderer that splits the frame into
+//!   tiles, which are distributed over the threads with work stealing.
 //
 
 use std::error;
 use std::fmt::{Display, Formatter, Result as FResult};
 use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::ScopedJoinHandle;
 use std::time::Instant;
 
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
 use indicatif::{ProgressBar, ProgressStyle};
-use log::info;
+use log::{info, warn};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 
-use super::super::RayRenderer;
-use super::super::image::Image;
-use super::cpu::ray_colour;
+use super::super::cancel::CancelToken;
+use super::super::checkpoint::Checkpointer;
+use super::super::integrators::{AnyIntegrator, Integrator};
+use super::super::lights::LightList;
+use super::super::pixel::{Film, PixelStats, render_pixel};
+use super::super::tiles::{Tile, TileOrder, tiles};
+use super::super::tui::Tui;
+use super::super::{Frame, RayRenderer};
 use crate::common::file::{impl_toml_from_path, impl_toml_from_string, impl_toml_to_path, impl_toml_to_string};
+use crate::common::input::Region;
 use crate::hittree::HitTree;
-use crate::math::camera::Rays;
-use crate::math::{Camera, Colour, Ray};
+use crate::math::Camera;
 use crate::specifications::scene::Environment;
 
 
@@ -63,19 +70,47 @@ impl error::Error for Error {
 
 
 
+/***** HELPER FUNCTIONS *****/
+/// Finds the next tile for a thread to render.
+///
+/// It first tries its own queue, then steals a batch from the global queue, and finally tries
+/// to steal from the other threads.
+///
+/// # Arguments
+/// - `local`: The thread's own queue of tiles.
+/// - `global`: The global queue of tiles that haven't been handed out yet.
+/// - `stealers`: Handles to the queues of all threads.
+///
+/// # Returns
+/// The next [`Tile`] to render, or [`None`] if there are none left anywhere.
+fn find_tile(local: &Worker<Tile>, global: &Injector<Tile>, stealers: &[Stealer<Tile>]) -> Option<Tile> {
+    local.pop().or_else(|| {
+        std::iter::repeat_with(|| global.steal_batch_and_pop(local).or_else(|| stealers.iter().map(Stealer::steal).collect()))
+            .find(|s| !s.is_retry())
+            .and_then(Steal::success)
+    })
+}
+
+
+
+
+
 /***** AUXILLARY *****/
 /// Defines the configuration options for the multi-threaded renderer.
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct MultiThreadRendererConfig {
     /// Defines the number of threads to spawn. If omitted, uses the number reported by `std::thread::available_parallelism()`.
-    n_threads: Option<NonZeroUsize>,
-    /// Defines the workload size to send to each thread everytime they finished rendering the previous one.
-    work_size: usize,
+    n_threads:  Option<NonZeroUsize>,
+    /// Defines the width and height of the tiles that are handed to the threads.
+    tile_size:  u32,
+    /// Defines the order in which the tiles are rendered.
+    tile_order: TileOrder,
 }
 
 impl Default for MultiThreadRendererConfig {
     #[inline]
-    fn default() -> Self { Self { n_threads: None, work_size: 64 } }
+    fn default() -> Self { Self { n_threads: None, tile_size: 32, tile_order: TileOrder::default() } }
 }
 impl MultiThreadRendererConfig {
     impl_toml_from_string!();
@@ -89,28 +124,35 @@ impl MultiThreadRendererConfig {
 
 
 /***** LIBRARY *****/
-/// The SingleThreadRenderer renders rays on multiple threads at once.
+/// The MultiThreadRenderer renders tiles of the frame on multiple threads at once.
 #[derive(Debug)]
-pub struct MultiThreadRenderer {
+pub struct MultiThreadRenderer<I = AnyIntegrator> {
     /// Whether to enable or disable the progress bar.
     show_prgs: bool,
-    /// The maximum bouncing depth.
-    max_depth: usize,
+    /// The integrator that computes the colour of every ray.
+    integrator: I,
+    /// The seed from which all random numbers are derived.
+    seed: u64,
     /// Whether to apply gamma correction.
     gamma_correction: bool,
 
     /// The number of threads to render with.
     n_threads: usize,
-    /// The number of rays to send to each thread every time they need work.
-    work_size: usize,
+    /// The width and height of the tiles to send to each thread.
+    tile_size:  u32,
+    /// The order in which the tiles are rendered.
+    tile_order: TileOrder,
+    /// If given, draws a live preview of the frame in the terminal.
+    tui:        Option<Arc<Tui>>,
 }
 
-impl MultiThreadRenderer {
+impl<I> MultiThreadRenderer<I> {
     /// Constructor for the MultiThreadRenderer.
     ///
     /// # Arguments
     /// - `show_prgs`: Whether or not to show the progress as we're rendering.
-    /// - `max_depth`: The maximum bounce depth for rays.
+    /// - `integrator`: The [`Integrator`] that computes the colour of every ray.
+    /// - `seed`: The seed from which all random numbers are derived. The same seed always renders the same image, regardless of the number of threads.
     /// - `gamma_correction`: Whether to apply gamma correction to the final image.
     /// - `config`: Any MultiThreadRenderer-specific config.
     ///
@@ -120,7 +162,7 @@ impl MultiThreadRenderer {
     /// # Errors
     /// This function may error if the user left the number of threads unspecified and we failed to query the number ourselves.
     #[inline]
-    pub fn new(show_prgs: bool, max_depth: usize, gamma_correction: bool, config: impl Into<MultiThreadRendererConfig>) -> Result<Self, Error> {
+    pub fn new(show_prgs: bool, integrator: I, seed: u64, gamma_correction: bool, config: impl Into<MultiThreadRendererConfig>) -> Result<Self, Error> {
         // Resolve the number of threads first
         let config = config.into();
         let n_threads: usize = match config.n_threads {
@@ -134,121 +176,201 @@ impl MultiThreadRenderer {
         };
 
         // Done
-        Ok(Self { show_prgs, max_depth, gamma_correction, n_threads, work_size: config.work_size })
+        Ok(Self { show_prgs, integrator, seed, gamma_correction, n_threads, tile_size: config.tile_size, tile_order: config.tile_order, tui: None })
+    }
+
+    /// Draws a live preview of the frame in the terminal while rendering.
+    ///
+    /// Since both draw on the terminal, this hides the progress bar if a [`Tui`] is given.
+    ///
+    /// # Arguments
+    /// - `tui`: The [`Tui`] to draw on, or [`None`] to not draw anything.
+    ///
+    /// # Returns
+    /// The same MultiThreadRenderer, but drawing on the given [`Tui`].
+    #[inline]
+    pub fn with_tui(mut self, tui: Option<Arc<Tui>>) -> Self {
+        self.show_prgs &= tui.is_none();
+        self.tui = tui;
+        self
     }
 }
-impl RayRenderer for MultiThreadRenderer {
+impl<I: Integrator + Sync> RayRenderer for MultiThreadRenderer<I> {
     type Error = std::convert::Infallible;
 
-    fn render_frame(&self, world: &HitTree, cam: &Camera, env: &Environment) -> Result<crate::render::image::Image, Self::Error> {
+    fn resume_frame(
+        &self,
+        world: &HitTree,
+        cam: &Camera,
+        env: &Environment,
+        film: Film,
+        checkpoints: Option<&Checkpointer>,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Frame, Self::Error> {
         info!("Rendering scene ({} objects)...", world.len());
 
-        // Let us define the camera (static, for now)
-        let dims: (u32, u32) = cam.dims();
-
-        // Now have the threads each do chunk of rays, popping them off the main queue
-        std::thread::scope(|s| {
-            let start: Instant = Instant::now();
-
-            // Define the main queue of rays & the progress bar
-            let queue: Arc<Mutex<(Rays, Option<(Instant, ProgressBar)>)>> = Arc::new(Mutex::new((
-                cam.rays(0),
-                if self.show_prgs {
-                    Some((
-                        Instant::now(),
-                        ProgressBar::new(dims.0 as u64 * dims.1 as u64 * cam.n_samples()).with_style(
-                            ProgressStyle::with_template(" Ray {human_pos}/{human_len} [{wide_bar}] {percent}% {elapsed} (ETA {eta}) ")
-                                .unwrap_or_else(|err| panic!("Invalid template given to progress bar: {err}"))
-                                .progress_chars("=> "),
-                        ),
-                    ))
-                } else {
-                    None
-                },
-            )));
+        // Find the lights to sample directly
+        let lights: LightList = LightList::collect(world);
+        info!("Found {} light(s) to sample directly", lights.len());
+        let lights: &LightList = &lights;
+
+        // Let us define the camera (static, for now), which may only want to render a region of the film
+        assert_eq!(film.dims(), cam.dims(), "Film and camera have different dimensions");
+        let region: Region = cam.region();
+        let dims: (u32, u32) = region.dims();
+        if let Some(tui) = &self.tui {
+            tui.start_pass(dims.0 as u64 * dims.1 as u64);
+        }
 
-            // Split one set of rays for every thread
-            let handles: Vec<ScopedJoinHandle<Image>> = (0..self.n_threads)
-                .map(|_| {
-                    // Spawn a thread that does this iterator
-                    let queue = queue.clone();
+        // Split the region into tiles, which we hand out to the threads through the global queue
+        let global: Injector<Tile> = Injector::new();
+        for tile in tiles(dims, self.tile_size, self.tile_order) {
+            global.push(Tile { pos: (region.x0 + tile.pos.0, region.y0 + tile.pos.1), ..tile });
+        }
+        let locals: Vec<Worker<Tile>> = (0..self.n_threads).map(|_| Worker::new_fifo()).collect();
+        let stealers: Vec<Stealer<Tile>> = locals.iter().map(Worker::stealer).collect();
+
+        // Prepare the output film & progress bar
+        let output: Mutex<Film> = Mutex::new(film);
+        let prgs: Option<ProgressBar> = if self.show_prgs {
+            Some(
+                ProgressBar::new(dims.0 as u64 * dims.1 as u64).with_style(
+                    ProgressStyle::with_template(" Pixel {human_pos}/{human_len} [{wide_bar}] {percent}% {elapsed} (ETA {eta}) ")
+                        .unwrap_or_else(|err| panic!("Invalid template given to progress bar: {err}"))
+                        .progress_chars("=> "),
+                ),
+            )
+        } else {
+            None
+        };
+
+        // Now have the threads each render tiles until there are none left (or we're cancelled)
+        let start: Instant = Instant::now();
+        let interrupted: AtomicBool = AtomicBool::new(false);
+        let n_rays: u64 = std::thread::scope(|s| {
+            let handles: Vec<ScopedJoinHandle<u64>> = locals
+                .into_iter()
+                .map(|local| {
+                    // Only move references to the shared state into the thread
+                    let (global, stealers, output, prgs, interrupted) = (&global, &stealers, &output, &prgs, &interrupted);
                     s.spawn(move || {
-                        // Prepare this local thread's frame to render to
-                        let mut count: u64 = 0;
-                        let mut image = Image::new(dims);
-                        let mut buf: Vec<(u64, u32, u32, Ray)> = Vec::with_capacity(self.work_size);
-
-                        // Keep popping work until all pixels are computed
-                        loop {
-                            // Pop a chunk of rays to render
-                            {
-                                let mut lock = queue.lock();
-                                buf.extend((&mut lock.0).take(self.work_size));
-                                if buf.is_empty() {
-                                    // Done, nothing to render anymore
-                                    break image;
-                                }
+                        let mut n_rays: u64 = 0;
+                        while let Some(tile) = find_tile(&local, global, stealers) {
+                            if cancel.is_some_and(CancelToken::is_cancelled) {
+                                interrupted.store(true, Ordering::Relaxed);
+                                break;
+                            }
 
-                                // Update the progress bar with what we're going to do
-                                if let Some(prgs) = &mut lock.1 {
-                                    if prgs.0.elapsed().as_millis() >= 500 {
-                                        prgs.1.inc(count);
-                                        prgs.0 += std::time::Duration::from_millis(500);
-                                        count = 0;
+                            // Find where we left off, if anywhere
+                            let (starts, mut film): (Vec<PixelStats>, Film) = {
+                                let lock = output.lock();
+                                (tile.pixels().map(|pixel| lock.get(pixel)).collect(), lock.empty_like(tile.dims).with_margin(cam.filter().margin()))
+                            };
+
+                            // Render the tile to a local film first
+                            let mut tile_rays: u64 = 0;
+                            for ((x, y), start) in tile.pixels().zip(starts) {
+                                let at: (u32, u32) = (x - tile.pos.0, y - tile.pos.1);
+                                let stats: PixelStats = render_pixel(&self.integrator, cam, (x, y), self.seed, start, &mut film, at, world, lights, env);
+                                film.set(at, stats);
+                                tile_rays += stats.n() - start.n();
+                            }
+                            n_rays += tile_rays;
+
+                            // Then write it to the shared one (and copy it if we have to checkpoint, which we do without holding up the others)
+                            let snapshot: Option<Film> = {
+                                let mut lock = output.lock();
+                                lock.move_into(film, tile.pos);
+                                if let Some(tui) = &self.tui {
+                                    tui.progress(tile.dims.0 as u64 * tile.dims.1 as u64, tile_rays);
+                                    if tui.due() {
+                                        tui.snapshot(&lock);
                                     }
                                 }
+                                checkpoints.filter(|checkpoints| checkpoints.due()).map(|_| lock.clone())
+                            };
+                            if let (Some(checkpoints), Some(snapshot)) = (checkpoints, snapshot) {
+                                if let Err(err) = checkpoints.write(&snapshot) {
+                                    warn!("Failed to write checkpoint: {err}");
+                                }
                             }
-
-                            // Iterate over the allocated rays to compute them
-                            for (_, x, y, ray) in buf.drain(..) {
-                                // Compute the colour of the Ray
-                                let colour: Colour = ray_colour(ray, world, self.max_depth, env);
-
-                                // Add the colour to the image.
-                                image[(x, y)] += colour;
-
-                                // Done this ray
-                                count += 1;
+                            if let Some(prgs) = prgs {
+                                prgs.inc(tile.dims.0 as u64 * tile.dims.1 as u64);
                             }
                         }
+                        n_rays
                     })
                 })
                 .collect();
 
             // Await them
-            let mut res: Option<Image> = None;
+            let mut n_rays: u64 = 0;
             for (i, handle) in handles.into_iter().enumerate() {
-                let image: Image = match handle.join() {
-                    Ok(image) => image,
+                match handle.join() {
+                    Ok(rays) => n_rays += rays,
                     Err(_) => panic!("Thread {i} panicked"),
-                };
-                match &mut res {
-                    Some(res) => *res += image,
-                    None => res = Some(image),
-                }
-            }
-            let mut res: Image = res.expect("No thread completed computation");
-
-            // Fix the colours in the resulting image
-            let scale: f64 = 1.0 / cam.n_samples() as f64;
-            for colour in res.iter_mut() {
-                *colour *= scale;
-                if self.gamma_correction {
-                    *colour = colour.gamma();
                 }
-                *colour = colour.opaque().clamp();
             }
+            n_rays
+        });
+        let interrupted: bool = interrupted.into_inner();
+        if interrupted {
+            info!("Rendering cancelled");
+        }
+        let film: Film = output.into_inner();
+        if let Some(tui) = &self.tui {
+            tui.snapshot(&film);
+        }
 
-            // Complete the progress bar
-            if let Some(prgs) = &queue.lock().1 {
-                prgs.1.finish_with_message(format!(
-                    "Done (averaged {:.2} rays/s)",
-                    (dims.0 as u64 * dims.1 as u64 * cam.n_samples()) as f64 / start.elapsed().as_secs() as f64
-                ));
-            }
+        // Complete the progress bar
+        if let Some(prgs) = prgs {
+            prgs.finish_with_message(format!(
+                "{} (averaged {:.2} rays/s)",
+                if interrupted { "Stopped" } else { "Done" },
+                n_rays as f64 / start.elapsed().as_secs() as f64
+            ));
+        }
+
+        // Fix the colours in the resulting image
+        Ok(Frame::new(film, cam, self.gamma_correction).with_interrupted(interrupted))
+    }
+}
 
-            // Done
-            Ok(res)
-        })
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use std::num::{NonZeroU32, NonZeroU64};
+
+    use super::*;
+    use crate::math::{Filter, FilterKind};
+    use crate::render::backends::SingleThreadRenderer;
+    use crate::render::integrators::PathTracer;
+    use crate::render::lights::MisHeuristic;
+    use crate::specifications::covers::{Book, cover};
+    use crate::specifications::scene::SceneFile;
+
+    #[test]
+    fn test_multi_deterministic() {
+        // Use a filter that spreads samples over neighbouring tiles
+        let mut scene: SceneFile = cover(Book::OneWeekend, 1000, 42);
+        scene.camera.dims = (NonZeroU32::new(24).unwrap(), NonZeroU32::new(16).unwrap());
+        scene.camera.n_samples = NonZeroU64::new(2).unwrap();
+        scene.camera.filter = Filter { kind: FilterKind::Gaussian, radius: None };
+        let integrator: AnyIntegrator = PathTracer { max_depth: 8, roulette_depth: 3, roulette_clamp: 0.95, mis: MisHeuristic::Power }.into();
+        let cam: Camera = Camera::from(scene.camera);
+        let world: HitTree = HitTree::with_objs(scene.objects, (0..=scene.camera.shutter_time.into()).into());
+        let expected: Frame = SingleThreadRenderer::new(false, integrator, 42, true).render_frame(&world, &cam, &scene.environment).unwrap();
+
+        // The result is the same to the bit, no matter how many threads render it
+        for n_threads in [1, 4] {
+            let config = MultiThreadRendererConfig { n_threads: NonZeroUsize::new(n_threads), tile_size: 4, tile_order: TileOrder::Spiral };
+            let renderer = MultiThreadRenderer::new(false, integrator, 42, true, config).unwrap();
+            let frame: Frame = renderer.render_frame(&world, &cam, &scene.environment).unwrap();
+            assert_eq!(format!("{:?}", frame.film), format!("{:?}", expected.film), "{n_threads} thread(s)");
+        }
     }
 }
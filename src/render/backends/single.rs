@@ -12,61 +12,105 @@
 //!   Defines the single-threaded renderer.
 //
 
+use std::sync::Arc;
 use std::time::Instant;
 
 use indicatif::{ProgressBar, ProgressStyle};
-use log::info;
+use log::{info, warn};
 
-use super::super::RayRenderer;
-use super::super::image::Image;
-use super::cpu::ray_colour;
+use super::super::cancel::CancelToken;
+use super::super::checkpoint::Checkpointer;
+use super::super::integrators::{AnyIntegrator, Integrator};
+use super::super::lights::LightList;
+use super::super::pixel::{Film, PixelStats, render_pixel};
+use super::super::tui::Tui;
+use super::super::{Frame, RayRenderer};
+use crate::common::input::Region;
 use crate::hittree::HitTree;
 use crate::math::camera::Camera;
-use crate::math::colour::Colour;
 use crate::specifications::scene::Environment;
 
 
 /***** LIBRARY *****/
 /// The SingleThreadRenderer renders rays straightforwardly on a single thread, no fuss.
 #[derive(Debug)]
-pub struct SingleThreadRenderer {
+pub struct SingleThreadRenderer<I = AnyIntegrator> {
     /// Whether to enable or disable the progress bar.
     show_prgs: bool,
-    /// The maximum bouncing depth.
-    max_depth: usize,
+    /// The integrator that computes the colour of every ray.
+    integrator: I,
+    /// The seed from which all random numbers are derived.
+    seed: u64,
     /// Whether to apply gamma correction.
     gamma_correction: bool,
+    /// If given, draws a live preview of the frame in the terminal.
+    tui: Option<Arc<Tui>>,
 }
 
-impl SingleThreadRenderer {
+impl<I> SingleThreadRenderer<I> {
     /// Constructor for the SingleThreadRenderer.
     ///
     /// # Arguments
     /// - `show_prgs`: Whether or not to show the progress as we're rendering.
-    /// - `max_depth`: The maximum bounce depth for rays.
+    /// - `integrator`: The [`Integrator`] that computes the colour of every ray.
+    /// - `seed`: The seed from which all random numbers are derived. The same seed always renders the same image.
     /// - `gamma_correction`: Whether to apply gamma correction to the final image.
     ///
     /// # Returns
     /// A new SingleThreadRenderer instance.
     #[inline]
-    pub const fn new(show_prgs: bool, max_depth: usize, gamma_correction: bool) -> Self { Self { show_prgs, max_depth, gamma_correction } }
+    pub const fn new(show_prgs: bool, integrator: I, seed: u64, gamma_correction: bool) -> Self {
+        Self { show_prgs, integrator, seed, gamma_correction, tui: None }
+    }
+
+    /// Draws a live preview of the frame in the terminal while rendering.
+    ///
+    /// Since both draw on the terminal, this hides the progress bar if a [`Tui`] is given.
+    ///
+    /// # Arguments
+    /// - `tui`: The [`Tui`] to draw on, or [`None`] to not draw anything.
+    ///
+    /// # Returns
+    /// The same SingleThreadRenderer, but drawing on the given [`Tui`].
+    #[inline]
+    pub fn with_tui(mut self, tui: Option<Arc<Tui>>) -> Self {
+        self.show_prgs &= tui.is_none();
+        self.tui = tui;
+        self
+    }
 }
-impl RayRenderer for SingleThreadRenderer {
+impl<I: Integrator> RayRenderer for SingleThreadRenderer<I> {
     type Error = std::convert::Infallible;
 
-    fn render_frame(&self, world: &HitTree, cam: &Camera, env: &Environment) -> Result<crate::render::image::Image, Self::Error> {
+    fn resume_frame(
+        &self,
+        world: &HitTree,
+        cam: &Camera,
+        env: &Environment,
+        mut film: Film,
+        checkpoints: Option<&Checkpointer>,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Frame, Self::Error> {
         info!("Rendering scene ({} objects)...", world.len());
 
-        // Create the image to render
-        let dims: (u32, u32) = cam.dims();
-        let mut image: Image = Image::new(dims);
+        // Find the lights to sample directly
+        let lights: LightList = LightList::collect(world);
+        info!("Found {} light(s) to sample directly", lights.len());
+
+        // We render to the given film, but only the region the camera wants
+        assert_eq!(film.dims(), cam.dims(), "Film and camera have different dimensions");
+        let region: Region = cam.region();
+        let dims: (u32, u32) = region.dims();
+        if let Some(tui) = &self.tui {
+            tui.start_pass(dims.0 as u64 * dims.1 as u64);
+        }
 
         // Prepare the progressbar if desired
         let mut prgs: Option<(Instant, ProgressBar)> = if self.show_prgs {
             Some((
                 Instant::now(),
-                ProgressBar::new(dims.0 as u64 * dims.1 as u64 * cam.n_samples()).with_style(
-                    ProgressStyle::with_template(" Ray {human_pos}/{human_len} [{wide_bar}] {percent}% {elapsed} (ETA {eta}) ")
+                ProgressBar::new(dims.0 as u64 * dims.1 as u64).with_style(
+                    ProgressStyle::with_template(" Pixel {human_pos}/{human_len} [{wide_bar}] {percent}% {elapsed} (ETA {eta}) ")
                         .unwrap_or_else(|err| panic!("Invalid template given to progress bar: {err}"))
                         .progress_chars("=> "),
                 ),
@@ -77,14 +121,35 @@ impl RayRenderer for SingleThreadRenderer {
 
         // Let us fire all the rays (we go top-to-bottom)
         let start: Instant = Instant::now();
-        for (i, (_, x, y, ray)) in cam.rays(0).enumerate() {
-            // Compute the colour of the Ray
-            let colour: Colour = ray_colour(ray, world, self.max_depth, env);
+        let mut n_rays: u64 = 0;
+        let mut interrupted: bool = false;
+        for (i, (x, y)) in region.pixels().enumerate() {
+            if cancel.is_some_and(CancelToken::is_cancelled) {
+                info!("Rendering cancelled after {i} of {} pixel(s)", dims.0 as u64 * dims.1 as u64);
+                interrupted = true;
+                break;
+            }
 
-            // Add the colour to the image.
-            image[(x, y)] += colour;
+            // Compute the colour of the pixel
+            let prev: PixelStats = film.get((x, y));
+            let stats: PixelStats = render_pixel(&self.integrator, cam, (x, y), self.seed, prev, &mut film, (x, y), world, &lights, env);
+            film.set((x, y), stats);
+            n_rays += stats.n() - prev.n();
+            if let Some(tui) = &self.tui {
+                tui.progress(1, stats.n() - prev.n());
+                if tui.due() {
+                    tui.snapshot(&film);
+                }
+            }
+            if let Some(checkpoints) = checkpoints {
+                if checkpoints.due() {
+                    if let Err(err) = checkpoints.write(&film) {
+                        warn!("Failed to write checkpoint: {err}");
+                    }
+                }
+            }
 
-            // Computed a ray!
+            // Computed a pixel!
             if let Some(prgs) = &mut prgs {
                 if prgs.0.elapsed().as_millis() >= 500 {
                     prgs.1.update(|state| state.set_pos(i as u64));
@@ -93,24 +158,18 @@ impl RayRenderer for SingleThreadRenderer {
             }
         }
 
-        // Fix the final pixel values
-        let scale: f64 = 1.0 / cam.n_samples() as f64;
-        for colour in image.iter_mut() {
-            *colour *= scale;
-            if self.gamma_correction {
-                *colour = colour.gamma();
-            }
-            *colour = colour.opaque().clamp();
-        }
-
         if let Some(prgs) = prgs {
             prgs.1.finish_with_message(format!(
-                "Done (averaged {:.2} rays/s)",
-                (dims.0 as u64 * dims.1 as u64 * cam.n_samples()) as f64 / start.elapsed().as_secs() as f64
+                "{} (averaged {:.2} rays/s)",
+                if interrupted { "Stopped" } else { "Done" },
+                n_rays as f64 / start.elapsed().as_secs() as f64
             ));
         }
 
-        // Done
-        Ok(image)
+        // Fix the colours in the resulting image
+        if let Some(tui) = &self.tui {
+            tui.snapshot(&film);
+        }
+        Ok(Frame::new(film, cam, self.gamma_correction).with_interrupted(interrupted))
     }
 }
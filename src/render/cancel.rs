@@ -0,0 +1,94 @@
+//  CANCEL.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the [`CancelToken`], with which a render can be stopped
+//!   early (e.g., because it ran out of time, or the user pressed Ctrl-C).
+//!
+//!   Backends check it before every piece of work they hand out. Once it
+//!   is cancelled, they finish what they are doing and return the frame
+//!   rendered so far, in which every pixel is averaged over the samples it
+//!   actually got.
+//
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+
+/***** LIBRARY *****/
+/// A token that tells a renderer to stop rendering.
+///
+/// Clones share their state, so any of them can be used to cancel all others.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    /// Whether the token was cancelled explicitly.
+    cancelled: Arc<AtomicBool>,
+    /// If given, the moment after which the token counts as cancelled.
+    deadline:  Option<Instant>,
+}
+impl CancelToken {
+    /// Constructor for a CancelToken that isn't cancelled until [`CancelToken::cancel()`] is called.
+    ///
+    /// # Returns
+    /// A new CancelToken.
+    #[inline]
+    pub fn new() -> Self { Self::default() }
+
+    /// Additionally cancels the token once some time has passed.
+    ///
+    /// # Arguments
+    /// - `limit`: The time (from now) after which the token counts as cancelled.
+    ///
+    /// # Returns
+    /// The same CancelToken, but with a deadline.
+    #[inline]
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.deadline = Instant::now().checked_add(limit);
+        self
+    }
+
+    /// Cancels the token, and all its clones.
+    #[inline]
+    pub fn cancel(&self) { self.cancelled.store(true, Ordering::Relaxed); }
+
+    /// Returns whether the token was cancelled, either explicitly or because its time ran out.
+    ///
+    /// # Returns
+    /// True if rendering should stop, or false otherwise.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::Relaxed) || self.timed_out() }
+
+    /// Returns whether the token's time ran out.
+    ///
+    /// # Returns
+    /// True if it has a deadline and that has passed, or false otherwise.
+    #[inline]
+    pub fn timed_out(&self) -> bool { self.deadline.is_some_and(|deadline| Instant::now() >= deadline) }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let clone: CancelToken = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(!clone.timed_out());
+
+        // Deadlines cancel by themselves
+        assert!(!CancelToken::new().with_time_limit(Duration::from_secs(3600)).is_cancelled());
+        let token = CancelToken::new().with_time_limit(Duration::ZERO);
+        assert!(token.is_cancelled());
+        assert!(token.timed_out());
+    }
+}
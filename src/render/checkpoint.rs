@@ -0,0 +1,262 @@
+//  CHECKPOINT.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines [`Checkpoint`]s, which store a partially rendered frame on
+//!   disk such that rendering can be continued later (e.g., after a
+//!   crash, or to add more samples to it).
+//
+
+use std::collections::BTreeMap;
+use std::error;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use super::integrators::AnyIntegrator;
+use super::pixel::Film;
+use crate::common::file::{impl_toml_from_path, impl_toml_from_string};
+use crate::specifications::covers::Book;
+use crate::specifications::objects::Object;
+use crate::specifications::scene::{CameraInfo, Environment};
+
+
+/***** ERRORS *****/
+/// Defines errors that may occur when writing checkpoints.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to create the temporary file to write the checkpoint to.
+    Create { path: PathBuf, err: std::io::Error },
+    /// Failed to write the checkpoint to the temporary file.
+    Write { path: PathBuf, err: serde_json::Error },
+    /// Failed to move the temporary file over the checkpoint.
+    Rename { from: PathBuf, to: PathBuf, err: std::io::Error },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use Error::*;
+        match self {
+            Create { path, .. } => write!(f, "Failed to create checkpoint file '{}'", path.display()),
+            Write { path, .. } => write!(f, "Failed to write checkpoint to '{}'", path.display()),
+            Rename { from, to, .. } => write!(f, "Failed to move checkpoint file '{}' to '{}'", from.display(), to.display()),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+        match self {
+            Create { err, .. } => Some(err),
+            Write { err, .. } => Some(err),
+            Rename { err, .. } => Some(err),
+        }
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// A [`std::io::Write`]r that computes the FNV-1a hash of everything written to it.
+///
+/// We use this instead of the standard library's hashers because those are not guaranteed to be
+/// stable across Rust versions, and checkpoints may well outlive the binary that wrote them.
+struct Fnv1a(u64);
+impl std::io::Write for Fnv1a {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+
+
+/// Borrowed version of a [`Checkpoint`] such that we don't have to clone the film to write it.
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    info: &'a CheckpointInfo,
+    film: &'a Film,
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Computes a hash of a scene, to check whether a checkpoint still belongs to it.
+///
+/// The scene is hashed as JSON, since that doesn't depend on the Rust version either.
+///
+/// # Arguments
+/// - `objects`: The [`Object`]s in the scene, after their external references are loaded (such
+///   that changes to, e.g., model files are detected too).
+/// - `definitions`: The named [`Object`]s that the scene instances, likewise after loading.
+/// - `env`: The [`Environment`] of the scene.
+///
+/// # Returns
+/// A hash that changes whenever anything in the scene does.
+///
+/// # Errors
+/// This function errors if we failed to serialize the scene.
+pub fn scene_hash(objects: &[Object], definitions: &BTreeMap<String, Object>, env: &Environment) -> Result<u64, serde_json::Error> {
+    let mut hasher: Fnv1a = Fnv1a(0xcbf2_9ce4_8422_2325);
+    serde_json::to_writer(&mut hasher, &(objects, definitions, env))?;
+    Ok(hasher.0)
+}
+
+
+
+/// Defines where the scene of a [`Checkpoint`] came from, such that it can be rebuilt.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum SceneSource {
+    /// The scene was read from a scene file at the given path.
+    File(PathBuf),
+    /// The scene is the cover of one of the books.
+    Cover { book: Book, shutter_time: u64 },
+}
+
+/// Defines everything needed to continue rendering a [`Checkpoint`], except the samples themselves.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CheckpointInfo {
+    /// Where the rendered scene came from.
+    pub source:     SceneSource,
+    /// The [`scene_hash()`] of the scene, to detect if it changed since the checkpoint was written.
+    pub scene_hash: u64,
+    /// The seed from which all random numbers are derived. Together with the number of samples
+    /// taken for every pixel, this is the full state of the random number generators.
+    pub seed:       u64,
+    /// The camera that was used to render, with all overrides applied.
+    pub camera:     CameraInfo,
+    /// The integrator that was used to render.
+    pub integrator: AnyIntegrator,
+}
+
+/// Defines a partially rendered frame that can be continued later.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// How to continue rendering.
+    pub info: CheckpointInfo,
+    /// The samples taken so far.
+    pub film: Film,
+}
+impl Checkpoint {
+    impl_toml_from_string!();
+    impl_toml_from_path!();
+}
+
+
+
+/// Periodically writes [`Checkpoint`]s while rendering.
+#[derive(Debug)]
+pub struct Checkpointer {
+    /// The path to write the checkpoints to.
+    path:     PathBuf,
+    /// The time between two checkpoints.
+    interval: Duration,
+    /// The information to write alongside the samples.
+    info:     CheckpointInfo,
+    /// The moment the last checkpoint was written.
+    last:     Mutex<Instant>,
+    /// Held while writing a checkpoint, so that slow writes don't overlap.
+    writing:  Mutex<()>,
+}
+
+impl Checkpointer {
+    /// Constructor for the Checkpointer.
+    ///
+    /// # Arguments
+    /// - `path`: The path to write the checkpoints to. Every new checkpoint overwrites the previous one.
+    /// - `interval`: The time between two checkpoints.
+    /// - `info`: The [`CheckpointInfo`] to write alongside the samples.
+    ///
+    /// # Returns
+    /// A new Checkpointer instance.
+    #[inline]
+    pub fn new(path: impl Into<PathBuf>, interval: Duration, info: CheckpointInfo) -> Self {
+        Self { path: path.into(), interval, info, last: Mutex::new(Instant::now()), writing: Mutex::new(()) }
+    }
+
+    /// Checks whether it's time to write a new checkpoint.
+    ///
+    /// If it is, this resets the timer, such that only one caller will write it.
+    ///
+    /// # Returns
+    /// True if the caller should call [`Checkpointer::write()`], or false otherwise.
+    pub fn due(&self) -> bool {
+        let mut last = self.last.lock();
+        if last.elapsed() < self.interval {
+            return false;
+        }
+        *last = Instant::now();
+        true
+    }
+
+    /// Writes a checkpoint to disk.
+    ///
+    /// The checkpoint is first written to a temporary file next to the target, and then moved
+    /// over it. As such, a crash while writing never destroys the previous checkpoint.
+    ///
+    /// Since this may take a while for large frames, callers that share the film between threads
+    /// should write a copy of it instead of keeping it locked.
+    ///
+    /// # Arguments
+    /// - `film`: The [`Film`] with the samples taken so far.
+    ///
+    /// # Errors
+    /// This function errors if we failed to write the file.
+    pub fn write(&self, film: &Film) -> Result<(), Error> {
+        let _writing = self.writing.lock();
+        let mut tmp: OsString = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp: PathBuf = tmp.into();
+
+        // Write the temporary file
+        let handle: File = File::create(&tmp).map_err(|err| Error::Create { path: tmp.clone(), err })?;
+        let mut writer: BufWriter<File> = BufWriter::new(handle);
+        serde_json::to_writer(&mut writer, &CheckpointRef { info: &self.info, film })
+            .map_err(|err| Error::Write { path: tmp.clone(), err })?;
+        writer.flush().map_err(|err| Error::Write { path: tmp.clone(), err: serde_json::Error::io(err) })?;
+
+        // Move it over the old one
+        fs::rename(&tmp, &self.path).map_err(|err| Error::Rename { from: tmp, to: self.path.clone(), err })
+    }
+
+    /// Returns the path to which the checkpoints are written.
+    #[inline]
+    pub fn path(&self) -> &Path { &self.path }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::specifications::covers::cover;
+
+    #[test]
+    fn test_scene_hash() {
+        // The same scene always hashes the same, but any change shows
+        let scene = cover(Book::OneWeekend, 1000, 42);
+        let hash: u64 = scene_hash(&scene.objects, &scene.definitions, &scene.environment).unwrap();
+        assert_eq!(hash, scene_hash(&cover(Book::OneWeekend, 1000, 42).objects, &scene.definitions, &scene.environment).unwrap());
+        assert_ne!(hash, scene_hash(&cover(Book::OneWeekend, 1000, 43).objects, &scene.definitions, &scene.environment).unwrap());
+        assert_ne!(hash, scene_hash(&scene.objects[1..], &scene.definitions, &scene.environment).unwrap());
+        let definitions: BTreeMap<String, Object> = BTreeMap::from([("ball".into(), scene.objects[1].clone())]);
+        assert_ne!(hash, scene_hash(&scene.objects, &definitions, &scene.environment).unwrap());
+    }
+}
@@ -0,0 +1,234 @@
+//  DENOISE.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements a denoiser that runs on the CPU after a frame has been
+//!   rendered.
+//!
+//!   It's an edge-avoiding À-Trous wavelet filter (Dammertz et al., 2010):
+//!   the image is repeatedly blurred with a 5x5 kernel whose taps spread
+//!   out further every iteration, where every tap is weighted by how much
+//!   its colour, albedo and normal resemble those of the pixel that is
+//!   filtered. That way, noise is smoothed away without blurring over the
+//!   edges of objects, textures or shadows.
+//
+
+use super::image::Image;
+use super::Frame;
+use crate::math::Colour;
+
+
+/***** CONSTANTS *****/
+/// The weights of the taps of the (1D) B3-spline kernel that is spread out every iteration.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Added to the albedo before dividing it out of the colour, to avoid dividing by zero for black
+/// materials.
+const ALBEDO_EPSILON: f64 = 0.01;
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns the squared distance between the RGB-channels of two colours.
+#[inline]
+fn distance2(lhs: &Colour, rhs: &Colour) -> f64 { (lhs.r - rhs.r).powi(2) + (lhs.g - rhs.g).powi(2) + (lhs.b - rhs.b).powi(2) }
+
+/// Compresses a linear colour into the `[0, 1)` range, such that differences between bright
+/// colours don't dwarf those between dark ones.
+#[inline]
+fn compress(colour: &Colour) -> Colour { Colour::new(colour.r / (1.0 + colour.r), colour.g / (1.0 + colour.g), colour.b / (1.0 + colour.b), colour.a) }
+
+/// Computes the weight of a guide (albedo or normals) for a pair of pixels.
+///
+/// # Arguments
+/// - `guide`: The guide, if any.
+/// - `p`: The index of the pixel that is filtered.
+/// - `q`: The index of the tap.
+/// - `sigma`: How much the guide may differ before the weight drops off.
+///
+/// # Returns
+/// The weight, which is zero if only one of the two pixels hit anything.
+#[inline]
+fn guide_weight(guide: Option<&Image>, p: usize, q: usize, sigma: f64) -> f64 {
+    let Some(guide) = guide else { return 1.0 };
+    let (gp, gq): (&Colour, &Colour) = (guide.at(p), guide.at(q));
+    match (gp.a > 0.0, gq.a > 0.0) {
+        (true, true) => (-distance2(gp, gq) / (sigma * sigma)).exp(),
+        (false, false) => 1.0,
+        _ => 0.0,
+    }
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Denoises rendered images, optionally guided by their albedo and normal [`Aov`](super::aov::Aov)s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    /// The number of iterations of the filter. Every iteration doubles the distance between its
+    /// taps, so the filter reaches `2^(iterations + 1)` pixels far.
+    pub iterations:   usize,
+    /// How much colours may differ before they stop being averaged. This halves every iteration.
+    pub colour_sigma: f64,
+    /// How much normals may differ before they stop being averaged.
+    pub normal_sigma: f64,
+    /// How much albedos may differ before they stop being averaged.
+    pub albedo_sigma: f64,
+}
+impl Default for Denoiser {
+    #[inline]
+    fn default() -> Self { Self { iterations: 5, colour_sigma: 0.25, normal_sigma: 0.3, albedo_sigma: 0.1 } }
+}
+impl Denoiser {
+    /// Denoises an image.
+    ///
+    /// If an albedo is given, it is divided out of the colour before filtering and multiplied
+    /// back in afterwards, so that textures stay sharp. Pixels that are transparent (i.e., weren't
+    /// rendered) are left alone, and aren't used to filter the others.
+    ///
+    /// # Arguments
+    /// - `image`: The (linear) [`Image`] to denoise.
+    /// - `albedo`: The raw albedo of every pixel (see [`Film::aov()`](super::pixel::Film::aov())), if any.
+    /// - `normal`: The raw normal of every pixel, if any.
+    ///
+    /// # Returns
+    /// A new (linear) [`Image`] with the noise filtered out.
+    ///
+    /// # Panics
+    /// This function panics if the guides don't have the same dimensions as the image.
+    pub fn denoise(&self, image: &Image, albedo: Option<&Image>, normal: Option<&Image>) -> Image {
+        for guide in [albedo, normal].into_iter().flatten() {
+            if guide.dims() != image.dims() {
+                panic!(
+                    "Guide of {}x{} pixels does not match image of {}x{} pixels",
+                    guide.dims().0,
+                    guide.dims().1,
+                    image.dims().0,
+                    image.dims().1
+                );
+            }
+        }
+        let (width, height): (i64, i64) = (image.width() as i64, image.height() as i64);
+
+        // Divide out the albedo wherever we know it
+        let modulation = |i: usize| -> Colour {
+            match albedo.map(|albedo| albedo.at(i)) {
+                Some(albedo) if albedo.a > 0.0 => Colour::new(albedo.r + ALBEDO_EPSILON, albedo.g + ALBEDO_EPSILON, albedo.b + ALBEDO_EPSILON, 1.0),
+                _ => Colour::new(1.0, 1.0, 1.0, 1.0),
+            }
+        };
+        let mut current: Image = image.clone();
+        for (i, colour) in current.iter_mut().enumerate() {
+            *colour /= modulation(i);
+        }
+
+        // Run the iterations
+        let mut next: Image = Image::new(image.dims());
+        for iteration in 0..self.iterations {
+            let step: i64 = 1 << iteration;
+            let colour_sigma: f64 = self.colour_sigma / (1u64 << iteration) as f64;
+            for y in 0..height {
+                for x in 0..width {
+                    let p: usize = (y * width + x) as usize;
+                    let centre: Colour = *current.at(p);
+                    if centre.a <= 0.0 {
+                        *next.at_mut(p) = centre;
+                        continue;
+                    }
+                    let compressed: Colour = compress(&centre);
+
+                    // Sum the taps, weighted by the kernel and how similar they are
+                    let (mut sum, mut total): (Colour, f64) = (Colour::zeroes(), 0.0);
+                    for (ky, wy) in KERNEL.iter().enumerate() {
+                        let qy: i64 = y + (ky as i64 - 2) * step;
+                        if qy < 0 || qy >= height {
+                            continue;
+                        }
+                        for (kx, wx) in KERNEL.iter().enumerate() {
+                            let qx: i64 = x + (kx as i64 - 2) * step;
+                            if qx < 0 || qx >= width {
+                                continue;
+                            }
+                            let q: usize = (qy * width + qx) as usize;
+                            let tap: &Colour = current.at(q);
+                            if tap.a <= 0.0 {
+                                continue;
+                            }
+
+                            let weight: f64 = wx
+                                * wy
+                                * (-distance2(&compressed, &compress(tap)) / (colour_sigma * colour_sigma)).exp()
+                                * guide_weight(albedo, p, q, self.albedo_sigma)
+                                * guide_weight(normal, p, q, self.normal_sigma);
+                            sum += *tap * weight;
+                            total += weight;
+                        }
+                    }
+                    // The centre tap always has a positive weight, so total is never zero
+                    *next.at_mut(p) = Colour { a: centre.a, ..sum * (1.0 / total) };
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+
+        // Multiply the albedo back in
+        for (i, colour) in current.iter_mut().enumerate() {
+            *colour *= modulation(i);
+        }
+        current
+    }
+
+    /// Denoises the image of a rendered [`Frame`].
+    ///
+    /// The frame's [linear](Frame::linear()) image is denoised, guided by its albedo and normal
+    /// buffers if it has them. The result replaces both it and the frame's [`Frame::BEAUTY`]
+    /// buffer.
+    ///
+    /// # Arguments
+    /// - `frame`: The [`Frame`] to denoise.
+    pub fn denoise_frame(&self, frame: &mut Frame) {
+        let linear: Image = self.denoise(frame.linear(), frame.get("albedo"), frame.get("normal"));
+        frame.set_linear(linear);
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Rng;
+
+    #[test]
+    fn test_denoise() {
+        // Make a noisy image of two flat halves with different normals
+        let mut rng = Rng::with_seed(42);
+        let (mut image, mut normal): (Image, Image) = (Image::new((32u32, 32u32)), Image::new((32u32, 32u32)));
+        for y in 0..32u32 {
+            for x in 0..32u32 {
+                let base: f64 = if x < 16 { 0.2 } else { 0.8 };
+                let noise: f64 = rng.f64() - 0.5;
+                image[(x, y)] = Colour::new(base + noise * 0.2, base + noise * 0.2, base + noise * 0.2, 1.0);
+                normal[(x, y)] = if x < 16 { Colour::new(1.0, 0.0, 0.0, 1.0) } else { Colour::new(0.0, 1.0, 0.0, 1.0) };
+            }
+        }
+        image[(5u32, 5u32)] = Colour::zeroes();
+
+        // The noise should be reduced, but the edge and unrendered pixels left alone
+        let denoised: Image = Denoiser::default().denoise(&image, None, Some(&normal));
+        let error = |image: &Image| -> f64 {
+            image.iter().enumerate().filter(|(_, c)| c.a > 0.0).map(|(i, c)| (c.r - if i % 32 < 16 { 0.2 } else { 0.8 }).abs()).sum()
+        };
+        assert!(error(&denoised) < error(&image) * 0.5);
+        assert!((denoised[(15u32, 10u32)].r - 0.2).abs() < 0.05);
+        assert!((denoised[(16u32, 10u32)].r - 0.8).abs() < 0.05);
+        assert_eq!(denoised[(5u32, 5u32)], Colour::zeroes());
+    }
+}
@@ -14,19 +14,20 @@
 //
 
 use std::fs;
-use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::io::{BufRead, BufReader, Cursor, Seek, SeekFrom};
 use std::ops::{AddAssign, Index, IndexMut};
 use std::path::{Path, PathBuf};
 
 use base64::Engine as _;
 use image::codecs::png::PngEncoder;
-use image::{ColorType, DynamicImage, GenericImageView, ImageFormat, Pixel, RgbaImage};
+use image::{ColorType, DynamicImage, GenericImageView, ImageFormat, Pixel, Rgb32FImage, Rgba32FImage, RgbaImage};
 use serde::de::Deserializer;
 use serde::ser::Serializer;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::math::colour::Colour;
+use crate::math::tonemap::ToneMapping;
 
 
 /***** ERRORS *****/
@@ -63,6 +64,49 @@ pub enum Error {
         #[source]
         err:  std::io::Error,
     },
+    #[error("Layer '{name}' has {}x{} pixels, but the first layer has {}x{} pixels", got.0, got.1, expected.0, expected.1)]
+    LayerDims { name: String, got: (u32, u32), expected: (u32, u32) },
+    #[error("Failed to write layers to OpenEXR file {path:?}")]
+    ToExr {
+        path: PathBuf,
+        #[source]
+        err:  exr::error::Error,
+    },
+    #[error("Failed to read layers from OpenEXR file {path:?}")]
+    FromExr {
+        path: PathBuf,
+        #[source]
+        err:  exr::error::Error,
+    },
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Makes sure the parent directory of a file we're about to write exists.
+///
+/// # Arguments
+/// - `path`: The path of the file to write to.
+/// - `fix_dirs`: Whether to create the parent directory if it doesn't exist or to error.
+///
+/// # Errors
+/// This function errors if the directory does not exist and `fix_dirs` is false, or if we failed
+/// to create it.
+fn prepare_parent(path: &Path, fix_dirs: bool) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        if !parent.exists() {
+            if fix_dirs {
+                if let Err(err) = fs::create_dir_all(parent) {
+                    return Err(Error::FixDirs { path: path.into(), err });
+                }
+            } else {
+                return Err(Error::ParentNotFound { path: parent.into() });
+            }
+        }
+    }
+    Ok(())
 }
 
 
@@ -113,9 +157,14 @@ impl Image {
         // Convert to ourselves
         let dims: (u32, u32) = image.dimensions();
         let mut pixels = Vec::with_capacity((dims.0 * dims.1) as usize);
-        for (_, _, pixel) in image.pixels() {
-            let rgba = pixel.to_rgba();
-            pixels.push(Colour::new(rgba[0] as f64 / 255.0, rgba[1] as f64 / 255.0, rgba[2] as f64 / 255.0, rgba[3] as f64 / 255.0));
+        if let DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) = image {
+            // Keep the colours of HDR images as they are
+            pixels.extend(image.to_rgba32f().pixels().map(|rgba| Colour::new(rgba[0], rgba[1], rgba[2], rgba[3])));
+        } else {
+            for (_, _, pixel) in image.pixels() {
+                let rgba = pixel.to_rgba();
+                pixels.push(Colour::new(rgba[0] as f64 / 255.0, rgba[1] as f64 / 255.0, rgba[2] as f64 / 255.0, rgba[3] as f64 / 255.0));
+            }
         }
         Ok(Self { pixels, dims })
     }
@@ -134,9 +183,13 @@ impl Image {
     /// that format.
     pub fn from_reader_auto<R: BufRead + Seek>(mut reader: R) -> Result<Self, Error> {
         // Attempt to guess the format
+        let start: u64 = reader.stream_position().map_err(Error::ReaderRead)?;
         let mut buffer: [u8; 8192] = [0; 8192];
         let buffer_len: usize = reader.read(&mut buffer).map_err(Error::ReaderRead)?;
         let fmt: ImageFormat = image::guess_format(&buffer[..buffer_len]).map_err(Error::GuessFormat)?;
+
+        // Go back to where we started before reading the image for real
+        reader.seek(SeekFrom::Start(start)).map_err(Error::ReaderRead)?;
         Self::from_reader(fmt, reader)
     }
 
@@ -246,15 +299,23 @@ impl Image {
             );
         }
 
-        // Perform the copy
-        let start: usize = position.1 as usize * self.dims.0 as usize + position.0 as usize;
-        self.pixels[start..start + other.pixels.len()].copy_from_slice(&other.pixels);
+        // Perform the copy, row-by-row (since the other image may be narrower than us)
+        let width: usize = other.dims.0 as usize;
+        for (y, row) in other.pixels.chunks_exact(width.max(1)).enumerate() {
+            let start: usize = (position.1 as usize + y) * self.dims.0 as usize + position.0 as usize;
+            self.pixels[start..start + width].copy_from_slice(row);
+        }
     }
 
 
 
     /// Writes the Image to disk using the [`image`] library.
     ///
+    /// The format is chosen by the extension of the path. OpenEXR (`.exr`) and Radiance HDR
+    /// (`.hdr`) files get the colours as they are (i.e., as unclamped 32-bit floats; see
+    /// [`Image::is_hdr_path()`]), where the latter drops the alpha channel. All other formats get
+    /// 8 bits per channel, clamped to the `[0, 1]` range.
+    ///
     /// # Arguments
     /// - `path`: The path of the file to write to.
     /// - `fix_dirs`: Whether to fix missing directories when writing or not.
@@ -265,18 +326,33 @@ impl Image {
         let path: &Path = path.as_ref();
 
         // Fix the directories, if needed and told to
-        if let Some(parent) = path.parent() {
-            if !parent.exists() {
-                if fix_dirs {
-                    if let Err(err) = fs::create_dir_all(parent) {
-                        return Err(Error::FixDirs { path: path.into(), err });
-                    }
-                } else {
-                    return Err(Error::ParentNotFound { path: parent.into() });
-                }
-            }
-        }
+        prepare_parent(path, fix_dirs)?;
+
+        // Write floats as-is to the formats that support it
+        let (width, height): (u32, u32) = self.dims;
+        let flipped = || self.pixels.chunks_exact((width as usize).max(1)).rev().flatten();
+        let res: Result<(), image::ImageError> = match ImageFormat::from_path(path) {
+            Ok(ImageFormat::OpenExr) => {
+                let samples: Vec<f32> = flipped().flat_map(|c| [c.r as f32, c.g as f32, c.b as f32, c.a as f32]).collect();
+                Rgba32FImage::from_raw(width, height, samples).expect("Buffer should fit the image").save(path)
+            },
+            Ok(ImageFormat::Hdr) => {
+                let samples: Vec<f32> = flipped().flat_map(|c| [c.r as f32, c.g as f32, c.b as f32]).collect();
+                Rgb32FImage::from_raw(width, height, samples).expect("Buffer should fit the image").save(path)
+            },
+            _ => return self.to_path_rgba8(path),
+        };
+        res.map_err(|err| Error::ToPath { path: path.into(), err })
+    }
 
+    /// Writes the Image to disk with 8 bits per channel.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the file to write to. Its parent directory must exist.
+    ///
+    /// # Errors
+    /// This function may error if we failed to create or write the file.
+    fn to_path_rgba8(&self, path: &Path) -> Result<(), Error> {
         // Cast our internal buffer to a [`Vec<u8>`]
         let mut buffer: RgbaImage = RgbaImage::new(self.dims.0 as u32, self.dims.1 as u32);
         for y in 0..self.dims.1 {
@@ -291,6 +367,163 @@ impl Image {
             Err(err) => Err(Error::ToPath { path: path.into(), err }),
         }
     }
+
+    /// Reads an Image written by [`Image::to_path()`] back from disk.
+    ///
+    /// Unlike [`Image::from_path_auto()`], this flips the rows back after reading, since images
+    /// are written to disk top-to-bottom but stored bottom-to-top.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the file to read.
+    ///
+    /// # Returns
+    /// A new Image.
+    ///
+    /// # Errors
+    /// This function errors if we couldn't read the file, guess its format or parse it.
+    pub fn from_written_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut image: Self = Self::from_path_auto(path)?;
+        let width: usize = (image.dims.0 as usize).max(1);
+        image.pixels = image.pixels.chunks_exact(width).rev().flatten().copied().collect();
+        Ok(image)
+    }
+
+    /// Returns whether [`Image::to_path()`] writes the given path as unclamped floats.
+    ///
+    /// Those formats should get linear colours, i.e., without gamma correction.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the file that would be written.
+    ///
+    /// # Returns
+    /// True if the path refers to an OpenEXR or Radiance HDR file, or false otherwise.
+    #[inline]
+    pub fn is_hdr_path(path: impl AsRef<Path>) -> bool { matches!(ImageFormat::from_path(path), Ok(ImageFormat::OpenExr | ImageFormat::Hdr)) }
+
+    /// Writes several Images as the layers of a single OpenEXR file.
+    ///
+    /// Unlike [`Image::to_path()`], this writes the colours as they are (i.e., as unclamped 32-bit
+    /// floats). Every layer gets the `R`, `G`, `B` and `A` channels.
+    ///
+    /// # Arguments
+    /// - `layers`: The Images to write, as `(name, image)`-pairs. They must all have the same dimensions.
+    /// - `path`: The path of the file to write to.
+    /// - `fix_dirs`: Whether to fix missing directories when writing or not.
+    ///
+    /// # Errors
+    /// This function may error if the layers have different dimensions, or if we failed to create
+    /// the file or its directories (if `fix_dirs` is true).
+    pub fn layers_to_path<'i>(layers: impl IntoIterator<Item = (&'i str, &'i Image)>, path: impl AsRef<Path>, fix_dirs: bool) -> Result<(), Error> {
+        use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, ImageAttributes, IntegerBounds, Layer, LayerAttributes, SmallVec, WritableImage as _};
+
+        let path: &Path = path.as_ref();
+
+        // Convert every image to a layer, flipping it like we do for other images
+        let mut dims: Option<(u32, u32)> = None;
+        let mut exr_layers: Vec<Layer<AnyChannels<FlatSamples>>> = Vec::new();
+        for (name, image) in layers {
+            let expected: (u32, u32) = *dims.get_or_insert(image.dims);
+            if image.dims != expected {
+                return Err(Error::LayerDims { name: name.into(), got: image.dims, expected });
+            }
+            let channel = |channel: &str, value: fn(&Colour) -> f64| -> AnyChannel<FlatSamples> {
+                let mut samples: Vec<f32> = Vec::with_capacity(image.pixels.len());
+                for row in image.pixels.chunks_exact((image.dims.0 as usize).max(1)).rev() {
+                    samples.extend(row.iter().map(|colour| value(colour) as f32));
+                }
+                AnyChannel::new(channel, FlatSamples::F32(samples))
+            };
+            let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> =
+                SmallVec::from_vec(vec![channel("R", |c| c.r), channel("G", |c| c.g), channel("B", |c| c.b), channel("A", |c| c.a)]);
+            exr_layers.push(Layer::new(
+                (image.dims.0 as usize, image.dims.1 as usize),
+                LayerAttributes::named(name),
+                Encoding::FAST_LOSSLESS,
+                AnyChannels::sort(channels),
+            ));
+        }
+        let dims: (u32, u32) = dims.unwrap_or((0, 0));
+
+        // Fix the directories, if needed and told to
+        prepare_parent(path, fix_dirs)?;
+
+        // Write it
+        let attrs: ImageAttributes = ImageAttributes::new(IntegerBounds::from_dimensions((dims.0 as usize, dims.1 as usize)));
+        exr::image::Image::from_layers(attrs, exr_layers).write().to_file(path).map_err(|err| Error::ToExr { path: path.into(), err })
+    }
+
+    /// Reads all layers of an OpenEXR file, e.g., as written by [`Image::layers_to_path()`].
+    ///
+    /// The colours are read as they are, and the rows are flipped back like
+    /// [`Image::from_written_path()`] does. Channels other than `R`, `G`, `B` and `A` are ignored;
+    /// missing colour channels are read as zero and a missing alpha channel as one.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the file to read.
+    ///
+    /// # Returns
+    /// The layers in the file, as `(name, image)`-pairs. Layers without a name get an empty one.
+    ///
+    /// # Errors
+    /// This function errors if we failed to read or parse the file.
+    pub fn layers_from_path(path: impl AsRef<Path>) -> Result<Vec<(String, Self)>, Error> {
+        let path: &Path = path.as_ref();
+        let file = exr::prelude::read_all_flat_layers_from_file(path).map_err(|err| Error::FromExr { path: path.into(), err })?;
+
+        let mut res: Vec<(String, Self)> = Vec::with_capacity(file.layer_data.len());
+        for layer in file.layer_data {
+            let (width, height): (usize, usize) = (layer.size.width(), layer.size.height());
+            let mut image: Self = Self::new((width as u32, height as u32));
+            image.iter_mut().for_each(|colour| colour.a = 1.0);
+            for channel in &layer.channel_data.list {
+                let i: usize = match channel.name.to_string().as_str() {
+                    "R" => 0,
+                    "G" => 1,
+                    "B" => 2,
+                    "A" => 3,
+                    _ => continue,
+                };
+                for (j, colour) in image.pixels.iter_mut().enumerate() {
+                    // Flip the rows while at it
+                    let (x, y): (usize, usize) = (j % width, j / width);
+                    colour[i] = channel.sample_data.value_by_flat_index((height - 1 - y) * width + x).to_f32() as f64;
+                }
+            }
+            res.push((layer.attributes.layer_name.map(String::from).unwrap_or_default(), image));
+        }
+        Ok(res)
+    }
+}
+
+// Colours
+impl Image {
+    /// Turns a linear Image into one to show, e.g., in an 8-bit image.
+    ///
+    /// The colours are first tone mapped and then (optionally) encoded with the sRGB transfer
+    /// function. Transparent pixels (e.g., that weren't rendered) stay transparent, and all others
+    /// become opaque.
+    ///
+    /// # Arguments
+    /// - `tone_mapping`: The [`ToneMapping`] that maps the colours to the `[0, 1]` range.
+    /// - `gamma_correction`: Whether to apply gamma correction (i.e., the sRGB transfer function).
+    ///
+    /// # Returns
+    /// A new Image with all colours in the `[0, 1]` range.
+    pub fn develop(&self, tone_mapping: ToneMapping, gamma_correction: bool) -> Self {
+        let mut res: Self = self.clone();
+        for colour in res.iter_mut() {
+            if colour.a <= 0.0 {
+                *colour = Colour::zeroes();
+                continue;
+            }
+            *colour = tone_mapping.apply(colour);
+            if gamma_correction {
+                *colour = colour.to_srgb();
+            }
+            *colour = colour.opaque().clamp();
+        }
+        res
+    }
 }
 
 // Collection stats
@@ -434,10 +667,11 @@ impl Serialize for Image {
         S: Serializer,
     {
         // Cast our internal buffer to a [`Vec<u8>`]
+        // NOTE: Keep the rows in the order we store them in, since deserializing doesn't flip them either
         let mut buffer: RgbaImage = RgbaImage::new(self.dims.0 as u32, self.dims.1 as u32);
         for y in 0..self.dims.1 {
             for x in 0..self.dims.0 {
-                buffer[(x as u32, (self.dims.1 - 1 - y) as u32)] = self.pixels[(x + self.dims.0 * y) as usize].into();
+                buffer[(x as u32, y as u32)] = self.pixels[(x + self.dims.0 * y) as usize].into();
             }
         }
         let mut image: Vec<u8> = Vec::new();
@@ -461,6 +695,60 @@ impl<'de> Deserialize<'de> for Image {
     }
 }
 
+/// Serializes and deserializes [`Image`]s losslessly, for use with `#[serde(with = "...")]`.
+///
+/// Unlike the [`Serialize`] implementation of the Image itself (which writes an 8-bit PNG), this
+/// keeps the full values of every channel (e.g., unnormalised or HDR data) as base64-encoded
+/// little-endian `f64`s.
+pub mod lossless {
+    use base64::Engine as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Image;
+    use crate::math::colour::Colour;
+
+    /// The serialized form of an [`Image`].
+    #[derive(Deserialize, Serialize)]
+    struct Raw {
+        /// The dimensions of the image.
+        dims: (u32, u32),
+        /// The base64-encoded channels of all pixels.
+        data: String,
+    }
+
+    /// Serializes an [`Image`] losslessly.
+    pub fn serialize<S: Serializer>(image: &Image, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(image.pixels.len() * 4 * 8);
+        for colour in &image.pixels {
+            for channel in [colour.r, colour.g, colour.b, colour.a] {
+                bytes.extend_from_slice(&channel.to_le_bytes());
+            }
+        }
+        Raw { dims: image.dims, data: base64::prelude::BASE64_STANDARD.encode(&bytes) }.serialize(serializer)
+    }
+
+    /// Deserializes an [`Image`] that was serialized with [`serialize()`].
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Image, D::Error> {
+        let raw: Raw = Raw::deserialize(deserializer)?;
+        let bytes: Vec<u8> = base64::prelude::BASE64_STANDARD.decode(&raw.data).map_err(serde::de::Error::custom)?;
+        let n_pixels: usize = raw.dims.0 as usize * raw.dims.1 as usize;
+        if bytes.len() != n_pixels * 4 * 8 {
+            return Err(serde::de::Error::custom(format!(
+                "Expected {} bytes for image of {}x{} pixels, got {}",
+                n_pixels * 4 * 8,
+                raw.dims.0,
+                raw.dims.1,
+                bytes.len()
+            )));
+        }
+
+        // Read the channels back
+        let channel = |i: usize| -> f64 { f64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap()) };
+        let pixels: Vec<Colour> = (0..n_pixels).map(|i| Colour::new(channel(4 * i), channel(4 * i + 1), channel(4 * i + 2), channel(4 * i + 3))).collect();
+        Ok(Image { pixels, dims: raw.dims })
+    }
+}
+
 // Iteration
 impl Image {
     /// Returns a read-only iterator over all [`Colour`]s in this Image.
@@ -492,3 +780,94 @@ impl IntoIterator for Image {
     #[inline]
     fn into_iter(self) -> Self::IntoIter { self.pixels.into_iter() }
 }
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_move_into() {
+        // Paste a 2x2 image in the middle of a 4x3 one
+        let mut image = Image::new((4u32, 3u32));
+        let mut other = Image::new((2u32, 2u32));
+        for (i, colour) in other.iter_mut().enumerate() {
+            *colour = Colour::new(i as f64 + 1.0, 0.0, 0.0, 1.0);
+        }
+        image.move_into(other, (1, 1));
+        let reds: Vec<f64> = image.iter().map(|c| c.r).collect();
+        assert_eq!(reds, vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 0.0, 0.0, 3.0, 4.0, 0.0]);
+    }
+
+    #[test]
+    fn test_image_serde() {
+        // 8-bit images (e.g., loaded textures) survive a round trip exactly
+        let mut image = Image::new((3u32, 2u32));
+        for (i, colour) in image.iter_mut().enumerate() {
+            *colour = Colour::new(i as f64 / 255.0, (40 * i) as f64 / 255.0, 1.0, 1.0);
+        }
+        let other: Image = serde_json::from_str(&serde_json::to_string(&image).unwrap()).unwrap();
+        assert_eq!(other.dims(), image.dims());
+        assert_eq!(other.pixels, image.pixels);
+    }
+
+    #[test]
+    fn test_image_to_path() {
+        // Give every pixel its own colour, so any flip or mirror changes the image
+        let mut image = Image::new((3u32, 2u32));
+        for (i, colour) in image.iter_mut().enumerate() {
+            *colour = Colour::new((10 * i) as f64 / 255.0, (255 - 20 * i) as f64 / 255.0, 1.0, 1.0);
+        }
+        let dir: PathBuf = std::env::temp_dir().join(format!("raytracer-test-image-to-path-{}", std::process::id()));
+        let path: PathBuf = dir.join("image.png");
+        image.to_path(&path, true).unwrap();
+
+        // We store rows bottom-to-top, but files are top-to-bottom, so the first row on disk is our last one
+        let raw: Image = Image::from_path_auto(&path).unwrap();
+        assert_eq!(raw.dims(), image.dims());
+        assert_eq!(raw.pixels[..3], image.pixels[3..]);
+        assert_eq!(raw.pixels[3..], image.pixels[..3]);
+        let other: Image = Image::from_written_path(&path).unwrap();
+        assert_eq!(other.pixels, image.pixels);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_image_lossless() {
+        let mut image = Image::new((3u32, 2u32));
+        for (i, colour) in image.iter_mut().enumerate() {
+            *colour = Colour::new(i as f64 * 123.456, 1.0 / (i as f64 + 3.0), -0.5, 1.0);
+        }
+        let mut raw: Vec<u8> = Vec::new();
+        lossless::serialize(&image, &mut serde_json::Serializer::new(&mut raw)).unwrap();
+        let other: Image = lossless::deserialize(&mut serde_json::Deserializer::from_slice(&raw)).unwrap();
+        assert_eq!(other.dims(), image.dims());
+        assert_eq!(other.pixels, image.pixels);
+    }
+
+    #[test]
+    fn test_image_hdr_path() {
+        let mut image = Image::new((3u32, 2u32));
+        for (i, colour) in image.iter_mut().enumerate() {
+            *colour = Colour::new(i as f64 * 4.0, 0.5, 7.0, 1.0);
+        }
+        let dir: PathBuf = std::env::temp_dir().join(format!("raytracer-test-image-hdr-{}", std::process::id()));
+        for ext in ["exr", "hdr"] {
+            // Colours above one survive the round trip (up to the precision of the format)
+            let path: PathBuf = dir.join(format!("image.{ext}"));
+            assert!(Image::is_hdr_path(&path));
+            image.to_path(&path, true).unwrap();
+            let other: Image = Image::from_written_path(&path).unwrap();
+            assert_eq!(other.dims(), image.dims());
+            for (lhs, rhs) in other.iter().zip(image.iter()) {
+                assert!((lhs.r - rhs.r).abs() <= 0.05 * rhs.r.max(1.0) && (lhs.b - rhs.b).abs() <= 0.05 * rhs.b, "{lhs:?} != {rhs:?}");
+            }
+        }
+        assert!(!Image::is_hdr_path("image.png"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
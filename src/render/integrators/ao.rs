@@ -0,0 +1,97 @@
+//  AO.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements the [`AmbientOcclusion`] integrator, which shows how much
+//!   of the surroundings of every point is blocked by nearby geometry.
+//
+
+use std::num::NonZeroUsize;
+
+use serde::{Deserialize, Serialize};
+
+use super::super::lights::LightList;
+use super::{Aovs, Integrator, RayColour};
+use crate::hittree::HitTree;
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
+use crate::specifications::materials::diffuse::random3_uniform;
+use crate::specifications::objects::{HitRecord, Hittable as _};
+use crate::specifications::scene::Environment;
+
+
+/***** LIBRARY *****/
+/// Defines an integrator that computes the ambient occlusion of the first hit.
+///
+/// Every point is as bright as the (cosine-weighted) fraction of rays leaving it that do not hit
+/// anything within a certain distance. Rays that hit nothing at all are white.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct AmbientOcclusion {
+    /// The distance within which geometry occludes a point.
+    pub distance:  f64,
+    /// The number of rays to cast from every hit to find occluders.
+    pub n_samples: NonZeroUsize,
+}
+impl Integrator for AmbientOcclusion {
+    fn ray_colour(&self, ray: Ray, world: &HitTree, _lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour {
+        let (object, record): (usize, HitRecord) = match world.hit_indexed(ray, 0.001, f64::INFINITY, env, sampler) {
+            Some(hit) => hit,
+            None => return Colour::new(1.0, 1.0, 1.0, 1.0).into(),
+        };
+
+        // Cast rays around the normal to see how many escape
+        let mut n_unoccluded: usize = 0;
+        for _ in 0..self.n_samples.get() {
+            let mut direct: Vec3 = record.data.normal + random3_uniform(sampler);
+            if direct.is_nearly_zero() {
+                direct = record.data.normal;
+            }
+            if world.hit(Ray::with_time(record.data.hit, direct.unit(), ray.time), 0.001, self.distance, env, sampler).is_none() {
+                n_unoccluded += 1;
+            }
+        }
+
+        // The fraction that escaped is the brightness
+        let visibility: f64 = n_unoccluded as f64 / self.n_samples.get() as f64;
+        RayColour { colour: Colour::new(visibility, visibility, visibility, 1.0), aovs: Some(Aovs::from_hit(object, &record)) }
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Sampler;
+    use crate::specifications::materials::{Lambertian, Material};
+    use crate::specifications::objects::plane::Qd;
+    use crate::specifications::objects::{Object, Quad};
+
+    fn quad(y: f64) -> Object {
+        Object::Quad(Quad {
+            qd: Qd { pos: Vec3::new(-1000.0, y, -1000.0), u: Vec3::new(2000.0, 0.0, 0.0), v: Vec3::new(0.0, 0.0, 2000.0) },
+            material: Material::Lambertian(Lambertian { colour: Colour::new(0.5, 0.5, 0.5, 1.0) }),
+        })
+    }
+
+    #[test]
+    fn test_ambient_occlusion() {
+        let ao = AmbientOcclusion { distance: f64::INFINITY, n_samples: NonZeroUsize::new(16).unwrap() };
+        let mut sampler: PixelSampler = PixelSampler::new(Sampler::Independent, 42, 0, 0, 0, 1);
+        let ray = Ray::new(Vec3::new(0.0, 0.1, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        // A lone floor is never occluded
+        let world = HitTree::with_objs([quad(0.0)], (0..=0).into());
+        let res: RayColour = ao.ray_colour(ray, &world, &LightList::default(), &Environment::default(), &mut sampler);
+        assert_eq!(res.colour.r, 1.0);
+        assert!((res.aovs.unwrap().depth - 0.1).abs() < 1e-9);
+
+        // A ceiling close by occludes everything, but not if it's out of range
+        let world = HitTree::with_objs([quad(0.0), quad(0.25)], (0..=0).into());
+        assert_eq!(ao.ray_colour(ray, &world, &LightList::default(), &Environment::default(), &mut sampler).colour.r, 0.0);
+        let ao = AmbientOcclusion { distance: 0.1, n_samples: NonZeroUsize::new(16).unwrap() };
+        assert_eq!(ao.ray_colour(ray, &world, &LightList::default(), &Environment::default(), &mut sampler).colour.r, 1.0);
+    }
+}
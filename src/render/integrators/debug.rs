@@ -0,0 +1,57 @@
+//  DEBUG.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements the [`DebugIntegrator`], which visualizes properties of
+//!   the first surface hit by a ray instead of its lighting.
+//
+
+use serde::{Deserialize, Serialize};
+
+use super::super::lights::LightList;
+use super::{Aovs, Integrator, RayColour};
+use crate::hittree::HitTree;
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
+use crate::specifications::objects::HitRecord;
+use crate::specifications::scene::Environment;
+
+
+/***** LIBRARY *****/
+/// Defines what a [`DebugIntegrator`] shows.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum DebugView {
+    /// Shows the surface normals, mapped from `[-1, 1]` to `[0, 1]`.
+    Normals,
+    /// Shows the base colour of the materials.
+    Albedo,
+}
+
+
+
+/// Defines an integrator that shows the normals or albedo of the first hit, for debugging scenes.
+///
+/// Rays that hit nothing are black.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct DebugIntegrator {
+    /// What to show.
+    pub view: DebugView,
+}
+impl Integrator for DebugIntegrator {
+    fn ray_colour(&self, ray: Ray, world: &HitTree, _lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour {
+        let (object, record): (usize, HitRecord) = match world.hit_indexed(ray, 0.001, f64::INFINITY, env, sampler) {
+            Some(hit) => hit,
+            None => return Colour::BLACK.into(),
+        };
+        let aovs: Aovs = Aovs::from_hit(object, &record);
+
+        // Show the requested property
+        let colour: Colour = match self.view {
+            DebugView::Normals => {
+                let normal: Vec3 = 0.5 * (aovs.normal + Vec3::new(1.0, 1.0, 1.0));
+                Colour::new(normal.x, normal.y, normal.z, 1.0)
+            },
+            DebugView::Albedo => aovs.albedo.opaque(),
+        };
+        RayColour { colour, aovs: Some(aovs) }
+    }
+}
@@ -0,0 +1,62 @@
+//  DIRECT.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements the [`DirectLighting`] integrator, which only computes the
+//!   light arriving at the first hit straight from the light sources.
+//
+
+use serde::{Deserialize, Serialize};
+
+use super::super::lights::{LightList, MisHeuristic};
+use super::{Aovs, Integrator, RayColour, background, sample_lights};
+use crate::hittree::HitTree;
+use crate::math::{Colour, PixelSampler, Ray};
+use crate::specifications::objects::{HitRecord, Hittable as _};
+use crate::specifications::scene::Environment;
+
+
+/***** LIBRARY *****/
+/// Defines an integrator that only computes direct lighting.
+///
+/// At the first hit, the lights are sampled with shadow rays, and the ray is scattered once more to
+/// find any light (or background) it sees directly. Light that bounces more than once is ignored.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct DirectLighting {
+    /// The heuristic with which to combine directly sampled lights and scattered rays.
+    pub mis: MisHeuristic,
+}
+impl Integrator for DirectLighting {
+    fn ray_colour(&self, ray: Ray, world: &HitTree, lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour {
+        let (object, record): (usize, HitRecord) = match world.hit_indexed(ray, 0.001, f64::INFINITY, env, sampler) {
+            Some(hit) => hit,
+            None => return background(ray, env).into(),
+        };
+        let aovs: Option<Aovs> = Some(Aovs::from_hit(object, &record));
+
+        // Collect what the surface emits itself and what it receives from the lights
+        let diffuse: bool = record.mat.is_diffuse() && !lights.is_empty();
+        let mut res: Colour = record.emitted();
+        if diffuse {
+            res += sample_lights(ray, &record, world, lights, self.mis, env, sampler);
+        }
+
+        // Then scatter once to find the light that we can't sample (or that the lights don't cover well)
+        let (scatter, attenuation): (Ray, Colour) = match record.scatter(ray, env, sampler) {
+            (Some(scatter), attenuation) => (Ray::with_time(scatter.origin, scatter.direct, ray.time), attenuation),
+            (None, colour) => return RayColour { colour: res + colour, aovs },
+        };
+        let incoming: Colour = match world.hit(scatter, 0.001, f64::INFINITY, env, sampler) {
+            Some(light) => {
+                let mut emitted: Colour = light.emitted();
+                if diffuse && lights.contains(light.mat) {
+                    let bsdf_pdf: f64 = record.pdf(ray, scatter.direct.unit());
+                    emitted *= self.mis.weight(bsdf_pdf, lights.pdf(light.mat, scatter.origin, scatter.direct, scatter.time));
+                }
+                emitted
+            },
+            None => background(scatter, env),
+        };
+        RayColour { colour: res + attenuation * incoming, aovs }
+    }
+}
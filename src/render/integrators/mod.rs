@@ -0,0 +1,268 @@
+//  MOD.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the [`Integrator`]s, which compute the colour of a single
+//!   ray fired into the world. These are what the rendering backends use
+//!   to decide what a pixel looks like.
+//
+
+// Declare submodules
+pub mod ao;
+pub mod debug;
+pub mod direct;
+pub mod path;
+
+// Imports
+use std::fmt::Debug;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+pub use ao::AmbientOcclusion;
+pub use debug::{DebugIntegrator, DebugView};
+pub use direct::DirectLighting;
+pub use path::PathTracer;
+
+use super::lights::{LightList, MisHeuristic};
+use crate::hittree::HitTree;
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
+use crate::specifications::materials::Scattering as _;
+use crate::specifications::objects::{HitRecord, Hittable as _};
+use crate::specifications::scene::{Background, Environment};
+
+
+/***** HELPER FUNCTIONS *****/
+/// Samples a random light directly from a hitpoint, and computes how much of it is scattered
+/// along the given ray.
+///
+/// # Arguments
+/// - `ray`: The [`Ray`] that hit something.
+/// - `record`: The [`HitRecord`] describing what it hit.
+/// - `world`: A [`HitTree`] that describes what to render, for casting shadow rays.
+/// - `lights`: The [`LightList`] to sample from.
+/// - `mis`: The [`MisHeuristic`] to weigh the sample with against scattering towards the light.
+/// - `env`: An [`Environment`]-struct relating properties about the environment.
+/// - `sampler`: The [`PixelSampler`] to draw random numbers from.
+///
+/// # Returns
+/// The [`Colour`] of the light arriving through the sampled light (black if it's occluded).
+pub fn sample_lights(ray: Ray, record: &HitRecord, world: &HitTree, lights: &LightList, mis: MisHeuristic, env: &Environment, sampler: &mut PixelSampler) -> Colour {
+    // Pick a point on a light
+    let (sample, mat) = match lights.sample(record.data.hit, ray.time, sampler) {
+        Some(res) => res,
+        None => return Colour::BLACK,
+    };
+    let to: Vec3 = sample.point - record.data.hit;
+    let dist: f64 = to.length();
+    let direct: Vec3 = to / dist;
+
+    // See if the material scatters anything in that direction at all
+    let f: Colour = record.eval(ray, direct);
+    if f.r <= 0.0 && f.g <= 0.0 && f.b <= 0.0 {
+        return Colour::BLACK;
+    }
+
+    // Cast a shadow ray to see if the light is occluded
+    let shadow: Ray = Ray::with_time(record.data.hit, direct, ray.time);
+    if world.hit(shadow, 0.001, dist - 0.001, env, sampler).is_some() {
+        return Colour::BLACK;
+    }
+
+    // It's visible, so return its contribution (weighted against the chance we'd have scattered towards it)
+    let weight: f64 = mis.weight(sample.pdf, record.pdf(ray, direct));
+    f * mat.emitted(sample.uv, sample.point) * (weight / sample.pdf)
+}
+
+/// Computes the colour of the background for a ray that hits nothing.
+///
+/// # Arguments
+/// - `ray`: The [`Ray`] that flies off into the void.
+/// - `env`: An [`Environment`]-struct relating properties about the environment.
+///
+/// # Returns
+/// The [`Colour`] of the background in the direction of the ray.
+pub fn background(ray: Ray, env: &Environment) -> Colour {
+    match env.background {
+        Background::IlluminatedSky => {
+            // Skybox of old
+            let udir: Vec3 = ray.direct.unit();
+            let t: f64 = 0.5 * (udir.y + 1.0);
+            ((1.0 - t) * Colour::new(1.0, 1.0, 1.0, 0.0) + t * Colour::new(0.5, 0.7, 1.0, 0.0)).opaque()
+        },
+
+        Background::Colour(colour) => colour,
+        Background::None => Colour::BLACK,
+    }
+}
+
+
+
+
+
+/***** AUXILLARY *****/
+/// Defines the arbitrary output variables (AOVs) that an [`Integrator`] may produce besides the colour of a ray.
+///
+/// These describe the first surface the ray hit.
+#[derive(Clone, Copy, Debug)]
+pub struct Aovs {
+    /// The distance along the ray to the hit (i.e., [`HitData::t`](crate::specifications::objects::HitData::t)).
+    pub depth:    f64,
+    /// The surface normal at the hit.
+    pub normal:   Vec3,
+    /// The base colour of the material at the hit.
+    pub albedo:   Colour,
+    /// The point in the world that was hit.
+    pub position: Vec3,
+    /// The texture coordinates of the hit.
+    pub uv:       (f64, f64),
+    /// The index of the object that was hit (see [`HitTree::hit_indexed()`]).
+    pub object:   usize,
+}
+impl Aovs {
+    /// Constructor for the Aovs that reads them from the first hit of a ray.
+    ///
+    /// # Arguments
+    /// - `object`: The index of the object that was hit, as returned by [`HitTree::hit_indexed()`].
+    /// - `record`: The [`HitRecord`] describing what it hit.
+    ///
+    /// # Returns
+    /// A new Aovs describing the hit.
+    #[inline]
+    pub fn from_hit(object: usize, record: &HitRecord) -> Self {
+        Self {
+            depth: record.data.t,
+            normal: record.data.normal,
+            albedo: record.albedo(),
+            position: record.data.hit,
+            uv: record.data.uv,
+            object,
+        }
+    }
+}
+
+/// Defines what an [`Integrator`] computes for a single ray.
+#[derive(Clone, Copy, Debug)]
+pub struct RayColour {
+    /// The colour of the ray.
+    pub colour: Colour,
+    /// The [`Aovs`] of the ray, if the integrator produced any and it hit something.
+    pub aovs:   Option<Aovs>,
+}
+impl From<Colour> for RayColour {
+    #[inline]
+    fn from(value: Colour) -> Self { Self { colour: value, aovs: None } }
+}
+
+
+
+
+
+/***** INTERFACE *****/
+/// Defines the trait for anything that computes the colour of a ray fired into the world.
+pub trait Integrator: Debug {
+    /// Computes the colour of the given ray.
+    ///
+    /// # Arguments
+    /// - `ray`: The [`Ray`] who's colour to compute.
+    /// - `world`: A [`HitTree`] that describes what to render.
+    /// - `lights`: A [`LightList`] with the lights in `world` that may be sampled directly.
+    /// - `env`: An [`Environment`]-struct relating properties about the environment.
+    /// - `sampler`: The [`PixelSampler`] to draw random numbers from.
+    ///
+    /// # Returns
+    /// A [`RayColour`] with the computed colour and any [`Aovs`].
+    fn ray_colour(&self, ray: Ray, world: &HitTree, lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour;
+}
+
+// Pointer-like impls
+macro_rules! integrator_ptr_impl {
+    ('a, $ty:ty) => {
+        impl<'a, T: Integrator> Integrator for $ty {
+            #[inline]
+            fn ray_colour(&self, ray: Ray, world: &HitTree, lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour {
+                <T as Integrator>::ray_colour(self, ray, world, lights, env, sampler)
+            }
+        }
+    };
+    ($ty:ty) => {
+        impl<T: Integrator> Integrator for $ty {
+            #[inline]
+            fn ray_colour(&self, ray: Ray, world: &HitTree, lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour {
+                <T as Integrator>::ray_colour(self, ray, world, lights, env, sampler)
+            }
+        }
+    };
+}
+integrator_ptr_impl!('a, &'a T);
+integrator_ptr_impl!('a, &'a mut T);
+integrator_ptr_impl!(std::boxed::Box<T>);
+integrator_ptr_impl!(std::rc::Rc<T>);
+integrator_ptr_impl!(std::sync::Arc<T>);
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines the integrators that can be selected by the user.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub enum IntegratorKind {
+    /// Traces full paths through the scene.
+    #[clap(name = "path")]
+    Path,
+    /// Shows the surface normals of the first hit.
+    #[clap(name = "normals")]
+    Normals,
+    /// Shows the base colour of the material of the first hit.
+    #[clap(name = "albedo")]
+    Albedo,
+    /// Shows how occluded the first hit is by nearby geometry.
+    #[clap(name = "ao", alias = "ambient_occlusion", alias = "ambient-occlusion")]
+    AmbientOcclusion,
+    /// Shows the light arriving at the first hit directly from the light sources.
+    #[clap(name = "direct")]
+    Direct,
+}
+
+
+
+/// Defines the collection of all our integrators.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AnyIntegrator {
+    /// It's a [`PathTracer`].
+    Path(PathTracer),
+    /// It's a [`DebugIntegrator`].
+    Debug(DebugIntegrator),
+    /// It's an [`AmbientOcclusion`] integrator.
+    AmbientOcclusion(AmbientOcclusion),
+    /// It's a [`DirectLighting`] integrator.
+    Direct(DirectLighting),
+}
+impl Integrator for AnyIntegrator {
+    #[inline]
+    fn ray_colour(&self, ray: Ray, world: &HitTree, lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour {
+        match self {
+            Self::Path(i) => i.ray_colour(ray, world, lights, env, sampler),
+            Self::Debug(i) => i.ray_colour(ray, world, lights, env, sampler),
+            Self::AmbientOcclusion(i) => i.ray_colour(ray, world, lights, env, sampler),
+            Self::Direct(i) => i.ray_colour(ray, world, lights, env, sampler),
+        }
+    }
+}
+impl From<PathTracer> for AnyIntegrator {
+    #[inline]
+    fn from(value: PathTracer) -> Self { Self::Path(value) }
+}
+impl From<DebugIntegrator> for AnyIntegrator {
+    #[inline]
+    fn from(value: DebugIntegrator) -> Self { Self::Debug(value) }
+}
+impl From<AmbientOcclusion> for AnyIntegrator {
+    #[inline]
+    fn from(value: AmbientOcclusion) -> Self { Self::AmbientOcclusion(value) }
+}
+impl From<DirectLighting> for AnyIntegrator {
+    #[inline]
+    fn from(value: DirectLighting) -> Self { Self::Direct(value) }
+}
@@ -0,0 +1,163 @@
+//  PATH.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements the [`PathTracer`], which traces full paths of light
+//!   through the scene.
+//
+
+use serde::{Deserialize, Serialize};
+
+use super::super::lights::{LightList, MisHeuristic};
+use super::{Aovs, Integrator, RayColour, background, sample_lights};
+use crate::hittree::HitTree;
+use crate::math::{Colour, PixelSampler, Ray};
+use crate::specifications::objects::HitRecord;
+use crate::specifications::scene::Environment;
+
+
+/***** LIBRARY *****/
+/// Defines the integrator that traces full paths of light through the scene.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct PathTracer {
+    /// The maximum number of times a ray bounces.
+    pub max_depth: usize,
+    /// The number of bounces after which paths may be terminated with Russian roulette.
+    pub roulette_depth: usize,
+    /// The maximum probability with which a path survives Russian roulette.
+    pub roulette_clamp: f64,
+    /// The heuristic with which to combine directly sampled lights and scattered rays.
+    pub mis: MisHeuristic,
+}
+impl Integrator for PathTracer {
+    /// Computes an Rgba quadruplet based on what the Ray hits.
+    ///
+    /// At every diffuse bounce, the lights in `lights` are sampled directly (next-event
+    /// estimation), and combined with the light found by scattering using multiple importance
+    /// sampling. Once the path bounced `roulette_depth` times, it is randomly terminated with a
+    /// probability based on how much light it still carries (Russian roulette).
+    fn ray_colour(&self, mut ray: Ray, world: &HitTree, lights: &LightList, env: &Environment, sampler: &mut PixelSampler) -> RayColour {
+        let mut res: Colour = Colour::zeroes();
+        // The fraction of light that the path still carries back to the camera
+        let mut throughput: Colour = Colour::new(1.0, 1.0, 1.0, 1.0);
+        // The density with which the previous bounce scattered the ray, if it also sampled the lights directly
+        let mut bsdf_pdf: Option<f64> = None;
+        // The AOVs of the first hit
+        let mut aovs: Option<Aovs> = None;
+        for depth in 0..self.max_depth {
+            // Try to find the object that hits closest
+            let (object, record): (usize, HitRecord) = match world.hit_indexed(ray, 0.001, f64::INFINITY, env, sampler) {
+                Some(hit) => hit,
+                None => {
+                    // Otherwise, return the background colour
+                    res += throughput * background(ray, env);
+                    break;
+                },
+            };
+            if depth == 0 {
+                aovs = Some(Aovs::from_hit(object, &record));
+            }
+
+            // Compute if the material emits anything (weighted against the chance we sampled it directly on the previous bounce)
+            let mut colour_from_emission = record.emitted();
+            if let Some(bsdf_pdf) = bsdf_pdf {
+                if lights.contains(record.mat) {
+                    colour_from_emission *= self.mis.weight(bsdf_pdf, lights.pdf(record.mat, ray.origin, ray.direct, ray.time));
+                }
+            }
+
+            // For diffuse materials, sample the lights directly
+            let diffuse: bool = record.mat.is_diffuse() && !lights.is_empty();
+            let colour_from_lights = if diffuse { sample_lights(ray, &record, world, lights, self.mis, env, sampler) } else { Colour::BLACK };
+            res += throughput * (colour_from_emission + colour_from_lights);
+
+            // Scatter the ray now we've found it
+            match record.scatter(ray, env, sampler) {
+                // Continue with the returned ray, attenuated by the material
+                (Some(scatter), attenuation) => {
+                    throughput *= attenuation;
+                    bsdf_pdf = if diffuse { Some(record.pdf(ray, scatter.direct.unit())) } else { None };
+                    // Keep the time of the path, since the materials don't
+                    ray = Ray::with_time(scatter.origin, scatter.direct, ray.time);
+                },
+
+                // The path ends here with the material's colour
+                (None, colour) => {
+                    res += throughput * colour;
+                    break;
+                },
+            }
+
+            // Play Russian roulette to decide whether the path is worth continuing (unless it ends anyway)
+            if depth + 1 >= self.roulette_depth && depth + 1 < self.max_depth {
+                let survival: f64 = throughput.r.max(throughput.g).max(throughput.b).min(self.roulette_clamp);
+                if survival <= 0.0 || sampler.f64() >= survival {
+                    break;
+                }
+                // Make up for the paths that we've terminated
+                throughput /= survival;
+            }
+        }
+        RayColour { colour: res, aovs }
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Sampler, Vec3};
+    use crate::specifications::materials::{DiffuseLight, Lambertian, Material};
+    use crate::specifications::objects::{Object, Sphere};
+
+    /// A closed, grey room with a light in the middle, in which paths bounce a lot.
+    fn room() -> HitTree {
+        let wall = Object::Sphere(Sphere {
+            center:   Vec3::zeroes(),
+            radius:   1.0,
+            material: Material::Lambertian(Lambertian { colour: Colour::new(0.5, 0.5, 0.5, 1.0) }),
+        });
+        let light = Object::Sphere(Sphere {
+            center:   Vec3::zeroes(),
+            radius:   0.25,
+            material: Material::DiffuseLight(DiffuseLight { colour: Colour::new(1.0, 1.0, 1.0, 1.0) }),
+        });
+        HitTree::with_objs([wall, light], (0..=0).into())
+    }
+
+    /// Renders `n` paths from inside the [`room()`], and returns their colours.
+    fn render(tracer: PathTracer, n: u64) -> Vec<Colour> {
+        let (world, lights, env): (HitTree, LightList, Environment) = (room(), LightList::default(), Environment::default());
+        let ray = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        (0..n)
+            .map(|i| {
+                let mut sampler: PixelSampler = PixelSampler::new(Sampler::Independent, 42, 0, 0, i, n);
+                tracer.ray_colour(ray, &world, &lights, &env, &mut sampler).colour
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_path_roulette() {
+        let tracer = PathTracer { max_depth: 32, roulette_depth: usize::MAX, roulette_clamp: 0.95, mis: MisHeuristic::Power };
+        let reference: Vec<Colour> = render(tracer, 20000);
+
+        // Playing from the last bounce onwards is the same as not playing at all
+        assert_eq!(render(PathTracer { roulette_depth: 32, ..tracer }, 20000), reference);
+
+        // Terminating paths doesn't change the result on average (just its noise)
+        let mean = |colours: Vec<Colour>| -> f64 { colours.iter().map(|c| c.r).sum::<f64>() / colours.len() as f64 };
+        let expected: f64 = mean(reference);
+        for roulette_clamp in [0.95, 0.5] {
+            let got: f64 = mean(render(PathTracer { roulette_depth: 1, roulette_clamp, ..tracer }, 20000));
+            assert!((got - expected).abs() < 0.05 * expected, "{got} != {expected} (clamp {roulette_clamp})");
+        }
+
+        // Paths never survive if the clamp doesn't let them, so they end after the first bounce
+        assert_eq!(render(PathTracer { roulette_depth: 1, roulette_clamp: 0.0, ..tracer }, 1000), render(PathTracer { max_depth: 1, ..tracer }, 1000));
+    }
+}
@@ -0,0 +1,298 @@
+//  LIGHTS.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines a [`LightList`], which collects the emitting objects in a
+//!   [`HitTree`] such that renderers can sample them directly instead of
+//!   waiting for a ray to randomly hit them.
+//!
+//!   Also defines the [`MisHeuristic`] with which directly sampled lights
+//!   and lights hit by scattered rays are combined.
+//
+
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::hittree::HitTree;
+use crate::math::{PixelSampler, Vec3};
+use crate::specifications::materials::{Material, Scattering};
+use crate::specifications::objects::{AnimatedSphere, Object, Quad, Sampleable, Sphere, SurfaceSample, Triangle};
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns the address of a material, for use as its identifier.
+#[inline]
+fn mat_addr<T: ?Sized>(mat: &T) -> usize { mat as *const T as *const () as usize }
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines a single light source in a scene.
+#[derive(Clone, Copy, Debug)]
+pub enum Light<'w> {
+    /// It's a lighting sphere that moves.
+    AnimatedSphere(&'w AnimatedSphere<Material>),
+    /// It's a lighting quad.
+    Quad(&'w Quad<Material>),
+    /// It's a lighting sphere.
+    Sphere(&'w Sphere<Material>),
+    /// It's a lighting triangle.
+    Triangle(&'w Triangle<Material>),
+}
+impl<'w> Light<'w> {
+    /// Returns the material of this light.
+    ///
+    /// # Returns
+    /// A reference to the [`Material`] that does the emitting.
+    #[inline]
+    pub fn material(&self) -> &'w Material {
+        match self {
+            Self::AnimatedSphere(s) => &s.sphere.material,
+            Self::Quad(q) => &q.material,
+            Self::Sphere(s) => &s.material,
+            Self::Triangle(t) => &t.material,
+        }
+    }
+}
+impl Sampleable for Light<'_> {
+    #[inline]
+    fn sample(&self, origin: Vec3, t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample> {
+        match self {
+            Self::AnimatedSphere(s) => s.sample(origin, t_us, sampler),
+            Self::Quad(q) => q.sample(origin, t_us, sampler),
+            Self::Sphere(s) => s.sample(origin, t_us, sampler),
+            Self::Triangle(t) => t.sample(origin, t_us, sampler),
+        }
+    }
+
+    #[inline]
+    fn pdf(&self, origin: Vec3, direct: Vec3, t_us: u64) -> f64 {
+        match self {
+            Self::AnimatedSphere(s) => s.pdf(origin, direct, t_us),
+            Self::Quad(q) => q.pdf(origin, direct, t_us),
+            Self::Sphere(s) => s.pdf(origin, direct, t_us),
+            Self::Triangle(t) => t.pdf(origin, direct, t_us),
+        }
+    }
+}
+
+
+
+/// Defines the heuristics with which we can weigh light samples in multiple importance sampling.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, ValueEnum)]
+pub enum MisHeuristic {
+    /// Weighs each strategy proportional to its density.
+    #[clap(name = "balance")]
+    Balance,
+    /// Weighs each strategy proportional to its squared density.
+    #[clap(name = "power")]
+    #[default]
+    Power,
+}
+impl MisHeuristic {
+    /// Computes the weight of a sample taken with one strategy, given the densities with which both
+    /// strategies would have produced it.
+    ///
+    /// # Arguments
+    /// - `pdf`: The density of the strategy that took the sample.
+    /// - `other`: The density of the other strategy for the same sample.
+    ///
+    /// # Returns
+    /// The weight of the sample, between 0 and 1.
+    #[inline]
+    pub fn weight(&self, pdf: f64, other: f64) -> f64 {
+        let (pdf, other): (f64, f64) = match self {
+            Self::Balance => (pdf, other),
+            Self::Power => (pdf * pdf, other * other),
+        };
+        if pdf.is_infinite() {
+            1.0
+        } else if pdf + other > 0.0 {
+            pdf / (pdf + other)
+        } else {
+            0.0
+        }
+    }
+}
+
+
+
+/// Collects all the light sources in a scene.
+///
+/// Note that only lights that we know how to sample are collected. Particularly, any light nested
+/// in a transformation (e.g., [`Object::Translate`]) is skipped, and can only be hit by chance.
+#[derive(Clone, Debug, Default)]
+pub struct LightList<'w> {
+    /// The lights we found.
+    lights: Vec<Light<'w>>,
+    /// Maps the addresses of the materials of the lights to their index in `lights`, to quickly check if we hit one.
+    mats:   HashMap<usize, usize>,
+}
+
+// Constructors
+impl<'w> LightList<'w> {
+    /// Constructor for the LightList that collects all emitting objects in the given world.
+    ///
+    /// # Arguments
+    /// - `world`: The [`HitTree`] to search for lights.
+    ///
+    /// # Returns
+    /// A new LightList that refers to the lights in `world`.
+    pub fn collect(world: &'w HitTree) -> Self {
+        let mut res: Self = Self::default();
+        res.collect_tree(world);
+        res
+    }
+
+    /// Adds all lights in the given (sub)tree to ourselves.
+    fn collect_tree(&mut self, tree: &'w HitTree) {
+        for obj in tree {
+            let light: Light = match obj {
+                Object::AnimatedSphere(s) if matches!(s.sphere.material, Material::DiffuseLight(_)) => Light::AnimatedSphere(s),
+                Object::Quad(q) if matches!(q.material, Material::DiffuseLight(_)) => Light::Quad(q),
+                Object::Sphere(s) if matches!(s.material, Material::DiffuseLight(_)) => Light::Sphere(s),
+                Object::Triangle(t) if matches!(t.material, Material::DiffuseLight(_)) => Light::Triangle(t),
+                Object::Group(g) => {
+                    self.collect_tree(g);
+                    continue;
+                },
+                _ => continue,
+            };
+            self.mats.insert(mat_addr(light.material()), self.lights.len());
+            self.lights.push(light);
+        }
+    }
+}
+
+// Sampling
+impl<'w> LightList<'w> {
+    /// Samples a point on a random light in the list.
+    ///
+    /// # Arguments
+    /// - `origin`: The point from which we look at the lights.
+    /// - `t_us`: The time at which we sample the lights. Time is in microseconds since the start of
+    ///   the scene.
+    /// - `sampler`: The [`PixelSampler`] to draw random numbers from.
+    ///
+    /// # Returns
+    /// A pair of the sampled [`SurfaceSample`] and the [`Material`] of the light we sampled. The
+    /// density of the sample already accounts for picking one of the lights. If the list is empty
+    /// or the picked light is not visible from `origin`, returns [`None`].
+    pub fn sample(&self, origin: Vec3, t_us: u64, sampler: &mut PixelSampler) -> Option<(SurfaceSample, &'w Material)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let light: &Light<'w> = &self.lights[sampler.usize(self.lights.len())];
+        let mut sample: SurfaceSample = light.sample(origin, t_us, sampler)?;
+        sample.pdf /= self.lights.len() as f64;
+        Some((sample, light.material()))
+    }
+
+    /// Checks whether the given material belongs to one of the lights in this list.
+    ///
+    /// This is used to avoid counting a light twice if we sampled it directly already.
+    ///
+    /// # Arguments
+    /// - `mat`: The material to check, typically the one from a [`HitRecord`](crate::specifications::objects::HitRecord).
+    ///
+    /// # Returns
+    /// True if it is one of our lights, or false otherwise.
+    #[inline]
+    pub fn contains(&self, mat: &dyn Scattering) -> bool { self.mats.contains_key(&mat_addr(mat)) }
+
+    /// Computes the probability density with which [`LightList::sample()`] would have picked the
+    /// point where a ray hits the light with the given material.
+    ///
+    /// # Arguments
+    /// - `mat`: The material of the light that was hit.
+    /// - `origin`: The point from which we looked at the light.
+    /// - `direct`: The direction in which we looked at the light.
+    /// - `t_us`: The time at which we looked at the light. Time is in microseconds since the start
+    ///   of the scene.
+    ///
+    /// # Returns
+    /// The density w.r.t. solid angle, or `0.0` if `mat` is not one of our lights.
+    pub fn pdf(&self, mat: &dyn Scattering, origin: Vec3, direct: Vec3, t_us: u64) -> f64 {
+        match self.mats.get(&mat_addr(mat)) {
+            Some(i) => self.lights[*i].pdf(origin, direct, t_us) / self.lights.len() as f64,
+            None => 0.0,
+        }
+    }
+
+    /// Returns the number of lights in this list.
+    #[inline]
+    pub fn len(&self) -> usize { self.lights.len() }
+
+    /// Returns whether there are any lights in this list.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.lights.is_empty() }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Colour, Sampler};
+    use crate::specifications::materials::{DiffuseLight, Lambertian};
+    use crate::specifications::objects::plane::Qd;
+
+    fn quad(material: Material) -> Object {
+        Object::Quad(Quad {
+            qd: Qd { pos: Vec3::new(-1.0, 1.0, -1.0), u: Vec3::new(2.0, 0.0, 0.0), v: Vec3::new(0.0, 0.0, 2.0) },
+            material,
+        })
+    }
+
+    #[test]
+    fn test_light_list_collect() {
+        let light = Material::DiffuseLight(DiffuseLight { colour: Colour::new(4.0, 4.0, 4.0, 1.0) });
+        let diffuse = Material::Lambertian(Lambertian { colour: Colour::new(0.5, 0.5, 0.5, 1.0) });
+        let group = HitTree::with_objs([quad(light.clone()), quad(diffuse.clone())], (0..=0).into());
+        let world = HitTree::with_objs([quad(light), quad(diffuse), Object::Group(std::boxed::Box::new(group))], (0..=0).into());
+
+        // We expect both lights to be found, including the one in the group
+        let lights = LightList::collect(&world);
+        assert_eq!(lights.len(), 2);
+        for obj in &world {
+            if let Object::Quad(q) = obj {
+                assert_eq!(lights.contains(&q.material), matches!(q.material, Material::DiffuseLight(_)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_mis_heuristic() {
+        assert_eq!(MisHeuristic::Balance.weight(1.0, 3.0), 0.25);
+        assert_eq!(MisHeuristic::Power.weight(1.0, 3.0), 0.1);
+        assert_eq!(MisHeuristic::Power.weight(2.0, 0.0), 1.0);
+        assert_eq!(MisHeuristic::Balance.weight(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_light_list_sample() {
+        let light = Material::DiffuseLight(DiffuseLight { colour: Colour::new(4.0, 4.0, 4.0, 1.0) });
+        let world = HitTree::with_objs([quad(light)], (0..=0).into());
+        let lights = LightList::collect(&world);
+
+        // Looking straight up at the 2x2 quad from one below its center
+        let mut sampler: PixelSampler = PixelSampler::new(Sampler::Independent, 42, 0, 0, 0, 1);
+        for _ in 0..16 {
+            let (sample, _) = lights.sample(Vec3::new(0.0, 0.0, 0.0), 0, &mut sampler).unwrap();
+            assert!((sample.point.y - 1.0).abs() < 1e-9);
+            assert!(sample.point.x.abs() <= 1.0 && sample.point.z.abs() <= 1.0);
+            // pdf = d^2 / (cos * area)
+            let d2: f64 = sample.point.length2();
+            assert!((sample.pdf - d2 / ((1.0 / d2.sqrt()) * 4.0)).abs() < 1e-9);
+            // Hitting the light in the sampled direction should give the same density
+            assert!((lights.pdf(lights.lights[0].material(), Vec3::zeroes(), sample.point, 0) - sample.pdf).abs() < 1e-9);
+        }
+    }
+}
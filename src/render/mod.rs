@@ -13,18 +13,33 @@
 //
 
 // Declare submodules
+pub mod aov;
 pub mod backends;
+pub mod cancel;
+pub mod checkpoint;
+pub mod denoise;
 pub mod image;
+pub mod integrators;
+pub mod lights;
+pub mod pixel;
+pub mod tiles;
+pub mod tui;
 
 // Imports
 use std::error::Error;
 use std::fmt::Debug;
+use std::num::NonZeroU64;
 
 use clap::ValueEnum;
+use log::info;
 
 use crate::hittree::HitTree;
-use crate::math::Camera;
+use crate::math::{Camera, ToneMapping};
+use crate::render::aov::Aov;
+use crate::render::cancel::CancelToken;
+use crate::render::checkpoint::Checkpointer;
 use crate::render::image::Image;
+use crate::render::pixel::{Film, heatmap};
 use crate::specifications::scene::Environment;
 
 
@@ -40,11 +55,93 @@ pub trait RayRenderer: Debug {
     /// - `world`: The [`HitTree`] that contains the scene to render.
     ///
     /// # Returns
-    /// A new [`Image`] struct that contains the rendered frame.
+    /// A new [`Frame`] struct that contains the rendered frame, without any [`Aov`]s. Use
+    /// [`RayRenderer::resume_frame()`] with a [`Film::with_aovs()`] to render those too.
     ///
     /// # Errors
     /// This function may error. This will typically be an error relating to the backend of the renderer, since the rendering process, mathmatically, does not error.
-    fn render_frame(&self, world: &HitTree, cam: &Camera, env: &Environment) -> Result<Image, Self::Error>;
+    #[inline]
+    fn render_frame(&self, world: &HitTree, cam: &Camera, env: &Environment) -> Result<Frame, Self::Error> {
+        self.resume_frame(world, cam, env, Film::new(cam.dims()), None, None)
+    }
+
+    /// Continues rendering a frame of which some samples have already been taken.
+    ///
+    /// Every pixel only takes the samples it's still missing, so resuming a [`Film`] rendered with
+    /// fewer samples (or one that was interrupted) gives the same frame as rendering it in one go.
+    ///
+    /// # Arguments
+    /// - `world`: The [`HitTree`] that contains the scene to render.
+    /// - `film`: The [`Film`] with the samples taken so far. Must have the same dimensions as `cam`. If it keeps track of
+    ///   [`Aov`]s, then the returned [`Frame`] contains them.
+    /// - `checkpoints`: If given, a [`Checkpointer`] that periodically writes the film to disk while rendering.
+    /// - `cancel`: If given, a [`CancelToken`] that stops rendering early when cancelled.
+    ///
+    /// # Returns
+    /// A new [`Frame`] struct that contains the rendered frame. If rendering was cancelled, it contains the samples taken until then, and is
+    /// [interrupted](Frame::interrupted()).
+    ///
+    /// # Errors
+    /// This function may error. This will typically be an error relating to the backend of the renderer, since the rendering process, mathmatically, does not error.
+    fn resume_frame(
+        &self,
+        world: &HitTree,
+        cam: &Camera,
+        env: &Environment,
+        film: Film,
+        checkpoints: Option<&Checkpointer>,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Frame, Self::Error>;
+
+    /// Renders a frame progressively, in passes over the whole frame.
+    ///
+    /// Every pass takes (at most) `pass_samples` more samples for every pixel, continuing from the
+    /// previous one (see [`Camera::with_sample_limit()`]). Thus, the final frame is the same as
+    /// when rendering it in one go. Passes start from the fewest samples that any pixel in the
+    /// `film` has already taken.
+    ///
+    /// # Arguments
+    /// - `world`: The [`HitTree`] that contains the scene to render.
+    /// - `film`: The [`Film`] with the samples taken so far (see [`RayRenderer::resume_frame()`]).
+    /// - `checkpoints`: If given, a [`Checkpointer`] that periodically writes the film to disk while rendering.
+    /// - `cancel`: If given, a [`CancelToken`] that stops rendering early when cancelled. The pass that is being rendered then is the last.
+    /// - `pass_samples`: The number of samples that every pixel takes per pass.
+    /// - `on_pass`: Called with the number of the pass (starting at 1) and the [`Frame`] rendered so far after every pass but the last.
+    ///
+    /// # Returns
+    /// A tuple of the final [`Frame`] and the number of passes rendered.
+    ///
+    /// # Errors
+    /// This function errors if any of the passes errors (see [`RayRenderer::resume_frame()`]).
+    #[allow(clippy::too_many_arguments)]
+    fn progressive_frame(
+        &self,
+        world: &HitTree,
+        cam: &Camera,
+        env: &Environment,
+        mut film: Film,
+        checkpoints: Option<&Checkpointer>,
+        cancel: Option<&CancelToken>,
+        pass_samples: NonZeroU64,
+        mut on_pass: impl FnMut(usize, &Frame),
+    ) -> Result<(Frame, usize), Self::Error>
+    where
+        Self: Sized,
+    {
+        let start: u64 = cam.region().pixels().map(|pixel| film.get(pixel).n()).min().unwrap_or(0);
+        let mut pass: usize = 0;
+        loop {
+            pass += 1;
+            let limit: u64 = start.saturating_add(pass_samples.get().saturating_mul(pass as u64));
+            info!("Rendering pass {pass} (up to {} samples per pixel)...", limit.min(cam.max_samples()));
+            let frame: Frame = self.resume_frame(world, &cam.with_sample_limit(Some(limit)), env, film, checkpoints, cancel)?;
+            if limit >= cam.max_samples() || frame.interrupted() {
+                return Ok((frame, pass));
+            }
+            on_pass(pass, &frame);
+            film = frame.film;
+        }
+    }
 }
 
 
@@ -52,6 +149,128 @@ pub trait RayRenderer: Debug {
 
 
 /***** LIBRARY *****/
+/// Defines everything produced by rendering a single frame.
+///
+/// The frame consists of named [`Image`] buffers. It always has the rendered image itself (named
+/// [`Frame::BEAUTY`], ready to be shown), followed by a [`Frame::HEATMAP`] of the number of samples taken for every
+/// pixel if the camera sampled adaptively, and the raw values of every [`Aov`] (named after
+/// [`Aov::name()`]) if the film kept track of them. Next to those, it keeps the
+/// [linear](Frame::linear()) image that the rendered image is developed from.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// The rendered image before tone mapping or gamma correction.
+    linear:      Image,
+    /// How the rendered image is developed from the linear one.
+    develop:     (ToneMapping, bool),
+    /// The buffers of the frame, in order, by name.
+    buffers:     Vec<(String, Image)>,
+    /// Whether rendering was stopped before all samples were taken.
+    interrupted: bool,
+    /// All samples taken for the frame, to write to a checkpoint.
+    pub film:    Film,
+}
+impl Frame {
+    /// The name of the rendered image itself.
+    pub const BEAUTY: &'static str = "beauty";
+    /// The name of the heatmap of the number of samples taken for every pixel.
+    pub const HEATMAP: &'static str = "heatmap";
+
+
+    /// Constructor for the Frame that develops all buffers from a rendered film.
+    ///
+    /// # Arguments
+    /// - `film`: The [`Film`] with all samples taken for the frame.
+    /// - `cam`: The [`Camera`] that took them, which also determines how they are
+    ///   [tone mapped](Camera::tone_mapping()).
+    /// - `gamma_correction`: Whether to apply gamma correction to the rendered image.
+    ///
+    /// # Returns
+    /// A new Frame with the buffers described in the [`Frame`]'s documentation.
+    pub fn new(film: Film, cam: &Camera, gamma_correction: bool) -> Self {
+        let linear: Image = film.linear();
+        let mut buffers: Vec<(String, Image)> = vec![(Self::BEAUTY.into(), linear.develop(cam.tone_mapping(), gamma_correction))];
+        if cam.adaptive().is_some() {
+            buffers.push((Self::HEATMAP.into(), heatmap(&film, cam)));
+        }
+        for aov in Aov::ALL {
+            if let Some(image) = film.aov(aov) {
+                buffers.push((aov.name().into(), image));
+            }
+        }
+        Self { linear, develop: (cam.tone_mapping(), gamma_correction), buffers, interrupted: false, film }
+    }
+
+    /// Marks whether the frame was interrupted, i.e., whether rendering it was stopped before all samples were taken.
+    ///
+    /// # Arguments
+    /// - `interrupted`: Whether the frame was interrupted.
+    ///
+    /// # Returns
+    /// The same Frame, but marked as given.
+    #[inline]
+    pub fn with_interrupted(mut self, interrupted: bool) -> Self {
+        self.interrupted = interrupted;
+        self
+    }
+
+    /// Replaces the linear image of the frame, and develops the rendered image from it again (in
+    /// the same way as before).
+    ///
+    /// # Arguments
+    /// - `linear`: The new linear [`Image`]. Should have the same dimensions as the frame.
+    pub fn set_linear(&mut self, linear: Image) {
+        self.insert(Self::BEAUTY, linear.develop(self.develop.0, self.develop.1));
+        self.linear = linear;
+    }
+
+    /// Adds a buffer to the frame, or replaces the one with the same name.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the buffer.
+    /// - `image`: The [`Image`] with its contents. Should have the same dimensions as the frame.
+    pub fn insert(&mut self, name: impl Into<String>, image: Image) {
+        let name: String = name.into();
+        match self.buffers.iter_mut().find(|(other, _)| *other == name) {
+            Some((_, buffer)) => *buffer = image,
+            None => self.buffers.push((name, image)),
+        }
+    }
+
+    /// Returns whether rendering the frame was stopped before all samples were taken.
+    ///
+    /// Every pixel of an interrupted frame is still averaged over the samples it got, but some may
+    /// have gotten fewer than asked for (or none at all, in which case they are transparent in the
+    /// [linear](Frame::linear()) image).
+    #[inline]
+    pub fn interrupted(&self) -> bool { self.interrupted }
+
+    /// Returns the rendered image itself.
+    #[inline]
+    pub fn image(&self) -> &Image { self.get(Self::BEAUTY).unwrap_or_else(|| panic!("Frame has no '{}' buffer", Self::BEAUTY)) }
+
+    /// Returns the rendered image before tone mapping or gamma correction, as written to HDR formats.
+    ///
+    /// Pixels that weren't rendered are transparent.
+    #[inline]
+    pub fn linear(&self) -> &Image { &self.linear }
+
+    /// Returns the buffer with the given name.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the buffer to return.
+    ///
+    /// # Returns
+    /// The [`Image`] with its contents, or [`None`] if there is no such buffer.
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&Image> { self.buffers.iter().find(|(other, _)| other == name).map(|(_, image)| image) }
+
+    /// Returns all buffers of the frame, in order, as `(name, buffer)`-pairs.
+    #[inline]
+    pub fn buffers(&self) -> impl Iterator<Item = (&str, &Image)> { self.buffers.iter().map(|(name, image)| (name.as_str(), image)) }
+}
+
+
+
 /// Defines the collection of all our renderers.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
 pub enum RenderBackend {
@@ -61,4 +280,61 @@ pub enum RenderBackend {
     /// Renders rays multi-threaded.
     #[clap(name = "multi", alias = "multi_threaded", alias = "multi-threaded")]
     MultiThreaded,
+    /// Hands out tiles to worker processes over TCP (see `raytracer serve-worker`).
+    #[clap(name = "farm")]
+    Farm,
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use std::num::{NonZeroU32, NonZeroU64};
+
+    use super::*;
+    use crate::math::{Filter, FilterKind};
+    use crate::render::backends::SingleThreadRenderer;
+    use crate::render::integrators::{AnyIntegrator, PathTracer};
+    use crate::render::lights::MisHeuristic;
+    use crate::specifications::covers::{Book, cover};
+    use crate::specifications::scene::SceneFile;
+
+    #[test]
+    fn test_progressive_frame() {
+        let mut scene: SceneFile = cover(Book::OneWeekend, 1000, 42);
+        scene.camera.dims = (NonZeroU32::new(12).unwrap(), NonZeroU32::new(8).unwrap());
+        scene.camera.n_samples = NonZeroU64::new(5).unwrap();
+        scene.camera.filter = Filter { kind: FilterKind::Gaussian, radius: None };
+        let integrator: AnyIntegrator = PathTracer { max_depth: 8, roulette_depth: 3, roulette_clamp: 0.95, mis: MisHeuristic::Power }.into();
+        let cam: Camera = Camera::from(scene.camera);
+        let world: HitTree = HitTree::with_objs(scene.objects, (0..=scene.camera.shutter_time.into()).into());
+        let env: &Environment = &scene.environment;
+        let renderer = SingleThreadRenderer::new(false, integrator, 42, true);
+        let expected: Frame = renderer.render_frame(&world, &cam, env).unwrap();
+
+        // Rendering in passes gives the same frame, and only previews the passes before the last
+        for (pass_samples, n_passes, previews) in [(1, 5, vec![1, 2, 3, 4]), (2, 3, vec![1, 2]), (5, 1, vec![]), (8, 1, vec![])] {
+            let mut seen: Vec<usize> = Vec::new();
+            let (frame, passes): (Frame, usize) = renderer
+                .progressive_frame(&world, &cam, env, Film::new(cam.dims()), None, None, NonZeroU64::new(pass_samples).unwrap(), |pass, _| {
+                    seen.push(pass)
+                })
+                .unwrap();
+            assert_eq!(passes, n_passes, "{pass_samples} sample(s) per pass");
+            assert_eq!(seen, previews, "{pass_samples} sample(s) per pass");
+            assert_eq!(format!("{:?}", frame.film), format!("{:?}", expected.film), "{pass_samples} sample(s) per pass");
+        }
+
+        // Passes continue from the samples already taken
+        let partial: Frame = renderer.resume_frame(&world, &cam.with_sample_limit(Some(3)), env, Film::new(cam.dims()), None, None).unwrap();
+        let mut seen: Vec<usize> = Vec::new();
+        let (frame, passes): (Frame, usize) =
+            renderer.progressive_frame(&world, &cam, env, partial.film, None, None, NonZeroU64::new(1).unwrap(), |pass, _| seen.push(pass)).unwrap();
+        assert_eq!(passes, 2);
+        assert_eq!(seen, vec![1]);
+        assert_eq!(format!("{:?}", frame.film), format!("{:?}", expected.film));
+    }
 }
@@ -0,0 +1,1054 @@
+//  PIXEL.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines how a single pixel is rendered, i.e., how many samples it
+//!   takes and how they are combined. This is shared by all backends,
+//!   such that they all render the same pixel the same way.
+//!
+//!   Also defines the [`Film`] that keeps track of all samples of a
+//!   frame (and, optionally, of the [`Aov`]s of the surfaces they hit),
+//!   such that more can be added to it later. Samples are reconstructed
+//!   into pixels with the camera's [`Filter`].
+//
+
+use std::fmt::{Display, Formatter, Result as FResult};
+
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use super::aov::Aov;
+use super::image::Image;
+use super::integrators::{Aovs, Integrator, RayColour};
+use super::lights::LightList;
+use crate::common::input::Region;
+use crate::hittree::HitTree;
+use crate::math::{Camera, Colour, Filter, PixelSampler, Ray, ToneMapping, Vec3};
+use crate::specifications::scene::Environment;
+
+
+/***** CONSTANTS *****/
+/// The scale of the fixed-point numbers in which [`Splats`] sum the filtered samples (i.e., they
+/// have 64 fractional bits).
+///
+/// Unlike floating-point numbers, these always sum to the same, regardless of the order in which
+/// the samples are added. That's what makes a frame come out the same no matter how it's split
+/// over threads, workers or passes, even if the filter spreads samples over multiple tiles.
+const SPLAT_SCALE: f64 = 18_446_744_073_709_551_616.0;
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Computes the perceived brightness of a colour.
+#[inline]
+fn luminance(colour: Colour) -> f64 { 0.2126 * colour.r + 0.7152 * colour.g + 0.0722 * colour.b }
+
+/// Maps a fraction to a colour going from black (`0.0`) through red and yellow to white (`1.0`).
+fn heat(frac: f64) -> Colour {
+    let frac: f64 = frac.clamp(0.0, 1.0) * 3.0;
+    Colour::new(frac.min(1.0), (frac - 1.0).clamp(0.0, 1.0), (frac - 2.0).clamp(0.0, 1.0), 1.0)
+}
+
+/// Stores a vector in the colour channels of an [`Image`].
+#[inline]
+fn vec_colour(vec: Vec3) -> Colour { Colour::new(vec.x, vec.y, vec.z, 0.0) }
+
+/// Reads a vector from the colour channels of an [`Image`].
+#[inline]
+fn colour_vec(colour: Colour) -> Vec3 { Vec3::new(colour.r, colour.g, colour.b) }
+
+/// Converts a value to the fixed-point representation in which [`Splats`] sum it.
+///
+/// Values too large to represent saturate (and NaNs become zero).
+#[inline]
+fn to_fixed(value: f64) -> i128 { (value * SPLAT_SCALE).round() as i128 }
+
+/// Converts a value from the fixed-point representation in which [`Splats`] sum it.
+#[inline]
+fn from_fixed(value: i128) -> f64 { value as f64 / SPLAT_SCALE }
+
+
+
+
+
+/***** LIBRARY *****/
+/// Keeps track of the samples taken for a single pixel.
+///
+/// Next to the sum of all samples, it keeps track of the variance of their brightness (using
+/// Welford's algorithm), such that we know how noisy the pixel still is.
+#[derive(Clone, Copy, Debug)]
+pub struct PixelStats {
+    /// The number of samples taken.
+    n:    u64,
+    /// The sum of all samples.
+    sum:  Colour,
+    /// The running mean of the brightness of the samples.
+    mean: f64,
+    /// The running sum of squared differences from the mean brightness.
+    m2:   f64,
+    /// The AOVs of the samples, if we keep track of them.
+    aovs: Option<AovStats>,
+}
+
+impl Default for PixelStats {
+    #[inline]
+    fn default() -> Self { Self::new() }
+}
+impl PixelStats {
+    /// Constructor for the PixelStats that has not seen any samples yet.
+    ///
+    /// # Returns
+    /// A new PixelStats instance.
+    #[inline]
+    pub fn new() -> Self { Self { n: 0, sum: Colour::zeroes(), mean: 0.0, m2: 0.0, aovs: None } }
+
+    /// Constructor for the PixelStats that has not seen any samples yet, and that keeps track of
+    /// their [`Aov`]s.
+    ///
+    /// # Returns
+    /// A new PixelStats instance.
+    #[inline]
+    pub fn with_aovs() -> Self { Self { aovs: Some(AovStats::default()), ..Self::new() } }
+
+    /// Adds a new sample to the pixel.
+    ///
+    /// # Arguments
+    /// - `colour`: The colour of the new sample.
+    pub fn add(&mut self, colour: Colour) {
+        self.n += 1;
+        self.sum += colour;
+        let lum: f64 = luminance(colour);
+        let delta: f64 = lum - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (lum - self.mean);
+    }
+
+    /// Adds the [`Aovs`] of a new sample to the pixel.
+    ///
+    /// Does nothing if the pixel doesn't keep track of them, or if the sample didn't hit anything.
+    ///
+    /// # Arguments
+    /// - `aovs`: The [`Aovs`] of the new sample, if it hit anything.
+    #[inline]
+    pub fn add_aovs(&mut self, aovs: Option<Aovs>) {
+        if let (Some(stats), Some(aovs)) = (&mut self.aovs, aovs) {
+            stats.add(aovs);
+        }
+    }
+
+    /// Adds all samples of another PixelStats to this one.
+    ///
+    /// This combines the statistics exactly (using Chan et al.'s parallel variant of Welford's
+    /// algorithm), as if all samples had been added to one of them.
+    ///
+    /// # Arguments
+    /// - `other`: The other PixelStats to add.
+    pub fn merge(&mut self, other: &PixelStats) {
+        if other.n == 0 {
+            return;
+        }
+        let n: u64 = self.n + other.n;
+        let delta: f64 = other.mean - self.mean;
+        self.mean += delta * other.n as f64 / n as f64;
+        self.m2 += other.m2 + delta * delta * (self.n as f64 * other.n as f64) / n as f64;
+        self.sum += other.sum;
+        self.n = n;
+        match (&mut self.aovs, &other.aovs) {
+            (Some(stats), Some(other)) => stats.merge(other),
+            // We can't tell the AOVs of the other's samples, so we don't know them anymore
+            (Some(_), None) => self.aovs = None,
+            (None, _) => {},
+        }
+    }
+
+    /// Returns the estimated error of the pixel's current colour.
+    ///
+    /// This is the standard error of the mean brightness, scaled by how visible it is after gamma
+    /// correction (which takes the square root, and thus scales errors by `1 / (2 * sqrt(mean))`).
+    ///
+    /// # Returns
+    /// The estimated error, or infinity if fewer than two samples have been taken.
+    pub fn error(&self) -> f64 {
+        if self.n < 2 {
+            return f64::INFINITY;
+        }
+        let variance: f64 = self.m2 / (self.n - 1) as f64;
+        let std_error: f64 = (variance / self.n as f64).sqrt();
+        std_error / (2.0 * self.mean.max(0.0).sqrt()).max(1e-3)
+    }
+
+    /// Returns the number of samples taken.
+    #[inline]
+    pub const fn n(&self) -> u64 { self.n }
+
+    /// Returns the colour of the pixel, i.e., the average of all samples.
+    #[inline]
+    pub fn colour(&self) -> Colour { if self.n > 0 { self.sum * (1.0 / self.n as f64) } else { self.sum } }
+
+    /// Returns the raw value of an [`Aov`] of the pixel.
+    ///
+    /// This is the average over all samples that hit anything, except for [`Aov::Object`], which
+    /// is the index of the object hit by the first of them. The alpha channel is the fraction of
+    /// samples that hit anything.
+    ///
+    /// # Arguments
+    /// - `aov`: The [`Aov`] to return.
+    ///
+    /// # Returns
+    /// The value of the [`Aov`], or [`None`] if the pixel doesn't keep track of them.
+    pub fn aov(&self, aov: Aov) -> Option<Colour> {
+        let stats: &AovStats = self.aovs.as_ref()?;
+        if stats.hits == 0 {
+            return Some(Colour::zeroes());
+        }
+        let (scale, coverage): (f64, f64) = (1.0 / stats.hits as f64, stats.hits as f64 / self.n.max(stats.hits) as f64);
+        let value: Colour = match aov {
+            Aov::Depth => Colour::new(stats.depth * scale, stats.depth * scale, stats.depth * scale, 0.0),
+            Aov::Normal => vec_colour(stats.normal * scale),
+            Aov::Albedo => stats.albedo * scale,
+            Aov::Position => vec_colour(stats.position * scale),
+            Aov::Uv => Colour::new(stats.uv.0 * scale, stats.uv.1 * scale, 0.0, 0.0),
+            Aov::Object => {
+                let index: f64 = stats.object.unwrap_or(0) as f64;
+                Colour::new(index, index, index, 0.0)
+            },
+        };
+        Some(Colour { a: coverage, ..value })
+    }
+}
+
+
+
+/// Keeps track of the [`Aovs`] of the samples taken for a single pixel.
+#[derive(Clone, Copy, Debug)]
+struct AovStats {
+    /// The number of samples that hit anything.
+    hits:     u64,
+    /// The sum of their depths.
+    depth:    f64,
+    /// The sum of their normals.
+    normal:   Vec3,
+    /// The sum of their albedos.
+    albedo:   Colour,
+    /// The sum of their positions.
+    position: Vec3,
+    /// The sum of their texture coordinates.
+    uv:       (f64, f64),
+    /// The index of the object hit by the first of them.
+    object:   Option<usize>,
+}
+impl Default for AovStats {
+    #[inline]
+    fn default() -> Self {
+        Self { hits: 0, depth: 0.0, normal: Vec3::zeroes(), albedo: Colour::zeroes(), position: Vec3::zeroes(), uv: (0.0, 0.0), object: None }
+    }
+}
+impl AovStats {
+    /// Adds the [`Aovs`] of a sample that hit something.
+    fn add(&mut self, aovs: Aovs) {
+        self.hits += 1;
+        self.depth += aovs.depth;
+        self.normal += aovs.normal;
+        self.albedo += aovs.albedo;
+        self.position += aovs.position;
+        self.uv = (self.uv.0 + aovs.uv.0, self.uv.1 + aovs.uv.1);
+        self.object = self.object.or(Some(aovs.object));
+    }
+
+    /// Adds all samples of another AovStats to this one.
+    fn merge(&mut self, other: &AovStats) {
+        self.hits += other.hits;
+        self.depth += other.depth;
+        self.normal += other.normal;
+        self.albedo += other.albedo;
+        self.position += other.position;
+        self.uv = (self.uv.0 + other.uv.0, self.uv.1 + other.uv.1);
+        self.object = self.object.or(other.object);
+    }
+}
+
+
+
+/// Keeps track of the samples taken for every pixel of a frame.
+///
+/// Unlike an [`Image`], this keeps the unnormalised sum of all samples (and the statistics needed
+/// for adaptive sampling), such that more samples can be added to it later. It serializes
+/// losslessly, which is what makes it suitable for checkpoints.
+///
+/// Next to that, it keeps the weighted sum of the samples that every pixel got through the
+/// [`Filter`] (see [`Film::splat()`]), which is what the final image is made of. Since samples may
+/// land outside of the pixels of a Film, Films of tiles can keep a margin around them, which is
+/// added to the neighbouring pixels when moving them into the whole frame.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "FilmData")]
+pub struct Film {
+    /// The sum of all samples of every pixel.
+    #[serde(with = "super::image::lossless")]
+    sum:      Image,
+    /// The number of samples (red channel), the running mean brightness (green channel) and the
+    /// running sum of squared differences from it (blue channel) of every pixel.
+    #[serde(with = "super::image::lossless")]
+    stats:    Image,
+    /// The filtered samples of every pixel.
+    weighted: Splats,
+    /// The sums of the [`Aov`]s of every pixel, if we keep track of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    aovs:     Option<Box<AovFilm>>,
+}
+
+impl Film {
+    /// Constructor for a Film that has not seen any samples yet.
+    ///
+    /// # Arguments
+    /// - `dims`: The dimensions of the frame, as a `(width, height)`-pair.
+    ///
+    /// # Returns
+    /// A new Film instance.
+    #[inline]
+    pub fn new(dims: (u32, u32)) -> Self { Self { sum: Image::new(dims), stats: Image::new(dims), weighted: Splats::new(dims, 0), aovs: None } }
+
+    /// Constructor for a Film that has not seen any samples yet, and that keeps track of the
+    /// [`Aov`]s of the samples taken.
+    ///
+    /// # Arguments
+    /// - `dims`: The dimensions of the frame, as a `(width, height)`-pair.
+    ///
+    /// # Returns
+    /// A new Film instance.
+    #[inline]
+    pub fn with_aovs(dims: (u32, u32)) -> Self { Self { aovs: Some(Box::new(AovFilm::new(dims))), ..Self::new(dims) } }
+
+    /// Constructor for a Film that has not seen any samples yet, but that keeps track of the same
+    /// things as this one.
+    ///
+    /// # Arguments
+    /// - `dims`: The dimensions of the new frame, as a `(width, height)`-pair.
+    ///
+    /// # Returns
+    /// A new Film instance.
+    #[inline]
+    pub fn empty_like(&self, dims: (u32, u32)) -> Self { if self.has_aovs() { Self::with_aovs(dims) } else { Self::new(dims) } }
+
+    /// Changes the margin around the Film in which the filtered samples of its pixels are kept.
+    ///
+    /// This is meant for Films of tiles, which are [moved](Film::move_into()) into the whole frame
+    /// later. Without a margin, samples that land outside of the Film are dropped.
+    ///
+    /// # Arguments
+    /// - `margin`: The number of pixels to keep on every side (see [`Filter::margin()`]).
+    ///
+    /// # Returns
+    /// The same Film, but with the given margin. Filtered samples that fall outside of it are
+    /// dropped.
+    pub fn with_margin(mut self, margin: u32) -> Self {
+        let mut weighted: Splats = Splats::new(self.dims(), margin);
+        weighted.add(&self.weighted, (0, 0));
+        self.weighted = weighted;
+        self
+    }
+
+    /// Returns the samples taken so far for the given pixel.
+    ///
+    /// # Arguments
+    /// - `pixel`: The pixel to get, as an `(x, y)`-pair.
+    ///
+    /// # Returns
+    /// The [`PixelStats`] of the pixel.
+    ///
+    /// # Panics
+    /// This function panics if the pixel is out-of-bounds.
+    #[inline]
+    pub fn get(&self, pixel: (u32, u32)) -> PixelStats {
+        let stats: Colour = self.stats[pixel];
+        PixelStats { n: stats.r as u64, sum: self.sum[pixel], mean: stats.g, m2: stats.b, aovs: self.aovs.as_ref().map(|aovs| aovs.get(pixel)) }
+    }
+
+    /// Overwrites the samples taken for the given pixel.
+    ///
+    /// If this Film keeps track of [`Aov`]s but the `stats` don't, then the pixel's are reset.
+    ///
+    /// This does not touch the filtered samples, which are added with [`Film::splat()`] instead.
+    ///
+    /// # Arguments
+    /// - `pixel`: The pixel to set, as an `(x, y)`-pair.
+    /// - `stats`: The new [`PixelStats`] of the pixel.
+    ///
+    /// # Panics
+    /// This function panics if the pixel is out-of-bounds.
+    #[inline]
+    pub fn set(&mut self, pixel: (u32, u32), stats: PixelStats) {
+        self.sum[pixel] = stats.sum;
+        self.stats[pixel] = Colour::new(stats.n as f64, stats.mean, stats.m2, 1.0);
+        if let Some(aovs) = &mut self.aovs {
+            aovs.set(pixel, stats.aovs.unwrap_or_default());
+        }
+    }
+
+    /// Adds a sample to the pixels around it, weighted by a [`Filter`].
+    ///
+    /// Parts of the filter that fall outside of the Film (and its margin) are dropped. Because the
+    /// pixels are normalised by the sum of the weights they got, this simply means that pixels on
+    /// the edge of the frame are made of fewer samples.
+    ///
+    /// # Arguments
+    /// - `pixel`: The pixel in which the sample was taken, as an `(x, y)`-pair.
+    /// - `offset`: The offset of the sample from the centre of that pixel (see [`Camera::sample()`]).
+    /// - `colour`: The colour of the sample.
+    /// - `filter`: The [`Filter`] that decides how much the sample counts for every pixel.
+    pub fn splat(&mut self, pixel: (u32, u32), offset: (f64, f64), colour: Colour, filter: &Filter) {
+        let reach: i64 = filter.margin() as i64;
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let weight: f64 = filter.weight(offset.0 - dx as f64, offset.1 - dy as f64);
+                if weight != 0.0 {
+                    let at: (i64, i64) = (pixel.0 as i64 + dx, pixel.1 as i64 + dy);
+                    self.weighted.add_at(at, Colour::new(colour.r * weight, colour.g * weight, colour.b * weight, weight));
+                }
+            }
+        }
+    }
+
+    /// Moves a smaller Film into this one, overwriting the pixels it covers.
+    ///
+    /// Its filtered samples (including those in its margin) are added to ours instead, since the
+    /// samples of neighbouring pixels land there too.
+    ///
+    /// # Arguments
+    /// - `other`: The other Film to move into this one.
+    /// - `pos`: The position of the other Film's top-left pixel in this one.
+    ///
+    /// # Panics
+    /// This function panics if the other Film does not fit at the given position, or if this Film
+    /// keeps track of [`Aov`]s but the other doesn't.
+    #[inline]
+    pub fn move_into(&mut self, other: Film, pos: (u32, u32)) {
+        self.sum.move_into(other.sum, pos);
+        self.stats.move_into(other.stats, pos);
+        self.weighted.add(&other.weighted, (pos.0 as i64, pos.1 as i64));
+        match (&mut self.aovs, other.aovs) {
+            (Some(aovs), Some(other)) => aovs.move_into(*other, pos),
+            (Some(_), None) => panic!("Cannot move film without AOVs into film with AOVs"),
+            (None, _) => {},
+        }
+    }
+
+    /// Adds all samples of another Film to this one.
+    ///
+    /// This can be used to combine renders of different regions, or renders of the same pixels
+    /// with different seeds (which are then weighted by the number of samples they took).
+    ///
+    /// If only this Film keeps track of [`Aov`]s, then it stops doing so, since we don't know
+    /// those of the other's samples.
+    ///
+    /// # Arguments
+    /// - `other`: The other Film to add.
+    ///
+    /// # Panics
+    /// This function panics if the Films have different dimensions.
+    pub fn merge(&mut self, other: &Film) {
+        assert_eq!(self.dims(), other.dims(), "Cannot merge films of different dimensions");
+        if other.aovs.is_none() {
+            self.aovs = None;
+        }
+        for pixel in Region::full(self.dims()).pixels() {
+            let mut stats: PixelStats = self.get(pixel);
+            stats.merge(&other.get(pixel));
+            self.set(pixel, stats);
+        }
+        self.weighted.add(&other.weighted, (0, 0));
+    }
+
+    /// Computes the image that the samples taken so far average to.
+    ///
+    /// Every pixel is the weighted average of the samples that were [splatted](Film::splat()) to
+    /// it, i.e., normalised by the sum of their weights. Pixels that didn't get any (positive)
+    /// weight fall back to the plain average of their own samples.
+    ///
+    /// # Returns
+    /// A new (linear, unclamped) [`Image`] with the colour of every pixel.
+    pub fn image(&self) -> Image {
+        let mut res: Image = Image::new(self.dims());
+        for pixel in Region::full(self.dims()).pixels() {
+            res[pixel] = self.average(pixel);
+        }
+        res
+    }
+
+    /// Computes the colour that the samples taken so far average to for a single pixel.
+    ///
+    /// This is the same as that pixel of [`Film::image()`], but cheaper if only a few pixels are
+    /// needed (e.g., for previews).
+    ///
+    /// # Arguments
+    /// - `pixel`: The pixel to compute, as an `(x, y)`-pair.
+    ///
+    /// # Returns
+    /// The (linear, unclamped) [`Colour`] of the pixel, or [`None`] if it hasn't taken any samples.
+    ///
+    /// # Panics
+    /// This function panics if the pixel is out-of-bounds.
+    #[inline]
+    pub fn colour(&self, pixel: (u32, u32)) -> Option<Colour> { if self.stats[pixel].r > 0.0 { Some(self.average(pixel)) } else { None } }
+
+    /// Computes the weighted average of the samples of a pixel (see [`Film::image()`]).
+    fn average(&self, (x, y): (u32, u32)) -> Colour {
+        let weighted: Colour = self.weighted.get((x as i64, y as i64));
+        let (sum, n): (Colour, f64) = (self.sum[(x, y)], self.stats[(x, y)].r);
+        let mean: Colour = if n > 0.0 { sum * (1.0 / n) } else { sum };
+        if weighted.a > 0.0 { Colour { a: mean.a, ..weighted * (1.0 / weighted.a) } } else { mean }
+    }
+
+    /// Computes the linear image from the samples taken so far, as it's written to HDR formats.
+    ///
+    /// Unlike [`Film::image()`], pixels with samples are opaque, and those without any samples
+    /// (e.g., outside of the rendered region) are transparent.
+    ///
+    /// # Returns
+    /// A new (linear, unclamped) [`Image`] with the colour of every pixel.
+    pub fn linear(&self) -> Image {
+        let mut res: Image = self.image();
+        for (colour, stats) in res.iter_mut().zip(self.stats.iter()) {
+            *colour = if stats.r > 0.0 { colour.opaque() } else { Colour::zeroes() };
+        }
+        res
+    }
+
+    /// Computes the final image to show from the samples taken so far.
+    ///
+    /// Tone mapping and gamma correction are applied to the averaged linear colours, so they're
+    /// only applied once no matter how many Films were merged. Pixels without any samples (e.g.,
+    /// outside of the rendered region) are left transparent.
+    ///
+    /// # Arguments
+    /// - `tone_mapping`: The [`ToneMapping`] that maps the colours to the `[0, 1]` range.
+    /// - `gamma_correction`: Whether to apply gamma correction.
+    ///
+    /// # Returns
+    /// A new [`Image`] that can be written to disk.
+    #[inline]
+    pub fn develop(&self, tone_mapping: ToneMapping, gamma_correction: bool) -> Image { self.linear().develop(tone_mapping, gamma_correction) }
+
+    /// Computes the raw values of an [`Aov`] from the samples taken so far.
+    ///
+    /// See [`PixelStats::aov()`] for what they are. Pixels without any samples, or of which no
+    /// sample hit anything, are left transparent.
+    ///
+    /// # Arguments
+    /// - `aov`: The [`Aov`] to compute.
+    ///
+    /// # Returns
+    /// A new [`Image`] with the values of the [`Aov`], or [`None`] if this Film doesn't keep track
+    /// of them.
+    pub fn aov(&self, aov: Aov) -> Option<Image> {
+        if !self.has_aovs() {
+            return None;
+        }
+        let mut res: Image = Image::new(self.dims());
+        for pixel in Region::full(self.dims()).pixels() {
+            res[pixel] = self.get(pixel).aov(aov)?;
+        }
+        Some(res)
+    }
+
+    /// Returns whether this Film keeps track of the [`Aov`]s of its samples.
+    #[inline]
+    pub fn has_aovs(&self) -> bool { self.aovs.is_some() }
+
+    /// Returns the dimensions of the frame.
+    #[inline]
+    pub fn dims(&self) -> (u32, u32) { self.sum.dims() }
+}
+
+/// The raw form of a [`Film`] before we checked its buffers fit together.
+#[derive(Deserialize)]
+struct FilmData {
+    #[serde(with = "super::image::lossless")]
+    sum:      Image,
+    #[serde(with = "super::image::lossless")]
+    stats:    Image,
+    #[serde(default)]
+    weighted: Option<Splats>,
+    #[serde(default)]
+    aovs:     Option<Box<AovFilm>>,
+}
+impl TryFrom<FilmData> for Film {
+    type Error = FilmDimsError;
+
+    #[inline]
+    fn try_from(value: FilmData) -> Result<Self, Self::Error> {
+        let mut buffers: Vec<(&'static str, &Image)> = vec![("statistics", &value.stats)];
+        if let Some(aovs) = &value.aovs {
+            buffers.extend([("AOVs", &aovs.depth_uv), ("AOVs", &aovs.normal), ("AOVs", &aovs.albedo), ("AOVs", &aovs.position), ("AOVs", &aovs.object)]);
+        }
+        for (what, buffer) in buffers {
+            if value.sum.dims() != buffer.dims() {
+                return Err(FilmDimsError { sum: value.sum.dims(), what, other: buffer.dims() });
+            }
+        }
+
+        // Films written before we had filters are box filtered, which means every pixel simply got its own samples
+        let weighted: Splats = match value.weighted {
+            Some(weighted) => {
+                if value.sum.dims() != weighted.dims {
+                    return Err(FilmDimsError { sum: value.sum.dims(), what: "filtered samples", other: weighted.dims });
+                }
+                weighted
+            },
+            None => {
+                let mut weighted: Splats = Splats::new(value.sum.dims(), 0);
+                for (sums, (sum, stats)) in weighted.weighted.iter_mut().zip(value.sum.iter().zip(value.stats.iter())) {
+                    *sums = [sum.r, sum.g, sum.b, stats.r].map(to_fixed);
+                }
+                weighted
+            },
+        };
+        Ok(Self { sum: value.sum, stats: value.stats, weighted, aovs: value.aovs })
+    }
+}
+
+/// Defines the error for when the buffers of a deserialized [`Film`] have different dimensions.
+#[derive(Debug)]
+pub struct FilmDimsError {
+    /// The dimensions of the sum of the samples.
+    sum:   (u32, u32),
+    /// What the other buffer contains.
+    what:  &'static str,
+    /// The dimensions of the other buffer.
+    other: (u32, u32),
+}
+impl Display for FilmDimsError {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "Film has samples of {}x{} pixels but {} of {}x{} pixels", self.sum.0, self.sum.1, self.what, self.other.0, self.other.1)
+    }
+}
+
+
+
+/// Keeps track of the filtered samples of every pixel of a [`Film`].
+///
+/// The samples are summed in fixed-point (see [`SPLAT_SCALE`]), such that it doesn't matter in
+/// which order they are added.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "SplatsData")]
+struct Splats {
+    /// The sum of the colours of the samples times their weights (first three channels) and the
+    /// sum of those weights (last channel) of every pixel, including those in the margin.
+    weighted: Vec<[i128; 4]>,
+    /// The dimensions of the Film, excluding the margin.
+    dims:     (u32, u32),
+    /// The number of pixels around the Film's own ones that are kept too.
+    margin:   u32,
+}
+impl Splats {
+    /// Constructor for Splats of the given size that have not seen any samples yet.
+    #[inline]
+    fn new(dims: (u32, u32), margin: u32) -> Self {
+        Self { weighted: vec![[0; 4]; (dims.0 + 2 * margin) as usize * (dims.1 + 2 * margin) as usize], dims, margin }
+    }
+
+    /// Returns the index of a pixel (relative to the Film's own top-left pixel), or [`None`] if it falls outside of the margin.
+    #[inline]
+    fn index(&self, (x, y): (i64, i64)) -> Option<usize> {
+        let margin: i64 = self.margin as i64;
+        let (width, height): (i64, i64) = (self.dims.0 as i64 + 2 * margin, self.dims.1 as i64 + 2 * margin);
+        let (x, y): (i64, i64) = (x + margin, y + margin);
+        if x < 0 || x >= width || y < 0 || y >= height {
+            return None;
+        }
+        Some((y * width + x) as usize)
+    }
+
+    /// Adds a weighted colour to a pixel (relative to the Film's own top-left pixel), dropping it if it falls outside of the margin.
+    #[inline]
+    fn add_at(&mut self, pixel: (i64, i64), colour: Colour) {
+        if let Some(i) = self.index(pixel) {
+            for (sum, channel) in self.weighted[i].iter_mut().zip([colour.r, colour.g, colour.b, colour.a]) {
+                *sum = sum.saturating_add(to_fixed(channel));
+            }
+        }
+    }
+
+    /// Adds other Splats to (part of) these, dropping the pixels that fall outside of the margin.
+    ///
+    /// # Arguments
+    /// - `other`: The Splats to add.
+    /// - `pos`: The position of the other's top-left pixel (excluding its margin) relative to ours.
+    fn add(&mut self, other: &Splats, pos: (i64, i64)) {
+        let margin: i64 = other.margin as i64;
+        let width: i64 = other.dims.0 as i64 + 2 * margin;
+        for (i, sums) in other.weighted.iter().enumerate() {
+            let (x, y): (i64, i64) = (i as i64 % width - margin, i as i64 / width - margin);
+            if let Some(j) = self.index((pos.0 + x, pos.1 + y)) {
+                for (sum, other) in self.weighted[j].iter_mut().zip(sums) {
+                    *sum = sum.saturating_add(*other);
+                }
+            }
+        }
+    }
+
+    /// Returns the sums of a pixel (relative to the Film's own top-left pixel) as a [`Colour`].
+    ///
+    /// # Panics
+    /// This function panics if the pixel falls outside of the margin.
+    #[inline]
+    fn get(&self, pixel: (i64, i64)) -> Colour {
+        let sums: [i128; 4] = self.weighted[self.index(pixel).unwrap_or_else(|| panic!("Pixel {pixel:?} is out-of-bounds"))];
+        Colour::new(from_fixed(sums[0]), from_fixed(sums[1]), from_fixed(sums[2]), from_fixed(sums[3]))
+    }
+}
+impl Serialize for Splats {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes: Vec<u8> = self.weighted.iter().flatten().flat_map(|sum| sum.to_le_bytes()).collect();
+        SplatsData::Fixed { dims: self.dims, fixed: base64::prelude::BASE64_STANDARD.encode(bytes), margin: self.margin }.serialize(serializer)
+    }
+}
+
+/// The serialized form of [`Splats`].
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum SplatsData {
+    /// The fixed-point sums, as base64-encoded little-endian `i128`s.
+    Fixed {
+        dims:   (u32, u32),
+        fixed:  String,
+        #[serde(default)]
+        margin: u32,
+    },
+    /// The floating-point sums that Films were written with before we summed in fixed-point.
+    Float {
+        #[serde(with = "super::image::lossless")]
+        weighted: Image,
+        #[serde(default)]
+        margin:   u32,
+    },
+}
+impl TryFrom<SplatsData> for Splats {
+    type Error = String;
+
+    fn try_from(value: SplatsData) -> Result<Self, Self::Error> {
+        match value {
+            SplatsData::Fixed { dims, fixed, margin } => {
+                let mut res: Self = Self::new(dims, margin);
+                let bytes: Vec<u8> = base64::prelude::BASE64_STANDARD.decode(fixed).map_err(|err| err.to_string())?;
+                if bytes.len() != res.weighted.len() * 4 * 16 {
+                    return Err(format!("Expected {} bytes for filtered samples of {}x{} pixels, got {}", res.weighted.len() * 4 * 16, dims.0, dims.1, bytes.len()));
+                }
+                for (sum, bytes) in res.weighted.iter_mut().flatten().zip(bytes.chunks_exact(16)) {
+                    *sum = i128::from_le_bytes(bytes.try_into().unwrap_or_else(|_| unreachable!()));
+                }
+                Ok(res)
+            },
+            SplatsData::Float { weighted, margin } => {
+                let dims: (u32, u32) = (weighted.width().saturating_sub(2 * margin), weighted.height().saturating_sub(2 * margin));
+                let mut res: Self = Self::new(dims, margin);
+                if res.weighted.len() != weighted.len() {
+                    return Err(format!("Filtered samples of {}x{} pixels do not fit a margin of {margin}", weighted.width(), weighted.height()));
+                }
+                for (sum, colour) in res.weighted.iter_mut().zip(weighted.iter()) {
+                    *sum = [colour.r, colour.g, colour.b, colour.a].map(to_fixed);
+                }
+                Ok(res)
+            },
+        }
+    }
+}
+
+
+
+/// Keeps track of the sums of the [`Aov`]s of every pixel of a [`Film`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct AovFilm {
+    /// The sum of the depths (red channel) and texture coordinates (green and blue channels) of
+    /// the samples that hit anything, and the number of them (alpha channel).
+    #[serde(with = "super::image::lossless")]
+    depth_uv: Image,
+    /// The sum of the normals of the samples that hit anything.
+    #[serde(with = "super::image::lossless")]
+    normal:   Image,
+    /// The sum of the albedos of the samples that hit anything.
+    #[serde(with = "super::image::lossless")]
+    albedo:   Image,
+    /// The sum of the positions of the samples that hit anything.
+    #[serde(with = "super::image::lossless")]
+    position: Image,
+    /// The index of the object hit by the first sample that hit anything, plus one (red channel),
+    /// or zero if none did.
+    #[serde(with = "super::image::lossless")]
+    object:   Image,
+}
+impl AovFilm {
+    /// Constructor for an AovFilm of the given size that has not seen any samples yet.
+    #[inline]
+    fn new(dims: (u32, u32)) -> Self {
+        Self { depth_uv: Image::new(dims), normal: Image::new(dims), albedo: Image::new(dims), position: Image::new(dims), object: Image::new(dims) }
+    }
+
+    /// Returns the [`AovStats`] of the given pixel.
+    #[inline]
+    fn get(&self, pixel: (u32, u32)) -> AovStats {
+        let depth_uv: Colour = self.depth_uv[pixel];
+        let object: f64 = self.object[pixel].r;
+        AovStats {
+            hits:     depth_uv.a as u64,
+            depth:    depth_uv.r,
+            normal:   colour_vec(self.normal[pixel]),
+            albedo:   self.albedo[pixel],
+            position: colour_vec(self.position[pixel]),
+            uv:       (depth_uv.g, depth_uv.b),
+            object:   if object > 0.0 { Some(object as usize - 1) } else { None },
+        }
+    }
+
+    /// Overwrites the [`AovStats`] of the given pixel.
+    #[inline]
+    fn set(&mut self, pixel: (u32, u32), stats: AovStats) {
+        self.depth_uv[pixel] = Colour::new(stats.depth, stats.uv.0, stats.uv.1, stats.hits as f64);
+        self.normal[pixel] = vec_colour(stats.normal);
+        self.albedo[pixel] = stats.albedo;
+        self.position[pixel] = vec_colour(stats.position);
+        self.object[pixel] = Colour::new(stats.object.map(|i| i as f64 + 1.0).unwrap_or(0.0), 0.0, 0.0, 0.0);
+    }
+
+    /// Moves a smaller AovFilm into this one, overwriting the pixels it covers.
+    #[inline]
+    fn move_into(&mut self, other: AovFilm, pos: (u32, u32)) {
+        self.depth_uv.move_into(other.depth_uv, pos);
+        self.normal.move_into(other.normal, pos);
+        self.albedo.move_into(other.albedo, pos);
+        self.position.move_into(other.position, pos);
+        self.object.move_into(other.object, pos);
+    }
+}
+impl std::error::Error for FilmDimsError {}
+
+
+
+/// Renders a single pixel.
+///
+/// This takes the camera's number of samples for it. If the camera enables adaptive sampling,
+/// then it keeps taking samples until the pixel converges (or until the maximum is reached). If
+/// the camera has a [sample limit](Camera::with_sample_limit()), then it stops there for now.
+///
+/// Samples are numbered, and every sample only depends on the seed and its number. Thus, when
+/// continuing from samples taken earlier, only the missing ones are taken, and the result is the
+/// same as if they had been taken all at once.
+///
+/// If the `stats` keep track of [`Aov`]s, then so do the returned ones.
+///
+/// Next to that, every new sample is [splatted](Film::splat()) to the given `film` with the
+/// camera's [`Filter`].
+///
+/// # Arguments
+/// - `integrator`: The [`Integrator`] that computes the colour of every ray.
+/// - `cam`: The [`Camera`] to cast the rays with.
+/// - `pixel`: The pixel to render, as an `(x, y)`-pair.
+/// - `seed`: The seed from which all random numbers are derived.
+/// - `stats`: The [`PixelStats`] of the samples taken earlier, if any, to continue from.
+/// - `film`: The [`Film`] to splat the new samples to.
+/// - `at`: The position of the pixel in that `film`, as an `(x, y)`-pair.
+/// - `world`: The [`HitTree`] that contains the scene to render.
+/// - `lights`: The [`LightList`] of lights to sample directly.
+/// - `env`: The [`Environment`] of the scene.
+///
+/// # Returns
+/// The [`PixelStats`] of all the samples taken.
+#[allow(clippy::too_many_arguments)]
+pub fn render_pixel<I: Integrator + ?Sized>(
+    integrator: &I,
+    cam: &Camera,
+    pixel: (u32, u32),
+    seed: u64,
+    mut stats: PixelStats,
+    film: &mut Film,
+    at: (u32, u32),
+    world: &HitTree,
+    lights: &LightList,
+    env: &Environment,
+) -> PixelStats {
+    for s in stats.n()..cam.limited_samples() {
+        // Stop if we've done enough
+        if s >= cam.n_samples() {
+            match cam.adaptive() {
+                Some(adaptive) if stats.error() > adaptive.threshold => {},
+                _ => break,
+            }
+        }
+
+        // Take the next sample
+        let (ray, mut sampler, offset): (Ray, PixelSampler, (f64, f64)) = cam.sample(pixel, s, 0, seed);
+        let res: RayColour = integrator.ray_colour(ray, world, lights, env, &mut sampler);
+        film.splat(at, offset, res.colour, &cam.filter());
+        stats.add(res.colour);
+        stats.add_aovs(res.aovs);
+    }
+    stats
+}
+
+
+
+/// Turns the number of samples taken per pixel into a heatmap.
+///
+/// # Arguments
+/// - `film`: The [`Film`] with the samples of every pixel.
+/// - `cam`: The [`Camera`] that was used to render them, which determines the range of the map.
+///
+/// # Returns
+/// A new [`Image`] that is black where the fewest samples were taken, and white where the most were.
+pub fn heatmap(film: &Film, cam: &Camera) -> Image {
+    let (min, max): (f64, f64) = (cam.n_samples() as f64, cam.max_samples() as f64);
+    let mut res: Image = Image::new(film.dims());
+    for (colour, n) in res.iter_mut().zip(film.stats.iter()) {
+        *colour = heat(if max > min { (n.r - min) / (max - min) } else { 1.0 });
+    }
+    res
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::FilterKind;
+
+    #[test]
+    fn test_pixel_stats() {
+        // A constant pixel has no error at all
+        let mut stats = PixelStats::new();
+        assert_eq!(stats.error(), f64::INFINITY);
+        for _ in 0..4 {
+            stats.add(Colour::new(0.5, 0.5, 0.5, 1.0));
+        }
+        assert_eq!(stats.n(), 4);
+        assert!(stats.error() < 1e-12);
+        assert!((stats.colour().r - 0.5).abs() < 1e-12);
+
+        // A noisy one has, which shrinks with more samples
+        let mut stats = PixelStats::new();
+        for i in 0..4 {
+            stats.add(if i % 2 == 0 { Colour::new(1.0, 1.0, 1.0, 1.0) } else { Colour::BLACK });
+        }
+        let error: f64 = stats.error();
+        assert!(error > 0.0);
+        for i in 0..12 {
+            stats.add(if i % 2 == 0 { Colour::new(1.0, 1.0, 1.0, 1.0) } else { Colour::BLACK });
+        }
+        assert!(stats.error() < error);
+    }
+
+    #[test]
+    fn test_film() {
+        let mut film = Film::new((3, 2));
+        let mut stats = PixelStats::new();
+        stats.add(Colour::new(0.25, 0.5, 3.0, 1.0));
+        stats.add(Colour::new(0.5, 0.1, 1.0, 1.0));
+        film.set((2, 1), stats);
+
+        // The stats survive a round-trip through a checkpoint exactly
+        let film: Film = serde_json::from_str(&serde_json::to_string(&film).unwrap()).unwrap();
+        let other: PixelStats = film.get((2, 1));
+        assert_eq!(other.n(), 2);
+        assert_eq!(other.error(), stats.error());
+        assert_eq!(film.image()[(2u32, 1u32)], stats.colour());
+        assert_eq!(film.get((0, 0)).n(), 0);
+        assert_eq!(film.develop(ToneMapping::default(), false)[(0u32, 0u32)], Colour::zeroes());
+    }
+
+    #[test]
+    fn test_film_aovs() {
+        let aovs = |depth: f64, object: usize| Aovs {
+            depth,
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            albedo: Colour::new(0.5, 0.25, 1.0, 1.0),
+            position: Vec3::new(1.0, 2.0, 3.0),
+            uv: (0.5, 0.75),
+            object,
+        };
+        let mut film = Film::with_aovs((2, 2));
+        let mut stats: PixelStats = film.get((1, 0));
+        for sample in [Some(aovs(1.0, 3)), None, Some(aovs(2.0, 5)), None] {
+            stats.add(Colour::BLACK);
+            stats.add_aovs(sample);
+        }
+        film.set((1, 0), stats);
+
+        // AOVs are averaged over the samples that hit anything, except the object
+        let film: Film = serde_json::from_str(&serde_json::to_string(&film).unwrap()).unwrap();
+        assert_eq!(film.aov(Aov::Depth).unwrap()[(1u32, 0u32)], Colour::new(1.5, 1.5, 1.5, 0.5));
+        assert_eq!(film.aov(Aov::Object).unwrap()[(1u32, 0u32)], Colour::new(3.0, 3.0, 3.0, 0.5));
+        assert_eq!(film.aov(Aov::Uv).unwrap()[(1u32, 0u32)], Colour::new(0.5, 0.75, 0.0, 0.5));
+        assert_eq!(film.aov(Aov::Normal).unwrap()[(0u32, 0u32)], Colour::zeroes());
+
+        // Films without them don't have any
+        assert!(Film::new((2, 2)).aov(Aov::Depth).is_none());
+        let mut merged: Film = film.clone();
+        merged.merge(&Film::new((2, 2)));
+        assert!(!merged.has_aovs());
+    }
+
+    #[test]
+    fn test_film_filter() {
+        let samples: Vec<_> = (0..24u32)
+            .map(|i| ((i % 4, (i / 4) % 3), ((i % 5) as f64 * 0.2 - 0.5, (i % 3) as f64 * 0.3 - 0.4), Colour::new(i as f64 * 0.1, 0.5, 1.0, 1.0)))
+            .collect();
+
+        // The box filter averages the samples of every pixel, like without filtering
+        let (mut film, mut stats) = (Film::new((4, 3)), vec![PixelStats::new(); 12]);
+        for (pixel, offset, colour) in &samples {
+            film.splat(*pixel, *offset, *colour, &Filter::default());
+            stats[(pixel.1 * 4 + pixel.0) as usize].add(*colour);
+        }
+        for (i, stats) in stats.into_iter().enumerate() {
+            film.set((i as u32 % 4, i as u32 / 4), stats);
+        }
+        let image: Image = film.image();
+        assert!((image[(1u32, 2u32)].r - film.get((1, 2)).colour().r).abs() < 1e-12);
+
+        // Wider filters reach other tiles, which is kept in their margin
+        let filter = Filter { kind: FilterKind::Tent, radius: None };
+        let (mut whole, mut tiles) = (Film::new((4, 3)), [Film::new((2, 3)).with_margin(1), Film::new((2, 3)).with_margin(1)]);
+        for (pixel, offset, colour) in &samples {
+            whole.splat(*pixel, *offset, *colour, &filter);
+            tiles[pixel.0 as usize / 2].splat((pixel.0 % 2, pixel.1), *offset, *colour, &filter);
+        }
+        let mut stitched = Film::new((4, 3));
+        for (i, tile) in tiles.into_iter().enumerate() {
+            let tile: Film = serde_json::from_str(&serde_json::to_string(&tile).unwrap()).unwrap();
+            stitched.move_into(tile, (2 * i as u32, 0));
+        }
+        let (whole, stitched): (Image, Image) = (whole.image(), stitched.image());
+        assert!(whole.iter().eq(stitched.iter()));
+        assert!(whole.iter().all(|colour| (colour.g - 0.5).abs() < 1e-12));
+        assert_ne!(whole[(1u32, 1u32)].r, image[(1u32, 1u32)].r);
+
+        // Filtered samples written as floats (before we summed in fixed-point) can still be read
+        let mut old = Image::new((2u32, 1u32));
+        old[(1u32, 0u32)] = Colour::new(0.25, 0.5, 1.5, 2.0);
+        let mut raw: Vec<u8> = b"{\"margin\":0,\"weighted\":".to_vec();
+        super::super::image::lossless::serialize(&old, &mut serde_json::Serializer::new(&mut raw)).unwrap();
+        raw.push(b'}');
+        let splats: Splats = serde_json::from_slice(&raw).unwrap();
+        assert_eq!((splats.dims, splats.get((1, 0))), ((2, 1), Colour::new(0.25, 0.5, 1.5, 2.0)));
+    }
+
+    #[test]
+    fn test_pixel_stats_merge() {
+        // Merging gives the same as adding everything to one
+        let samples: Vec<Colour> = (0..7).map(|i| Colour::new(i as f64 * 0.1, 0.3, (i * i) as f64 * 0.05, 1.0)).collect();
+        let mut all = PixelStats::new();
+        samples.iter().for_each(|s| all.add(*s));
+        let (mut first, mut second) = (PixelStats::new(), PixelStats::new());
+        samples[..3].iter().for_each(|s| first.add(*s));
+        samples[3..].iter().for_each(|s| second.add(*s));
+        first.merge(&second);
+        assert_eq!(first.n(), all.n());
+        assert!((first.colour().r - all.colour().r).abs() < 1e-12);
+        assert!((first.error() - all.error()).abs() < 1e-12);
+    }
+}
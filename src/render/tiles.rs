@@ -0,0 +1,186 @@
+//  TILES.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines how to split a frame into [`Tile`]s, and in which order to
+//!   render them.
+//
+
+use serde::{Deserialize, Serialize};
+
+
+/***** HELPER FUNCTIONS *****/
+/// Converts a distance along a Hilbert curve to a coordinate on it.
+///
+/// # Arguments
+/// - `n`: The size of the (square) grid the curve fills. Must be a power of two.
+/// - `d`: The distance along the curve.
+///
+/// # Returns
+/// The `(x, y)`-coordinate on the grid.
+fn hilbert_to_xy(n: u32, mut d: u64) -> (u32, u32) {
+    let (mut x, mut y): (u64, u64) = (0, 0);
+    let mut s: u64 = 1;
+    while s < n as u64 {
+        let rx: u64 = 1 & (d / 2);
+        let ry: u64 = 1 & (d ^ rx);
+        // Rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        d /= 4;
+        s *= 2;
+    }
+    (x as u32, y as u32)
+}
+
+/// Orders the coordinates of a grid in a spiral, going outwards from its center.
+///
+/// # Arguments
+/// - `dims`: The size of the grid.
+///
+/// # Returns
+/// All coordinates in the grid, in spiral order.
+fn spiral(dims: (u32, u32)) -> Vec<(u32, u32)> {
+    let total: usize = dims.0 as usize * dims.1 as usize;
+    let mut res: Vec<(u32, u32)> = Vec::with_capacity(total);
+    let (mut x, mut y): (i64, i64) = ((dims.0 as i64 - 1) / 2, (dims.1 as i64 - 1) / 2);
+    let (mut dx, mut dy): (i64, i64) = (1, 0);
+    let mut len: u64 = 1;
+    while res.len() < total {
+        // Walk two legs of the same length, then grow
+        for _ in 0..2 {
+            for _ in 0..len {
+                if x >= 0 && y >= 0 && x < dims.0 as i64 && y < dims.1 as i64 {
+                    res.push((x as u32, y as u32));
+                }
+                x += dx;
+                y += dy;
+            }
+            (dx, dy) = (-dy, dx);
+        }
+        len += 1;
+    }
+    res
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines the orders in which [`Tile`]s can be rendered.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum TileOrder {
+    /// Renders the tiles row-by-row, from top to bottom.
+    #[default]
+    Scanline,
+    /// Renders the tiles in a spiral, starting at the center of the frame (where the interesting
+    /// stuff usually is).
+    Spiral,
+    /// Renders the tiles along a Hilbert curve, such that tiles rendered close in time are also
+    /// close in space.
+    Hilbert,
+}
+
+
+
+/// Defines a rectangular part of a frame that is rendered as one unit of work.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Tile {
+    /// The position of the tile's top-left pixel in the frame, as an `(x, y)`-pair.
+    pub pos:  (u32, u32),
+    /// The size of the tile, as a `(width, height)`-pair. This is smaller than the requested size
+    /// for the tiles at the right and bottom edges of the frame.
+    pub dims: (u32, u32),
+}
+
+impl Tile {
+    /// Returns an iterator over the pixels in this tile, in row-major order.
+    ///
+    /// # Returns
+    /// An iterator yielding the `(x, y)`-coordinates of every pixel, in frame coordinates.
+    #[inline]
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let Self { pos, dims } = *self;
+        (0..dims.1).flat_map(move |y| (0..dims.0).map(move |x| (pos.0 + x, pos.1 + y)))
+    }
+}
+
+
+
+/// Splits a frame into tiles.
+///
+/// # Arguments
+/// - `dims`: The size of the frame, as a `(width, height)`-pair.
+/// - `tile_size`: The (maximum) width and height of every tile.
+/// - `order`: The [`TileOrder`] in which to return them.
+///
+/// # Returns
+/// A list of [`Tile`]s that together cover the whole frame exactly once.
+pub fn tiles(dims: (u32, u32), tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let tile_size: u32 = tile_size.max(1);
+    let grid: (u32, u32) = (dims.0.div_ceil(tile_size), dims.1.div_ceil(tile_size));
+
+    // Find the order of the tiles in the grid
+    let coords: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline => (0..grid.1).flat_map(|y| (0..grid.0).map(move |x| (x, y))).collect(),
+        TileOrder::Spiral => spiral(grid),
+        TileOrder::Hilbert => {
+            // Walk a curve over the smallest power-of-two square around the grid, skipping anything outside of it
+            let n: u32 = grid.0.max(grid.1).max(1).next_power_of_two();
+            (0..n as u64 * n as u64).map(|d| hilbert_to_xy(n, d)).filter(|(x, y)| *x < grid.0 && *y < grid.1).collect()
+        },
+    };
+
+    // Turn them into tiles
+    coords
+        .into_iter()
+        .map(|(x, y)| {
+            let pos: (u32, u32) = (x * tile_size, y * tile_size);
+            Tile { pos, dims: (tile_size.min(dims.0 - pos.0), tile_size.min(dims.1 - pos.1)) }
+        })
+        .collect()
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            // Every pixel should be covered exactly once
+            let mut pixels: Vec<(u32, u32)> = tiles((70, 45), 16, order).iter().flat_map(Tile::pixels).collect();
+            pixels.sort_by_key(|(x, y)| (*y, *x));
+            assert_eq!(pixels, (0..45).flat_map(|y| (0..70).map(move |x| (x, y))).collect::<Vec<(u32, u32)>>(), "Wrong pixels for {order:?}");
+        }
+
+        // The edges are cut off
+        assert_eq!(tiles((20, 10), 16, TileOrder::Scanline), vec![Tile { pos: (0, 0), dims: (16, 10) }, Tile { pos: (16, 0), dims: (4, 10) }]);
+    }
+
+    #[test]
+    fn test_tile_orders() {
+        // Spiral starts in the center of the frame
+        assert_eq!(tiles((48, 48), 16, TileOrder::Spiral)[0].pos, (16, 16));
+
+        // Every next tile along the Hilbert curve neighbours the previous one
+        let hilbert: Vec<Tile> = tiles((64, 64), 16, TileOrder::Hilbert);
+        for pair in hilbert.windows(2) {
+            let dist: u32 = pair[0].pos.0.abs_diff(pair[1].pos.0) + pair[0].pos.1.abs_diff(pair[1].pos.1);
+            assert_eq!(dist, 16);
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//  TUI.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements a live preview of the frame being rendered in the
+//!   terminal, which is handy when rendering over SSH.
+//!
+//!   Every terminal cell shows two pixels of a downscaled version of the
+//!   frame, using an upper half block coloured with ANSI truecolour escape
+//!   codes. The progress of the render is shown next to it.
+//!
+//!   The render threads only take a (cheap) snapshot of the few pixels
+//!   that are shown every now and then; developing and drawing it happens
+//!   on a thread of its own.
+//
+
+use std::error;
+use std::fmt::{Display, Formatter, Result as FResult, Write as _};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use console::Term;
+use indicatif::{HumanCount, HumanDuration, HumanFloatCount};
+use log::debug;
+use parking_lot::Mutex;
+
+use super::pixel::Film;
+use crate::math::{Colour, ToneMapping};
+
+
+/***** CONSTANTS *****/
+/// A colour as it is sent to the terminal.
+type Rgb8 = (u8, u8, u8);
+
+/// The number of columns reserved for the statistics next to the preview.
+const STATS_WIDTH: u16 = 36;
+
+/// The width of the progress bar in the statistics.
+const BAR_WIDTH: usize = 24;
+
+/// The colour of pixels that haven't been rendered yet.
+const UNRENDERED: Rgb8 = (24, 24, 24);
+
+
+
+
+
+/***** ERRORS *****/
+/// Defines the errors that may occur when starting the terminal preview.
+#[derive(Debug)]
+pub enum Error {
+    /// The standard output is not a terminal.
+    NotATerminal,
+    /// The refresh rate is not a positive number.
+    IllegalRate { rate: f64 },
+    /// Failed to spawn the thread that draws the preview.
+    Spawn { err: std::io::Error },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use Error::*;
+        match self {
+            NotATerminal => write!(f, "Standard output is not a terminal"),
+            IllegalRate { rate } => write!(f, "Refresh rate must be a positive number, got {rate}"),
+            Spawn { .. } => write!(f, "Failed to spawn terminal preview thread"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+        match self {
+            NotATerminal => None,
+            IllegalRate { .. } => None,
+            Spawn { err } => Some(err),
+        }
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Turns a developed colour into 8-bit RGB.
+#[inline]
+fn rgb8(colour: Colour) -> Rgb8 {
+    let channel = |c: f64| -> u8 { (c.clamp(0.0, 1.0) * 255.0).round() as u8 };
+    (channel(colour.r), channel(colour.g), channel(colour.b))
+}
+
+/// Computes the size of the preview of a frame such that it fits in the given number of cells.
+///
+/// Every cell holds two (square) pixels on top of each other. The frame is never upscaled.
+///
+/// # Arguments
+/// - `dims`: The dimensions of the frame, as a `(width, height)`-pair.
+/// - `cells`: The number of cells available, as a `(columns, rows)`-pair.
+///
+/// # Returns
+/// The dimensions of the preview in pixels, as a `(width, height)`-pair.
+fn preview_dims(dims: (u32, u32), cells: (u16, u16)) -> (u32, u32) {
+    let scale: f64 = (cells.0 as f64 / dims.0 as f64).min(2.0 * cells.1 as f64 / dims.1 as f64).min(1.0);
+    (((dims.0 as f64 * scale) as u32).max(1), ((dims.1 as f64 * scale) as u32).max(1))
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Draws a live preview of the frame being rendered, and its progress, in the terminal.
+///
+/// Backends report their progress with [`Tui::start_pass()`] and [`Tui::progress()`], and call
+/// [`Tui::snapshot()`] with their film whenever [`Tui::due()`] says so. The preview is drawn on an
+/// alternate screen, which is left again when the Tui is [stopped](Tui::stop()).
+#[derive(Debug)]
+pub struct Tui {
+    /// The terminal to draw on.
+    term: Term,
+    /// The time between two refreshes.
+    interval: Duration,
+    /// How to map the rendered colours to ones that can be shown.
+    tone_mapping: ToneMapping,
+    /// Whether to apply gamma correction to them.
+    gamma_correction: bool,
+
+    /// When the Tui was started.
+    start: Instant,
+    /// When the next snapshot is due, in microseconds since `start`.
+    next:  AtomicU64,
+    /// Whether the drawing thread should stop.
+    stop:  AtomicBool,
+    /// The number of pixels rendered in the current pass.
+    pixels: AtomicU64,
+    /// The number of rays cast in the current pass.
+    rays:   AtomicU64,
+    /// The state shared with the drawing thread.
+    state:  Mutex<State>,
+    /// The drawing thread, until it's stopped.
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// The state of a [`Tui`] that changes less often.
+#[derive(Debug)]
+struct State {
+    /// The number of the current pass.
+    pass:     usize,
+    /// When the current pass started.
+    started:  Instant,
+    /// The number of pixels to render in the current pass.
+    total:    u64,
+    /// The number of cells available for the preview, as a `(columns, rows)`-pair.
+    cells:    (u16, u16),
+    /// The developed pixels of the last snapshot, top row first (or [`None`] for unrendered ones).
+    snapshot: Vec<Option<Rgb8>>,
+    /// The dimensions of the last snapshot.
+    preview:  (u32, u32),
+}
+
+impl Tui {
+    /// Starts drawing the preview in the terminal.
+    ///
+    /// # Arguments
+    /// - `rate`: The number of times per second to refresh the preview.
+    /// - `tone_mapping`: The [`ToneMapping`] to develop the preview with.
+    /// - `gamma_correction`: Whether to apply gamma correction to the preview.
+    ///
+    /// # Returns
+    /// A new Tui that is drawing.
+    ///
+    /// # Errors
+    /// This function errors if the standard output is not a terminal, the rate doesn't make sense
+    /// or we failed to spawn the drawing thread.
+    pub fn spawn(rate: f64, tone_mapping: ToneMapping, gamma_correction: bool) -> Result<Arc<Self>, Error> {
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err(Error::IllegalRate { rate });
+        }
+        let term: Term = Term::stdout();
+        if !term.is_term() {
+            return Err(Error::NotATerminal);
+        }
+
+        // Switch to the alternate screen, so we don't mess up whatever was there
+        if let Err(err) = term.write_str("\x1b[?1049h").and_then(|_| term.hide_cursor()) {
+            debug!("Failed to prepare terminal: {err}");
+        }
+        let tui: Arc<Self> = Arc::new(Self {
+            term,
+            interval: Duration::from_secs_f64(1.0 / rate),
+            tone_mapping,
+            gamma_correction,
+            start: Instant::now(),
+            next: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+            pixels: AtomicU64::new(0),
+            rays: AtomicU64::new(0),
+            state: Mutex::new(State { pass: 0, started: Instant::now(), total: 0, cells: (0, 0), snapshot: Vec::new(), preview: (0, 0) }),
+            thread: Mutex::new(None),
+        });
+
+        // Then draw on a thread of our own
+        let drawer: Arc<Self> = tui.clone();
+        let handle: JoinHandle<()> = std::thread::Builder::new()
+            .name("tui".into())
+            .spawn(move || {
+                while !drawer.stop.load(Ordering::Relaxed) {
+                    drawer.draw();
+                    std::thread::park_timeout(drawer.interval);
+                }
+                drawer.draw();
+            })
+            .map_err(|err| Error::Spawn { err })?;
+        *tui.thread.lock() = Some(handle);
+        Ok(tui)
+    }
+
+    /// Stops drawing, and restores the terminal.
+    ///
+    /// Does nothing if it was stopped already.
+    pub fn stop(&self) {
+        let Some(handle) = self.thread.lock().take() else { return };
+        self.stop.store(true, Ordering::Relaxed);
+        handle.thread().unpark();
+        if handle.join().is_err() {
+            debug!("Terminal preview thread panicked");
+        }
+        if let Err(err) = self.term.show_cursor().and_then(|_| self.term.write_str("\x1b[?1049l")) {
+            debug!("Failed to restore terminal: {err}");
+        }
+    }
+
+
+
+    /// Starts a new pass over the frame (or the frame itself, if not rendering progressively).
+    ///
+    /// # Arguments
+    /// - `total`: The number of pixels to render in it.
+    pub fn start_pass(&self, total: u64) {
+        let mut state = self.state.lock();
+        state.pass += 1;
+        state.started = Instant::now();
+        state.total = total;
+        self.pixels.store(0, Ordering::Relaxed);
+        self.rays.store(0, Ordering::Relaxed);
+    }
+
+    /// Reports progress on the current pass.
+    ///
+    /// # Arguments
+    /// - `pixels`: The number of pixels rendered since the last report.
+    /// - `rays`: The number of rays cast for them.
+    #[inline]
+    pub fn progress(&self, pixels: u64, rays: u64) {
+        self.pixels.fetch_add(pixels, Ordering::Relaxed);
+        self.rays.fetch_add(rays, Ordering::Relaxed);
+    }
+
+    /// Returns whether a new snapshot should be taken.
+    ///
+    /// This returns `true` to only one caller every refresh, which should then call
+    /// [`Tui::snapshot()`].
+    pub fn due(&self) -> bool {
+        let now: u64 = self.start.elapsed().as_micros() as u64;
+        let next: u64 = self.next.load(Ordering::Relaxed);
+        now >= next && self.next.compare_exchange(next, now + self.interval.as_micros() as u64, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+    }
+
+    /// Takes a snapshot of the pixels of a film that are shown in the preview.
+    ///
+    /// Only the pixels that are shown are read, so this is cheap enough to do while holding a lock
+    /// on the film.
+    ///
+    /// # Arguments
+    /// - `film`: The [`Film`] with the samples taken so far.
+    pub fn snapshot(&self, film: &Film) {
+        let cells: (u16, u16) = self.state.lock().cells;
+        if cells.0 == 0 || cells.1 == 0 {
+            return;
+        }
+        let dims: (u32, u32) = film.dims();
+        let preview: (u32, u32) = preview_dims(dims, cells);
+
+        // Sample the pixel in the middle of every preview pixel (remember that films are stored bottom-to-top)
+        let mut pixels: Vec<Option<Rgb8>> = Vec::with_capacity(preview.0 as usize * preview.1 as usize);
+        for j in 0..preview.1 {
+            let y: u32 = dims.1 - 1 - (((j as f64 + 0.5) * dims.1 as f64 / preview.1 as f64) as u32).min(dims.1 - 1);
+            for i in 0..preview.0 {
+                let x: u32 = (((i as f64 + 0.5) * dims.0 as f64 / preview.0 as f64) as u32).min(dims.0 - 1);
+                pixels.push(film.colour((x, y)).map(|colour| {
+                    let colour: Colour = self.tone_mapping.apply(&colour);
+                    rgb8(if self.gamma_correction { colour.to_srgb() } else { colour })
+                }));
+            }
+        }
+        let mut state = self.state.lock();
+        state.snapshot = pixels;
+        state.preview = preview;
+    }
+
+
+
+    /// Draws the last snapshot and the progress on the terminal.
+    fn draw(&self) {
+        let (rows, cols): (u16, u16) = self.term.size();
+        let cells: (u16, u16) = (cols.saturating_sub(STATS_WIDTH + 1), rows.saturating_sub(1));
+
+        // Collect the statistics
+        let (pixels, rays): (u64, u64) = (self.pixels.load(Ordering::Relaxed), self.rays.load(Ordering::Relaxed));
+        let mut state = self.state.lock();
+        state.cells = cells;
+        let elapsed: Duration = state.started.elapsed();
+        let frac: f64 = if state.total > 0 { (pixels as f64 / state.total as f64).min(1.0) } else { 0.0 };
+        let eta: Duration = if frac > 0.0 { elapsed.mul_f64((1.0 - frac) / frac) } else { Duration::ZERO };
+        let filled: usize = (frac * BAR_WIDTH as f64) as usize;
+        let stats: [String; 6] = [
+            format!("Pass {}", state.pass),
+            format!("Pixel {}/{}", HumanCount(pixels), HumanCount(state.total)),
+            format!("[{}{}] {:.0}%", "=".repeat(filled), " ".repeat(BAR_WIDTH - filled), frac * 100.0),
+            format!("Elapsed {}", HumanDuration(elapsed)),
+            format!("ETA {}", HumanDuration(eta)),
+            format!("{} rays/s", HumanFloatCount(rays as f64 / elapsed.as_secs_f64().max(1e-3))),
+        ];
+
+        // Draw the preview row-by-row, with the statistics next to the first rows
+        let mut out: String = String::from("\x1b[H");
+        let (snapshot, preview): (&[Option<Rgb8>], (u32, u32)) = (&state.snapshot, state.preview);
+        let n_rows: usize = (preview.1 as usize).div_ceil(2).max(stats.len());
+        for row in 0..n_rows.min(cells.1 as usize) {
+            for i in 0..preview.0 as usize {
+                let top: Rgb8 = snapshot.get(2 * row * preview.0 as usize + i).copied().flatten().unwrap_or(UNRENDERED);
+                if 2 * row + 1 < preview.1 as usize {
+                    let bottom: Rgb8 = snapshot.get((2 * row + 1) * preview.0 as usize + i).copied().flatten().unwrap_or(UNRENDERED);
+                    let _ = write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}", top.0, top.1, top.2, bottom.0, bottom.1, bottom.2);
+                } else if 2 * row < preview.1 as usize {
+                    let _ = write!(out, "\x1b[38;2;{};{};{}m\x1b[49m\u{2580}", top.0, top.1, top.2);
+                } else {
+                    out.push(' ');
+                }
+            }
+            let _ = write!(out, "\x1b[0m  {}\x1b[K\r\n", stats.get(row).map(String::as_str).unwrap_or(""));
+        }
+        out.push_str("\x1b[J");
+        drop(state);
+        if let Err(err) = self.term.write_str(&out) {
+            debug!("Failed to draw terminal preview: {err}");
+        }
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_dims() {
+        // Every cell holds two pixels, and we never upscale
+        assert_eq!(preview_dims((200, 100), (50, 50)), (50, 25));
+        assert_eq!(preview_dims((100, 200), (100, 25)), (25, 50));
+        assert_eq!(preview_dims((10, 10), (100, 100)), (10, 10));
+        assert_eq!(preview_dims((1000, 1), (10, 10)), (10, 1));
+    }
+}
@@ -0,0 +1,265 @@
+//  COVERS.rs
+//    by Lut99
+//
+//  Description:
+//!   Generates the scenes on the covers of the Raytracing In One Weekend
+//!   books.
+//
+
+use std::collections::BTreeMap;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::animations::{Animation, Vertical};
+use super::materials::{Dielectric, DiffuseLight, Isotropic, Lambertian, LambertianTexture, Material, Metal};
+use super::objects::plane::Qd;
+use super::objects::{AnimatedSphere, Box, ConstantDensity, Object, Quad, RotateY, Sphere, Translate};
+use super::scene::{Background, CameraInfo, CameraPos, Environment, SceneFile, default_camera_info_shutter_time};
+use super::textures::image::Image as TexImage;
+use super::textures::{SpatialChecker, Texture};
+use crate::hittree::HitTree;
+use crate::math::{AABB, Colour, Rng, Vec3};
+
+
+/***** HELPER FUNCTIONS *****/
+/// Generates the objects on the cover of the first book.
+///
+/// # Arguments
+/// - `rng`: The [`Rng`] that decides where the small spheres go.
+///
+/// # Returns
+/// The list of [`Object`]s in the scene.
+fn one_weekend(rng: &mut Rng) -> Vec<Object> {
+    let mut objects: Vec<Object> = Vec::with_capacity(1 + 21 * 21 + 3);
+    objects.push(Object::Sphere(Sphere {
+        center:   Vec3::new(0.0, -1000.0, 0.0),
+        radius:   1000.0,
+        material: Material::LambertianTexture(LambertianTexture {
+            texture: Texture::SpatialChecker(SpatialChecker {
+                scale: 0.32,
+                black: Colour::new(0.2, 0.3, 0.1, 1.0),
+                white: Colour::new(0.9, 0.9, 0.9, 1.0),
+            }),
+        }),
+    }));
+    for a in -11..11 {
+        for b in -11..11 {
+            let mat = rng.f64();
+            let center = Vec3::new(a as f64 + 0.9 * rng.f64(), 0.2, b as f64 + 0.9 * rng.f64());
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if mat < 0.8 {
+                    // It'll be a tiny diffuse sphere
+                    let colour = Colour::new(rng.f64(), rng.f64(), rng.f64(), 1.0);
+                    let sphere = Sphere { center, radius: 0.2, material: Material::Lambertian(Lambertian { colour }) };
+                    objects.push(if rng.f64() < 0.1 {
+                        Object::AnimatedSphere(AnimatedSphere {
+                            sphere,
+                            animation: Animation::Vertical(Vertical { len: 0.5 * rng.f64(), at: 0, duration: 1000 }),
+                        })
+                    } else {
+                        Object::Sphere(sphere)
+                    });
+                } else if mat < 0.95 {
+                    // Metal, with random fuzziness
+                    let colour = Colour::new(rng.f64() / 2.0 + 0.5, rng.f64() / 2.0 + 0.5, rng.f64() / 2.0 + 0.5, 1.0);
+                    let fuzz = rng.f64() / 2.0;
+                    objects.push(Object::Sphere(Sphere { center, radius: 0.2, material: Material::Metal(Metal { colour, fuzz }) }));
+                } else {
+                    // Glass
+                    objects.push(Object::Sphere(Sphere {
+                        center,
+                        radius: 0.2,
+                        material: Material::Dielectric(Dielectric { refraction_index: 1.5, colour: Colour::new(1.0, 1.0, 1.0, 1.0) }),
+                    }));
+                }
+            }
+        }
+    }
+    objects.push(Object::Sphere(Sphere {
+        center:   Vec3::new(0.0, 1.0, 0.0),
+        radius:   1.0,
+        material: Material::Dielectric(Dielectric { refraction_index: 1.5, colour: Colour::new(1.0, 1.0, 1.0, 1.0) }),
+    }));
+    objects.push(Object::Sphere(Sphere {
+        center:   Vec3::new(-4.0, 1.0, 0.0),
+        radius:   1.0,
+        material: Material::Lambertian(Lambertian { colour: Colour::new(0.4, 0.2, 0.1, 1.0) }),
+    }));
+    objects.push(Object::Sphere(Sphere {
+        center:   Vec3::new(4.0, 1.0, 0.0),
+        radius:   1.0,
+        material: Material::Metal(Metal { colour: Colour::new(0.7, 0.6, 0.5, 1.0), fuzz: 0.0 }),
+    }));
+    objects
+}
+
+/// Generates the objects on the cover of the second book.
+///
+/// # Arguments
+/// - `rng`: The [`Rng`] that decides the height of the floor and where the orbs go.
+/// - `shutter_time`: The shutter time (in microseconds) over which the blurry sphere moves.
+///
+/// # Returns
+/// The list of [`Object`]s in the scene.
+fn next_week(rng: &mut Rng, shutter_time: u64) -> Vec<Object> {
+    // Define materials
+    let ground = Material::Lambertian(Lambertian { colour: Colour::new(0.48, 0.83, 0.53, 1.0) });
+    let light = Material::DiffuseLight(DiffuseLight { colour: Colour::new(7.0, 7.0, 7.0, 1.0) });
+    let brown = Material::Lambertian(Lambertian { colour: Colour::new(0.7, 0.3, 0.1, 1.0) });
+    let glass = Material::Dielectric(Dielectric { colour: Colour::new(1.0, 1.0, 1.0, 1.0), refraction_index: 1.5 });
+    let grey_metal = Material::Metal(Metal { colour: Colour::new(0.8, 0.8, 0.9, 1.0), fuzz: 1.0 });
+    let earth = Material::LambertianTexture(LambertianTexture {
+        texture: Texture::Image(TexImage::ToLoad { path: PathBuf::from("tests/scenes/earthmap.jpg"), format: Some(image::ImageFormat::Jpeg) }),
+    });
+    let perlin_wink = Material::Lambertian(Lambertian { colour: Colour::new(0.5, 0.5, 0.5, 1.0) });
+    let white = Material::Lambertian(Lambertian { colour: Colour::new(0.73, 0.73, 0.73, 1.0) });
+
+    // Define the ground
+    let mut objects: Vec<Object> = Vec::with_capacity(1000);
+    const BOXES_PER_SIDE: u32 = 20;
+    for i in 0..BOXES_PER_SIDE {
+        for j in 0..BOXES_PER_SIDE {
+            // Compute the dimensions of each box
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64 * w;
+            let z0 = -1000.0 + j as f64 * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = rng.f64() * 100.0 + 1.0;
+            let z1 = z0 + w;
+            objects.push(Object::Box(Box { aabb: AABB::from_points(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1)), material: ground.clone() }));
+        }
+    }
+
+    // Define the ceiling light
+    objects.push(Object::Quad(Quad {
+        qd: Qd { pos: Vec3::new(123.0, 554.0, 147.0), u: Vec3::new(300.0, 0.0, 0.0), v: Vec3::new(0.0, 0.0, 265.0) },
+        material: light,
+    }));
+
+    // Define the blurry sphere
+    objects.push(Object::AnimatedSphere(AnimatedSphere {
+        sphere:    Sphere { center: Vec3::new(400.0, 400.0, 200.0), radius: 50.0, material: brown },
+        animation: Animation::Vertical(Vertical { len: 30.0, at: 0, duration: shutter_time }),
+    }));
+
+    // Define the loose glass & metal spheres
+    objects.push(Object::Sphere(Sphere { center: Vec3::new(260.0, 150.0, 45.0), radius: 50.0, material: glass.clone() }));
+    objects.push(Object::Sphere(Sphere { center: Vec3::new(0.0, 150.0, 145.0), radius: 50.0, material: grey_metal }));
+
+    // Define glossy sphere (a dense fog in a glass sphere)
+    let boundary = Object::Sphere(Sphere { center: Vec3::new(360.0, 150.0, 145.0), radius: 70.0, material: glass.clone() });
+    objects.push(boundary.clone());
+    objects.push(Object::ConstantDensity(ConstantDensity {
+        boundary: std::boxed::Box::new(boundary),
+        density: 0.2,
+        phase_function: Isotropic { colour: Colour::new(0.2, 0.4, 0.9, 1.0) },
+    }));
+
+    // Define the overall haze over the scene
+    objects.push(Object::ConstantDensity(ConstantDensity {
+        boundary: std::boxed::Box::new(Object::Sphere(Sphere { center: Vec3::new(0.0, 0.0, 0.0), radius: 5000.0, material: glass })),
+        density: 0.0001,
+        phase_function: Isotropic { colour: Colour::new(1.0, 1.0, 1.0, 1.0) },
+    }));
+
+    // Define the earthy sphere and perlin noise sphere (although we just use a blank lambertian sphere)
+    objects.push(Object::Sphere(Sphere { center: Vec3::new(400.0, 200.0, 400.0), radius: 100.0, material: earth }));
+    objects.push(Object::Sphere(Sphere { center: Vec3::new(220.0, 280.0, 300.0), radius: 80.0, material: perlin_wink }));
+
+    // Define the box made out of spheres
+    const NUMBER_OF_SPHERES: usize = 1000;
+    let mut orbs = Vec::with_capacity(NUMBER_OF_SPHERES);
+    for _ in 0..NUMBER_OF_SPHERES {
+        orbs.push(Object::Sphere(Sphere {
+            center:   Vec3::new(rng.f64() * 165.0, rng.f64() * 165.0, rng.f64() * 165.0),
+            radius:   10.0,
+            material: white.clone(),
+        }));
+    }
+    objects.push(Object::Translate(Translate {
+        pos: Vec3::new(-100.0, 270.0, 395.0),
+        obj: std::boxed::Box::new(Object::RotateY(RotateY {
+            angle: 15.0,
+            obj:   std::boxed::Box::new(Object::Group(std::boxed::Box::new(HitTree::with_objs(orbs, (0..=shutter_time).into())))),
+        })),
+    }));
+
+    // Done
+    objects
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines possible book covers.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, ValueEnum)]
+#[clap(rename_all = "snake_case")]
+pub enum Book {
+    #[clap(alias = "book1")]
+    OneWeekend,
+    #[clap(alias = "book2")]
+    NextWeek,
+}
+
+
+
+/// Generates the scene on the cover of one of the books.
+///
+/// Note that the scene is generated, not read from a file, so the returned [`SceneFile`] cannot
+/// be serialized (some of its objects are [`Object::Group`]s).
+///
+/// # Arguments
+/// - `book`: The [`Book`] of which to generate the cover.
+/// - `shutter_time`: The shutter time (in microseconds) of the camera. Since the covers are secretly animated, this reveals motion blur.
+/// - `seed`: The seed that decides the random parts of the scene.
+///
+/// # Returns
+/// A new [`SceneFile`] describing the cover, of which the external references still have to be
+/// loaded relative to the root of the repository.
+pub fn cover(book: Book, shutter_time: u64, seed: u64) -> SceneFile {
+    let mut rng: Rng = Rng::with_seed(seed);
+    let shutter: NonZeroU64 = NonZeroU64::new(shutter_time).unwrap_or(default_camera_info_shutter_time());
+    match book {
+        Book::OneWeekend => SceneFile {
+            environment: Environment::default(),
+            camera:      CameraInfo {
+                // SAFETY: This works because the value is not 0.
+                n_samples: unsafe { NonZeroU64::new_unchecked(100) },
+                vfov: 20.0,
+                defocus_angle: 0.6,
+                focus_dist: 10.0,
+                shutter_time: shutter,
+                pos: CameraPos { lookfrom: Vec3::new(13.0, 2.0, 3.0), lookat: Vec3::new(0.0, 0.0, 0.0), lookup: Vec3::new(0.0, 1.0, 0.0) },
+                ..Default::default()
+            },
+            definitions: BTreeMap::new(),
+            objects:     one_weekend(&mut rng),
+        },
+
+        Book::NextWeek => SceneFile {
+            environment: Environment { background: Background::None, ..Default::default() },
+            camera:      CameraInfo {
+                // SAFETY: This works because the value is not 0.
+                n_samples: unsafe { NonZeroU64::new_unchecked(5000) },
+                vfov: 40.0,
+                defocus_angle: 0.0,
+                focus_dist: 0.0,
+                shutter_time: shutter,
+                pos: CameraPos {
+                    lookfrom: Vec3::new(478.0, 278.0, -600.0),
+                    lookat:   Vec3::new(278.0, 278.0, 0.0),
+                    lookup:   Vec3::new(0.0, 1.0, 0.0),
+                },
+                ..Default::default()
+            },
+            definitions: BTreeMap::new(),
+            objects:     next_week(&mut rng, shutter_time),
+        },
+    }
+}
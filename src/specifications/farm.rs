@@ -0,0 +1,247 @@
+//  FARM.rs
+//    by Lut99
+//
+//  Description:
+//!   Defines the network protocol spoken between the coordinator of a
+//!   render farm (the `farm` backend) and its workers (started with
+//!   `raytracer serve-worker`).
+//!
+//!   # Framing
+//!   Workers listen on a TCP socket, to which the coordinator connects.
+//!   Every message is sent as a 4-byte, big-endian length, followed by
+//!   that many bytes of JSON encoding either a [`Request`] (coordinator to
+//!   worker) or a [`Response`] (worker to coordinator).
+//!
+//!   # Conversation
+//!   1. The coordinator sends [`Request::Hello`] with its
+//!      [`PROTOCOL_VERSION`], and the worker answers with
+//!      [`Response::Hello`] with its own. If they differ, the worker sends
+//!      a [`Response::Error`] instead and both hang up.
+//!   2. The coordinator sends [`Request::Scene`] with the scene to render,
+//!      with all external references (textures, models, ...) already
+//!      loaded such that the worker needs nothing but the message. The
+//!      worker answers with [`Response::Ready`] once it has built it.
+//!   3. The coordinator sends a [`Request::Render`] for one tile at a
+//!      time, which the worker answers with a [`Response::Rendered`].
+//!   4. Once all tiles are done, the coordinator sends [`Request::Goodbye`]
+//!      and both hang up. The worker then waits for a new coordinator.
+//!
+//!   At any point, the worker may answer with a [`Response::Error`] if it
+//!   cannot do what was asked, after which it hangs up.
+//!
+//!   # Dropouts
+//!   Every sample only depends on the seed, the pixel and the index of
+//!   the sample, so tiles may be rendered by any worker, in any order.
+//!   If a worker hangs up, misbehaves or doesn't answer in time, the
+//!   coordinator drops it and hands its tile to one of the others.
+//!
+//!   # Versioning
+//!   [`PROTOCOL_VERSION`] is bumped on every change to the framing or
+//!   the messages (including the scene format they carry).
+//
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::io::{Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::scene::SceneFile;
+use crate::render::integrators::AnyIntegrator;
+use crate::render::pixel::Film;
+use crate::render::tiles::Tile;
+
+
+/***** CONSTANTS *****/
+/// The version of the protocol implemented by this binary.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// The largest message we accept, in bytes, to avoid allocating whatever a misbehaving peer tells us to.
+pub const MAX_MESSAGE_SIZE: u32 = 1 << 30;
+
+
+
+
+
+/***** ERRORS *****/
+/// Defines errors that may occur when sending or receiving messages.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to serialize a message.
+    Serialize { err: serde_json::Error },
+    /// Failed to write a message to the socket.
+    Write { err: std::io::Error },
+    /// Failed to read a message from the socket.
+    Read { err: std::io::Error },
+    /// A message is larger than [`MAX_MESSAGE_SIZE`].
+    TooLarge { size: u64 },
+    /// Failed to deserialize a message.
+    Deserialize { err: serde_json::Error },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use Error::*;
+        match self {
+            Serialize { .. } => write!(f, "Failed to serialize message"),
+            Write { .. } => write!(f, "Failed to send message"),
+            Read { .. } => write!(f, "Failed to receive message"),
+            TooLarge { size } => write!(f, "Message of {size} bytes is larger than the maximum of {MAX_MESSAGE_SIZE} bytes"),
+            Deserialize { .. } => write!(f, "Failed to deserialize received message"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+        match self {
+            Serialize { err } => Some(err),
+            Write { err } => Some(err),
+            Read { err } => Some(err),
+            TooLarge { .. } => None,
+            Deserialize { err } => Some(err),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines the messages sent by the coordinator to a worker.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request<'a> {
+    /// Opens the conversation.
+    Hello {
+        /// The [`PROTOCOL_VERSION`] of the coordinator.
+        version: u32,
+    },
+    /// Defines what to render.
+    Scene {
+        /// The scene to render, with all external references loaded, and with the camera that
+        /// will be used to render it (i.e., with all overrides applied).
+        scene:      Box<Cow<'a, SceneFile>>,
+        /// The integrator that computes the colour of every ray.
+        integrator: Cow<'a, AnyIntegrator>,
+        /// The seed from which all random numbers are derived.
+        seed:       u64,
+    },
+    /// Asks the worker to render a tile.
+    Render {
+        /// The tile to render.
+        tile: Tile,
+        /// The samples taken so far for the pixels in the tile, as a film of the tile's size.
+        film:  Film,
+        /// If given, the number of samples after which pixels stop for now (see
+        /// [`Camera::with_sample_limit()`](crate::math::Camera::with_sample_limit())).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u64>,
+    },
+    /// Closes the conversation.
+    Goodbye,
+}
+
+/// Defines the messages sent by a worker to the coordinator.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    /// Answers the coordinator's [`Request::Hello`].
+    Hello {
+        /// The [`PROTOCOL_VERSION`] of the worker.
+        version: u32,
+    },
+    /// Answers a [`Request::Scene`] once it's ready to render it.
+    Ready,
+    /// Answers a [`Request::Render`].
+    Rendered {
+        /// The tile that was rendered.
+        tile: Tile,
+        /// All samples taken for the pixels in the tile (including the ones given in the request),
+        /// as a film of the tile's size.
+        film: Film,
+    },
+    /// Tells the coordinator the worker failed to do what was asked, after which it hangs up.
+    Error {
+        /// A description of what went wrong.
+        message: String,
+    },
+}
+
+
+
+/// Sends a message over a socket.
+///
+/// # Arguments
+/// - `writer`: The socket (or other [`Write`]r) to send the message on.
+/// - `msg`: The message to send.
+///
+/// # Errors
+/// This function errors if we failed to serialize the message or to write it.
+pub fn write_message<T: Serialize>(mut writer: impl Write, msg: &T) -> Result<(), Error> {
+    let raw: Vec<u8> = serde_json::to_vec(msg).map_err(|err| Error::Serialize { err })?;
+    if raw.len() > MAX_MESSAGE_SIZE as usize {
+        return Err(Error::TooLarge { size: raw.len() as u64 });
+    }
+    writer.write_all(&(raw.len() as u32).to_be_bytes()).map_err(|err| Error::Write { err })?;
+    writer.write_all(&raw).map_err(|err| Error::Write { err })?;
+    writer.flush().map_err(|err| Error::Write { err })
+}
+
+/// Receives a message from a socket.
+///
+/// # Arguments
+/// - `reader`: The socket (or other [`Read`]er) to receive the message from.
+///
+/// # Returns
+/// The received message.
+///
+/// # Errors
+/// This function errors if we failed to read the message or to deserialize it as a `T`.
+pub fn read_message<T: DeserializeOwned>(mut reader: impl Read) -> Result<T, Error> {
+    let mut size: [u8; 4] = [0; 4];
+    reader.read_exact(&mut size).map_err(|err| Error::Read { err })?;
+    let size: u32 = u32::from_be_bytes(size);
+    if size > MAX_MESSAGE_SIZE {
+        return Err(Error::TooLarge { size: size as u64 });
+    }
+    let mut raw: Vec<u8> = vec![0; size as usize];
+    reader.read_exact(&mut raw).map_err(|err| Error::Read { err })?;
+    serde_json::from_slice(&raw).map_err(|err| Error::Deserialize { err })
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::render::integrators::{DebugIntegrator, DebugView};
+    use crate::specifications::covers::{Book, cover};
+
+    #[test]
+    fn test_messages() {
+        // Send a few messages back-to-back and read them again
+        let scene: SceneFile = cover(Book::NextWeek, 1000, 42);
+        let integrator: AnyIntegrator = AnyIntegrator::Debug(DebugIntegrator { view: DebugView::Normals });
+        let mut raw: Vec<u8> = Vec::new();
+        write_message(&mut raw, &Request::Hello { version: PROTOCOL_VERSION }).unwrap();
+        write_message(&mut raw, &Request::Scene { scene: Box::new(Cow::Borrowed(&scene)), integrator: Cow::Borrowed(&integrator), seed: 42 }).unwrap();
+        write_message(&mut raw, &Request::Goodbye).unwrap();
+
+        let mut reader = Cursor::new(raw);
+        assert!(matches!(read_message(&mut reader).unwrap(), Request::Hello { version: PROTOCOL_VERSION }));
+        match read_message(&mut reader).unwrap() {
+            Request::Scene { scene: other, seed: 42, .. } => assert_eq!(format!("{:?}", other.objects), format!("{:?}", scene.objects)),
+            other => panic!("Expected scene, got {other:?}"),
+        }
+        assert!(matches!(read_message(&mut reader).unwrap(), Request::Goodbye));
+        assert!(matches!(read_message::<Request>(&mut reader), Err(Error::Read { .. })));
+    }
+}
@@ -15,7 +15,7 @@ use super::super::Loadable;
 use super::super::scene::Environment;
 use super::Scattering;
 use super::metal::reflect;
-use crate::math::{Colour, Ray, Vec3};
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
 use crate::specifications::objects::HitData;
 
 
@@ -90,7 +90,7 @@ impl Loadable for PartialDielectric {
 }
 impl Scattering for PartialDielectric {
     #[inline]
-    fn scatter(&self, ray: Ray, record: &HitData, env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, ray: Ray, record: &HitData, env: &Environment, _sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // NOTE: We are always assuming we are refracting against air here
         let eta_over_eta_prime: f64 =
             if record.front_face { env.air_refraction_index / self.refraction_index } else { self.refraction_index / env.air_refraction_index };
@@ -106,6 +106,9 @@ impl Scattering for PartialDielectric {
         // Then bounce the ray
         (Some(Ray::new(record.hit, refracted)), self.colour)
     }
+
+    #[inline]
+    fn albedo(&self, _record: &HitData) -> Colour { self.colour }
 }
 
 
@@ -133,7 +136,7 @@ impl Loadable for Dielectric {
 }
 impl Scattering for Dielectric {
     #[inline]
-    fn scatter(&self, ray: Ray, record: &HitData, env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, ray: Ray, record: &HitData, env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // NOTE: We are always assuming we are refracting against air here
         let eta_over_eta_prime: f64 =
             if record.front_face { env.air_refraction_index / self.refraction_index } else { self.refraction_index / env.air_refraction_index };
@@ -148,7 +151,7 @@ impl Scattering for Dielectric {
         let cannot_refract: bool = eta_over_eta_prime * sin_theta > 1.0;
 
         // Compute the refraction
-        let out: Vec3 = if cannot_refract || reflectance(cos_theta, eta_over_eta_prime) > fastrand::f64() {
+        let out: Vec3 = if cannot_refract || reflectance(cos_theta, eta_over_eta_prime) > sampler.f64() {
             reflect(unit_direction, record.normal)
         } else {
             refract(unit_direction, record.normal, cos_theta, eta_over_eta_prime)
@@ -157,4 +160,7 @@ impl Scattering for Dielectric {
         // Then bounce the ray
         (Some(Ray::new(record.hit, out)), self.colour)
     }
+
+    #[inline]
+    fn albedo(&self, _record: &HitData) -> Colour { self.colour }
 }
@@ -14,6 +14,7 @@
 //
 
 use std::convert::Infallible;
+use std::f64::consts::PI;
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
@@ -21,7 +22,7 @@ use serde::{Deserialize, Serialize};
 use super::super::Loadable;
 use super::super::scene::Environment;
 use super::Scattering;
-use crate::math::{Colour, Ray, Vec3};
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
 use crate::specifications::objects::HitData;
 use crate::specifications::textures::{Texture, Textured};
 
@@ -29,31 +30,41 @@ use crate::specifications::textures::{Texture, Textured};
 /***** HELPER FUNCTIONS *****/
 /// Generates a random, uniformly sampled vector in a unit sphere around the origin.
 ///
+/// # Arguments
+/// - `sampler`: The [`PixelSampler`] to draw random numbers from.
+///
 /// # Returns
 /// A new [`Vec3`] that represents the random vector.
-pub fn random3_uniform() -> Vec3 {
-    // // Generate the three coordinates randomly
-    // let res: Vec3 = Vec3 { x: fastrand::f64(), y: fastrand::f64(), z: fastrand::f64() };
-
-    // // Always return a unit vector version of this vector
-    // res.unit()
-
-    // We'll use a loop - sadly
-    loop {
-        let p = Vec3::new(1.0 - 2.0 * fastrand::f64(), 1.0 - 2.0 * fastrand::f64(), 1.0 - 2.0 * fastrand::f64());
-        let lensq = p.length2();
-        if lensq < 1.0 {
-            return p / lensq.sqrt();
-        }
-    }
+pub fn random3_uniform(sampler: &mut PixelSampler) -> Vec3 {
+    // Map a point in the unit square to the sphere directly, such that well-spread samples stay well-spread (unlike with rejection sampling)
+    let (u1, u2): (f64, f64) = sampler.f64_2d();
+    let z: f64 = 1.0 - 2.0 * u1;
+    let r: f64 = (1.0 - z * z).max(0.0).sqrt();
+    let phi: f64 = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
 /// Generates a random, uniformly sampled vector on a hemisphere w.r.t. the normal.
-pub fn random3_on_hemisphere(normal: Vec3) -> Vec3 {
-    let on_unit_sphere: Vec3 = random3_uniform();
+pub fn random3_on_hemisphere(normal: Vec3, sampler: &mut PixelSampler) -> Vec3 {
+    let on_unit_sphere: Vec3 = random3_uniform(sampler);
     if on_unit_sphere.dot(normal) > 0.0 { on_unit_sphere } else { -on_unit_sphere }
 }
 
+/// Computes the density with which Lambertian scattering (i.e., `normal + random3_uniform(sampler)`)
+/// picks the given direction.
+///
+/// Because Lambertian materials scatter with a cosine distribution, this is also their BSDF times
+/// the cosine term (without the colour).
+///
+/// # Arguments
+/// - `normal`: The (unit) surface normal to scatter around.
+/// - `direct`: The (unit) direction to compute the density of.
+///
+/// # Returns
+/// The density w.r.t. solid angle.
+#[inline]
+pub fn lambertian_pdf(normal: Vec3, direct: Vec3) -> f64 { f64::max(0.0, normal.dot(direct)) / PI }
+
 
 
 
@@ -73,11 +84,26 @@ impl Loadable for Diffuse {
 }
 impl Scattering for Diffuse {
     #[inline]
-    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // Return a ray scattered in a random direction
-        let direction: Vec3 = random3_on_hemisphere(record.normal);
-        (Some(Ray::new(record.hit, direction)), self.colour)
+        let direction: Vec3 = random3_on_hemisphere(record.normal, sampler);
+        (Some(Ray::new(record.hit, direction)), record.tint(self.colour))
     }
+
+    #[inline]
+    fn eval(&self, _ray: Ray, record: &HitData, direct: Vec3) -> Colour {
+        // Every direction in the hemisphere is equally likely, so the colour is spread out over it
+        if record.normal.dot(direct) > 0.0 { record.tint(self.colour) * (1.0 / (2.0 * PI)) } else { Colour::BLACK }
+    }
+
+    #[inline]
+    fn pdf(&self, _ray: Ray, record: &HitData, direct: Vec3) -> f64 { if record.normal.dot(direct) > 0.0 { 1.0 / (2.0 * PI) } else { 0.0 } }
+
+    #[inline]
+    fn is_diffuse(&self) -> bool { true }
+
+    #[inline]
+    fn albedo(&self, record: &HitData) -> Colour { record.tint(self.colour) }
 }
 
 
@@ -97,11 +123,17 @@ impl Loadable for DiffuseLight {
 impl Scattering for DiffuseLight {
     #[inline]
     fn emitted(&self, _uv: (f64, f64), _p: Vec3) -> Colour { self.colour }
+
+    #[inline]
+    fn albedo(&self, _record: &HitData) -> Colour { self.colour.clamp() }
 }
 
 
 
 /// A diffuse material with truer scattering.
+///
+/// Like the other diffuse materials, its colour is tinted by the colour of the surface if it has
+/// any (see [`HitData::tint()`]).
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub struct Lambertian {
     /// The colour of the material.
@@ -115,16 +147,28 @@ impl Loadable for Lambertian {
 }
 impl Scattering for Lambertian {
     #[inline]
-    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // Compute the scattered ray, making sure the scattered one is not zero
-        let mut scattered: Vec3 = record.normal + random3_uniform();
+        let mut scattered: Vec3 = record.normal + random3_uniform(sampler);
         if scattered.is_nearly_zero() {
             scattered = record.normal;
         }
 
         // Now we can simply return the new ray to bounce and the colour
-        (Some(Ray::new(record.hit, scattered)), self.colour)
+        (Some(Ray::new(record.hit, scattered)), record.tint(self.colour))
     }
+
+    #[inline]
+    fn eval(&self, _ray: Ray, record: &HitData, direct: Vec3) -> Colour { record.tint(self.colour) * lambertian_pdf(record.normal, direct) }
+
+    #[inline]
+    fn pdf(&self, _ray: Ray, record: &HitData, direct: Vec3) -> f64 { lambertian_pdf(record.normal, direct) }
+
+    #[inline]
+    fn is_diffuse(&self) -> bool { true }
+
+    #[inline]
+    fn albedo(&self, record: &HitData) -> Colour { record.tint(self.colour) }
 }
 
 
@@ -143,14 +187,28 @@ impl<T: Loadable> Loadable for LambertianTexture<T> {
 }
 impl<T: Textured> Scattering for LambertianTexture<T> {
     #[inline]
-    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // Compute the scattered ray, making sure the scattered one is not zero
-        let mut scattered: Vec3 = record.normal + random3_uniform();
+        let mut scattered: Vec3 = record.normal + random3_uniform(sampler);
         if scattered.is_nearly_zero() {
             scattered = record.normal;
         }
 
         // Now we can simply return the new ray to bounce and the colour
-        (Some(Ray::new(record.hit, scattered)), self.texture.value(record.uv, record.hit))
+        (Some(Ray::new(record.hit, scattered)), record.tint(self.texture.value(record.uv, record.hit)))
     }
+
+    #[inline]
+    fn eval(&self, _ray: Ray, record: &HitData, direct: Vec3) -> Colour {
+        record.tint(self.texture.value(record.uv, record.hit)) * lambertian_pdf(record.normal, direct)
+    }
+
+    #[inline]
+    fn pdf(&self, _ray: Ray, record: &HitData, direct: Vec3) -> f64 { lambertian_pdf(record.normal, direct) }
+
+    #[inline]
+    fn is_diffuse(&self) -> bool { true }
+
+    #[inline]
+    fn albedo(&self, record: &HitData) -> Colour { record.tint(self.texture.value(record.uv, record.hit)) }
 }
@@ -6,6 +6,7 @@
 //
 
 use std::convert::Infallible;
+use std::f64::consts::PI;
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
@@ -15,7 +16,7 @@ use super::super::objects::HitData;
 use super::super::scene::Environment;
 use super::Scattering;
 use super::diffuse::random3_uniform;
-use crate::math::{Colour, Ray, Vec3};
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
 
 
 /***** HELPER FUNCTIONS *****/
@@ -23,6 +24,39 @@ use crate::math::{Colour, Ray, Vec3};
 #[inline]
 pub fn reflect(vec: Vec3, norm: Vec3) -> Vec3 { vec - 2.0 * vec.dot(norm) * norm }
 
+/// Computes the density with which a fuzzed reflection picks the given direction.
+///
+/// The fuzzed reflection is made by adding a random vector on a sphere of radius `fuzz` to the end
+/// of the (unit) reflected vector. Any direction whose line crosses that sphere can thus be picked,
+/// and its density is the sum of the sphere's (uniform) area density at every crossing, converted
+/// to solid angle.
+///
+/// # Arguments
+/// - `reflected`: The (unit) perfectly reflected direction.
+/// - `fuzz`: The radius of the fuzz sphere.
+/// - `direct`: The (unit) direction to compute the density of.
+///
+/// # Returns
+/// The density w.r.t. solid angle.
+pub fn fuzz_pdf(reflected: Vec3, fuzz: f64, direct: Vec3) -> f64 {
+    // Solve `|t * direct - reflected| = fuzz` for `t`
+    let b: f64 = direct.dot(reflected);
+    let disc: f64 = b * b - (1.0 - fuzz * fuzz);
+    if disc <= 0.0 || fuzz <= 0.0 {
+        return 0.0;
+    }
+    let sqrtd: f64 = disc.sqrt();
+
+    // Every crossing at `t > 0` contributes `t^2 / (area * cos)`, where `cos = sqrt(disc) / fuzz`
+    let mut res: f64 = 0.0;
+    for t in [b - sqrtd, b + sqrtd] {
+        if t > 0.0 {
+            res += t * t;
+        }
+    }
+    res / (4.0 * PI * fuzz * sqrtd)
+}
+
 
 
 
@@ -47,15 +81,57 @@ impl Loadable for Metal {
 }
 impl Scattering for Metal {
     #[inline]
-    fn scatter(&self, ray: Ray, record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, ray: Ray, record: &HitData, _env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // Compute the scattered ray, making sure the scattered one is not zero
         let reflected: Vec3 = reflect(ray.direct, record.normal);
         // Add some fuzz by offsetting the endpoint of the reflected vector by a small amount.
         // This is done by randomly choosing a small vector on a sphere (who's radius is `fuzz`)
         // and then adding it to the end vector.
-        let reflected: Vec3 = reflected.unit() + self.fuzz * random3_uniform();
+        let reflected: Vec3 = reflected.unit() + self.fuzz * random3_uniform(sampler);
 
         // Now we can simply return the new ray to bounce and the colour
         (Some(Ray::new(record.hit, reflected)), self.colour)
     }
+
+    #[inline]
+    fn eval(&self, ray: Ray, record: &HitData, direct: Vec3) -> Colour { self.colour * self.pdf(ray, record, direct) }
+
+    #[inline]
+    fn pdf(&self, ray: Ray, record: &HitData, direct: Vec3) -> f64 { fuzz_pdf(reflect(ray.direct, record.normal).unit(), self.fuzz, direct) }
+
+    #[inline]
+    fn is_diffuse(&self) -> bool { self.fuzz > 0.0 }
+
+    #[inline]
+    fn albedo(&self, _record: &HitData) -> Colour { self.colour }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzz_pdf() {
+        // Integrate the density over the unit sphere, which should sum to one
+        const N: usize = 200000;
+        let golden: f64 = PI * (3.0 - 5.0f64.sqrt());
+        let reflected: Vec3 = Vec3::new(1.0, 2.0, 3.0).unit();
+        for fuzz in [0.3, 0.6, 0.9, 1.5] {
+            let mut sum: f64 = 0.0;
+            for i in 0..N {
+                // Fibonacci sphere points, which have roughly equal area each
+                let y: f64 = 1.0 - 2.0 * (i as f64 + 0.5) / N as f64;
+                let r: f64 = (1.0 - y * y).sqrt();
+                let phi: f64 = golden * i as f64;
+                sum += fuzz_pdf(reflected, fuzz, Vec3::new(r * phi.cos(), y, r * phi.sin()));
+            }
+            let total: f64 = sum * 4.0 * PI / N as f64;
+            assert!((total - 1.0).abs() < 0.02, "Density for fuzz {fuzz} integrates to {total}");
+        }
+    }
 }
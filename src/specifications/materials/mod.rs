@@ -37,7 +37,7 @@ pub use simple::{NormalMap, StaticColour};
 use thiserror::Error;
 
 use super::Loadable;
-use crate::math::{Colour, Ray, Vec3};
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
 use crate::specifications::objects::HitData;
 use crate::specifications::scene::Environment;
 
@@ -51,9 +51,21 @@ macro_rules! scattering_ptr_impl {
             fn emitted(&self, uv: (f64, f64), p: Vec3) -> Colour { <T as Scattering>::emitted(self, uv, p) }
 
             #[inline]
-            fn scatter(&self, ray: Ray, record: &HitData, env: &Environment) -> (Option<Ray>, Colour) {
-                <T as Scattering>::scatter(self, ray, record, env)
+            fn scatter(&self, ray: Ray, record: &HitData, env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
+                <T as Scattering>::scatter(self, ray, record, env, sampler)
             }
+
+            #[inline]
+            fn eval(&self, ray: Ray, record: &HitData, direct: Vec3) -> Colour { <T as Scattering>::eval(self, ray, record, direct) }
+
+            #[inline]
+            fn pdf(&self, ray: Ray, record: &HitData, direct: Vec3) -> f64 { <T as Scattering>::pdf(self, ray, record, direct) }
+
+            #[inline]
+            fn is_diffuse(&self) -> bool { <T as Scattering>::is_diffuse(self) }
+
+            #[inline]
+            fn albedo(&self, record: &HitData) -> Colour { <T as Scattering>::albedo(self, record) }
         }
     };
     ($ty:ty) => {
@@ -62,9 +74,21 @@ macro_rules! scattering_ptr_impl {
             fn emitted(&self, uv: (f64, f64), p: Vec3) -> Colour { <T as Scattering>::emitted(self, uv, p) }
 
             #[inline]
-            fn scatter(&self, ray: Ray, record: &HitData, env: &Environment) -> (Option<Ray>, Colour) {
-                <T as Scattering>::scatter(self, ray, record, env)
+            fn scatter(&self, ray: Ray, record: &HitData, env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
+                <T as Scattering>::scatter(self, ray, record, env, sampler)
             }
+
+            #[inline]
+            fn eval(&self, ray: Ray, record: &HitData, direct: Vec3) -> Colour { <T as Scattering>::eval(self, ray, record, direct) }
+
+            #[inline]
+            fn pdf(&self, ray: Ray, record: &HitData, direct: Vec3) -> f64 { <T as Scattering>::pdf(self, ray, record, direct) }
+
+            #[inline]
+            fn is_diffuse(&self) -> bool { <T as Scattering>::is_diffuse(self) }
+
+            #[inline]
+            fn albedo(&self, record: &HitData) -> Colour { <T as Scattering>::albedo(self, record) }
         }
     };
 }
@@ -99,14 +123,84 @@ pub trait Scattering {
     ///   and such.
     /// - `env`: An [`Environment`] object relating properties about the scene's global
     ///   environment.
+    /// - `sampler`: The [`PixelSampler`] to draw any random numbers from.
     ///
     /// # Returns
     /// A tuple that represents the bounced [`Ray`] and the attenuated colour from this bounce. If
     /// [`None`] is returned for the [`Ray`], then no more bounce is necessary.
-    fn scatter(&self, _ray: Ray, _record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, _ray: Ray, _record: &HitData, _env: &Environment, _sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         /* Standard impl: no scattering */
         (None, Colour::BLACK)
     }
+
+    /// Evaluates how much light this material scatters from a given direction towards the inbound
+    /// ray.
+    ///
+    /// This is the BSDF times the cosine of the given direction with the surface normal, such that
+    /// dividing it by [`Scattering::pdf()`] gives the same attenuation as [`Scattering::scatter()`]
+    /// would have returned for that direction.
+    ///
+    /// # Arguments
+    /// - `ray`: The inbound [`Ray`] that hit the material.
+    /// - `record`: The [`HitData`] that determines where the hit was and what the hit normal was
+    ///   and such.
+    /// - `direct`: The (unit) direction in which light is leaving the hitpoint towards the
+    ///   thing that lights it.
+    ///
+    /// # Returns
+    /// The attenuation [`Colour`] for light arriving from `direct`. Is black for materials that
+    /// do not scatter or that only do so in singular directions (e.g., mirrors).
+    #[inline]
+    fn eval(&self, _ray: Ray, _record: &HitData, _direct: Vec3) -> Colour {
+        /* Standard impl: nothing scattered in arbitrary directions */
+        Colour::BLACK
+    }
+
+    /// Computes the probability density with which [`Scattering::scatter()`] picks the given
+    /// direction.
+    ///
+    /// # Arguments
+    /// - `ray`: The inbound [`Ray`] that hit the material.
+    /// - `record`: The [`HitData`] that determines where the hit was and what the hit normal was
+    ///   and such.
+    /// - `direct`: The (unit) direction of which to compute the density.
+    ///
+    /// # Returns
+    /// The density w.r.t. solid angle, or `0.0` if this material never scatters in that direction
+    /// (or only in singular directions).
+    #[inline]
+    fn pdf(&self, _ray: Ray, _record: &HitData, _direct: Vec3) -> f64 {
+        /* Standard impl: no density */
+        0.0
+    }
+
+    /// Returns whether this material scatters light diffusely (i.e., not just in singular directions
+    /// like a mirror), meaning it makes sense to sample light sources directly when hitting it.
+    ///
+    /// # Returns
+    /// True if [`Scattering::eval()`] and [`Scattering::pdf()`] are meaningful for this material,
+    /// or false otherwise.
+    #[inline]
+    fn is_diffuse(&self) -> bool {
+        /* Standard impl: not diffuse */
+        false
+    }
+
+    /// Returns the base colour of this material at the given hit, regardless of lighting.
+    ///
+    /// This is used for debugging and as an auxillary buffer for denoising.
+    ///
+    /// # Arguments
+    /// - `record`: The [`HitData`] that determines where the hit was and what the hit normal was
+    ///   and such.
+    ///
+    /// # Returns
+    /// The [`Colour`] of the material at that spot.
+    #[inline]
+    fn albedo(&self, _record: &HitData) -> Colour {
+        /* Standard impl: no colour */
+        Colour::BLACK
+    }
 }
 
 // Standard impls
@@ -117,9 +211,25 @@ impl Scattering for () {
 
     #[inline]
     #[track_caller]
-    fn scatter(&self, _ray: Ray, _record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, _ray: Ray, _record: &HitData, _env: &Environment, _sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         panic!("You called <() as Scattering>::scatter() - this is not implemented")
     }
+
+    #[inline]
+    #[track_caller]
+    fn eval(&self, _ray: Ray, _record: &HitData, _direct: Vec3) -> Colour { panic!("You called <() as Scattering>::eval() - this is not implemented") }
+
+    #[inline]
+    #[track_caller]
+    fn pdf(&self, _ray: Ray, _record: &HitData, _direct: Vec3) -> f64 { panic!("You called <() as Scattering>::pdf() - this is not implemented") }
+
+    #[inline]
+    #[track_caller]
+    fn is_diffuse(&self) -> bool { panic!("You called <() as Scattering>::is_diffuse() - this is not implemented") }
+
+    #[inline]
+    #[track_caller]
+    fn albedo(&self, _record: &HitData) -> Colour { panic!("You called <() as Scattering>::albedo() - this is not implemented") }
 }
 
 // Pointer-like impls
@@ -188,9 +298,37 @@ macro_rules! material_impl {
             }
 
             #[inline]
-            fn scatter(&self, ray: Ray, record: &HitData, env: &Environment) -> (Option<Ray>, Colour) {
+            fn scatter(&self, ray: Ray, record: &HitData, env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
+                match self {
+                    $(Self::$mat(m) => m.scatter(ray, record, env, sampler),)*
+                }
+            }
+
+            #[inline]
+            fn eval(&self, ray: Ray, record: &HitData, direct: Vec3) -> Colour {
+                match self {
+                    $(Self::$mat(m) => m.eval(ray, record, direct),)*
+                }
+            }
+
+            #[inline]
+            fn pdf(&self, ray: Ray, record: &HitData, direct: Vec3) -> f64 {
+                match self {
+                    $(Self::$mat(m) => m.pdf(ray, record, direct),)*
+                }
+            }
+
+            #[inline]
+            fn is_diffuse(&self) -> bool {
+                match self {
+                    $(Self::$mat(m) => m.is_diffuse(),)*
+                }
+            }
+
+            #[inline]
+            fn albedo(&self, record: &HitData) -> Colour {
                 match self {
-                    $(Self::$mat(m) => m.scatter(ray, record, env),)*
+                    $(Self::$mat(m) => m.albedo(record),)*
                 }
             }
         }
@@ -6,6 +6,7 @@
 //
 
 use std::convert::Infallible;
+use std::f64::consts::PI;
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
@@ -15,7 +16,7 @@ use super::super::objects::HitData;
 use super::super::scene::Environment;
 use super::Scattering;
 use super::diffuse::random3_uniform;
-use crate::math::{Colour, Ray};
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
 
 
 /***** LIBRARY *****/
@@ -37,8 +38,20 @@ impl Loadable for Isotropic {
 }
 impl Scattering for Isotropic {
     #[inline]
-    fn scatter(&self, ray: Ray, rec: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, ray: Ray, rec: &HitData, _env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // Create a new ray bouncing randomly in any direction
-        (Some(Ray::with_time(rec.hit, random3_uniform(), ray.time)), self.colour)
+        (Some(Ray::with_time(rec.hit, random3_uniform(sampler), ray.time)), self.colour)
     }
+
+    #[inline]
+    fn eval(&self, _ray: Ray, _rec: &HitData, _direct: Vec3) -> Colour { self.colour * (1.0 / (4.0 * PI)) }
+
+    #[inline]
+    fn pdf(&self, _ray: Ray, _rec: &HitData, _direct: Vec3) -> f64 { 1.0 / (4.0 * PI) }
+
+    #[inline]
+    fn is_diffuse(&self) -> bool { true }
+
+    #[inline]
+    fn albedo(&self, _rec: &HitData) -> Colour { self.colour }
 }
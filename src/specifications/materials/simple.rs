@@ -21,7 +21,7 @@ use serde::{Deserialize, Serialize};
 use super::super::Loadable;
 use super::super::scene::Environment;
 use super::Scattering;
-use crate::math::{Colour, Ray};
+use crate::math::{Colour, PixelSampler, Ray};
 use crate::specifications::objects::HitData;
 
 
@@ -40,10 +40,13 @@ impl Loadable for StaticColour {
 }
 impl Scattering for StaticColour {
     #[inline]
-    fn scatter(&self, _ray: Ray, _record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, _ray: Ray, _record: &HitData, _env: &Environment, _sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // Compute the normal map colour based on the normal
         (None, self.colour)
     }
+
+    #[inline]
+    fn albedo(&self, _record: &HitData) -> Colour { self.colour }
 }
 
 
@@ -59,8 +62,11 @@ impl Loadable for NormalMap {
 }
 impl Scattering for NormalMap {
     #[inline]
-    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment) -> (Option<Ray>, Colour) {
+    fn scatter(&self, _ray: Ray, record: &HitData, _env: &Environment, _sampler: &mut PixelSampler) -> (Option<Ray>, Colour) {
         // Compute the normal map colour based on the normal
-        (None, 0.5 * Colour::new(record.normal.x + 1.0, record.normal.y + 1.0, record.normal.z + 1.0, 2.0))
+        (None, self.albedo(record))
     }
+
+    #[inline]
+    fn albedo(&self, record: &HitData) -> Colour { 0.5 * Colour::new(record.normal.x + 1.0, record.normal.y + 1.0, record.normal.z + 1.0, 2.0) }
 }
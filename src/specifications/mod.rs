@@ -16,6 +16,8 @@
 // Declare the submodules
 pub mod animations;
 pub mod archive;
+pub mod covers;
+pub mod farm;
 pub mod materials;
 pub mod objects;
 pub mod scene;
@@ -12,7 +12,7 @@ use serde::{Deserialize, Serialize};
 use super::super::Loadable;
 use super::super::materials::Scattering;
 use super::{BoundingBoxable, HitRecord, Hittable};
-use crate::math::{AABB, Ray};
+use crate::math::{AABB, PixelSampler, Ray};
 use crate::specifications::scene::Environment;
 
 
@@ -40,7 +40,7 @@ impl<M> BoundingBoxable for Box<M> {
 }
 impl<M: Scattering> Hittable for Box<M> {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
-        self.aabb.hit(ray, t_min, t_max, env).map(|rec| HitRecord { mat: &self.material, data: rec.data })
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+        self.aabb.hit(ray, t_min, t_max, env, sampler).map(|rec| HitRecord { mat: &self.material, data: rec.data })
     }
 }
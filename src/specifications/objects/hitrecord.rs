@@ -7,7 +7,7 @@
 
 use super::super::materials::Scattering;
 use super::super::scene::Environment;
-use crate::math::{Colour, Ray, Vec3};
+use crate::math::{Colour, PixelSampler, Ray, Vec3};
 
 
 /***** LIBRARY *****/
@@ -24,6 +24,8 @@ pub struct HitData {
     pub front_face: bool,
     /// An XY-coordinate pair relative to the object (useful for texture mapping).
     pub uv: (f64, f64),
+    /// The colour of the surface at the hit interpolated from its vertices, if it has any (e.g., scanned meshes).
+    pub colour: Option<Colour>,
 }
 
 // Constructors
@@ -46,7 +48,25 @@ impl HitData {
         let normal: Vec3 = if front_face { outward_normal } else { -outward_normal };
 
         // Return ourselves
-        Self { hit, t, normal, front_face, uv }
+        Self { hit, t, normal, front_face, uv, colour: None }
+    }
+}
+
+// Shading
+impl HitData {
+    /// Tints a colour of a material by the colour of the surface at the hit, if any.
+    ///
+    /// # Arguments
+    /// - `colour`: The [`Colour`] to tint.
+    ///
+    /// # Returns
+    /// The `colour` multiplied by the [`HitData::colour`], or `colour` as-is if there is none.
+    #[inline]
+    pub fn tint(&self, colour: Colour) -> Colour {
+        match self.colour {
+            Some(surface) => colour * surface,
+            None => colour,
+        }
     }
 }
 
@@ -95,10 +115,40 @@ impl<'a> HitRecord<'a> {
     /// # Arguments
     /// - `ray`: The [`Ray`] that we hit the object with.
     /// - `env`: Some [`Environment`] describing global properties of the scene.
+    /// - `sampler`: The [`PixelSampler`] to draw any random numbers from.
     ///
     /// # Returns
     /// A next [`Ray`] after the object's bounce, if any, and an attenuated [`Colour`] for this
     /// material.
     #[inline]
-    pub fn scatter(&self, ray: Ray, env: &Environment) -> (Option<Ray>, Colour) { self.mat.scatter(ray, &self.data, env) }
+    pub fn scatter(&self, ray: Ray, env: &Environment, sampler: &mut PixelSampler) -> (Option<Ray>, Colour) { self.mat.scatter(ray, &self.data, env, sampler) }
+
+    /// Evaluates the internal material for light arriving from the given direction.
+    ///
+    /// # Arguments
+    /// - `ray`: The [`Ray`] that we hit the object with.
+    /// - `direct`: The (unit) direction towards where the light comes from.
+    ///
+    /// # Returns
+    /// The attenuation [`Colour`] for light arriving from `direct` (including the cosine term).
+    #[inline]
+    pub fn eval(&self, ray: Ray, direct: Vec3) -> Colour { self.mat.eval(ray, &self.data, direct) }
+
+    /// Computes the density with which the internal material scatters in the given direction.
+    ///
+    /// # Arguments
+    /// - `ray`: The [`Ray`] that we hit the object with.
+    /// - `direct`: The (unit) direction to compute the density of.
+    ///
+    /// # Returns
+    /// The density w.r.t. solid angle.
+    #[inline]
+    pub fn pdf(&self, ray: Ray, direct: Vec3) -> f64 { self.mat.pdf(ray, &self.data, direct) }
+
+    /// Returns the base colour of the internal material using the internal [`HitData`].
+    ///
+    /// # Returns
+    /// The [`Colour`] of the material at the hit, regardless of lighting.
+    #[inline]
+    pub fn albedo(&self) -> Colour { self.mat.albedo(&self.data) }
 }
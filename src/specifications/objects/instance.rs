@@ -0,0 +1,338 @@
+//  INSTANCE.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements [`Instance`]s, which place an object from the
+//!   `definitions` of a scene with their own transform and (optionally)
+//!   material.
+//!
+//!   Every definition is loaded once and shared by all its instances, such
+//!   that, e.g., a model scattered a hundred times only keeps one copy of
+//!   its triangles (and their BVH) in memory.
+//
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::super::Loadable;
+use super::super::materials::{self, Material};
+use super::super::scene::Environment;
+use super::{BoundingBoxable, HitRecord, Hittable, Object};
+use crate::math::{AABB, PixelSampler, Ray, Transform, Vec3};
+
+
+/***** ERRORS *****/
+/// Defines problems with linking instances to their definitions.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Definition {name:?} (indirectly) instances itself")]
+    CyclicDefinition { name: String },
+    #[error("Instance of definition {name:?} has a transform that cannot be undone (it flattens space)")]
+    SingularTransform { name: String },
+    #[error("Instance refers to unknown definition {name:?}")]
+    UnknownDefinition { name: String },
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns whether a transform does nothing, to skip serializing it.
+#[inline]
+fn is_identity(transform: &Transform) -> bool { *transform == Transform::IDENTITY }
+
+/// Calls a closure for every [`Instance`] in an object, including those nested in transformations and groups.
+///
+/// The AABBs of groups are recomputed afterwards, in case the closure changed their instances.
+///
+/// # Arguments
+/// - `obj`: The [`Object`] to search.
+/// - `f`: The closure to call.
+///
+/// # Errors
+/// This function errors if the closure does, in which case the search stops.
+fn walk_instances(obj: &mut Object, f: &mut impl FnMut(&mut Instance) -> Result<(), Error>) -> Result<(), Error> {
+    match obj {
+        Object::Instance(instance) => f(instance),
+        Object::ConstantDensity(c) => walk_instances(&mut c.boundary, f),
+        Object::RotateX(r) => walk_instances(&mut r.obj, f),
+        Object::RotateY(r) => walk_instances(&mut r.obj, f),
+        Object::RotateZ(r) => walk_instances(&mut r.obj, f),
+        Object::Translate(t) => walk_instances(&mut t.obj, f),
+        Object::Group(g) => {
+            for obj in g.iter_mut() {
+                walk_instances(obj, f)?;
+            }
+            g.refresh_aabbs();
+            Ok(())
+        },
+        Object::AnimatedSphere(_) | Object::Sphere(_) | Object::Quad(_) | Object::Box(_) | Object::Triangle(_) | Object::Model(_) => Ok(()),
+    }
+}
+
+/// Links a definition (and, first, every definition it instances) and moves it to the shared ones.
+///
+/// # Arguments
+/// - `name`: The name of the definition to link.
+/// - `raw`: The definitions that haven't been linked yet.
+/// - `shared`: The definitions that have.
+/// - `stack`: The definitions we're linking right now, to detect cycles.
+///
+/// # Errors
+/// This function errors if the definition does not exist, instances itself or is instanced with a transform that cannot be undone.
+fn share_one(name: &str, raw: &mut BTreeMap<String, Object>, shared: &mut Definitions, stack: &mut Vec<String>) -> Result<(), Error> {
+    if shared.contains_key(name) {
+        return Ok(());
+    }
+    let Some(mut def) = raw.remove(name) else {
+        return Err(if stack.iter().any(|n| n == name) {
+            Error::CyclicDefinition { name: name.into() }
+        } else {
+            Error::UnknownDefinition { name: name.into() }
+        });
+    };
+
+    // Make sure whatever it instances is shared before we link it
+    stack.push(name.into());
+    let mut deps: Vec<String> = Vec::new();
+    walk_instances(&mut def, &mut |instance| {
+        deps.push(instance.definition.clone());
+        Ok(())
+    })?;
+    for dep in deps {
+        share_one(&dep, raw, shared, stack)?;
+    }
+    link(&mut def, shared)?;
+    stack.pop();
+
+    shared.insert(name.into(), Arc::new(def));
+    Ok(())
+}
+
+
+
+
+
+/***** AUXILLARY *****/
+/// The definitions of a scene after they are linked, which are shared by their instances.
+pub type Definitions = HashMap<String, Arc<Object>>;
+
+
+
+/// The state of an [`Instance`] that is linked to its definition.
+#[derive(Clone, Debug)]
+struct Linked {
+    /// The definition itself.
+    object:  Arc<Object>,
+    /// The transform from the world to the definition.
+    inverse: Transform,
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Turns the definitions of a scene into ones that can be shared by their instances.
+///
+/// Definitions may instance other definitions, as long as they don't (indirectly) instance
+/// themselves. Their external references must already be loaded.
+///
+/// # Arguments
+/// - `definitions`: The named [`Object`]s to share.
+///
+/// # Returns
+/// The [`Definitions`], in which every definition is linked.
+///
+/// # Errors
+/// This function errors if any definition instances an unknown definition, instances itself or
+/// instances another with a transform that cannot be undone.
+pub fn share(definitions: BTreeMap<String, Object>) -> Result<Definitions, Error> {
+    let mut raw: BTreeMap<String, Object> = definitions;
+    let mut shared: Definitions = HashMap::with_capacity(raw.len());
+    while let Some(name) = raw.keys().next().cloned() {
+        share_one(&name, &mut raw, &mut shared, &mut Vec::new())?;
+    }
+    Ok(shared)
+}
+
+/// Links every [`Instance`] in an object to its definition.
+///
+/// # Arguments
+/// - `obj`: The [`Object`] to link.
+/// - `definitions`: The [`Definitions`] to link to.
+///
+/// # Errors
+/// This function errors if any instance refers to an unknown definition or has a transform that cannot be undone.
+pub fn link(obj: &mut Object, definitions: &Definitions) -> Result<(), Error> { walk_instances(obj, &mut |instance| instance.link(definitions)) }
+
+
+
+/// Places an object from the scene's definitions, sharing it with all other instances of it.
+///
+/// Note that lights in definitions are never sampled directly, just like lights in
+/// transformations (see [`LightList`](crate::render::lights::LightList)).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Instance {
+    /// The name of the definition to place.
+    pub definition: String,
+    /// The transform from the definition to the world.
+    #[serde(default, skip_serializing_if = "is_identity")]
+    pub transform:  Transform,
+    /// If given, the material to use instead of the one(s) in the definition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material:   Option<Material>,
+    /// The definition, once linked (see [`Instance::link()`]).
+    #[serde(skip)]
+    linked:         Option<Linked>,
+}
+
+// Constructors
+impl Instance {
+    /// Constructor for the Instance.
+    ///
+    /// # Arguments
+    /// - `definition`: The name of the definition to place.
+    /// - `transform`: The [`Transform`] from the definition to the world.
+    /// - `material`: If given, the [`Material`] to use instead of the one(s) in the definition.
+    ///
+    /// # Returns
+    /// A new Instance, which still has to be linked before it can be hit.
+    #[inline]
+    pub fn new(definition: impl Into<String>, transform: Transform, material: Option<Material>) -> Self {
+        Self { definition: definition.into(), transform, material, linked: None }
+    }
+}
+
+// Linking
+impl Instance {
+    /// Links the Instance to its definition.
+    ///
+    /// Until this is done, the Instance cannot be hit.
+    ///
+    /// # Arguments
+    /// - `definitions`: The [`Definitions`] to find the definition in.
+    ///
+    /// # Errors
+    /// This function errors if the definition is unknown or the transform cannot be undone.
+    pub fn link(&mut self, definitions: &Definitions) -> Result<(), Error> {
+        let Some(object) = definitions.get(&self.definition) else { return Err(Error::UnknownDefinition { name: self.definition.clone() }) };
+        let Some(inverse) = self.transform.inverse() else { return Err(Error::SingularTransform { name: self.definition.clone() }) };
+        self.linked = Some(Linked { object: object.clone(), inverse });
+        Ok(())
+    }
+}
+
+// Interface
+impl Loadable for Instance {
+    type Error = materials::Error;
+
+    #[inline]
+    fn load(&mut self, dir: &Path) -> Result<(), Self::Error> {
+        match &mut self.material {
+            Some(mat) => mat.load(dir),
+            None => Ok(()),
+        }
+    }
+}
+impl BoundingBoxable for Instance {
+    fn aabb(&self, t_us: u64) -> AABB {
+        let Some(linked) = &self.linked else {
+            let origin: Vec3 = self.transform.point(Vec3::zeroes());
+            return AABB::from_points(origin, origin);
+        };
+
+        // Find the box around the transformed corners of the definition's box
+        let aabb: AABB = linked.object.aabb(t_us);
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+        for x in [aabb.x.min(), aabb.x.max()] {
+            for y in [aabb.y.min(), aabb.y.max()] {
+                for z in [aabb.z.min(), aabb.z.max()] {
+                    let corner: Vec3 = self.transform.point(Vec3::new(x, y, z));
+                    for c in 0..3 {
+                        min[c] = f64::min(min[c], corner[c]);
+                        max[c] = f64::max(max[c], corner[c]);
+                    }
+                }
+            }
+        }
+        AABB::from_points(min, max)
+    }
+}
+impl Hittable for Instance {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+        let linked: &Linked = self.linked.as_ref()?;
+
+        // Hit the definition in its own space; since the direction isn't normalized, distances along the ray stay the same
+        let local = Ray { origin: linked.inverse.point(ray.origin), direct: linked.inverse.vector(ray.direct), time: ray.time };
+        let mut rec: HitRecord = linked.object.hit(local, t_min, t_max, env, sampler)?;
+        rec.data.hit = self.transform.point(rec.data.hit);
+        rec.data.normal = self.transform.normal(rec.data.normal).unit();
+        if let Some(mat) = &self.material {
+            rec.mat = mat;
+        }
+        Some(rec)
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Colour, Sampler};
+    use crate::specifications::materials::Lambertian;
+    use crate::specifications::objects::Sphere;
+
+    #[test]
+    fn test_instance() {
+        let red = Material::Lambertian(Lambertian { colour: Colour::new(1.0, 0.0, 0.0, 1.0) });
+        let blue = Material::Lambertian(Lambertian { colour: Colour::new(0.0, 0.0, 1.0, 1.0) });
+        let definitions: BTreeMap<String, Object> = BTreeMap::from([
+            ("ball".into(), Object::Sphere(Sphere { center: Vec3::zeroes(), radius: 1.0, material: red })),
+            // Two balls next to each other, as instances of the other definition
+            ("pair".into(), Object::Group(std::boxed::Box::new(crate::hittree::HitTree::with_objs(
+                [
+                    Object::Instance(Instance::new("ball", Transform::translation(Vec3::new(-2.0, 0.0, 0.0)), None)),
+                    Object::Instance(Instance::new("ball", Transform::translation(Vec3::new(2.0, 0.0, 0.0)), None)),
+                ],
+                (0..=0).into(),
+            )))),
+        ]);
+        let definitions: Definitions = share(definitions).unwrap();
+
+        // Place the pair twice as large, and in blue
+        let scale = Transform { rows: [[2.0, 0.0, 0.0, 0.0], [0.0, 2.0, 0.0, 0.0], [0.0, 0.0, 2.0, 10.0]] };
+        let mut obj = Object::Instance(Instance::new("pair", scale, Some(blue)));
+        link(&mut obj, &definitions).unwrap();
+        let aabb: AABB = obj.aabb(0);
+        assert_eq!((aabb.x.min(), aabb.x.max(), aabb.z.min(), aabb.z.max()), (-6.0, 6.0, 8.0, 12.0));
+
+        // Hits are where the scaled balls are, at the same distance along the ray
+        let env = Environment::default();
+        let mut sampler = PixelSampler::new(Sampler::default(), 42, 0, 0, 0, 1);
+        let rec: HitRecord = obj.hit(Ray::new(Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)), 0.001, f64::INFINITY, &env, &mut sampler).unwrap();
+        assert!((rec.data.t - 8.0).abs() < 1e-9);
+        assert!((rec.data.hit - Vec3::new(4.0, 0.0, 8.0)).length() < 1e-9);
+        assert!((rec.data.normal - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+        assert_eq!(rec.albedo(), Colour::new(0.0, 0.0, 1.0, 1.0));
+        assert!(obj.hit(Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)), 0.001, f64::INFINITY, &env, &mut sampler).is_none());
+
+        // Both balls in the pair share the same one
+        assert_eq!(Arc::strong_count(&definitions["ball"]), 3);
+
+        // Definitions cannot refer to themselves or ones that don't exist
+        let cycle = |name: &str, other: &str| (name.to_string(), Object::Instance(Instance::new(other, Transform::IDENTITY, None)));
+        assert!(matches!(share(BTreeMap::from([cycle("a", "b"), cycle("b", "a")])), Err(Error::CyclicDefinition { .. })));
+        assert!(matches!(share(BTreeMap::from([cycle("a", "c")])), Err(Error::UnknownDefinition { name }) if name == "c"));
+    }
+}
@@ -16,7 +16,7 @@ use super::super::Loadable;
 use super::super::materials::Isotropic;
 use super::super::objects::{HitData, HitRecord};
 use super::{BoundingBoxable, Hittable};
-use crate::math::{Ray, Vec3};
+use crate::math::{PixelSampler, Ray, Vec3};
 use crate::specifications::scene::Environment;
 
 
@@ -46,10 +46,10 @@ impl<T: BoundingBoxable> BoundingBoxable for ConstantDensity<T> {
 }
 impl<T: Hittable> Hittable for ConstantDensity<T> {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         // Check if the ray hits the boundary on _two_ points (in- and out of the gas)
-        let mut rec1: HitData = self.boundary.hit(ray, -f64::INFINITY, f64::INFINITY, env)?.data;
-        let mut rec2: HitData = self.boundary.hit(ray, rec1.t + 0.0001, f64::INFINITY, env)?.data;
+        let mut rec1: HitData = self.boundary.hit(ray, -f64::INFINITY, f64::INFINITY, env, sampler)?.data;
+        let mut rec2: HitData = self.boundary.hit(ray, rec1.t + 0.0001, f64::INFINITY, env, sampler)?.data;
 
         // Bound the record's t's by the given ones and quit if it's too close
         rec1.t = f64::max(rec1.t, t_min);
@@ -62,7 +62,7 @@ impl<T: Hittable> Hittable for ConstantDensity<T> {
         // Compute a random hitpoint in the gas (or outside of it)
         let ray_len: f64 = ray.direct.length();
         let dist_in_boundary: f64 = (rec2.t - rec1.t) * ray_len;
-        let hit_dist: f64 = (-1.0 / self.density) * fastrand::f64().ln();
+        let hit_dist: f64 = (-1.0 / self.density) * sampler.f64().ln();
         if hit_dist > dist_in_boundary {
             // No hit, the ray passes through.
             // Unless...? - if the shape is not convex, it may re-enter the material here!
@@ -0,0 +1,328 @@
+//  MESH.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements an indexed triangle [`Mesh`], in which triangles refer to
+//!   shared buffers of positions, normals and texture coordinates.
+//!
+//!   Hits interpolate the normals, texture coordinates and colours of the
+//!   corners of the triangle that was hit, such that loaded models can be
+//!   smoothly shaded and textured.
+//
+
+use std::ops::{Add, Mul};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use super::super::scene::Environment;
+use super::plane::Triag;
+use super::{BoundingBoxable, HitData, HitRecord, Hittable};
+use crate::hittree::HitTree;
+use crate::math::{AABB, Colour, PixelSampler, Ray, Vec3};
+
+
+/***** ERRORS *****/
+/// Defines problems with the buffers of a mesh.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Triangle {i} refers to {what} {index}, but the mesh only has {len}")]
+    IndexOutOfBounds { i: usize, what: &'static str, index: u32, len: usize },
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Checks that all indices of a triangle are within a buffer.
+///
+/// # Arguments
+/// - `i`: The index of the triangle (for debugging).
+/// - `what`: The name of the things in the buffer (for debugging).
+/// - `indices`: The indices to check.
+/// - `len`: The length of the buffer.
+///
+/// # Errors
+/// This function errors if any index is out-of-bounds.
+#[inline]
+fn check_indices(i: usize, what: &'static str, indices: [u32; 3], len: usize) -> Result<(), Error> {
+    match indices.into_iter().find(|index| *index as usize >= len) {
+        Some(index) => Err(Error::IndexOutOfBounds { i, what, index, len }),
+        None => Ok(()),
+    }
+}
+
+/// Interpolates three values (e.g., [`Vec3`]s or [`Colour`]s) with barycentric coordinates.
+///
+/// # Arguments
+/// - `values`: The values at the corners of a triangle.
+/// - `uv`: The barycentric coordinates of the second and third corner.
+///
+/// # Returns
+/// The interpolated value.
+#[inline]
+fn interpolate<T: Add<Output = T> + Mul<f64, Output = T>>(values: [T; 3], uv: (f64, f64)) -> T {
+    let [v0, v1, v2] = values;
+    v0 * (1.0 - uv.0 - uv.1) + v1 * uv.0 + v2 * uv.1
+}
+
+
+
+
+
+/***** AUXILLARY *****/
+/// Defines a single triangle in a [`Mesh`], as indices into its buffers.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct MeshTriangle {
+    /// The indices of the positions of the corners, in counter-clockwise order when seen from the front.
+    pub positions: [u32; 3],
+    /// The indices of the normals of the corners, if any. If omitted, the triangle is flat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normals:   Option<[u32; 3]>,
+    /// The indices of the texture coordinates of the corners, if any. If omitted, the barycentric coordinates of the hit are used instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uvs:       Option<[u32; 3]>,
+    /// The indices of the colours of the corners, if any. If omitted, the colour of the surface is left to the material.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colours:   Option<[u32; 3]>,
+}
+
+
+
+/// Defines the buffers of a [`Mesh`], which is also how it is (de)serialized.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MeshBuffers {
+    /// The positions of the vertices.
+    pub positions: Vec<Vec3>,
+    /// The normals of the vertices.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub normals:   Vec<Vec3>,
+    /// The texture coordinates of the vertices.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uvs:       Vec<(f64, f64)>,
+    /// The colours of the vertices, which tint diffuse materials.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colours:   Vec<Colour>,
+    /// The triangles, as indices into the other buffers.
+    pub triangles: Vec<MeshTriangle>,
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Implements a triangle mesh with shared buffers of positions, normals, texture coordinates and colours.
+///
+/// It is (de)serialized as its [`MeshBuffers`]; the BVH over its triangles is built when it is
+/// created.
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    /// The buffers of the mesh.
+    buffers: MeshBuffers,
+    /// The triangles as flat [`Triag`]s (in the same order), in a BVH.
+    triags:  HitTree<Triag>,
+}
+
+// Constructors
+impl Mesh {
+    /// Constructor for the Mesh.
+    ///
+    /// # Arguments
+    /// - `buffers`: The [`MeshBuffers`] that define the mesh.
+    ///
+    /// # Returns
+    /// A new Mesh, ready for rendering.
+    ///
+    /// # Errors
+    /// This function errors if any triangle refers to vertices, normals, texture coordinates or colours that aren't there.
+    pub fn new(buffers: MeshBuffers) -> Result<Self, Error> {
+        for (i, triangle) in buffers.triangles.iter().enumerate() {
+            check_indices(i, "position", triangle.positions, buffers.positions.len())?;
+            if let Some(normals) = triangle.normals {
+                check_indices(i, "normal", normals, buffers.normals.len())?;
+            }
+            if let Some(uvs) = triangle.uvs {
+                check_indices(i, "texture coordinate", uvs, buffers.uvs.len())?;
+            }
+            if let Some(colours) = triangle.colours {
+                check_indices(i, "colour", colours, buffers.colours.len())?;
+            }
+        }
+
+        // Build the BVH over flat versions of the triangles
+        let triags: HitTree<Triag> = HitTree::with_objs(
+            buffers.triangles.iter().map(|triangle| {
+                let [p0, p1, p2] = triangle.positions.map(|i| buffers.positions[i as usize]);
+                Triag { pos: p0, u: p1 - p0, v: p2 - p0 }
+            }),
+            (0..=1).into(),
+        );
+        Ok(Self { buffers, triags })
+    }
+}
+
+// Interface
+impl BoundingBoxable for Mesh {
+    #[inline]
+    fn aabb(&self, t_us: u64) -> AABB { self.triags.aabb(t_us) }
+}
+impl Hittable for Mesh {
+    #[inline]
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+        self.hit_indexed(ray, t_min, t_max, env, sampler).map(|(_, rec)| rec)
+    }
+}
+
+// Raytracing
+impl Mesh {
+    /// Computes if the given ray hits the mesh, and returns which triangle was hit if so.
+    ///
+    /// # Arguments
+    /// - `ray`: The [`Ray`] to test.
+    /// - `t_min`: The minimum distance along the ray to consider.
+    /// - `t_max`: The maximum distance along the ray to consider.
+    /// - `env`: An [`Environment`] describing global properties of the scene.
+    /// - `sampler`: The [`PixelSampler`] to draw any random numbers from.
+    ///
+    /// # Returns
+    /// The index of the triangle that was hit and the [`HitRecord`] describing the hit, or else
+    /// [`None`] if the ray does not hit.
+    pub fn hit_indexed(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<(usize, HitRecord<'_>)> {
+        let (i, rec): (usize, HitRecord) = self.triags.hit_indexed(ray, t_min, t_max, env, sampler)?;
+        let triangle: &MeshTriangle = &self.buffers.triangles[i];
+        let bary: (f64, f64) = rec.data.uv;
+
+        // Use the interpolated normal for shading, but keep it on the side of the geometric normal the ray is on
+        let mut data: HitData = rec.data;
+        if let Some(normals) = triangle.normals {
+            let normal: Vec3 = interpolate(normals.map(|i| self.buffers.normals[i as usize]), bary);
+            if normal.length2() > 1e-12 {
+                let normal: Vec3 = normal.unit();
+                data.normal = if normal.dot(data.normal) < 0.0 { -normal } else { normal };
+            }
+        }
+        if let Some(uvs) = triangle.uvs {
+            let uv: Vec3 = interpolate(uvs.map(|i| Vec3::new(self.buffers.uvs[i as usize].0, self.buffers.uvs[i as usize].1, 0.0)), bary);
+            data.uv = (uv.x, uv.y);
+        }
+        if let Some(colours) = triangle.colours {
+            data.colour = Some(interpolate(colours.map(|i| self.buffers.colours[i as usize]), bary));
+        }
+        Some((i, HitRecord { mat: rec.mat, data }))
+    }
+
+    /// Computes how the position on a triangle changes along its texture coordinates.
+    ///
+    /// This gives the directions in which a texture runs over the triangle, e.g., for bump
+    /// mapping.
+    ///
+    /// # Arguments
+    /// - `i`: The index of the triangle.
+    ///
+    /// # Returns
+    /// The derivatives of the position w.r.t. the first and second texture coordinate, or
+    /// [`None`] if the triangle has no (or degenerate) texture coordinates.
+    ///
+    /// # Panics
+    /// This function panics if `i` is out-of-bounds.
+    pub fn uv_tangents(&self, i: usize) -> Option<(Vec3, Vec3)> {
+        let triangle: &MeshTriangle = &self.buffers.triangles[i];
+        let [p0, p1, p2] = triangle.positions.map(|i| self.buffers.positions[i as usize]);
+        let [uv0, uv1, uv2] = triangle.uvs?.map(|i| self.buffers.uvs[i as usize]);
+
+        // Solve `p1 - p0 = du1 * dpdu + dv1 * dpdv` and `p2 - p0 = du2 * dpdu + dv2 * dpdv`
+        let (du1, dv1, du2, dv2): (f64, f64, f64, f64) = (uv1.0 - uv0.0, uv1.1 - uv0.1, uv2.0 - uv0.0, uv2.1 - uv0.1);
+        let det: f64 = du1 * dv2 - dv1 * du2;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let (e1, e2): (Vec3, Vec3) = (p1 - p0, p2 - p0);
+        Some(((dv2 * e1 - dv1 * e2) / det, (du1 * e2 - du2 * e1) / det))
+    }
+}
+
+// Info
+impl Mesh {
+    /// Returns the buffers of the mesh.
+    #[inline]
+    pub fn buffers(&self) -> &MeshBuffers { &self.buffers }
+
+    /// Returns the number of triangles in the mesh.
+    #[inline]
+    pub fn len(&self) -> usize { self.buffers.triangles.len() }
+
+    /// Returns whether the mesh has no triangles.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.buffers.triangles.is_empty() }
+}
+
+// Serde
+impl Serialize for Mesh {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.buffers.serialize(serializer)
+    }
+}
+impl<'de> Deserialize<'de> for Mesh {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(MeshBuffers::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Sampler;
+
+    #[test]
+    fn test_mesh_interpolation() {
+        // A single triangle in the XY-plane whose normals bend outwards, which is textured from (0, 0) to (1, 1) and red, green and blue in the corners
+        let mesh = Mesh::new(MeshBuffers {
+            positions: vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)],
+            normals:   vec![Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 1.0)],
+            uvs:       vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+            colours:   vec![Colour::new(1.0, 0.0, 0.0, 1.0), Colour::new(0.0, 1.0, 0.0, 1.0), Colour::new(0.0, 0.0, 1.0, 1.0)],
+            triangles: vec![MeshTriangle { positions: [0, 1, 2], normals: Some([0, 1, 2]), uvs: Some([0, 1, 2]), colours: Some([0, 1, 2]) }],
+        })
+        .unwrap();
+        let env = Environment::default();
+        let mut sampler = PixelSampler::new(Sampler::default(), 42, 0, 0, 0, 1);
+
+        // Inside the triangle, all are weighted by how close the hit is to every corner
+        let rec = mesh.hit(Ray::new(Vec3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, f64::INFINITY, &env, &mut sampler).unwrap();
+        assert!(rec.data.front_face);
+        assert!((rec.data.normal - Vec3::new(0.5, 0.25, 1.0).unit()).length() < 1e-9);
+        assert!((rec.data.uv.0 - 0.75).abs() < 1e-9 && (rec.data.uv.1 - 0.25).abs() < 1e-9);
+        assert_eq!(rec.data.colour, Some(Colour::new(0.25, 0.5, 0.25, 1.0)));
+
+        // Seen from behind, the normal flips with the geometric one
+        let rec = mesh.hit(Ray::new(Vec3::new(0.25, 0.25, -1.0), Vec3::new(0.0, 0.0, 1.0)), 0.001, f64::INFINITY, &env, &mut sampler).unwrap();
+        assert!(!rec.data.front_face);
+        assert!(rec.data.normal.z < 0.0);
+
+        // The texture runs diagonally over the triangle
+        let (dpdu, dpdv): (Vec3, Vec3) = mesh.uv_tangents(0).unwrap();
+        assert!((dpdu - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((dpdv - Vec3::new(-1.0, 1.0, 0.0)).length() < 1e-9);
+
+        // Out-of-bounds indices are caught
+        assert!(matches!(
+            Mesh::new(MeshBuffers { triangles: vec![MeshTriangle { positions: [0, 1, 3], normals: None, uvs: None, colours: None }], ..mesh.buffers().clone() }),
+            Err(Error::IndexOutOfBounds { i: 0, index: 3, .. })
+        ));
+    }
+}
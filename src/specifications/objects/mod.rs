@@ -19,9 +19,12 @@
 // Define the submodules
 pub mod boxed;
 mod hitrecord;
+pub mod instance;
 pub mod medium;
-#[cfg(feature = "obj")]
+pub mod mesh;
 pub mod model;
+#[cfg(feature = "obj")]
+pub mod mtl;
 pub mod plane;
 pub mod sphere;
 pub mod translate;
@@ -34,7 +37,9 @@ use std::sync::{Arc, MutexGuard, RwLockReadGuard, RwLockWriteGuard};
 
 pub use boxed::Box;
 pub use hitrecord::*;
+pub use instance::Instance;
 pub use medium::ConstantDensity;
+pub use mesh::Mesh;
 pub use model::Model;
 pub use plane::{Quad, Triangle};
 use serde::{Deserialize, Serialize};
@@ -46,7 +51,7 @@ use super::Loadable;
 use super::materials::Material;
 use super::scene::Environment;
 use crate::hittree::HitTree;
-use crate::math::{AABB, Ray};
+use crate::math::{AABB, PixelSampler, Ray, Vec3};
 
 
 /***** MACRO RULES *****/
@@ -69,16 +74,16 @@ macro_rules! hittable_ptr_impl {
     ('a, $ty:ty) => {
         impl<'a, T: Hittable> Hittable for $ty {
             #[inline]
-            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
-                <T as Hittable>::hit(self, ray, t_min, t_max, env)
+            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+                <T as Hittable>::hit(self, ray, t_min, t_max, env, sampler)
             }
         }
     };
     ($ty:ty) => {
         impl<T: Hittable> Hittable for $ty {
             #[inline]
-            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
-                <T as Hittable>::hit(self, ray, t_min, t_max, env)
+            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+                <T as Hittable>::hit(self, ray, t_min, t_max, env, sampler)
             }
         }
     };
@@ -128,10 +133,11 @@ pub trait Hittable: BoundingBoxable {
     /// - `t_min`: The minimum point along the ray we still accept (we don't count it as a hit before that).
     /// - `t_max`: The maximum point along the ray we still accept (we don't count is as a hit after that).
     /// - `env`: An [`Environment`] struct relating information about the scene's total environment.
+    /// - `sampler`: The [`PixelSampler`] to draw any random numbers from (e.g., for volumes that scatter randomly).
     ///
     /// # Returns
     /// A new [`HitRecord`] struct, which collects relevant information of this hit, or else [`None`] if the ray does not hit.
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>>;
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>>;
 }
 
 // Pointer-like impls
@@ -151,6 +157,54 @@ hittable_ptr_impl!('a, parking_lot::MutexGuard<'a, T>);
 
 
 
+/// Defines a point sampled on the surface of an object.
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceSample {
+    /// The sampled point on the object's surface.
+    pub point:  Vec3,
+    /// The outward (unit) surface normal at the sampled point.
+    pub normal: Vec3,
+    /// An XY-coordinate pair relative to the object (useful for texture mapping).
+    pub uv:     (f64, f64),
+    /// The probability density of having sampled this point, w.r.t. solid angle as seen from the
+    /// origin given when sampling.
+    pub pdf:    f64,
+}
+
+/// Defines the functions for objects of which we can sample points on their surface.
+///
+/// This is used to sample light sources directly instead of hoping a ray hits them.
+pub trait Sampleable {
+    /// Samples a random point on the surface of this object that is (potentially) visible from
+    /// the given origin.
+    ///
+    /// # Arguments
+    /// - `origin`: The point from which the object is looked at.
+    /// - `t_us`: The time at which we sample the object. Matters if this object is animated. Time
+    ///   is in microseconds since the start of the scene.
+    /// - `sampler`: The [`PixelSampler`] to draw random numbers from.
+    ///
+    /// # Returns
+    /// A new [`SurfaceSample`] describing the sampled point, or else [`None`] if no point on this
+    /// object can be seen from `origin`.
+    fn sample(&self, origin: Vec3, t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample>;
+
+    /// Computes the probability density with which [`Sampleable::sample()`] picks the point where
+    /// a ray from the given origin in the given direction hits this object.
+    ///
+    /// # Arguments
+    /// - `origin`: The point from which the object is looked at.
+    /// - `direct`: The direction in which we look at the object.
+    /// - `t_us`: The time at which we sample the object. Matters if this object is animated. Time
+    ///   is in microseconds since the start of the scene.
+    ///
+    /// # Returns
+    /// The density w.r.t. solid angle, or `0.0` if looking in `direct` does not hit this object.
+    fn pdf(&self, origin: Vec3, direct: Vec3, t_us: u64) -> f64;
+}
+
+
+
 
 
 /***** LIBRARY *****/
@@ -198,7 +252,6 @@ macro_rules! object_impl {
             /// A translation.
             Translate(Translate<std::boxed::Box<Self>>),
             /// A nested group of objects.
-            #[serde(skip)]
             Group(std::boxed::Box<HitTree>),
         }
 
@@ -235,15 +288,15 @@ macro_rules! object_impl {
         }
         impl Hittable for Object {
             #[inline]
-            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
                 match self {
-                    $(Self::$obj(o) => o.hit(ray, t_min, t_max, env),)*
-                    Self::ConstantDensity(c) => c.hit(ray, t_min, t_max, env),
-                    Self::RotateX(r) => r.hit(ray, t_min, t_max, env),
-                    Self::RotateY(r) => r.hit(ray, t_min, t_max, env),
-                    Self::RotateZ(r) => r.hit(ray, t_min, t_max, env),
-                    Self::Translate(t) => t.hit(ray, t_min, t_max, env),
-                    Self::Group(g) => g.hit(ray, t_min, t_max, env),
+                    $(Self::$obj(o) => o.hit(ray, t_min, t_max, env, sampler),)*
+                    Self::ConstantDensity(c) => c.hit(ray, t_min, t_max, env, sampler),
+                    Self::RotateX(r) => r.hit(ray, t_min, t_max, env, sampler),
+                    Self::RotateY(r) => r.hit(ray, t_min, t_max, env, sampler),
+                    Self::RotateZ(r) => r.hit(ray, t_min, t_max, env, sampler),
+                    Self::Translate(t) => t.hit(ray, t_min, t_max, env, sampler),
+                    Self::Group(g) => g.hit(ray, t_min, t_max, env, sampler),
                 }
             }
         }
@@ -277,4 +330,6 @@ object_impl!(
     Triangle{Material}(super::materials::Error),
     /// A complex, triangle-based model.
     Model(model::Error),
+    /// An object from the scene's definitions, placed with its own transform (and material).
+    Instance(super::materials::Error),
 );
@@ -5,26 +5,45 @@
 //!   Implements an object that loads a model.
 //
 
+// Declare submodules
+#[cfg(feature = "gltf")]
+pub mod gltf;
+#[cfg(feature = "ply")]
+pub mod ply;
+#[cfg(feature = "stl")]
+pub mod stl;
+
 use std::borrow::Cow;
+#[cfg(feature = "obj")]
+use std::collections::HashMap;
 use std::ffi::OsStr;
 #[cfg(feature = "obj")]
 use std::fs::File;
+#[cfg(feature = "obj")]
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
+#[cfg(any(feature = "gltf", feature = "obj", feature = "ply", feature = "stl"))]
 use log::debug;
-use obj::Vertex;
+#[cfg(feature = "obj")]
+use log::warn;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use super::super::Loadable;
 #[cfg(feature = "obj")]
-use super::super::materials::Lambertian;
-use super::super::materials::Material;
+use super::super::materials::{Dielectric, DiffuseLight, Metal};
+use super::super::materials::{Lambertian, Material};
 use super::super::scene::Environment;
-use super::plane::Triag;
+use super::super::textures::image::image_value;
+#[cfg(feature = "obj")]
+use super::mesh::{MeshBuffers, MeshTriangle};
+use super::mesh::{self, Mesh};
+#[cfg(feature = "obj")]
+use super::mtl::{self, MtlMaterial, TextureMap};
 use super::{BoundingBoxable, HitRecord, Hittable};
-use crate::hittree::HitTree;
-use crate::math::{AABB, Colour, Ray, Vec3};
+use crate::math::{AABB, Colour, PixelSampler, Ray, Vec3};
+use crate::render::image::Image;
 use crate::specifications::materials::LambertianTexture;
 use crate::specifications::textures::{SpatialChecker, Texture};
 
@@ -55,15 +74,28 @@ pub enum Error {
         #[source]
         err:  std::io::Error,
     },
+    #[cfg(feature = "gltf")]
+    #[error("Failed to load file {path:?} as .gltf/.glb file")]
+    Gltf {
+        path: PathBuf,
+        #[source]
+        err:  ::gltf::Error,
+    },
     #[cfg(feature = "obj")]
     #[error("Index {got} overflows for list of length {len}")]
     IndexOverflow { got: isize, len: usize },
+    #[error("Failed to build mesh from file {path:?}")]
+    Mesh {
+        path: PathBuf,
+        #[source]
+        err:  mesh::Error,
+    },
     #[cfg(feature = "obj")]
     #[error("Failed to load file {path:?} as .mtl file")]
-    Mtllib {
+    Mtl {
         path: PathBuf,
         #[source]
-        err:  mtllib::Error,
+        err:  mtl::Error,
     },
     #[cfg(feature = "obj")]
     #[error("Face {i}{}{} in file {path:?} is not a face of 3/4 vertices (i.e., one or two triangle(s)), but rather {got}", if let Some(oname) = oname {format!(" in object {oname:?}")} else { String::new()}, if let Some(gname) = gname {format!(" in group {gname:?}")} else { String::new()})]
@@ -75,6 +107,20 @@ pub enum Error {
         #[source]
         err:  obj::Error,
     },
+    #[cfg(feature = "ply")]
+    #[error("Failed to load file {path:?} as .ply file")]
+    Ply {
+        path: PathBuf,
+        #[source]
+        err:  ply::Error,
+    },
+    #[cfg(feature = "stl")]
+    #[error("Failed to load file {path:?} as .stl file")]
+    Stl {
+        path: PathBuf,
+        #[source]
+        err:  std::io::Error,
+    },
     #[error("Cannot guess format from {name:?} (specify it manually instead)")]
     UnknownModelExtension { name: String },
     #[cfg(feature = "obj")]
@@ -87,15 +133,27 @@ pub enum Error {
 
 
 /***** HELPER FUNCTIONS *****/
-/// Resolves a [`isize`] index to a vertex.
+/// Returns whether a path has one of the given extensions, ignoring their case.
+///
+/// # Arguments
+/// - `path`: The path to check.
+/// - `exts`: The extensions to check for, without a leading dot.
+///
+/// # Returns
+/// True if the extension of `path` is one of `exts`, or false otherwise.
+#[cfg(any(feature = "gltf", feature = "obj", feature = "ply", feature = "stl"))]
+#[inline]
+fn has_extension(path: &Path, exts: &[&str]) -> bool { path.extension().is_some_and(|ext| exts.iter().any(|e| ext.eq_ignore_ascii_case(e))) }
+
+/// Resolves an OBJ-style index (i.e., one-based, or relative to the end if negative) into a list.
 #[cfg(feature = "obj")]
 #[inline]
-const fn vertex_get(vertices: &[Vertex], i: isize) -> Result<&Vertex, Error> {
+const fn index_get(len: usize, i: isize) -> Result<usize, Error> {
     if i < 0 {
-        let ri: isize = vertices.len() as isize - i;
-        if ri >= 0 { Ok(&vertices[ri as usize]) } else { Err(Error::IndexOverflow { got: i, len: vertices.len() }) }
+        let ri: isize = len as isize + i;
+        if ri >= 0 { Ok(ri as usize) } else { Err(Error::IndexOverflow { got: i, len }) }
     } else if i > 0 {
-        if i as usize <= vertices.len() { Ok(&vertices[i as usize - 1]) } else { Err(Error::IndexOverflow { got: i, len: vertices.len() }) }
+        if i as usize <= len { Ok(i as usize - 1) } else { Err(Error::IndexOverflow { got: i, len }) }
     } else {
         Err(Error::ZeroIndex)
     }
@@ -107,20 +165,21 @@ const fn vertex_get(vertices: &[Vertex], i: isize) -> Result<&Vertex, Error> {
 /// - `vs`: The list of vertices that are the points to split along.
 ///
 /// # Returns
-/// Two sets of vertices that make two triangles.
-fn split_four_into_triangles(vs: [Vec3; 4]) -> [[Vec3; 3]; 2] {
+/// Two sets of indices into `vs` that make two triangles.
+#[cfg(feature = "obj")]
+fn split_four_into_triangles(vs: [Vec3; 4]) -> [[usize; 3]; 2] {
     #[inline]
     const fn midpoint_of(p1: Vec3, p2: Vec3) -> Vec3 { Vec3::new(0.5 * (p1.x + p2.x), 0.5 * (p1.y + p2.y), 0.5 * (p1.z + p2.z)) }
 
     // The first triangle is the first three vertices
-    let t1 = [vs[0], vs[1], vs[2]];
+    let t1 = [0, 1, 2];
 
     // The second triangle is the line + the fourth point s.t. the fourth point is closest to it.
     // See: <https://stackoverflow.com/a/73431349/5270125>
     let axis = [(t1[0], t1[1]), (t1[1], t1[2]), (t1[0], t1[2])];
     let mut smallest_l: Option<(usize, f64)> = None;
     for (i, (p1, p2)) in axis.into_iter().enumerate() {
-        let m = midpoint_of(p1, p2);
+        let m = midpoint_of(vs[p1], vs[p2]);
         let dist = (vs[3] - m).length2();
         if let Some((si, sd)) = &mut smallest_l {
             if dist < *sd {
@@ -132,7 +191,151 @@ fn split_four_into_triangles(vs: [Vec3; 4]) -> [[Vec3; 3]; 2] {
         }
     }
     let smallest_i: usize = smallest_l.unwrap().0;
-    [t1, [axis[smallest_i].0, axis[smallest_i].1, vs[3]]]
+    [t1, [axis[smallest_i].0, axis[smallest_i].1, 3]]
+}
+
+/// Computes the brightness of an image at the given texture coordinates, e.g., to use it as a height or an opacity.
+#[inline]
+fn brightness(image: &Image, uv: (f64, f64)) -> f64 {
+    let colour: Colour = image_value(image, uv);
+    (colour.r + colour.g + colour.b) / 3.0
+}
+
+/// Loads the image of a texture map in an MTL file.
+///
+/// # Arguments
+/// - `map`: The [`TextureMap`] to load.
+/// - `dir`: The directory of the MTL file, which relative paths are resolved to.
+/// - `name`: The name of the material with the map (for debugging).
+///
+/// # Returns
+/// The loaded [`Image`], or [`None`] if it failed to load (which is reported as a warning).
+#[cfg(feature = "obj")]
+fn load_map(map: &TextureMap, dir: &Path, name: &str) -> Option<Image> {
+    let path: Cow<Path> = if map.path.is_relative() { Cow::Owned(dir.join(&map.path)) } else { Cow::Borrowed(&map.path) };
+    match Image::from_path_auto(&path) {
+        Ok(image) => {
+            debug!("Loaded texture {path:?} of material {name:?}");
+            Some(image)
+        },
+        Err(err) => {
+            warn!("Failed to load texture {path:?} of material {name:?}, ignoring it: {err}");
+            None
+        },
+    }
+}
+
+/// Converts a material from an MTL file into the closest [`Material`] we can render.
+///
+/// In order of precedence, the material becomes:
+/// - a [`DiffuseLight`] if it emits anything (`Ke`);
+/// - a [`Dielectric`] if it is transparent (`d` or `Tr`) and refracts (`Ni`, or an `illum` that
+///   implies refraction);
+/// - a [`Metal`] if its specular colour (`Ks`) is brighter than its diffuse colour or `illum`
+///   enables reflections, which is fuzzier for lower specular exponents (`Ns`); or
+/// - a [`LambertianTexture`] with `map_Kd` or a [`Lambertian`] with `Kd` otherwise.
+///
+/// Transparency without refraction (`d` and `map_d`) and bumps (`map_bump`) are returned as a
+/// [`Surface`], since they change which hits happen rather than how they scatter. Properties that
+/// cannot be represented this way are reported as a warning.
+///
+/// # Arguments
+/// - `mtl`: The [`MtlMaterial`] to convert.
+/// - `path`: The path of the MTL file, which textures are resolved relative to.
+///
+/// # Returns
+/// The [`Material`] and the [`Surface`] of the material.
+#[cfg(feature = "obj")]
+fn convert_mtl(mtl: MtlMaterial, path: &Path) -> (Material, Surface) {
+    #[inline]
+    fn max(colour: Colour) -> f64 { colour.r.max(colour.g).max(colour.b) }
+
+    let dir: &Path = path.parent().unwrap_or(Path::new(""));
+    let mut ignored: Vec<String> = mtl.unsupported;
+    let mut surface: Surface = Surface::default();
+    let transparency: f64 = mtl.dissolve.map(|d| (1.0 - d).clamp(0.0, 1.0)).unwrap_or(0.0);
+    let refracts: bool = mtl.optical_density.is_some_and(|ni| ni != 1.0) || matches!(mtl.illum, Some(4 | 6 | 7 | 9));
+
+    // Decide on the material that does the scattering
+    let mat: Material = if let Some(ke) = mtl.emissive.filter(|ke| max(*ke) > 0.0) {
+        Material::DiffuseLight(DiffuseLight { colour: ke })
+    } else if transparency > 0.0 && refracts {
+        if mtl.dissolve_map.is_some() {
+            ignored.push("map_d".into());
+        }
+        Material::Dielectric(Dielectric {
+            refraction_index: mtl.optical_density.unwrap_or(1.5),
+            colour: mtl.transmission_filter.unwrap_or(Colour::new(1.0, 1.0, 1.0, 1.0)),
+        })
+    } else {
+        // Anything else is opaque, but may have holes in it
+        surface.opacity = 1.0 - transparency;
+        surface.alpha = mtl.dissolve_map.as_ref().and_then(|map| load_map(map, dir, &mtl.name));
+
+        let kd: Option<Colour> = mtl.diffuse;
+        match mtl.specular.filter(|ks| max(*ks) > 0.0 && (mtl.illum == Some(3) || max(*ks) > kd.map(max).unwrap_or(0.0))) {
+            Some(ks) => {
+                if mtl.diffuse_map.is_some() {
+                    ignored.push("map_Kd".into());
+                }
+                // Map the exponent to roughness as is done to go from Blinn-Phong to Beckmann
+                Material::Metal(Metal { colour: ks, fuzz: (2.0 / (mtl.specular_exponent.unwrap_or(0.0).max(0.0) + 2.0)).sqrt() })
+            },
+            None => match (mtl.diffuse_map.as_ref().and_then(|map| load_map(map, dir, &mtl.name)), kd) {
+                (Some(image), _) => Material::LambertianTexture(LambertianTexture { texture: Texture::Image(image.into()) }),
+                (None, Some(kd)) => Material::Lambertian(Lambertian { colour: kd }),
+                (None, None) => DEFAULT_MAT,
+            },
+        }
+    };
+    surface.bump = mtl.bump_map.as_ref().and_then(|map| Some((load_map(map, dir, &mtl.name)?, map.bump_multiplier)));
+
+    // Tell the user what we've left out
+    if mtl.ambient.is_some_and(|ka| max(ka) > 0.0) {
+        ignored.push("Ka".into());
+    }
+    if !matches!(mat, Material::Dielectric(_)) && mtl.transmission_filter.is_some_and(|tf| tf.r < 1.0 || tf.g < 1.0 || tf.b < 1.0) {
+        ignored.push("Tf".into());
+    }
+    if !ignored.is_empty() {
+        ignored.sort_unstable();
+        ignored.dedup();
+        warn!(
+            "Ignoring unsupported propert{} {} of material {:?} in {:?}",
+            if ignored.len() == 1 { "y" } else { "ies" },
+            ignored.iter().map(|key| format!("'{key}'")).collect::<Vec<String>>().join(", "),
+            mtl.name,
+            path
+        );
+    }
+    (mat, surface)
+}
+
+/// Collects the elements of a list that a group of faces refers to into a buffer of its own.
+#[cfg(feature = "obj")]
+struct Remap<'a, T> {
+    /// The list with all elements.
+    list:    &'a [T],
+    /// Maps indices in `list` to indices in `buffer`.
+    indices: HashMap<usize, u32>,
+    /// The elements referred to so far.
+    buffer:  Vec<T>,
+}
+#[cfg(feature = "obj")]
+impl<'a, T: Copy> Remap<'a, T> {
+    /// Constructor for the Remap.
+    #[inline]
+    fn new(list: &'a [T]) -> Self { Self { list, indices: HashMap::new(), buffer: Vec::new() } }
+
+    /// Resolves an OBJ-style index into the list to one into the buffer, adding the element to it if it's new.
+    #[inline]
+    fn get(&mut self, i: isize) -> Result<u32, Error> {
+        let i: usize = index_get(self.list.len(), i)?;
+        Ok(*self.indices.entry(i).or_insert_with(|| {
+            self.buffer.push(self.list[i]);
+            (self.buffer.len() - 1) as u32
+        }))
+    }
 }
 
 
@@ -140,13 +343,89 @@ fn split_four_into_triangles(vs: [Vec3; 4]) -> [[Vec3; 3]; 2] {
 
 
 /***** AUXILLARY *****/
+/// Defines the parts of a material that change where a group of triangles is hit, rather than how
+/// the hits scatter.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Surface {
+    /// How opaque the group is, where rays pass through the rest of the time.
+    opacity: f64,
+    /// If given, an image whose brightness is multiplied with the opacity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alpha:   Option<Image>,
+    /// If given, an image whose brightness (times the given factor) are heights that bump the normal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bump:    Option<(Image, f64)>,
+    /// If given, a tangent-space normal map, whose sideways components are scaled by the given factor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    normal:  Option<(Image, f64)>,
+}
+impl Default for Surface {
+    #[inline]
+    fn default() -> Self { Self { opacity: 1.0, alpha: None, bump: None, normal: None } }
+}
+impl Surface {
+    /// Returns how opaque the surface is at the given texture coordinates.
+    #[inline]
+    fn opacity(&self, uv: (f64, f64)) -> f64 { self.alpha.as_ref().map(|alpha| self.opacity * brightness(alpha, uv)).unwrap_or(self.opacity) }
+
+    /// Bumps a normal by the normal map and the heights in the bump map, if any.
+    ///
+    /// # Arguments
+    /// - `normal`: The (unit) normal to bump.
+    /// - `uv`: The texture coordinates of the hit.
+    /// - `tangents`: The derivatives of the position w.r.t. the texture coordinates, if known.
+    ///
+    /// # Returns
+    /// The bumped normal, on the same side of the surface as `normal`.
+    fn bump(&self, mut normal: Vec3, uv: (f64, f64), tangents: Option<(Vec3, Vec3)>) -> Vec3 {
+        let Some((dpdu, dpdv)) = tangents else { return normal };
+
+        // Normal maps give the normal directly, in a frame where X runs along the first texture coordinate and Y along the second
+        if let Some((map, scale)) = &self.normal {
+            let tangent: Vec3 = dpdu - normal.dot(dpdu) * normal;
+            if tangent.length2() > 1e-24 {
+                let tangent: Vec3 = tangent.unit();
+                let bitangent: Vec3 = if normal.cross(tangent).dot(dpdv) < 0.0 { -normal.cross(tangent) } else { normal.cross(tangent) };
+                let texel: Colour = image_value(map, uv);
+                let mapped: Vec3 = scale * (2.0 * texel.r - 1.0) * tangent + scale * (2.0 * texel.g - 1.0) * bitangent + (2.0 * texel.b - 1.0) * normal;
+                if mapped.length2() > 1e-24 {
+                    normal = mapped.unit();
+                }
+            }
+        }
+        let Some((bump, scale)) = &self.bump else { return normal };
+
+        // Find the slope of the heights over one pixel in both directions
+        let (du, dv): (f64, f64) = (1.0 / bump.width().max(1) as f64, 1.0 / bump.height().max(1) as f64);
+        let height: f64 = brightness(bump, uv);
+        let dhdu: f64 = scale * (brightness(bump, (uv.0 + du, uv.1)) - height) / du;
+        let dhdv: f64 = scale * (brightness(bump, (uv.0, uv.1 + dv)) - height) / dv;
+
+        // Displace the surface along the normal, and see where the normal of that points
+        let side: f64 = if dpdu.cross(dpdv).dot(normal) < 0.0 { -1.0 } else { 1.0 };
+        let bumped: Vec3 = side * (dpdu + dhdu * normal).cross(dpdv + dhdv * normal);
+        if bumped.length2() > 1e-24 { bumped.unit() } else { normal }
+    }
+}
+
+
+
 /// Defines all the model formats we support.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ModelFormat {
+    /// `.gltf` and `.glb` file formats (glTF 2.0).
+    #[cfg(feature = "gltf")]
+    Gltf,
     /// `.obj` file formats.
     #[cfg(feature = "obj")]
     Obj,
+    /// `.ply` file formats (Polygon File Format), in ASCII or binary.
+    #[cfg(feature = "ply")]
+    Ply,
+    /// `.stl` file formats (stereolithography), in ASCII or binary.
+    #[cfg(feature = "stl")]
+    Stl,
 }
 
 
@@ -156,7 +435,6 @@ pub enum ModelFormat {
 #[serde(untagged)]
 pub enum Model {
     /// A model that's already loaded.
-    #[serde(skip)]
     Loaded(LoadedModel),
     /// A reference to a to-be-loaded model.
     ToLoad { path: PathBuf, format: Option<ModelFormat> },
@@ -166,6 +444,7 @@ pub enum Model {
 impl Loadable for Model {
     type Error = Error;
 
+    #[cfg_attr(not(any(feature = "gltf", feature = "obj", feature = "ply", feature = "stl")), allow(unused_variables))]
     fn load(&mut self, dir: &Path) -> Result<(), Self::Error> {
         let Self::ToLoad { path, format } = &*self else { return Ok(()) };
 
@@ -173,11 +452,22 @@ impl Loadable for Model {
         let fmt: ModelFormat = format
             .ok_or_else(|| {
                 // Inspect the file extension to see what's what
-                let spath = path.to_string_lossy();
+                #[cfg(feature = "gltf")]
+                if gltf::is_gltf_path(path) {
+                    return Ok(ModelFormat::Gltf);
+                }
                 #[cfg(feature = "obj")]
-                if spath.ends_with(".obj") {
+                if has_extension(path, &["obj"]) {
                     return Ok(ModelFormat::Obj);
                 }
+                #[cfg(feature = "ply")]
+                if has_extension(path, &["ply"]) {
+                    return Ok(ModelFormat::Ply);
+                }
+                #[cfg(feature = "stl")]
+                if has_extension(path, &["stl"]) {
+                    return Ok(ModelFormat::Stl);
+                }
                 return Err(Error::UnknownModelExtension {
                     name: path.file_name().map(OsStr::to_string_lossy).map(Cow::into_owned).unwrap_or_else(String::new),
                 });
@@ -186,11 +476,30 @@ impl Loadable for Model {
 
         // Load as that format
         match fmt {
+            #[cfg(feature = "gltf")]
+            ModelFormat::Gltf => {
+                let path: Cow<Path> = if path.is_relative() { Cow::Owned(dir.join(path)) } else { Cow::Borrowed(path) };
+                debug!("Loading model {path:?} as .gltf/.glb file...");
+                *self = Self::Loaded(gltf::load(&path)?);
+                Ok(())
+            },
+            #[cfg(feature = "ply")]
+            ModelFormat::Ply => {
+                let path: Cow<Path> = if path.is_relative() { Cow::Owned(dir.join(path)) } else { Cow::Borrowed(path) };
+                debug!("Loading model {path:?} as .ply file...");
+                *self = Self::Loaded(ply::load(&path)?);
+                Ok(())
+            },
+            #[cfg(feature = "stl")]
+            ModelFormat::Stl => {
+                let path: Cow<Path> = if path.is_relative() { Cow::Owned(dir.join(path)) } else { Cow::Borrowed(path) };
+                debug!("Loading model {path:?} as .stl file...");
+                *self = Self::Loaded(stl::load(&path)?);
+                Ok(())
+            },
             #[cfg(feature = "obj")]
             ModelFormat::Obj => {
                 // Open the file
-
-                use std::collections::HashMap;
                 let path: Cow<Path> = if path.is_relative() { Cow::Owned(dir.join(path)) } else { Cow::Borrowed(path) };
                 debug!("Loading model {path:?} as .obj file...");
                 let handle = match File::open(&path) {
@@ -203,91 +512,89 @@ impl Loadable for Model {
                     Ok(handle) => handle,
                     Err(err) => return Err(Error::Obj { path: path.into_owned(), err }),
                 };
-                let mut mtls = HashMap::<String, mtllib::Material>::new();
+                let mut mtls = HashMap::<String, (Material, Surface)>::new();
                 for mtl in &obj.mtllibs {
                     // Resolve the path
                     let mtl: Cow<Path> = if mtl.is_relative() { Cow::Owned(dir.join(mtl)) } else { Cow::Borrowed(mtl) };
 
                     // Attempt to load the file
-                    debug!("Loading model {mtl:?} as .mtllib file...");
+                    debug!("Loading model {mtl:?} as .mtl file...");
                     let handle = match File::open(&mtl) {
                         Ok(handle) => handle,
                         Err(err) => return Err(Error::FileOpen { path: mtl.into(), err }),
                     };
-                    let mtl = match mtllib::Mtl::from_reader(handle) {
-                        Ok(mtl) => mtl,
-                        Err(err) => return Err(Error::Mtllib { path: mtl.into(), err }),
+                    let materials: Vec<MtlMaterial> = match mtl::parse(BufReader::new(handle)) {
+                        Ok(materials) => materials,
+                        Err(err) => return Err(Error::Mtl { path: mtl.into(), err }),
                     };
-                    mtls.extend(mtl.mtls);
-                }
 
-                // Generate materials from the loaded ones
-                let mtls: HashMap<String, Material> = mtls
-                    .into_iter()
-                    .map(|(name, mtl)| {
-                        // Note: very narrow, extend as we go
-                        if let Some(kd) = mtl.color_diffuse {
-                            (name, Material::Lambertian(Lambertian { colour: Colour::new(kd.r, kd.g, kd.b, 1.0) }))
-                        } else {
-                            panic!("Unsupported coloring on material {name:?}");
-                        }
-                    })
-                    .collect();
+                    // Generate materials from the loaded ones
+                    for material in materials {
+                        mtls.insert(material.name.clone(), convert_mtl(material, &mtl));
+                    }
+                }
 
-                // Generate a list of Raytracer vertices from this
+                // Generate an indexed mesh for every group, which only has the vertices that group uses
                 let mut i: usize = 0;
                 let mut groups: Vec<LoadedGroup> = Vec::with_capacity(obj.objs.values().map(|o| o.faces.len()).sum::<usize>());
                 for (oname, obj) in obj.objs {
+                    let positions: Vec<Vec3> = obj.vertices.iter().map(|v| Vec3::new(v.x, v.y, v.z)).collect();
+                    let normals: Vec<Vec3> = obj.normals.iter().map(|n| Vec3::new(n.x, n.y, n.z)).collect();
+                    let uvs: Vec<(f64, f64)> = obj.texcoords.iter().map(|t| (t.u, t.v)).collect();
                     for group in obj.faces {
                         if group.faces.is_empty() {
                             continue;
                         }
-                        let mut triags = Vec::with_capacity(group.faces.len());
+                        let (mut pmap, mut nmap, mut tmap) = (Remap::new(&positions), Remap::new(&normals), Remap::new(&uvs));
+                        let mut triangles: Vec<MeshTriangle> = Vec::with_capacity(group.faces.len());
                         for face in group.faces {
-                            // Get the three vertices for this face and turn it into a triangle
-                            match face.elems.as_slice() {
-                                [v1, v2, v3] => {
-                                    let [v1, v2, v3] = [
-                                        vertex_get(&obj.vertices, v1.vertex)?,
-                                        vertex_get(&obj.vertices, v2.vertex)?,
-                                        vertex_get(&obj.vertices, v3.vertex)?,
-                                    ];
-                                    let [v1, v2, v3] = [Vec3::new(v1.x, v1.y, v1.z), Vec3::new(v2.x, v2.y, v2.z), Vec3::new(v3.x, v3.y, v3.z)];
-                                    triags.push(Triag { pos: v1, u: v2 - v1, v: v3 - v1 });
-                                },
-                                [v1, v2, v3, v4] => {
-                                    // Get the vertex equivalent
-                                    let [v1, v2, v3, v4] = [
-                                        vertex_get(&obj.vertices, v1.vertex)?,
-                                        vertex_get(&obj.vertices, v2.vertex)?,
-                                        vertex_get(&obj.vertices, v3.vertex)?,
-                                        vertex_get(&obj.vertices, v4.vertex)?,
-                                    ];
-                                    let [v1, v2, v3, v4] = [
-                                        Vec3::new(v1.x, v1.y, v1.z),
-                                        Vec3::new(v2.x, v2.y, v2.z),
-                                        Vec3::new(v3.x, v3.y, v3.z),
-                                        Vec3::new(v4.x, v4.y, v4.z),
-                                    ];
-
-                                    // Split it into two triangles and add them
-                                    let sides = split_four_into_triangles([v1, v2, v3, v4]);
-                                    triags.push(Triag { pos: sides[0][0], u: sides[0][1] - sides[0][0], v: sides[0][2] - sides[0][0] });
-                                    triags.push(Triag { pos: sides[1][0], u: sides[1][1] - sides[1][0], v: sides[1][2] - sides[1][0] });
-                                },
-                                _ => return Err(Error::NonTriangleFace { path: path.into(), oname, gname: None, i, got: face.elems.len() }),
+                            // Resolve the indices of every corner of the face. Normals and texture coordinates are only used if all corners have them.
+                            if face.elems.len() != 3 && face.elems.len() != 4 {
+                                return Err(Error::NonTriangleFace { path: path.into(), oname, gname: None, i, got: face.elems.len() });
+                            }
+                            let mut corners: Vec<(u32, Option<u32>, Option<u32>)> = Vec::with_capacity(face.elems.len());
+                            for elem in &face.elems {
+                                corners.push((
+                                    pmap.get(elem.vertex)?,
+                                    elem.normal.map(|n| nmap.get(n)).transpose()?,
+                                    elem.texture.map(|t| tmap.get(t)).transpose()?,
+                                ));
+                            }
+                            let triangle = |[c1, c2, c3]: [usize; 3]| {
+                                let [c1, c2, c3] = [corners[c1], corners[c2], corners[c3]];
+                                MeshTriangle {
+                                    positions: [c1.0, c2.0, c3.0],
+                                    normals:   c1.1.zip(c2.1).zip(c3.1).map(|((n1, n2), n3)| [n1, n2, n3]),
+                                    uvs:       c1.2.zip(c2.2).zip(c3.2).map(|((t1, t2), t3)| [t1, t2, t3]),
+                                    colours:   None,
+                                }
+                            };
+
+                            // Turn it into one or two triangles
+                            if corners.len() == 3 {
+                                triangles.push(triangle([0, 1, 2]));
+                            } else {
+                                // Split it into two triangles and add them
+                                let sides = split_four_into_triangles([0, 1, 2, 3].map(|c| pmap.buffer[corners[c].0 as usize]));
+                                triangles.push(triangle(sides[0]));
+                                triangles.push(triangle(sides[1]));
                             }
                             i += 1;
                         }
-                        groups.push(LoadedGroup {
-                            triags: HitTree::with_objs(triags, (0..=1).into()),
-                            mat:    group.material.as_ref().map(|m| mtls.get(m).unwrap_or(&UNKNOWN_MAT)).unwrap_or(&DEFAULT_MAT).clone(),
-                        });
+                        let mesh: Mesh = match Mesh::new(MeshBuffers { positions: pmap.buffer, normals: nmap.buffer, uvs: tmap.buffer, colours: Vec::new(), triangles }) {
+                            Ok(mesh) => mesh,
+                            Err(err) => return Err(Error::Mesh { path: path.into_owned(), err }),
+                        };
+                        let (mat, surface): (Material, Surface) = match group.material.as_ref() {
+                            Some(m) => mtls.get(m).cloned().unwrap_or_else(|| (UNKNOWN_MAT, Surface::default())),
+                            None => (DEFAULT_MAT, Surface::default()),
+                        };
+                        groups.push(LoadedGroup { mesh, mat, surface });
                     }
                 }
 
                 // When loaded, replace us with the loaded model
-                debug!("Succesfully loaded model {path:?} with {i} faces ({} triangles)", groups.iter().map(|g| g.triags.len()).sum::<usize>());
+                debug!("Succesfully loaded model {path:?} with {i} faces ({} triangles)", groups.iter().map(|g| g.mesh.len()).sum::<usize>());
                 // for t in &triangles {
                 //     println!("{{ {}, {} x {} }}", t.pos, t.u, t.v);
                 // }
@@ -308,9 +615,9 @@ impl BoundingBoxable for Model {
 }
 impl Hittable for Model {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         match self {
-            Self::Loaded(m) => m.hit(ray, t_min, t_max, env),
+            Self::Loaded(m) => m.hit(ray, t_min, t_max, env, sampler),
             Self::ToLoad { path, format: _ } => panic!("Cannot check hit of unloaded model {path:?}"),
         }
     }
@@ -322,30 +629,46 @@ impl Hittable for Model {
 
 /***** LIBRARY *****/
 /// Represents a group of triangles, already loaded.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 struct LoadedGroup {
-    /// A list of triangles that we can render.
-    triags: HitTree<Triag>,
+    /// The triangles that we can render.
+    mesh:    Mesh,
     /// The material that we render with.
-    mat:    Material,
+    mat:     Material,
+    /// Any holes or bumps in the surface.
+    #[serde(default)]
+    surface: Surface,
 }
 
 // Interface
 impl BoundingBoxable for LoadedGroup {
     #[inline]
-    fn aabb(&self, t_us: u64) -> AABB { self.triags.aabb(t_us) }
+    fn aabb(&self, t_us: u64) -> AABB { self.mesh.aabb(t_us) }
 }
 impl Hittable for LoadedGroup {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
-        self.triags.hit(ray, t_min, t_max, env).map(|rec| HitRecord { mat: &self.mat, data: rec.data })
+    fn hit(&self, ray: Ray, mut t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+        loop {
+            let (i, rec): (usize, HitRecord) = self.mesh.hit_indexed(ray, t_min, t_max, env, sampler)?;
+            let mut data = rec.data;
+
+            // Where the surface is (partially) transparent, randomly let the ray pass through to whatever's behind
+            let opacity: f64 = self.surface.opacity(data.uv);
+            if opacity < 1.0 && sampler.f64() >= opacity {
+                t_min = data.t.next_up();
+                continue;
+            }
+
+            data.normal = self.surface.bump(data.normal, data.uv, self.mesh.uv_tangents(i));
+            return Some(HitRecord { mat: &self.mat, data });
+        }
     }
 }
 
 
 
 /// A loaded counterpart of [`Model`].
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct LoadedModel {
     /// Overarching set of AABBs.
     aabb:   AABB,
@@ -360,12 +683,12 @@ impl BoundingBoxable for LoadedModel {
 }
 impl Hittable for LoadedModel {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         // Attempt to hit all groups
         let mut hit = None;
         let mut t = t_max;
         for g in &self.groups {
-            if let Some(ghit) = g.hit(ray, t_min, t, env) {
+            if let Some(ghit) = g.hit(ray, t_min, t, env, sampler) {
                 hit = Some(ghit);
                 t = ghit.data.t;
             }
@@ -373,3 +696,79 @@ impl Hittable for LoadedModel {
         hit
     }
 }
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "obj")]
+    #[test]
+    fn test_convert_mtl() {
+        let convert = |mtl: &str| -> (Material, Surface) { convert_mtl(mtl::parse(mtl.as_bytes()).unwrap().remove(0), Path::new("model.mtl")) };
+
+        // Plain materials are diffuse, even if they have a dimmer highlight
+        assert!(matches!(convert("newmtl A\nKd 0.5 0.5 0.5\nKs 0.2 0.2 0.2\nNs 256\nd 1\n").0, Material::Lambertian(Lambertian { colour }) if colour.r == 0.5));
+        assert!(matches!(convert("newmtl A\nKs 0 0 0\n").0, Material::Lambertian(_)));
+
+        // Highlights make them metal, glossier for higher exponents
+        let (Material::Metal(rough), Material::Metal(glossy)) =
+            (convert("newmtl A\nKd 0.1 0.1 0.1\nKs 0.9 0.8 0.7\nNs 10\n").0, convert("newmtl A\nKs 0.9 0.8 0.7\nNs 1000\nillum 3\n").0)
+        else {
+            panic!("Expected metal materials");
+        };
+        assert_eq!(rough.colour, Colour::new(0.9, 0.8, 0.7, 1.0));
+        assert!(glossy.fuzz < rough.fuzz && glossy.fuzz > 0.0);
+
+        // Transparency either refracts or lets rays through
+        assert!(matches!(convert("newmtl A\nKd 1 1 1\nNi 1.33\nd 0.1\n").0, Material::Dielectric(Dielectric { refraction_index, .. }) if refraction_index == 1.33));
+        let (mat, surface): (Material, Surface) = convert("newmtl A\nKd 1 1 1\nTr 0.25\n");
+        assert!(matches!(mat, Material::Lambertian(_)));
+        assert_eq!(surface.opacity((0.5, 0.5)), 0.75);
+
+        // Emission trumps everything
+        assert!(matches!(convert("newmtl A\nKd 1 1 1\nKs 1 1 1\nKe 4 4 4\n").0, Material::DiffuseLight(DiffuseLight { colour }) if colour.r == 4.0));
+
+        // Missing textures are left out instead of failing
+        let (mat, surface): (Material, Surface) = convert("newmtl A\nKd 0.5 0.5 0.5\nmap_Kd does_not_exist.png\nmap_bump does_not_exist.png\n");
+        assert!(matches!(mat, Material::Lambertian(_)));
+        assert!(surface.bump.is_none());
+    }
+
+    #[cfg(any(feature = "obj", feature = "ply", feature = "stl"))]
+    #[test]
+    fn test_model_extension() {
+        // Extensions are recognized regardless of their case (so we get to opening the file)
+        let dir: PathBuf = std::env::temp_dir().join(format!("raytracer-test-model-extension-{}", std::process::id()));
+        for (enabled, name) in [(cfg!(feature = "obj"), "MISSING.OBJ"), (cfg!(feature = "ply"), "scan.PLY"), (cfg!(feature = "stl"), "PART.STL")] {
+            if enabled {
+                assert!(matches!(Model::ToLoad { path: name.into(), format: None }.load(&dir), Err(Error::FileOpen { .. })), "{name}");
+            }
+        }
+        assert!(matches!(Model::ToLoad { path: "missing.xyz".into(), format: None }.load(&dir), Err(Error::UnknownModelExtension { .. })));
+    }
+
+    #[test]
+    fn test_surface_bump() {
+        // Heights that rise along the first texture coordinate tilt the normal backwards along it
+        let mut image = Image::new((4u32, 1u32));
+        for x in 0..4u32 {
+            image[(x, 0u32)] = Colour::new(x as f64 / 4.0, x as f64 / 4.0, x as f64 / 4.0, 1.0);
+        }
+        let surface = Surface { bump: Some((image, 1.0)), ..Default::default() };
+        let tangents: Option<(Vec3, Vec3)> = Some((Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)));
+        let normal: Vec3 = surface.bump(Vec3::new(0.0, 0.0, 1.0), (0.375, 0.5), tangents);
+        assert!(normal.x < 0.0 && normal.z > 0.0 && normal.y.abs() < 1e-9);
+
+        // The same holds when looking from behind
+        let normal: Vec3 = surface.bump(Vec3::new(0.0, 0.0, -1.0), (0.375, 0.5), tangents);
+        assert!(normal.z < 0.0);
+
+        // Without tangents, nothing happens
+        assert_eq!(surface.bump(Vec3::new(0.0, 0.0, 1.0), (0.375, 0.5), None), Vec3::new(0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,482 @@
+//  GLTF.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements loading glTF 2.0 files (both `.gltf` and `.glb`) as a
+//!   [`LoadedModel`], and importing their cameras and punctual lights into
+//!   a [`SceneFile`].
+//!
+//!   The node hierarchy is flattened while loading, i.e., every mesh that a
+//!   node refers to becomes its own group of triangles with the transforms
+//!   of all its ancestors applied.
+//
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use ::gltf::camera::Projection;
+use ::gltf::image::{Data as ImageData, Format};
+use ::gltf::khr_lights_punctual::Kind;
+use ::gltf::material::AlphaMode;
+use ::gltf::mesh::Mode;
+use ::gltf::{Document, Gltf, Node};
+use log::{debug, warn};
+
+use super::super::super::materials::{Dielectric, DiffuseLight, Lambertian, LambertianTexture, Material, Metal};
+use super::super::super::scene::{CameraInfo, CameraPos, SceneFile};
+use super::super::super::textures::Texture;
+use super::super::mesh::{Mesh, MeshBuffers, MeshTriangle};
+use super::super::{BoundingBoxable as _, Object, Sphere};
+use super::{DEFAULT_MAT, Error, LoadedGroup, LoadedModel, Model, ModelFormat, Surface, has_extension};
+use crate::math::{Colour, Transform, Vec3};
+use crate::render::image::Image;
+
+
+/***** CONSTANTS *****/
+/// The radius of the spheres that stand in for point- and spot lights, which have no size themselves.
+pub const POINT_LIGHT_RADIUS: f64 = 0.05;
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns whether a path looks like a glTF file, judging by its extension.
+///
+/// # Arguments
+/// - `path`: The path to check.
+///
+/// # Returns
+/// True if it ends in `.gltf` or `.glb` (in any case), or false otherwise.
+#[inline]
+pub fn is_gltf_path(path: &Path) -> bool { has_extension(path, &["gltf", "glb"]) }
+
+/// Calls a closure for every node in the default scene of a document, together with the transform from the node to the world.
+///
+/// If the document has no default scene, its first scene is used instead.
+///
+/// # Arguments
+/// - `doc`: The [`Document`] to walk.
+/// - `f`: The closure to call.
+fn walk_nodes<'d>(doc: &'d Document, mut f: impl FnMut(&Node<'d>, Transform)) {
+    fn walk<'d>(node: Node<'d>, parent: Transform, f: &mut impl FnMut(&Node<'d>, Transform)) {
+        let world: Transform = parent * Transform::from_cols(node.transform().matrix().map(|col| col.map(f64::from)));
+        f(&node, world);
+        for child in node.children() {
+            walk(child, world, f);
+        }
+    }
+
+    let Some(scene) = doc.default_scene().or_else(|| doc.scenes().next()) else {
+        warn!("glTF file has no scenes; nothing to load");
+        return;
+    };
+    for node in scene.nodes() {
+        walk(node, Transform::IDENTITY, &mut f);
+    }
+}
+
+/// Converts an image decoded by the [`gltf`](::gltf)-library into one of ours.
+///
+/// Single- and dual-channel images are assumed to be gray (with alpha).
+///
+/// # Arguments
+/// - `data`: The [`ImageData`] to convert.
+///
+/// # Returns
+/// A new [`Image`] with the same pixels.
+fn convert_image(data: &ImageData) -> Image {
+    let (channels, width): (usize, usize) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |i: usize| -> f64 {
+        let bytes: &[u8] = &data.pixels[i * width..(i + 1) * width];
+        match width {
+            1 => bytes[0] as f64 / 255.0,
+            2 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 65535.0,
+            _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        }
+    };
+
+    let mut image = Image::new((data.width, data.height));
+    for y in 0..data.height {
+        for x in 0..data.width {
+            let i: usize = (y as usize * data.width as usize + x as usize) * channels;
+            image[(x, y)] = match channels {
+                1 => Colour::new(channel(i), channel(i), channel(i), 1.0),
+                2 => Colour::new(channel(i), channel(i), channel(i), channel(i + 1)),
+                3 => Colour::new(channel(i), channel(i + 1), channel(i + 2), 1.0),
+                _ => Colour::new(channel(i), channel(i + 1), channel(i + 2), channel(i + 3)),
+            };
+        }
+    }
+    image
+}
+
+/// Converts a glTF material into the closest [`Material`] we can render.
+///
+/// In order of precedence, the material becomes:
+/// - a [`DiffuseLight`] if it is emissive;
+/// - a [`Dielectric`] if it transmits light (`KHR_materials_transmission`), with the index of
+///   refraction from `KHR_materials_ior`;
+/// - a [`Metal`] if it is mostly metallic, which is as fuzzy as it is rough; or
+/// - a [`LambertianTexture`] or [`Lambertian`] with the base colour otherwise.
+///
+/// Blended and masked alpha, as well as normal maps, are returned as a [`Surface`]. Properties that
+/// cannot be represented this way are reported as a warning.
+///
+/// # Arguments
+/// - `mat`: The glTF [`Material`](::gltf::Material) to convert.
+/// - `images`: The images in the file, already converted.
+/// - `path`: The path of the file (for debugging).
+///
+/// # Returns
+/// The [`Material`] and the [`Surface`] of the material.
+fn convert_material(mat: ::gltf::Material, images: &[Image], path: &Path) -> (Material, Surface) {
+    let name: String = mat.name().map(String::from).unwrap_or_else(|| format!("#{}", mat.index().unwrap_or(0)));
+    let mut ignored: Vec<&str> = Vec::new();
+    let mut surface: Surface = Surface::default();
+    let image = |info: ::gltf::texture::Info, ignored: &mut Vec<&str>| -> Image {
+        if info.tex_coord() != 0 {
+            ignored.push("additional texture coordinates");
+        }
+        images[info.texture().source().index()].clone()
+    };
+
+    // Bake the base colour factor into the texture, converting it to linear colours as we do
+    let pbr = mat.pbr_metallic_roughness();
+    let [r, g, b, a]: [f64; 4] = pbr.base_color_factor().map(f64::from);
+    let factor: Colour = Colour::new(r, g, b, a);
+    let base: Option<Image> = pbr.base_color_texture().map(|info| {
+        let mut base: Image = image(info, &mut ignored);
+        for colour in base.iter_mut() {
+            *colour = colour.from_srgb() * factor;
+        }
+        base
+    });
+
+    // Decide on the material that does the scattering
+    let [er, eg, eb]: [f64; 3] = mat.emissive_factor().map(f64::from);
+    let emission: Colour = Colour::new(er, eg, eb, 1.0) * mat.emissive_strength().map(f64::from).unwrap_or(1.0);
+    let result: Material = if emission.r > 0.0 || emission.g > 0.0 || emission.b > 0.0 {
+        if mat.emissive_texture().is_some() {
+            ignored.push("emissiveTexture");
+        }
+        Material::DiffuseLight(DiffuseLight { colour: emission.opaque() })
+    } else if mat.transmission().is_some_and(|t| t.transmission_factor() > 0.0) {
+        Material::Dielectric(Dielectric { refraction_index: mat.ior().map(f64::from).unwrap_or(1.5), colour: factor.opaque() })
+    } else if pbr.metallic_factor() >= 0.5 {
+        if base.is_some() {
+            ignored.push("baseColorTexture");
+        }
+        if pbr.metallic_roughness_texture().is_some() {
+            ignored.push("metallicRoughnessTexture");
+        }
+        Material::Metal(Metal { colour: factor.opaque(), fuzz: pbr.roughness_factor() as f64 })
+    } else {
+        match &base {
+            Some(base) => Material::LambertianTexture(LambertianTexture { texture: Texture::Image(base.clone().into()) }),
+            None => Material::Lambertian(Lambertian { colour: factor.opaque() }),
+        }
+    };
+
+    // Any transparency and the normals change which hits happen and how they're oriented
+    let cutoff: Option<f64> = match mat.alpha_mode() {
+        AlphaMode::Opaque => None,
+        AlphaMode::Mask => Some(mat.alpha_cutoff().unwrap_or(0.5) as f64),
+        AlphaMode::Blend => Some(f64::NAN),
+    };
+    if let Some(cutoff) = cutoff {
+        // Masks are all-or-nothing, blends are partial
+        let alpha = |a: f64| -> f64 {
+            if cutoff.is_nan() {
+                a
+            } else if a >= cutoff {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        match &base {
+            Some(base) => {
+                let mut map: Image = base.clone();
+                for colour in map.iter_mut() {
+                    let a: f64 = alpha(colour.a);
+                    *colour = Colour::new(a, a, a, 1.0);
+                }
+                surface.alpha = Some(map);
+            },
+            None => surface.opacity = alpha(factor.a),
+        }
+    }
+    if let Some(normal) = mat.normal_texture() {
+        if normal.tex_coord() != 0 {
+            ignored.push("additional texture coordinates");
+        }
+        surface.normal = Some((images[normal.texture().source().index()].clone(), normal.scale() as f64));
+    }
+
+    // Tell the user what we've left out
+    if !ignored.is_empty() {
+        ignored.sort_unstable();
+        ignored.dedup();
+        warn!(
+            "Ignoring unsupported propert{} {} of material {:?} in {:?}",
+            if ignored.len() == 1 { "y" } else { "ies" },
+            ignored.iter().map(|key| format!("'{key}'")).collect::<Vec<String>>().join(", "),
+            name,
+            path
+        );
+    }
+    (result, surface)
+}
+
+/// Reads a single primitive of a mesh into [`MeshBuffers`].
+///
+/// # Arguments
+/// - `prim`: The [`Primitive`](::gltf::Primitive) to read.
+/// - `buffers`: The buffers of the file.
+/// - `world`: The [`Transform`] from the node of the mesh to the world.
+///
+/// # Returns
+/// The buffers of the primitive in world space, or [`None`] if it has no triangles we can render
+/// (which is reported as a warning).
+fn read_primitive(prim: &::gltf::Primitive, buffers: &[::gltf::buffer::Data], world: Transform) -> Option<MeshBuffers> {
+    let reader = prim.reader(|buffer| buffers.get(buffer.index()).map(|data| data.0.as_slice()));
+    let Some(positions) = reader.read_positions() else {
+        warn!("Skipping primitive {} without positions", prim.index());
+        return None;
+    };
+    let positions: Vec<Vec3> = positions.map(|[x, y, z]| world.point(Vec3::new(x as f64, y as f64, z as f64))).collect();
+    let normals: Vec<Vec3> = reader.read_normals().map(|n| n.map(|[x, y, z]| world.normal(Vec3::new(x as f64, y as f64, z as f64))).collect()).unwrap_or_default();
+    // NOTE: glTF puts the origin of textures in the top left, we put it in the bottom left
+    let uvs: Vec<(f64, f64)> = reader.read_tex_coords(0).map(|t| t.into_f32().map(|[u, v]| (u as f64, 1.0 - v as f64)).collect()).unwrap_or_default();
+    let colours: Vec<Colour> = reader.read_colors(0).map(|c| c.into_rgba_f32().map(|[r, g, b, a]| Colour::new(r, g, b, a)).collect()).unwrap_or_default();
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    // Assemble the triangles, keeping them counter-clockwise if the transform mirrors
+    let mut corners: Vec<[u32; 3]> = match prim.mode() {
+        Mode::Triangles => indices.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+        Mode::TriangleStrip => indices.windows(3).enumerate().map(|(i, w)| if i % 2 == 0 { [w[0], w[1], w[2]] } else { [w[1], w[0], w[2]] }).collect(),
+        Mode::TriangleFan => indices.windows(2).skip(1).map(|w| [indices[0], w[0], w[1]]).collect(),
+        mode => {
+            warn!("Skipping primitive {} with unsupported mode {mode:?}", prim.index());
+            return None;
+        },
+    };
+    if world.determinant() < 0.0 {
+        for corner in &mut corners {
+            corner.swap(1, 2);
+        }
+    }
+    let has_normals: bool = normals.len() == positions.len();
+    let has_uvs: bool = uvs.len() == positions.len();
+    let has_colours: bool = colours.len() == positions.len();
+    let triangles: Vec<MeshTriangle> = corners
+        .into_iter()
+        .map(|c| MeshTriangle { positions: c, normals: has_normals.then_some(c), uvs: has_uvs.then_some(c), colours: has_colours.then_some(c) })
+        .collect();
+    Some(MeshBuffers { positions, normals, uvs, colours, triangles })
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Loads a glTF file as a model.
+///
+/// Only the meshes are loaded; see [`scene()`] for the cameras and lights.
+///
+/// # Arguments
+/// - `path`: The path of the `.gltf` or `.glb` file. Any external buffers and images are resolved relative to it.
+///
+/// # Returns
+/// A new [`LoadedModel`] with all the meshes in the default scene of the file.
+///
+/// # Errors
+/// This function errors if we failed to read or parse the file (or its buffers and images), or if
+/// it has invalid meshes.
+pub fn load(path: &Path) -> Result<LoadedModel, Error> {
+    let (doc, buffers, images) = ::gltf::import(path).map_err(|err| Error::Gltf { path: path.into(), err })?;
+    let images: Vec<Image> = images.iter().map(convert_image).collect();
+
+    // Convert every mesh, converting the materials as we encounter them
+    let mut mats: HashMap<Option<usize>, (Material, Surface)> = HashMap::new();
+    let mut groups: Vec<LoadedGroup> = Vec::new();
+    let mut res: Result<(), Error> = Ok(());
+    walk_nodes(&doc, |node, world| {
+        let Some(mesh) = node.mesh() else { return };
+        for prim in mesh.primitives() {
+            if res.is_err() {
+                return;
+            }
+            let Some(buffers) = read_primitive(&prim, &buffers, world) else { continue };
+            let mesh: Mesh = match Mesh::new(buffers) {
+                Ok(mesh) => mesh,
+                Err(err) => {
+                    res = Err(Error::Mesh { path: path.into(), err });
+                    return;
+                },
+            };
+            let (mat, surface): (Material, Surface) = mats
+                .entry(prim.material().index())
+                .or_insert_with(|| match prim.material().index() {
+                    Some(_) => convert_material(prim.material(), &images, path),
+                    None => (DEFAULT_MAT, Surface::default()),
+                })
+                .clone();
+            groups.push(LoadedGroup { mesh, mat, surface });
+        }
+    });
+    res?;
+
+    debug!("Succesfully loaded model {path:?} with {} triangles in {} groups", groups.iter().map(|g| g.mesh.len()).sum::<usize>(), groups.len());
+    Ok(LoadedModel { aabb: groups.iter().map(|g| g.aabb(0)).collect(), groups })
+}
+
+/// Imports a glTF file as a whole scene.
+///
+/// The scene renders the file as a [`Model`], and additionally takes over the first (perspective)
+/// camera and all point- and spot lights in it. The latter become small emitting [`Sphere`]s of
+/// [`POINT_LIGHT_RADIUS`] that have the same intensity, taking the intensities in the file as-is.
+/// Directional lights are not supported.
+///
+/// # Arguments
+/// - `path`: The path of the `.gltf` or `.glb` file.
+///
+/// # Returns
+/// A new [`SceneFile`] with the model, camera and lights.
+///
+/// # Errors
+/// This function errors if we failed to read or parse the file.
+pub fn scene(path: &Path) -> Result<SceneFile, Error> {
+    let gltf: Gltf = Gltf::open(path).map_err(|err| Error::Gltf { path: path.into(), err })?;
+
+    // The model itself will be loaded relative to the directory of the file
+    let mut objects: Vec<Object> = vec![Object::Model(Model::ToLoad {
+        path:   path.file_name().map(PathBuf::from).unwrap_or_else(|| path.into()),
+        format: Some(ModelFormat::Gltf),
+    })];
+    let mut camera: Option<CameraInfo> = None;
+    walk_nodes(&gltf.document, |node, world| {
+        if let Some(cam) = node.camera() {
+            match cam.projection() {
+                Projection::Perspective(persp) if camera.is_none() => {
+                    let mut info: CameraInfo = CameraInfo {
+                        vfov: (persp.yfov() as f64).to_degrees(),
+                        // Cameras look along their negative Z-axis, with Y up
+                        pos: CameraPos {
+                            lookfrom: world.point(Vec3::zeroes()),
+                            lookat:   world.point(Vec3::new(0.0, 0.0, -1.0)),
+                            lookup:   world.vector(Vec3::new(0.0, 1.0, 0.0)),
+                        },
+                        ..Default::default()
+                    };
+                    if let Some(width) = persp.aspect_ratio().and_then(|aspect| NonZeroU32::new((info.dims.1.get() as f64 * aspect as f64).round() as u32)) {
+                        info.dims.0 = width;
+                    }
+                    camera = Some(info);
+                },
+                Projection::Perspective(_) => debug!("Ignoring camera {} in {path:?}, since we already found one", cam.index()),
+                Projection::Orthographic(_) => warn!("Ignoring orthographic camera {} in {path:?} (unsupported)", cam.index()),
+            }
+        }
+        if let Some(light) = node.light() {
+            if let Kind::Directional = light.kind() {
+                warn!("Ignoring directional light {} in {path:?} (unsupported)", light.index());
+                return;
+            }
+            if let Kind::Spot { .. } = light.kind() {
+                warn!("Treating spot light {} in {path:?} as a point light", light.index());
+            }
+
+            // A sphere emitting `L` has an intensity of `L * pi * r^2` in every direction
+            let [r, g, b]: [f64; 3] = light.color().map(f64::from);
+            let radiance: f64 = light.intensity() as f64 / (PI * POINT_LIGHT_RADIUS * POINT_LIGHT_RADIUS);
+            objects.push(Object::Sphere(Sphere {
+                center:   world.point(Vec3::zeroes()),
+                radius:   POINT_LIGHT_RADIUS,
+                material: Material::DiffuseLight(DiffuseLight { colour: Colour::new(r * radiance, g * radiance, b * radiance, 1.0) }),
+            }));
+        }
+    });
+    if camera.is_none() {
+        debug!("No perspective camera found in {path:?}; using the default camera");
+    }
+
+    Ok(SceneFile { environment: Default::default(), camera: camera.unwrap_or_default(), definitions: Default::default(), objects })
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single triangle in the XY-plane, moved by its node, with a red metal, a camera and a point light.
+    const TRIANGLE: &str = r#"{
+  "asset": { "version": "2.0" },
+  "extensionsUsed": ["KHR_lights_punctual"],
+  "extensions": { "KHR_lights_punctual": { "lights": [{ "type": "point", "color": [1.0, 1.0, 1.0], "intensity": 2.0 }] } },
+  "scene": 0,
+  "scenes": [{ "nodes": [0, 1] }],
+  "nodes": [
+    { "translation": [1.0, 2.0, 3.0], "mesh": 0 },
+    { "translation": [0.0, 0.0, 5.0], "camera": 0, "children": [2] },
+    { "translation": [0.0, 1.0, 0.0], "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+  ],
+  "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.7853981633974483, "aspectRatio": 2.0, "znear": 0.1 } }],
+  "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+  "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [1.0, 0.0, 0.0, 1.0], "metallicFactor": 1.0, "roughnessFactor": 0.25 } }],
+  "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0] }],
+  "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+  "buffers": [{ "byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA" }]
+}"#;
+
+    #[test]
+    fn test_load_gltf() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("raytracer-test-gltf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path: PathBuf = dir.join("triangle.gltf");
+        std::fs::write(&path, TRIANGLE).unwrap();
+        assert!(is_gltf_path(&path));
+        assert!(is_gltf_path(Path::new("Scene.GLB")) && !is_gltf_path(Path::new("gltf")));
+
+        // The mesh is moved by its node
+        let model: LoadedModel = load(&path).unwrap();
+        assert_eq!(model.groups.len(), 1);
+        assert_eq!((model.aabb.x.min(), model.aabb.x.max()), (1.0, 2.0));
+        assert_eq!((model.aabb.y.min(), model.aabb.y.max()), (2.0, 3.0));
+        assert!(matches!(model.groups[0].mat, Material::Metal(Metal { colour, fuzz }) if colour == Colour::new(1.0, 0.0, 0.0, 1.0) && fuzz == 0.25));
+
+        // The camera and the light (which inherits the camera's transform) are found too
+        let scene: SceneFile = scene(&path).unwrap();
+        assert!((scene.camera.vfov - 45.0).abs() < 1e-4);
+        assert_eq!(scene.camera.dims.0.get(), 2 * scene.camera.dims.1.get());
+        assert_eq!(scene.camera.pos.lookfrom, Vec3::new(0.0, 0.0, 5.0));
+        assert_eq!(scene.camera.pos.lookat, Vec3::new(0.0, 0.0, 4.0));
+        assert_eq!(scene.objects.len(), 2);
+        assert!(matches!(&scene.objects[0], Object::Model(Model::ToLoad { format: Some(ModelFormat::Gltf), .. })));
+        assert!(matches!(&scene.objects[1], Object::Sphere(Sphere { center, .. }) if *center == Vec3::new(0.0, 1.0, 5.0)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,435 @@
+//  PLY.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements a parser for `.ply` (Polygon File Format) files, both in
+//!   ASCII and binary, and loading them as a [`LoadedModel`].
+//!
+//!   Only the `vertex` and `face` elements are used. Vertices may have
+//!   normals and colours next to their positions; the colours tint the
+//!   (diffuse) material of the model, i.e., act as a per-vertex albedo.
+//
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use log::{debug, warn};
+use thiserror::Error;
+
+use super::super::super::materials::{Lambertian, Material};
+use super::super::mesh::{Mesh, MeshBuffers, MeshTriangle};
+use super::super::BoundingBoxable as _;
+use super::{DEFAULT_MAT, Error as ModelError, LoadedGroup, LoadedModel, Surface};
+use crate::math::{Colour, Vec3};
+
+
+/***** ERRORS *****/
+/// Defines problems with parsing `.ply` files.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unexpected end of file in {element:?} {i}")]
+    Eof { element: String, i: usize },
+    #[error("Line {line}: illegal header line {raw:?}")]
+    IllegalHeader { line: usize, raw: String },
+    #[error("Face {i} refers to illegal vertex index {got}")]
+    IllegalIndex { i: usize, got: f64 },
+    #[error("Expected a number in {element:?} {i}, got {got:?}")]
+    IllegalValue { element: String, i: usize, got: String },
+    #[error("Vertices have no 'x', 'y' and 'z' properties")]
+    MissingPosition,
+    #[error("File does not start with 'ply'")]
+    NotPly,
+    #[error("Failed to read file")]
+    Read {
+        #[source]
+        err: std::io::Error,
+    },
+    #[error("Line {line}: unknown format {got:?} (expected 'ascii', 'binary_little_endian' or 'binary_big_endian')")]
+    UnknownFormat { line: usize, got: String },
+    #[error("Line {line}: unknown property type {got:?}")]
+    UnknownType { line: usize, got: String },
+}
+
+
+
+
+
+/***** AUXILLARY *****/
+/// The ways in which the body of a file may be written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// The types of the values of properties.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+impl Scalar {
+    /// Parses a type as written in the header, including its aliases (e.g., `uchar` for `uint8`).
+    fn parse(line: usize, raw: &str) -> Result<Self, Error> {
+        match raw {
+            "char" | "int8" => Ok(Self::I8),
+            "uchar" | "uint8" => Ok(Self::U8),
+            "short" | "int16" => Ok(Self::I16),
+            "ushort" | "uint16" => Ok(Self::U16),
+            "int" | "int32" => Ok(Self::I32),
+            "uint" | "uint32" => Ok(Self::U32),
+            "float" | "float32" => Ok(Self::F32),
+            "double" | "float64" => Ok(Self::F64),
+            got => Err(Error::UnknownType { line, got: got.into() }),
+        }
+    }
+
+    /// Returns the size of a binary value of this type, in bytes.
+    #[inline]
+    const fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Returns the scale that maps the range of integer colour channels of this type to `[0, 1]`.
+    #[inline]
+    const fn colour_scale(self) -> f64 {
+        match self {
+            Self::I8 | Self::U8 => 255.0,
+            Self::I16 | Self::U16 => 65535.0,
+            Self::I32 | Self::U32 => 4294967295.0,
+            Self::F32 | Self::F64 => 1.0,
+        }
+    }
+
+    /// Decodes a binary value of this type.
+    ///
+    /// # Arguments
+    /// - `bytes`: The [`Scalar::size()`] bytes of the value.
+    /// - `big_endian`: Whether the bytes are big endian (true) or little endian (false).
+    ///
+    /// # Returns
+    /// The value, as a float.
+    fn decode(self, bytes: &[u8], big_endian: bool) -> f64 {
+        macro_rules! decode {
+            ($ty:ty) => {{
+                let bytes: [u8; std::mem::size_of::<$ty>()] = bytes.try_into().unwrap();
+                (if big_endian { <$ty>::from_be_bytes(bytes) } else { <$ty>::from_le_bytes(bytes) }) as f64
+            }};
+        }
+        match self {
+            Self::I8 => decode!(i8),
+            Self::U8 => decode!(u8),
+            Self::I16 => decode!(i16),
+            Self::U16 => decode!(u16),
+            Self::I32 => decode!(i32),
+            Self::U32 => decode!(u32),
+            Self::F32 => decode!(f32),
+            Self::F64 => decode!(f64),
+        }
+    }
+}
+
+/// A property of an element, as declared in the header.
+#[derive(Clone, Debug)]
+struct Property {
+    /// The name of the property.
+    name: String,
+    /// The type of the value, or of the items if it's a list.
+    ty:   Scalar,
+    /// If it's a list, the type of its length.
+    list: Option<Scalar>,
+}
+
+/// An element, as declared in the header.
+#[derive(Clone, Debug)]
+struct Element {
+    /// The name of the element.
+    name:  String,
+    /// The number of instances in the body.
+    count: usize,
+    /// The properties of every instance.
+    props: Vec<Property>,
+}
+
+
+
+/// Reads the values in the body of a file one-by-one, whatever its format.
+struct Body<R> {
+    /// The reader to read from.
+    reader: R,
+    /// The format of the body.
+    format: Format,
+    /// For ASCII bodies, the remaining words on the current line (reversed).
+    words:  Vec<String>,
+}
+impl<R: BufRead> Body<R> {
+    /// Reads the next value.
+    ///
+    /// # Arguments
+    /// - `ty`: The type of the value.
+    /// - `element`: The name of the element we're reading (for debugging).
+    /// - `i`: The index of the instance we're reading (for debugging).
+    ///
+    /// # Returns
+    /// The value, as a float.
+    ///
+    /// # Errors
+    /// This function errors if we failed to read the value or it is not a number.
+    fn next(&mut self, ty: Scalar, element: &str, i: usize) -> Result<f64, Error> {
+        match self.format {
+            Format::Ascii => {
+                while self.words.is_empty() {
+                    let mut line: String = String::new();
+                    if self.reader.read_line(&mut line).map_err(|err| Error::Read { err })? == 0 {
+                        return Err(Error::Eof { element: element.into(), i });
+                    }
+                    self.words = line.split_whitespace().rev().map(String::from).collect();
+                }
+                let word: String = self.words.pop().unwrap();
+                word.parse::<f64>().map_err(|_| Error::IllegalValue { element: element.into(), i, got: word })
+            },
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+                let mut bytes: [u8; 8] = [0; 8];
+                match self.reader.read_exact(&mut bytes[..ty.size()]) {
+                    Ok(()) => Ok(ty.decode(&bytes[..ty.size()], self.format == Format::BinaryBigEndian)),
+                    Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Err(Error::Eof { element: element.into(), i }),
+                    Err(err) => Err(Error::Read { err }),
+                }
+            },
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Parses a `.ply` file into the buffers of a mesh.
+///
+/// Polygonal faces are split into triangles around their first vertex. Colours are assumed to be
+/// in sRGB, and are converted to linear colours.
+///
+/// # Arguments
+/// - `reader`: The reader to parse from.
+///
+/// # Returns
+/// The [`MeshBuffers`] with the vertices (and their normals and colours, if any) and faces.
+///
+/// # Errors
+/// This function errors if we failed to read the file or it is not a valid `.ply` file.
+pub fn parse(mut reader: impl BufRead) -> Result<MeshBuffers, Error> {
+    // Parse the header
+    let mut format: Option<Format> = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line: usize = 0;
+    loop {
+        let mut raw: String = String::new();
+        if reader.read_line(&mut raw).map_err(|err| Error::Read { err })? == 0 {
+            return Err(if line == 0 { Error::NotPly } else { Error::IllegalHeader { line: line + 1, raw } });
+        }
+        line += 1;
+        let words: Vec<&str> = raw.split_whitespace().collect();
+        match (line, &words[..]) {
+            (1, ["ply"]) => continue,
+            (1, _) => return Err(Error::NotPly),
+            (_, ["format", fmt, _]) => {
+                format = Some(match *fmt {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    got => return Err(Error::UnknownFormat { line, got: got.into() }),
+                })
+            },
+            (_, ["comment" | "obj_info", ..]) | (_, []) => continue,
+            (_, ["element", name, count]) => match count.parse::<usize>() {
+                Ok(count) => elements.push(Element { name: (*name).into(), count, props: Vec::new() }),
+                Err(_) => return Err(Error::IllegalHeader { line, raw }),
+            },
+            (_, ["property", "list", len, ty, name]) if !elements.is_empty() => {
+                let prop = Property { name: (*name).into(), ty: Scalar::parse(line, ty)?, list: Some(Scalar::parse(line, len)?) };
+                elements.last_mut().unwrap().props.push(prop);
+            },
+            (_, ["property", ty, name]) if !elements.is_empty() => {
+                let prop = Property { name: (*name).into(), ty: Scalar::parse(line, ty)?, list: None };
+                elements.last_mut().unwrap().props.push(prop);
+            },
+            (_, ["end_header"]) => break,
+            _ => return Err(Error::IllegalHeader { line, raw }),
+        }
+    }
+    let Some(format) = format else { return Err(Error::IllegalHeader { line, raw: "end_header".into() }) };
+
+    // Parse the body, element by element
+    let mut buffers: MeshBuffers = MeshBuffers::default();
+    let mut faces: Vec<Vec<u32>> = Vec::new();
+    let (mut has_normals, mut has_colours): (bool, bool) = (false, false);
+    let mut body = Body { reader, format, words: Vec::new() };
+    for element in &elements {
+        // Find where the properties we want are, if they're there
+        let find = |names: &[&str]| -> Option<usize> { element.props.iter().position(|p| p.list.is_none() && names.contains(&p.name.as_str())) };
+        let pos: [Option<usize>; 3] = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal: [Option<usize>; 3] = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let colour: [Option<usize>; 4] =
+            [find(&["red", "r", "diffuse_red"]), find(&["green", "g", "diffuse_green"]), find(&["blue", "b", "diffuse_blue"]), find(&["alpha", "a"])];
+        let indices: Option<usize> = element.props.iter().position(|p| p.list.is_some() && (p.name == "vertex_indices" || p.name == "vertex_index"));
+        if element.name == "vertex" {
+            if pos.iter().any(Option::is_none) {
+                return Err(Error::MissingPosition);
+            }
+            has_normals = normal.iter().all(Option::is_some);
+            has_colours = colour[..3].iter().all(Option::is_some);
+        } else if element.name != "face" {
+            debug!("Skipping {} instances of unused element {:?}", element.count, element.name);
+        }
+
+        // Read the instances
+        let mut values: Vec<f64> = vec![0.0; element.props.len()];
+        for i in 0..element.count {
+            let mut list: Vec<u32> = Vec::new();
+            for (p, prop) in element.props.iter().enumerate() {
+                match prop.list {
+                    Some(len) => {
+                        let len: usize = body.next(len, &element.name, i)? as usize;
+                        for _ in 0..len {
+                            let index: f64 = body.next(prop.ty, &element.name, i)?;
+                            if Some(p) == indices {
+                                if index < 0.0 || index > u32::MAX as f64 || index.fract() != 0.0 {
+                                    return Err(Error::IllegalIndex { i, got: index });
+                                }
+                                list.push(index as u32);
+                            }
+                        }
+                    },
+                    None => values[p] = body.next(prop.ty, &element.name, i)?,
+                }
+            }
+
+            // Store what we need
+            let get = |prop: Option<usize>| -> f64 { prop.map(|p| values[p]).unwrap_or(0.0) };
+            match element.name.as_str() {
+                "vertex" => {
+                    buffers.positions.push(Vec3::new(get(pos[0]), get(pos[1]), get(pos[2])));
+                    if has_normals {
+                        buffers.normals.push(Vec3::new(get(normal[0]), get(normal[1]), get(normal[2])));
+                    }
+                    if has_colours {
+                        let [r, g, b]: [f64; 3] = [0, 1, 2].map(|c| get(colour[c]) / element.props[colour[c].unwrap()].ty.colour_scale());
+                        let a: f64 = colour[3].map(|p| values[p] / element.props[p].ty.colour_scale()).unwrap_or(1.0);
+                        buffers.colours.push(Colour::new(r, g, b, a).from_srgb());
+                    }
+                },
+                "face" if list.len() >= 3 => faces.push(list),
+                "face" => warn!("Skipping face {i} with only {} vertices", list.len()),
+                _ => {},
+            }
+        }
+    }
+
+    // Split the faces into triangles
+    for face in faces {
+        for c in 1..face.len() - 1 {
+            let corners: [u32; 3] = [face[0], face[c], face[c + 1]];
+            buffers.triangles.push(MeshTriangle {
+                positions: corners,
+                normals:   has_normals.then_some(corners),
+                uvs:       None,
+                colours:   has_colours.then_some(corners),
+            });
+        }
+    }
+    Ok(buffers)
+}
+
+
+
+/// Loads a `.ply` file as a model.
+///
+/// Models with vertex colours are rendered with a white [`Lambertian`] material, such that the
+/// colours become their albedo. Others use the [`DEFAULT_MAT`].
+///
+/// # Arguments
+/// - `path`: The path of the `.ply` file.
+///
+/// # Returns
+/// A new [`LoadedModel`] with a single group.
+///
+/// # Errors
+/// This function errors if we failed to read or parse the file, or if its faces refer to vertices that aren't there.
+pub fn load(path: &Path) -> Result<LoadedModel, ModelError> {
+    let handle: File = File::open(path).map_err(|err| ModelError::FileOpen { path: path.into(), err })?;
+    let buffers: MeshBuffers = parse(BufReader::new(handle)).map_err(|err| ModelError::Ply { path: path.into(), err })?;
+    let mat: Material =
+        if buffers.colours.is_empty() { DEFAULT_MAT } else { Material::Lambertian(Lambertian { colour: Colour::new(1.0, 1.0, 1.0, 1.0) }) };
+    let mesh: Mesh = Mesh::new(buffers).map_err(|err| ModelError::Mesh { path: path.into(), err })?;
+
+    debug!("Succesfully loaded model {path:?} with {} triangles", mesh.len());
+    let group = LoadedGroup { mesh, mat, surface: Surface::default() };
+    Ok(LoadedModel { aabb: group.aabb(0), groups: vec![group] })
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // An ASCII quad with colours, split into two triangles
+        let buffers: MeshBuffers = parse(
+            "ply\nformat ascii 1.0\ncomment made by hand\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nproperty uchar \
+             red\nproperty uchar green\nproperty uchar blue\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0 255 0 0\n1 0 0 0 \
+             255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(buffers.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        assert!(buffers.normals.is_empty());
+        assert_eq!(buffers.colours[1], Colour::new(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(buffers.triangles.len(), 2);
+        assert_eq!(buffers.triangles[1].positions, [0, 2, 3]);
+        assert_eq!(buffers.triangles[1].colours, Some([0, 2, 3]));
+
+        // The same triangle in binary, big endian, with normals and an unused element in between
+        let mut data: Vec<u8> = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\nproperty double x\nproperty double y\nproperty double z\nproperty \
+                                  float nx\nproperty float ny\nproperty float nz\nelement edge 1\nproperty int vertex1\nproperty int vertex2\nelement face \
+                                  1\nproperty uchar flags\nproperty list uchar uint vertex_index\nend_header\n"
+            .to_vec();
+        for [x, y] in [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]] {
+            for v in [x, y, 0.0f64] {
+                data.extend(v.to_be_bytes());
+            }
+            for n in [0.0, 0.0, 1.0f32] {
+                data.extend(n.to_be_bytes());
+            }
+        }
+        data.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        data.extend([7, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2]);
+        let buffers: MeshBuffers = parse(&data[..]).unwrap();
+        assert_eq!(buffers.positions[1], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(buffers.normals[2], Vec3::new(0.0, 0.0, 1.0));
+        assert!(buffers.colours.is_empty());
+        assert_eq!(buffers.triangles, vec![MeshTriangle { positions: [0, 1, 2], normals: Some([0, 1, 2]), uvs: None, colours: None }]);
+
+        // Missing data is caught
+        assert!(matches!(parse(&data[..data.len() - 1]), Err(Error::Eof { i: 0, .. })));
+        assert!(matches!(parse("obj\n".as_bytes()), Err(Error::NotPly)));
+    }
+}
@@ -0,0 +1,99 @@
+//  STL.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements loading `.stl` (stereolithography) files, both in ASCII
+//!   and binary, as a [`LoadedModel`].
+//!
+//!   STL files only describe flat triangles, so models are rendered
+//!   without smooth normals, texture coordinates or materials.
+//
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use log::debug;
+use stl_io::IndexedMesh;
+
+use super::super::mesh::{Mesh, MeshBuffers, MeshTriangle};
+use super::super::BoundingBoxable as _;
+use super::{DEFAULT_MAT, Error, LoadedGroup, LoadedModel, Surface};
+use crate::math::Vec3;
+
+
+/***** LIBRARY *****/
+/// Converts an STL mesh into the buffers of one of ours.
+///
+/// The normals stored in the file are ignored, since they are often missing or wrong; instead,
+/// triangles face the side from which their corners are counter-clockwise (as the format demands).
+///
+/// # Arguments
+/// - `stl`: The [`IndexedMesh`] to convert.
+///
+/// # Returns
+/// The [`MeshBuffers`] of the same triangles.
+fn convert(stl: IndexedMesh) -> MeshBuffers {
+    MeshBuffers {
+        positions: stl.vertices.into_iter().map(|v| Vec3::new(v[0] as f64, v[1] as f64, v[2] as f64)).collect(),
+        triangles: stl
+            .faces
+            .into_iter()
+            .map(|face| MeshTriangle { positions: face.vertices.map(|i| i as u32), normals: None, uvs: None, colours: None })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+
+
+/// Loads an `.stl` file as a model.
+///
+/// # Arguments
+/// - `path`: The path of the `.stl` file.
+///
+/// # Returns
+/// A new [`LoadedModel`] with a single group, which is rendered with the [`DEFAULT_MAT`].
+///
+/// # Errors
+/// This function errors if we failed to read or parse the file.
+pub fn load(path: &Path) -> Result<LoadedModel, Error> {
+    let handle: File = File::open(path).map_err(|err| Error::FileOpen { path: path.into(), err })?;
+    let stl: IndexedMesh = stl_io::read_stl(&mut BufReader::new(handle)).map_err(|err| Error::Stl { path: path.into(), err })?;
+    let mesh: Mesh = Mesh::new(convert(stl)).map_err(|err| Error::Mesh { path: path.into(), err })?;
+
+    debug!("Succesfully loaded model {path:?} with {} triangles", mesh.len());
+    let group = LoadedGroup { mesh, mat: DEFAULT_MAT, surface: Surface::default() };
+    Ok(LoadedModel { aabb: group.aabb(0), groups: vec![group] })
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        // Two triangles sharing an edge, in binary (an 80-byte header, the number of triangles, then the triangles)
+        let mut data: Vec<u8> = vec![0; 80];
+        data.extend(2u32.to_le_bytes());
+        for triangle in [[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]], [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0f32]]] {
+            data.extend([0.0, 0.0, 1.0f32].iter().flat_map(|n| n.to_le_bytes()));
+            data.extend(triangle.iter().flatten().flat_map(|v| v.to_le_bytes()));
+            data.extend([0, 0]);
+        }
+
+        // The shared vertices are only stored once
+        let buffers: MeshBuffers = convert(stl_io::read_stl(&mut Cursor::new(data)).unwrap());
+        assert_eq!(buffers.positions.len(), 4);
+        assert_eq!(buffers.triangles.len(), 2);
+        let [p0, p1, p2] = buffers.triangles[1].positions.map(|i| buffers.positions[i as usize]);
+        assert_eq!((p0, p1, p2), (Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)));
+    }
+}
@@ -0,0 +1,326 @@
+//  MTL.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements a parser for `.mtl` files, which describe the materials of
+//!   `.obj` models.
+//!
+//!   It only collects the properties as written; turning them into
+//!   materials we can render is done by the [`Model`](super::Model).
+//
+
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::math::Colour;
+
+
+/***** CONSTANTS *****/
+/// The options that may precede the path in a texture map statement, and how many arguments they take at most.
+const MAP_OPTIONS: [(&str, usize); 13] = [
+    ("-blendu", 1),
+    ("-blendv", 1),
+    ("-bm", 1),
+    ("-boost", 1),
+    ("-cc", 1),
+    ("-clamp", 1),
+    ("-imfchan", 1),
+    ("-mm", 2),
+    ("-o", 3),
+    ("-s", 3),
+    ("-t", 3),
+    ("-texres", 1),
+    ("-type", 1),
+];
+
+
+
+
+
+/***** ERRORS *****/
+/// Defines problems with parsing `.mtl` files.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Line {line}: expected {expected} for property {key:?}, got {got:?}")]
+    IllegalValue { line: usize, key: String, expected: &'static str, got: String },
+    #[error("Line {line}: missing name after 'newmtl'")]
+    MissingName { line: usize },
+    #[error("Line {line}: property {key:?} appears before any 'newmtl'")]
+    NoMaterial { line: usize, key: String },
+    #[error("Failed to read line {line}")]
+    Read {
+        line: usize,
+        #[source]
+        err:  std::io::Error,
+    },
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Parses the arguments of a property as numbers.
+///
+/// # Arguments
+/// - `line`: The line number of the property (for debugging).
+/// - `key`: The name of the property (for debugging).
+/// - `args`: The arguments to parse.
+///
+/// # Returns
+/// The parsed numbers.
+///
+/// # Errors
+/// This function errors if any argument is not a number.
+fn parse_numbers(line: usize, key: &str, args: &[&str]) -> Result<Vec<f64>, Error> {
+    args.iter()
+        .map(|arg| arg.parse::<f64>().map_err(|_| Error::IllegalValue { line, key: key.into(), expected: "a number", got: (*arg).into() }))
+        .collect()
+}
+
+/// Parses the arguments of a property as a single number.
+///
+/// # Arguments
+/// - `line`: The line number of the property (for debugging).
+/// - `key`: The name of the property (for debugging).
+/// - `args`: The arguments to parse.
+///
+/// # Returns
+/// The parsed number.
+///
+/// # Errors
+/// This function errors if there isn't exactly one argument or it is not a number.
+fn parse_number(line: usize, key: &str, args: &[&str]) -> Result<f64, Error> {
+    match parse_numbers(line, key, args)?[..] {
+        [value] => Ok(value),
+        _ => Err(Error::IllegalValue { line, key: key.into(), expected: "a single number", got: args.join(" ") }),
+    }
+}
+
+/// Parses the arguments of a property as an RGB colour.
+///
+/// A single number is a shade of gray.
+///
+/// # Arguments
+/// - `line`: The line number of the property (for debugging).
+/// - `key`: The name of the property (for debugging).
+/// - `args`: The arguments to parse.
+///
+/// # Returns
+/// The parsed [`Colour`], or [`None`] if it's given in another colour space (e.g., `spectral` or `xyz`).
+///
+/// # Errors
+/// This function errors if there aren't one or three arguments or they are not numbers.
+fn parse_colour(line: usize, key: &str, args: &[&str]) -> Result<Option<Colour>, Error> {
+    if matches!(args.first(), Some(&"spectral" | &"xyz")) {
+        return Ok(None);
+    }
+    match parse_numbers(line, key, args)?[..] {
+        [v] => Ok(Some(Colour::new(v, v, v, 1.0))),
+        [r, g, b] => Ok(Some(Colour::new(r, g, b, 1.0))),
+        _ => Err(Error::IllegalValue { line, key: key.into(), expected: "one or three numbers", got: args.join(" ") }),
+    }
+}
+
+/// Parses the arguments of a texture map property.
+///
+/// # Arguments
+/// - `line`: The line number of the property (for debugging).
+/// - `key`: The name of the property (for debugging).
+/// - `args`: The arguments to parse.
+/// - `unsupported`: A list to which any options are added that the [`TextureMap`] does not represent.
+///
+/// # Returns
+/// The parsed [`TextureMap`].
+///
+/// # Errors
+/// This function errors if an option has illegal arguments or there is no path.
+fn parse_map(line: usize, key: &str, args: &[&str], unsupported: &mut Vec<String>) -> Result<TextureMap, Error> {
+    let mut map = TextureMap { path: PathBuf::new(), bump_multiplier: 1.0 };
+    let mut i: usize = 0;
+    while let Some((option, max_args)) = args.get(i).and_then(|arg| MAP_OPTIONS.iter().find(|(option, _)| option.eq_ignore_ascii_case(arg))) {
+        // Options with a variable number of arguments take as many numbers as there are
+        let n: usize = if *max_args == 3 { args[i + 1..].iter().take(3).take_while(|arg| arg.parse::<f64>().is_ok()).count().max(1) } else { *max_args };
+        if i + n >= args.len() {
+            return Err(Error::IllegalValue { line, key: key.into(), expected: "a path after the options", got: args.join(" ") });
+        }
+        if *option == "-bm" {
+            map.bump_multiplier = parse_number(line, key, &args[i + 1..i + 2])?;
+        } else {
+            unsupported.push(format!("{key} {option}"));
+        }
+        i += 1 + n;
+    }
+    if i >= args.len() {
+        return Err(Error::IllegalValue { line, key: key.into(), expected: "a path", got: args.join(" ") });
+    }
+
+    // The rest is the path, which may have been written on Windows
+    map.path = PathBuf::from(args[i..].join(" ").replace('\\', "/"));
+    Ok(map)
+}
+
+
+
+
+
+/***** AUXILLARY *****/
+/// Defines a texture map of a [`MtlMaterial`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureMap {
+    /// The path to the image, as written in the file.
+    pub path: PathBuf,
+    /// How much to scale the heights by if this is a bump map (the `-bm` option).
+    pub bump_multiplier: f64,
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Defines a single material from a `.mtl` file.
+///
+/// Every property is [`None`] if it was not given.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MtlMaterial {
+    /// The name of the material.
+    pub name: String,
+
+    /// The ambient colour (`Ka`).
+    pub ambient: Option<Colour>,
+    /// The diffuse colour (`Kd`).
+    pub diffuse: Option<Colour>,
+    /// The specular colour (`Ks`).
+    pub specular: Option<Colour>,
+    /// The emitted colour (`Ke`).
+    pub emissive: Option<Colour>,
+    /// The colour that light is filtered with when it passes through (`Tf`).
+    pub transmission_filter: Option<Colour>,
+    /// The specular exponent (`Ns`), where higher is glossier.
+    pub specular_exponent: Option<f64>,
+    /// The index of refraction (`Ni`).
+    pub optical_density: Option<f64>,
+    /// How opaque the material is (`d`, or one minus `Tr`).
+    pub dissolve: Option<f64>,
+    /// The illumination model (`illum`).
+    pub illum: Option<u32>,
+
+    /// The texture with the diffuse colour (`map_Kd`).
+    pub diffuse_map: Option<TextureMap>,
+    /// The texture with heights (`map_bump` or `bump`).
+    pub bump_map: Option<TextureMap>,
+    /// The texture with opacities (`map_d`).
+    pub dissolve_map: Option<TextureMap>,
+
+    /// The properties (and options) that were given but that aren't represented in this struct.
+    pub unsupported: Vec<String>,
+}
+
+/// Parses all materials in a `.mtl` file.
+///
+/// # Arguments
+/// - `reader`: The [`BufRead`] to read the file from.
+///
+/// # Returns
+/// The materials in the order in which they are defined.
+///
+/// # Errors
+/// This function errors if we failed to read from the `reader` or the file is not valid.
+pub fn parse(reader: impl BufRead) -> Result<Vec<MtlMaterial>, Error> {
+    let mut mtls: Vec<MtlMaterial> = Vec::new();
+    for (i, raw) in reader.lines().enumerate() {
+        let line: usize = i + 1;
+        let raw: String = raw.map_err(|err| Error::Read { line, err })?;
+        let raw: &str = raw.trim();
+        if raw.is_empty() || raw.starts_with('#') {
+            continue;
+        }
+        let mut args: Vec<&str> = raw.split_whitespace().collect();
+        let key: &str = args.remove(0);
+
+        // New materials start a new section
+        if key == "newmtl" {
+            if args.is_empty() {
+                return Err(Error::MissingName { line });
+            }
+            mtls.push(MtlMaterial { name: args.join(" "), ..Default::default() });
+            continue;
+        }
+        let Some(mtl) = mtls.last_mut() else { return Err(Error::NoMaterial { line, key: key.into() }) };
+
+        // Otherwise, we're setting a property of the last one
+        match key.to_ascii_lowercase().as_str() {
+            "ka" => mtl.ambient = parse_colour(line, key, &args)?,
+            "kd" => mtl.diffuse = parse_colour(line, key, &args)?,
+            "ks" => mtl.specular = parse_colour(line, key, &args)?,
+            "ke" => mtl.emissive = parse_colour(line, key, &args)?,
+            "tf" => mtl.transmission_filter = parse_colour(line, key, &args)?,
+            "ns" => mtl.specular_exponent = Some(parse_number(line, key, &args)?),
+            "ni" => mtl.optical_density = Some(parse_number(line, key, &args)?),
+            "d" => {
+                if args.first() == Some(&"-halo") {
+                    mtl.unsupported.push(format!("{key} -halo"));
+                    args.remove(0);
+                }
+                mtl.dissolve = Some(parse_number(line, key, &args)?);
+            },
+            "tr" => mtl.dissolve = Some(1.0 - parse_number(line, key, &args)?),
+            "illum" => {
+                mtl.illum = Some(parse_number(line, key, &args).and_then(|illum| {
+                    if illum >= 0.0 && illum.fract() == 0.0 {
+                        Ok(illum as u32)
+                    } else {
+                        Err(Error::IllegalValue { line, key: key.into(), expected: "an illumination model", got: args.join(" ") })
+                    }
+                })?)
+            },
+            "map_kd" => mtl.diffuse_map = Some(parse_map(line, key, &args, &mut mtl.unsupported)?),
+            "map_bump" | "bump" => mtl.bump_map = Some(parse_map(line, key, &args, &mut mtl.unsupported)?),
+            "map_d" => mtl.dissolve_map = Some(parse_map(line, key, &args, &mut mtl.unsupported)?),
+            _ => mtl.unsupported.push(key.into()),
+        }
+    }
+    Ok(mtls)
+}
+
+
+
+
+
+/***** TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mtls: Vec<MtlMaterial> = parse(
+            "# A comment\nnewmtl Glass\nKd 0.5\nNi 1.5\nTr 0.75\nillum 7\n\nnewmtl Bark tree\nKd 0.2 0.1 0.05\nmap_Kd -s 2 2 textures\\bark diffuse.png\nbump -bm \
+             0.5 bark_bump.png\nmap_Ka ambient.png\nd -halo 1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(mtls.len(), 2);
+        assert_eq!(mtls[0].name, "Glass");
+        assert_eq!(mtls[0].diffuse, Some(Colour::new(0.5, 0.5, 0.5, 1.0)));
+        assert_eq!(mtls[0].optical_density, Some(1.5));
+        assert_eq!(mtls[0].dissolve, Some(0.25));
+        assert_eq!(mtls[0].illum, Some(7));
+        assert!(mtls[0].unsupported.is_empty());
+
+        // Maps have their options parsed, and what we don't know is remembered
+        assert_eq!(mtls[1].name, "Bark tree");
+        assert_eq!(mtls[1].diffuse_map, Some(TextureMap { path: "textures/bark diffuse.png".into(), bump_multiplier: 1.0 }));
+        assert_eq!(mtls[1].bump_map, Some(TextureMap { path: "bark_bump.png".into(), bump_multiplier: 0.5 }));
+        assert_eq!(mtls[1].dissolve, Some(1.0));
+        assert_eq!(mtls[1].unsupported, vec!["map_Kd -s".to_string(), "map_Ka".into(), "d -halo".into()]);
+
+        // Errors are reported with their line
+        assert!(matches!(parse("Kd 1 1 1\n".as_bytes()), Err(Error::NoMaterial { line: 1, .. })));
+        assert!(matches!(parse("newmtl A\nNs shiny\n".as_bytes()), Err(Error::IllegalValue { line: 2, .. })));
+        assert!(matches!(parse("newmtl A\nmap_Kd -bm 1\n".as_bytes()), Err(Error::IllegalValue { line: 2, .. })));
+    }
+}
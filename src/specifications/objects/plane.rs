@@ -12,8 +12,8 @@ use serde::{Deserialize, Serialize};
 use super::super::Loadable;
 use super::super::materials::Scattering;
 use super::super::scene::Environment;
-use super::{BoundingBoxable, HitData, HitRecord, Hittable};
-use crate::math::{AABB, Ray, Vec3};
+use super::{BoundingBoxable, HitData, HitRecord, Hittable, Sampleable, SurfaceSample};
+use crate::math::{AABB, PixelSampler, Ray, Vec3};
 
 
 /***** HELPER FUNCTIONS *****/
@@ -91,6 +91,33 @@ fn plane_hit(pos: Vec3, u: Vec3, v: Vec3, ray: Ray, t_min: f64, t_max: f64) -> O
 }
 
 
+/// Computes the density w.r.t. solid angle of sampling the given `point` on a planar shape (with uniform density over its `area`), as seen from `origin`.
+///
+/// Returns [`None`] if the shape is seen from the side.
+#[inline(always)]
+fn plane_pdf(normal: Vec3, area: f64, origin: Vec3, point: Vec3) -> Option<f64> {
+    // Convert the density (`1 / area` on the surface) to solid angle by scaling with `d^2 / cos`
+    let to: Vec3 = point - origin;
+    let dist2: f64 = to.length2();
+    let cos: f64 = normal.dot(to).abs() / dist2.sqrt();
+    if cos < 1e-8 || dist2 < 1e-12 {
+        // We're looking at it from the side, so it's invisible
+        return None;
+    }
+    Some(dist2 / (cos * area))
+}
+
+/// Converts a point sampled uniformly on a planar shape to a [`SurfaceSample`] as seen from the given `origin`.
+///
+/// The `area` is the area of the shape, which is used to go from an area density to one w.r.t. solid angle.
+#[inline(always)]
+fn plane_sample(pos: Vec3, u: Vec3, v: Vec3, area: f64, origin: Vec3, uv: (f64, f64)) -> Option<SurfaceSample> {
+    let point: Vec3 = pos + uv.0 * u + uv.1 * v;
+    let normal: Vec3 = u.cross(v).unit();
+    Some(SurfaceSample { point, normal, uv, pdf: plane_pdf(normal, area, origin, point)? })
+}
+
+
 
 
 
@@ -118,7 +145,7 @@ impl BoundingBoxable for Triag {
 }
 impl Hittable for Triag {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment, _sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         // Compute a hit with this vertex' plane
         let rec: HitData = plane_hit(self.pos, self.u, self.v, ray, t_min, t_max)?;
 
@@ -133,6 +160,30 @@ impl Hittable for Triag {
     }
 }
 
+impl Sampleable for Triag {
+    #[inline]
+    fn sample(&self, origin: Vec3, _t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample> {
+        // Sample in the quad spanned by `u` and `v`, then fold the half outside of the triangle back in
+        let (mut alpha, mut beta): (f64, f64) = sampler.f64_2d();
+        if alpha + beta > 1.0 {
+            alpha = 1.0 - alpha;
+            beta = 1.0 - beta;
+        }
+        plane_sample(self.pos, self.u, self.v, 0.5 * self.u.cross(self.v).length(), origin, (alpha, beta))
+    }
+
+    #[inline]
+    fn pdf(&self, origin: Vec3, direct: Vec3, _t_us: u64) -> f64 {
+        let un: Vec3 = self.u.cross(self.v);
+        match plane_hit(self.pos, self.u, self.v, Ray::new(origin, direct), 0.001, f64::INFINITY) {
+            Some(rec) if rec.uv.0 >= 0.0 && rec.uv.1 >= 0.0 && rec.uv.0 + rec.uv.1 <= 1.0 => {
+                plane_pdf(un.unit(), 0.5 * un.length(), origin, rec.hit).unwrap_or(0.0)
+            },
+            _ => 0.0,
+        }
+    }
+}
+
 
 
 /// Defines the internals of a [`Quad`] without the material.
@@ -158,7 +209,7 @@ impl BoundingBoxable for Qd {
 }
 impl Hittable for Qd {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment, _sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         // Compute a hit with this quad's plane
         let rec: HitData = plane_hit(self.pos, self.u, self.v, ray, t_min, t_max)?;
 
@@ -173,6 +224,24 @@ impl Hittable for Qd {
     }
 }
 
+impl Sampleable for Qd {
+    #[inline]
+    fn sample(&self, origin: Vec3, _t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample> {
+        plane_sample(self.pos, self.u, self.v, self.u.cross(self.v).length(), origin, sampler.f64_2d())
+    }
+
+    #[inline]
+    fn pdf(&self, origin: Vec3, direct: Vec3, _t_us: u64) -> f64 {
+        let un: Vec3 = self.u.cross(self.v);
+        match plane_hit(self.pos, self.u, self.v, Ray::new(origin, direct), 0.001, f64::INFINITY) {
+            Some(rec) if rec.uv.0 >= 0.0 && rec.uv.0 <= 1.0 && rec.uv.1 >= 0.0 && rec.uv.1 <= 1.0 => {
+                plane_pdf(un.unit(), un.length(), origin, rec.hit).unwrap_or(0.0)
+            },
+            _ => 0.0,
+        }
+    }
+}
+
 
 
 
@@ -202,11 +271,19 @@ impl<M> BoundingBoxable for Triangle<M> {
 }
 impl<M: Scattering> Hittable for Triangle<M> {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
-        self.triag.hit(ray, t_min, t_max, env).map(|rec| HitRecord { mat: &self.material, data: rec.data })
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+        self.triag.hit(ray, t_min, t_max, env, sampler).map(|rec| HitRecord { mat: &self.material, data: rec.data })
     }
 }
 
+impl<M> Sampleable for Triangle<M> {
+    #[inline]
+    fn sample(&self, origin: Vec3, t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample> { self.triag.sample(origin, t_us, sampler) }
+
+    #[inline]
+    fn pdf(&self, origin: Vec3, direct: Vec3, t_us: u64) -> f64 { self.triag.pdf(origin, direct, t_us) }
+}
+
 
 
 /// Implements a rectangle that needn't have straight corners.
@@ -233,7 +310,15 @@ impl<M> BoundingBoxable for Quad<M> {
 }
 impl<M: Scattering> Hittable for Quad<M> {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
-        self.qd.hit(ray, t_min, t_max, env).map(|rec| HitRecord { mat: &self.material, data: rec.data })
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
+        self.qd.hit(ray, t_min, t_max, env, sampler).map(|rec| HitRecord { mat: &self.material, data: rec.data })
     }
 }
+
+impl<M> Sampleable for Quad<M> {
+    #[inline]
+    fn sample(&self, origin: Vec3, t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample> { self.qd.sample(origin, t_us, sampler) }
+
+    #[inline]
+    fn pdf(&self, origin: Vec3, direct: Vec3, t_us: u64) -> f64 { self.qd.pdf(origin, direct, t_us) }
+}
@@ -20,10 +20,12 @@ use serde::{Deserialize, Serialize};
 use super::super::Loadable;
 use super::super::animations::{Animating, Animation};
 use super::super::materials::Scattering;
+use super::super::materials::diffuse::random3_uniform;
 use super::super::scene::Environment;
 use super::hitrecord::HitRecord;
-use super::{BoundingBoxable, Hittable};
-use crate::math::{AABB, Ray, Vec3};
+use super::{BoundingBoxable, Hittable, Sampleable, SurfaceSample};
+use crate::math::utils::orthonormal_basis;
+use crate::math::{AABB, PixelSampler, Ray, Vec3};
 
 
 /***** HELPER FUNCTIONS *****/
@@ -83,6 +85,72 @@ fn sphere_hit<M: Scattering>(center: Vec3, radius: f64, ray: Ray, t_min: f64, t_
     }
 }
 
+/// Computes the density with which [`sphere_sample()`] picks the point hit when looking from `origin` in direction `direct`.
+fn sphere_pdf(center: Vec3, radius: f64, origin: Vec3, direct: Vec3) -> f64 {
+    let direct: Vec3 = direct.unit();
+    let rec: HitRecord = match sphere_hit(center, radius, Ray::new(origin, direct), 0.001, f64::INFINITY, &()) {
+        Some(rec) => rec,
+        None => return 0.0,
+    };
+
+    let dist2: f64 = (center - origin).length2();
+    if dist2 <= radius * radius {
+        // We were sampling uniformly on the surface, so convert that to solid angle
+        let cos: f64 = rec.data.normal.dot(direct).abs();
+        if cos < 1e-8 {
+            return 0.0;
+        }
+        return (rec.data.t * rec.data.t) / (cos * 4.0 * PI * radius * radius);
+    }
+
+    // Otherwise, it's uniform in the cone
+    let cos_max: f64 = (1.0 - radius * radius / dist2).max(0.0).sqrt();
+    if cos_max >= 1.0 { 0.0 } else { 1.0 / (2.0 * PI * (1.0 - cos_max)) }
+}
+
+/// Samples a point on a sphere that is visible from the given origin.
+///
+/// We do so by sampling a direction in the cone that the sphere spans as seen from `origin`,
+/// which has a uniform density w.r.t. solid angle.
+fn sphere_sample(center: Vec3, radius: f64, origin: Vec3, sampler: &mut PixelSampler) -> Option<SurfaceSample> {
+    let to_center: Vec3 = center - origin;
+    let dist2: f64 = to_center.length2();
+    if dist2 <= radius * radius {
+        // We're inside of the sphere, so any point on it is visible; sample uniformly on its surface instead
+        let normal: Vec3 = random3_uniform(sampler);
+        let point: Vec3 = center + radius * normal;
+        let to: Vec3 = point - origin;
+        let cos: f64 = normal.dot(to.unit()).abs();
+        if cos < 1e-8 {
+            return None;
+        }
+        return Some(SurfaceSample { point, normal, uv: sphere_uv(normal), pdf: to.length2() / (cos * 4.0 * PI * radius * radius) });
+    }
+
+    // Find the cone's half-angle
+    let dist: f64 = dist2.sqrt();
+    let cos_max: f64 = (1.0 - radius * radius / dist2).max(0.0).sqrt();
+    if cos_max >= 1.0 {
+        // It's so far away it is effectively invisible
+        return None;
+    }
+
+    // Sample a direction in the cone
+    let (u1, u2): (f64, f64) = sampler.f64_2d();
+    let cos_theta: f64 = 1.0 - u1 * (1.0 - cos_max);
+    let sin_theta: f64 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi: f64 = 2.0 * PI * u2;
+    let w: Vec3 = to_center / dist;
+    let (u, v): (Vec3, Vec3) = orthonormal_basis(w);
+    let direct: Vec3 = (phi.cos() * sin_theta) * u + (phi.sin() * sin_theta) * v + cos_theta * w;
+
+    // Find where that direction hits the sphere (taking the closest point, clamping to the silhouette)
+    let t: f64 = dist * cos_theta - (radius * radius - dist2 * sin_theta * sin_theta).max(0.0).sqrt();
+    let point: Vec3 = origin + t * direct;
+    let normal: Vec3 = (point - center) / radius;
+    Some(SurfaceSample { point, normal, uv: sphere_uv(normal), pdf: 1.0 / (2.0 * PI * (1.0 - cos_max)) })
+}
+
 
 
 
@@ -112,11 +180,19 @@ impl<M> BoundingBoxable for Sphere<M> {
     fn aabb(&self, _t_us: u64) -> AABB { sphere_aabb(self.center, self.radius) }
 }
 impl<M: Scattering> Hittable for Sphere<M> {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment, _sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         sphere_hit(self.center, self.radius, ray, t_min, t_max, &self.material)
     }
 }
 
+impl<M> Sampleable for Sphere<M> {
+    #[inline]
+    fn sample(&self, origin: Vec3, _t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample> { sphere_sample(self.center, self.radius, origin, sampler) }
+
+    #[inline]
+    fn pdf(&self, origin: Vec3, direct: Vec3, _t_us: u64) -> f64 { sphere_pdf(self.center, self.radius, origin, direct) }
+}
+
 
 
 /// Defines an animated sphere.
@@ -142,11 +218,23 @@ impl<M, A: Animating> BoundingBoxable for AnimatedSphere<M, A> {
 }
 impl<M: Scattering, A: Animating> Hittable for AnimatedSphere<M, A> {
     #[inline]
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, _env: &Environment, _sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         sphere_hit(self.animation.animate(self.sphere.center, ray.time), self.sphere.radius, ray, t_min, t_max, &self.sphere.material)
     }
 }
 
+impl<M, A: Animating> Sampleable for AnimatedSphere<M, A> {
+    #[inline]
+    fn sample(&self, origin: Vec3, t_us: u64, sampler: &mut PixelSampler) -> Option<SurfaceSample> {
+        sphere_sample(self.animation.animate(self.sphere.center, t_us), self.sphere.radius, origin, sampler)
+    }
+
+    #[inline]
+    fn pdf(&self, origin: Vec3, direct: Vec3, t_us: u64) -> f64 {
+        sphere_pdf(self.animation.animate(self.sphere.center, t_us), self.sphere.radius, origin, direct)
+    }
+}
+
 
 
 
@@ -14,7 +14,7 @@ use super::super::Loadable;
 use super::super::scene::Environment;
 use super::{BoundingBoxable, HitRecord, Hittable};
 use crate::math::camera::degrees_to_radians;
-use crate::math::{AABB, Ray, Vec3};
+use crate::math::{AABB, PixelSampler, Ray, Vec3};
 
 
 /***** HELPER FUNCTIONS *****/
@@ -84,9 +84,9 @@ fn rotate_z_back(vec: Vec3, sin_theta: f64, cos_theta: f64) -> Vec3 {
 // }
 // impl<T: Hittable<M>, M> Hittable<M> for Scale<T> {
 //     #[inline]
-//     fn hit(&self, mut ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<&'_ M>> {
+//     fn hit(&self, mut ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<&'_ M>> {
 //         ray.origin
-//         let mut rec: HitRecord<&M> = self.obj.hit(ray, t_min, t_max, env)?;
+//         let mut rec: HitRecord<&M> = self.obj.hit(ray, t_min, t_max, env, sampler)?;
 //         rec.data.hit += self.pos;
 //         Some(rec)
 //     }
@@ -122,9 +122,9 @@ impl<T: BoundingBoxable> BoundingBoxable for Translate<T> {
 }
 impl<T: Hittable> Hittable for Translate<T> {
     #[inline]
-    fn hit(&self, mut ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+    fn hit(&self, mut ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
         ray.origin -= self.pos;
-        let mut rec: HitRecord = self.obj.hit(ray, t_min, t_max, env)?;
+        let mut rec: HitRecord = self.obj.hit(ray, t_min, t_max, env, sampler)?;
         rec.data.hit += self.pos;
         Some(rec)
     }
@@ -196,7 +196,7 @@ macro_rules! rotate_impl {
         }
         impl<T: Hittable> Hittable for $name<T> {
             #[inline]
-            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment) -> Option<HitRecord<'_>> {
+            fn hit(&self, ray: Ray, t_min: f64, t_max: f64, env: &Environment, sampler: &mut PixelSampler) -> Option<HitRecord<'_>> {
                 // Compute the sin_theta and cos_theta for this angle
                 let angle_radians: f64 = degrees_to_radians(self.angle);
                 let sin_theta: f64 = angle_radians.sin();
@@ -208,7 +208,7 @@ macro_rules! rotate_impl {
                 let rotated_ray = Ray::with_time(origin, direct, ray.time);
 
                 // Determine the intersection in object space and quit if it doesn't hit
-                let mut rec: HitRecord = self.obj.hit(rotated_ray, t_min, t_max, env)?;
+                let mut rec: HitRecord = self.obj.hit(rotated_ray, t_min, t_max, env, sampler)?;
 
                 // Rotate the answer back to normal space
                 rec.data.hit = $rotate_back(rec.data.hit, sin_theta, cos_theta);
@@ -12,13 +12,18 @@
 //!   Defines the scene file.
 //
 
+use std::collections::BTreeMap;
 use std::num::{NonZeroU32, NonZeroU64};
 
 use serde::{Deserialize, Serialize};
 
+use super::objects::instance;
+#[cfg(feature = "gltf")]
+use super::objects::model;
 use super::objects::Object;
 use crate::common::file::{impl_toml_from_path, impl_toml_from_string, impl_toml_to_path, impl_toml_to_string};
-use crate::math::{Camera, Colour, Vec3};
+use crate::common::input::Region;
+use crate::math::{AdaptiveSampling, Camera, Colour, Filter, Sampler, ToneMapping, Vec3};
 
 
 /***** HELPER FUNCTIONS *****/
@@ -58,6 +63,18 @@ pub const fn default_camera_info_defocus_angle() -> f64 { 0.0 }
 #[inline]
 pub const fn default_camera_info_focus_dist() -> f64 { 0.0 }
 
+/// Returns the default maximum number of bounces for a [`CameraInfo`].
+#[inline]
+pub const fn default_camera_info_ray_max_depth() -> usize { 50 }
+
+/// Returns the default number of bounces after which Russian roulette kicks in for a [`CameraInfo`].
+#[inline]
+pub const fn default_camera_info_roulette_depth() -> usize { 5 }
+
+/// Returns the default maximum survival probability for Russian roulette for a [`CameraInfo`].
+#[inline]
+pub const fn default_camera_info_roulette_clamp() -> f64 { 0.95 }
+
 /// Returns the default shutter time for a [`CameraInfo`].
 #[inline]
 pub const fn default_camera_info_shutter_time() -> NonZeroU64 {
@@ -127,12 +144,31 @@ pub struct CameraInfo {
     // Image properties
     /// The dimensions of the camera.
     #[serde(default = "default_camera_info_dims")]
-    pub dims: (NonZeroU32, NonZeroU32),
+    pub dims:   (NonZeroU32, NonZeroU32),
+    /// If given, only renders this window of the frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
 
     // Features
     /// The number of rays fired per pixel.
     #[serde(default = "default_camera_info_n_samples")]
     pub n_samples: NonZeroU64,
+    /// The sampler that decides which random numbers each sample of a pixel uses.
+    #[serde(default)]
+    pub sampler: Sampler,
+    /// If given, keeps sampling every pixel beyond `n_samples` until it converges.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adaptive: Option<AdaptiveSampling>,
+    /// The maximum number of times a ray may bounce.
+    #[serde(default = "default_camera_info_ray_max_depth")]
+    pub ray_max_depth: usize,
+    /// The number of bounces after which rays may be terminated early with Russian roulette.
+    #[serde(default = "default_camera_info_roulette_depth")]
+    pub roulette_depth: usize,
+    /// The maximum probability with which a ray survives Russian roulette. Should be in the range
+    /// `(0.0, 1.0]`.
+    #[serde(default = "default_camera_info_roulette_clamp")]
+    pub roulette_clamp: f64,
     /// The vertical field-of-view of the camera.
     #[serde(default = "default_camera_info_vfov")]
     pub vfov: f64,
@@ -147,6 +183,12 @@ pub struct CameraInfo {
     /// Use `1` to disable it (instant shutter).
     #[serde(default = "default_camera_info_shutter_time")]
     pub shutter_time: NonZeroU64,
+    /// How the rendered colours are mapped to colours that can be shown.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub tone_mapping: ToneMapping,
+    /// How samples are reconstructed into pixels.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub filter: Filter,
 
     // Position
     /// Defining the position & orientation of the camera.
@@ -158,11 +200,19 @@ impl Default for CameraInfo {
     fn default() -> Self {
         CameraInfo {
             dims: default_camera_info_dims(),
+            region: None,
             n_samples: default_camera_info_n_samples(),
+            sampler: Sampler::default(),
+            adaptive: None,
+            ray_max_depth: default_camera_info_ray_max_depth(),
+            roulette_depth: default_camera_info_roulette_depth(),
+            roulette_clamp: default_camera_info_roulette_clamp(),
             vfov: default_camera_info_vfov(),
             defocus_angle: default_camera_info_defocus_angle(),
             focus_dist: default_camera_info_focus_dist(),
             shutter_time: default_camera_info_shutter_time(),
+            tone_mapping: ToneMapping::default(),
+            filter: Filter::default(),
             pos: CameraPos::default(),
         }
     }
@@ -173,6 +223,8 @@ impl From<CameraInfo> for Camera {
         Camera::new(
             (value.dims.0.into(), value.dims.1.into()),
             value.n_samples.into(),
+            value.sampler,
+            value.adaptive,
             value.vfov,
             value.defocus_angle,
             value.focus_dist,
@@ -181,6 +233,9 @@ impl From<CameraInfo> for Camera {
             value.pos.lookat,
             value.pos.lookup,
         )
+        .with_region(value.region)
+        .with_tone_mapping(value.tone_mapping)
+        .with_filter(value.filter)
     }
 }
 
@@ -195,6 +250,9 @@ pub struct SceneFile {
     /// The environment properties.
     #[serde(default, skip_serializing_if = "is_default")]
     pub camera:      CameraInfo,
+    /// Named objects that are placed in the scene by [`Instance`](super::objects::Instance)s instead of directly.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub definitions: BTreeMap<String, Object>,
     /// The objects found in this scene.
     pub objects:     Vec<Object>,
 }
@@ -203,6 +261,38 @@ impl SceneFile {
     impl_toml_to_string!();
     impl_toml_from_path!();
     impl_toml_to_path!();
+
+    /// Imports a glTF file as a scene, with the file as its only model and its cameras and lights.
+    ///
+    /// See [`model::gltf::scene()`] for how the cameras and lights are translated.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the `.gltf` or `.glb` file.
+    ///
+    /// # Returns
+    /// A new SceneFile.
+    ///
+    /// # Errors
+    /// This function errors if we failed to read or parse the file.
+    #[cfg(feature = "gltf")]
+    #[inline]
+    pub fn from_gltf(path: impl AsRef<std::path::Path>) -> Result<Self, model::Error> { model::gltf::scene(path.as_ref()) }
+
+    /// Links every [`Instance`](super::objects::Instance) in the scene to its definition.
+    ///
+    /// This moves the definitions into shared ones, so afterwards, [`SceneFile::definitions`] is
+    /// empty. The external references of the definitions and objects must already be loaded.
+    ///
+    /// # Errors
+    /// This function errors if any instance refers to an unknown definition or has a transform
+    /// that cannot be undone, or if a definition (indirectly) instances itself.
+    pub fn link(&mut self) -> Result<(), instance::Error> {
+        let definitions: instance::Definitions = instance::share(std::mem::take(&mut self.definitions))?;
+        for obj in &mut self.objects {
+            instance::link(obj, &definitions)?;
+        }
+        Ok(())
+    }
 }
 
 
@@ -220,7 +310,7 @@ mod tests {
     #[test]
     fn test_scene_file_serialize() {
         assert_eq!(
-            SceneFile { camera: CameraInfo::default(), environment: Environment::default(), objects: Vec::new() }.to_string().unwrap(),
+            SceneFile { camera: CameraInfo::default(), environment: Environment::default(), definitions: BTreeMap::new(), objects: Vec::new() }.to_string().unwrap(),
             r#"{
   "objects": []
 }"#
@@ -229,6 +319,7 @@ mod tests {
             SceneFile {
                 camera:      CameraInfo::default(),
                 environment: Environment::default(),
+                definitions: BTreeMap::new(),
                 objects:     vec![Object::Sphere(Sphere {
                     center:   [0.0, 0.0, 0.0].into(),
                     radius:   1.0,
@@ -259,6 +350,7 @@ mod tests {
             SceneFile {
                 camera:      CameraInfo::default(),
                 environment: Environment::default(),
+                definitions: BTreeMap::new(),
                 objects:     vec![Object::Sphere(Sphere {
                     center:   [0.0, 0.0, 0.0].into(),
                     radius:   1.0,
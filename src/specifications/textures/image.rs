@@ -21,6 +21,32 @@ use super::checker::spatial_checker_value;
 use crate::math::{Colour, Vec3};
 
 
+/***** HELPER FUNCTIONS *****/
+/// Samples an image at the given texture coordinates.
+///
+/// The image repeats itself outside of the `[0, 1]` range.
+///
+/// # Arguments
+/// - `image`: The [`Image`](crate::render::image::Image) to sample.
+/// - `uv`: The texture coordinates, where `(0, 0)` is the bottom left of the image.
+///
+/// # Returns
+/// The [`Colour`] of the pixel at those coordinates.
+pub fn image_value(image: &crate::render::image::Image, uv: (f64, f64)) -> Colour {
+    // Scale the logical pixel coordinates to concrete coordinates
+    // NOTE: Flip the Y-axis
+    let (w, h): (u32, u32) = image.dims();
+    let x: u32 = ((w as f64 * uv.0.rem_euclid(1.0)).floor() as u32).min(w.saturating_sub(1));
+    let y: u32 = ((h as f64 * (1.0 - uv.1.rem_euclid(1.0))).floor() as u32).min(h.saturating_sub(1));
+
+    // Now sample that coordinate from the image
+    image[(x, y)]
+}
+
+
+
+
+
 /***** LIBRARY *****/
 /// Wraps the render [`Image`](crate::render::image::Image) to make it a texture.
 ///
@@ -65,14 +91,7 @@ impl Textured for Image {
     #[inline]
     fn value(&self, uv: (f64, f64), p: Vec3) -> Colour {
         match self {
-            Self::Loaded(image) => {
-                // Scale the logical pixel coordinates to concrete coordinates
-                // NOTE: Flip the Y-axis
-                let (x, y): (u32, u32) = ((image.dims().0 as f64 * uv.0).floor() as u32, (image.dims().1 as f64 * (1.0 - uv.1)).floor() as u32);
-
-                // Now sample that coordinate from ourselves
-                image[(x, y)]
-            },
+            Self::Loaded(image) => image_value(image, uv),
 
             // Else return the magenta colour pattern we know and love
             Self::ToLoad { .. } => spatial_checker_value(1.0, Colour::new(1.0, 0.0, 1.0, 1.0), Colour::new(0.0, 0.0, 0.0, 1.0), p),
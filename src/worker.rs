@@ -0,0 +1,294 @@
+//  WORKER.rs
+//    by Lut99
+//
+//  Description:
+//!   Implements the `serve-worker` subcommand, which renders tiles
+//!   handed out by the coordinator of a render farm (i.e., a `render` with
+//!   the `farm` backend).
+//!
+//!   See [`crate::specifications::farm`] for the protocol.
+//
+
+use std::error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::ScopedJoinHandle;
+
+use console::style;
+use error_trace::ErrorTrace as _;
+use log::{debug, info, warn};
+
+use crate::hittree::HitTree;
+use crate::math::Camera;
+use crate::render::integrators::AnyIntegrator;
+use crate::render::lights::LightList;
+use crate::render::pixel::{Film, PixelStats, render_pixel};
+use crate::render::tiles::Tile;
+use crate::specifications::farm::{PROTOCOL_VERSION, Request, Response, read_message, write_message};
+use crate::specifications::scene::{Environment, SceneFile};
+
+
+/***** ERRORS *****/
+/// Defines the errors for the `serve-worker` subcommand.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to listen on the given address.
+    Listen { address: String, err: std::io::Error },
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use Error::*;
+        match self {
+            Listen { address, .. } => write!(f, "Failed to listen on '{address}'"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+        match self {
+            Listen { err, .. } => Some(err),
+        }
+    }
+}
+
+
+
+/// Defines the reasons for hanging up on a coordinator.
+#[derive(Debug)]
+enum ConnectionError {
+    /// Failed to talk to the coordinator.
+    Protocol { err: crate::specifications::farm::Error },
+    /// The coordinator speaks another version of the protocol.
+    VersionMismatch { got: u32 },
+    /// The coordinator sent something else than expected.
+    Unexpected { what: &'static str },
+    /// The coordinator sent a scene we can't render.
+    InvalidScene { reason: String },
+    /// The coordinator asked for a tile that isn't in the frame.
+    InvalidTile { tile: Tile },
+}
+impl Display for ConnectionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use ConnectionError::*;
+        match self {
+            Protocol { .. } => write!(f, "Failed to communicate"),
+            VersionMismatch { got } => write!(f, "Coordinator speaks protocol version {got}, but we speak version {PROTOCOL_VERSION}"),
+            Unexpected { what } => write!(f, "Coordinator did not send the expected {what}"),
+            InvalidScene { reason } => write!(f, "Cannot render scene: {reason}"),
+            InvalidTile { tile } => write!(f, "Tile at {:?} of {}x{} pixels is not in the frame", tile.pos, tile.dims.0, tile.dims.1),
+        }
+    }
+}
+impl error::Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use ConnectionError::*;
+        match self {
+            Protocol { err } => Some(err),
+            VersionMismatch { .. } => None,
+            Unexpected { .. } => None,
+            InvalidScene { .. } => None,
+            InvalidTile { .. } => None,
+        }
+    }
+}
+impl From<crate::specifications::farm::Error> for ConnectionError {
+    #[inline]
+    fn from(err: crate::specifications::farm::Error) -> Self { Self::Protocol { err } }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Renders a tile, splitting its rows over multiple threads.
+///
+/// # Arguments
+/// - `integrator`: The [`AnyIntegrator`] that computes the colour of every ray.
+/// - `cam`: The [`Camera`] to render with.
+/// - `seed`: The seed from which all random numbers are derived.
+/// - `world`: The [`HitTree`] with the scene to render.
+/// - `lights`: The [`LightList`] of lights in the `world`.
+/// - `env`: The [`Environment`] of the scene.
+/// - `tile`: The [`Tile`] to render.
+/// - `start`: A [`Film`] of the tile's size with the samples taken so far.
+/// - `n_threads`: The number of threads to render with.
+///
+/// # Returns
+/// A [`Film`] of the tile's size with all samples taken, with a margin for the samples that land outside of it.
+#[allow(clippy::too_many_arguments)]
+fn render_tile(
+    integrator: &AnyIntegrator,
+    cam: &Camera,
+    seed: u64,
+    world: &HitTree,
+    lights: &LightList,
+    env: &Environment,
+    tile: Tile,
+    start: &Film,
+    n_threads: usize,
+) -> Film {
+    let rows: Vec<u32> = (0..tile.dims.1).collect();
+    let rows_per_thread: usize = rows.len().div_ceil(n_threads).max(1);
+    let parts: Vec<(u32, Film)> = std::thread::scope(|s| {
+        let handles: Vec<ScopedJoinHandle<(u32, Film)>> = rows
+            .chunks(rows_per_thread)
+            .map(|rows| {
+                // Every thread renders its rows to a film of its own
+                s.spawn(move || {
+                    let mut film: Film = start.empty_like((tile.dims.0, rows.len() as u32)).with_margin(cam.filter().margin());
+                    for (i, y) in rows.iter().enumerate() {
+                        for x in 0..tile.dims.0 {
+                            let pixel: (u32, u32) = (tile.pos.0 + x, tile.pos.1 + y);
+                            let stats: PixelStats = render_pixel(integrator, cam, pixel, seed, start.get((x, *y)), &mut film, (x, i as u32), world, lights, env);
+                            film.set((x, i as u32), stats);
+                        }
+                    }
+                    (rows[0], film)
+                })
+            })
+            .collect();
+        handles.into_iter().enumerate().map(|(i, handle)| handle.join().unwrap_or_else(|_| panic!("Thread {i} panicked"))).collect()
+    });
+
+    // Stitch them together
+    let mut film: Film = start.empty_like(tile.dims).with_margin(cam.filter().margin());
+    for (y, part) in parts {
+        film.move_into(part, (0, y));
+    }
+    film
+}
+
+/// Talks to a single coordinator until it says goodbye.
+///
+/// # Arguments
+/// - `stream`: The connection to the coordinator.
+/// - `n_threads`: The number of threads to render every tile with.
+///
+/// # Errors
+/// This function errors if the coordinator misbehaved or we failed to talk to it.
+fn converse(stream: &mut TcpStream, n_threads: usize) -> Result<(), ConnectionError> {
+    // Introduce ourselves
+    match read_message(&mut *stream)? {
+        Request::Hello { version } if version == PROTOCOL_VERSION => write_message(&mut *stream, &Response::Hello { version: PROTOCOL_VERSION })?,
+        Request::Hello { version } => return Err(ConnectionError::VersionMismatch { got: version }),
+        _ => return Err(ConnectionError::Unexpected { what: "hello" }),
+    }
+
+    // Receive the scene and build it
+    let (mut scene, integrator, seed): (SceneFile, AnyIntegrator, u64) = match read_message(&mut *stream)? {
+        Request::Scene { scene, integrator, seed } => ((*scene).into_owned(), integrator.into_owned(), seed),
+        _ => return Err(ConnectionError::Unexpected { what: "scene" }),
+    };
+    let dims: (u32, u32) = (scene.camera.dims.0.into(), scene.camera.dims.1.into());
+    if let Some(region) = scene.camera.region {
+        if !region.fits(dims) {
+            return Err(ConnectionError::InvalidScene { reason: format!("region {region} does not fit in an image of {}x{} pixels", dims.0, dims.1) });
+        }
+    }
+    if let Err(err) = scene.link() {
+        return Err(ConnectionError::InvalidScene { reason: err.to_string() });
+    }
+    let cam: Camera = Camera::from(scene.camera);
+    let world: HitTree = HitTree::with_objs(scene.objects, (0..=scene.camera.shutter_time.into()).into());
+    let lights: LightList = LightList::collect(&world);
+    info!("Received scene with {} object(s) and {} light(s) to sample directly", world.len(), lights.len());
+    write_message(&mut *stream, &Response::Ready)?;
+
+    // Render tiles until the coordinator is done
+    loop {
+        match read_message(&mut *stream)? {
+            Request::Render { tile, film, limit } => {
+                if tile.pos.0.checked_add(tile.dims.0).is_none_or(|x| x > dims.0)
+                    || tile.pos.1.checked_add(tile.dims.1).is_none_or(|y| y > dims.1)
+                    || film.dims() != tile.dims
+                {
+                    return Err(ConnectionError::InvalidTile { tile });
+                }
+                debug!("Rendering tile at {:?} of {}x{} pixels...", tile.pos, tile.dims.0, tile.dims.1);
+                let cam: Camera = cam.with_sample_limit(limit);
+                let film: Film = render_tile(&integrator, &cam, seed, &world, &lights, &scene.environment, tile, &film, n_threads);
+                write_message(&mut *stream, &Response::Rendered { tile, film })?;
+            },
+            Request::Goodbye => return Ok(()),
+            _ => return Err(ConnectionError::Unexpected { what: "tile or goodbye" }),
+        }
+    }
+}
+
+/// Serves a single coordinator until it says goodbye.
+///
+/// # Arguments
+/// - `stream`: The connection to the coordinator.
+/// - `n_threads`: The number of threads to render every tile with.
+///
+/// # Errors
+/// This function errors if the coordinator misbehaved or we failed to talk to it. If possible,
+/// the coordinator is told why before hanging up.
+fn serve_coordinator(mut stream: TcpStream, n_threads: usize) -> Result<(), ConnectionError> {
+    let res: Result<(), ConnectionError> = converse(&mut stream, n_threads);
+
+    // Tell the coordinator why we're leaving, unless we can't talk to it anyway
+    if let Err(err) = &res {
+        if !matches!(err, ConnectionError::Protocol { .. }) {
+            if let Err(err) = write_message(&mut stream, &Response::Error { message: err.to_string() }) {
+                debug!("Failed to tell coordinator about error: {err}");
+            }
+        }
+    }
+    res
+}
+
+
+
+
+
+/***** LIBRARY *****/
+/// Serves coordinators that connect to the given listener, forever.
+///
+/// Every coordinator is served on its own thread.
+///
+/// # Arguments
+/// - `listener`: The [`TcpListener`] on which coordinators connect.
+/// - `n_threads`: The number of threads to render every tile with.
+pub fn serve_on(listener: TcpListener, n_threads: usize) {
+    for stream in listener.incoming() {
+        let stream: TcpStream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Failed to accept coordinator: {err}");
+                continue;
+            },
+        };
+        let peer: String = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "<unknown>".into());
+        info!("Coordinator '{peer}' connected");
+        std::thread::spawn(move || {
+            if let Err(err) = stream.set_nodelay(true) {
+                debug!("Failed to disable Nagle's algorithm: {err}");
+            }
+            match serve_coordinator(stream, n_threads) {
+                Ok(_) => info!("Coordinator '{peer}' is done"),
+                Err(err) => warn!("Hung up on coordinator '{peer}': {}", err.trace()),
+            }
+        });
+    }
+}
+
+/// Listens for coordinators of a render farm, and renders the tiles they hand out.
+///
+/// This never returns unless it failed to start listening.
+///
+/// # Arguments
+/// - `address`: The address to listen on, as a `<HOST>:<PORT>`-pair.
+/// - `n_threads`: The number of threads to render every tile with.
+///
+/// # Errors
+/// This function errors if we failed to listen on the given address.
+pub fn serve(address: &str, n_threads: usize) -> Result<(), Error> {
+    let listener: TcpListener = TcpListener::bind(address).map_err(|err| Error::Listen { address: address.into(), err })?;
+    let local: String = listener.local_addr().as_ref().map(SocketAddr::to_string).unwrap_or_else(|_| address.into());
+    println!("Worker {} on {} with {} thread(s)", style("listening").bold().green(), style(local).bold(), n_threads);
+    serve_on(listener, n_threads);
+    Ok(())
+}